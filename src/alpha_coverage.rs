@@ -0,0 +1,176 @@
+use image::{imageops::FilterType, Rgba, Rgba32FImage, RgbaImage};
+
+/// Fraction of texels that pass the alpha test at `cutoff`.
+pub fn coverage(image: &RgbaImage, cutoff: f32) -> f32 {
+    scaled_coverage(image, cutoff, 1.0)
+}
+
+fn scaled_coverage(image: &RgbaImage, cutoff: f32, scale: f32) -> f32 {
+    let threshold = cutoff * 255.0;
+    let passing = image
+        .pixels()
+        .filter(|p| p[3] as f32 * scale >= threshold)
+        .count();
+    passing as f32 / (image.width() * image.height()).max(1) as f32
+}
+
+pub fn premultiply(image: &mut RgbaImage) {
+    for p in image.pixels_mut() {
+        let a = p[3] as u32;
+        for c in 0..3 {
+            p[c] = ((p[c] as u32 * a + 127) / 255) as u8;
+        }
+    }
+}
+
+/// Resizes with the colors weighted by alpha, so fully transparent texels (often black) don't
+/// bleed into the visible edges.
+pub fn resize(image: &RgbaImage, width: u32, height: u32) -> RgbaImage {
+    let premultiplied = Rgba32FImage::from_fn(image.width(), image.height(), |x, y| {
+        let p = image.get_pixel(x, y);
+        let a = p[3] as f32 / 255.0;
+        Rgba([
+            p[0] as f32 / 255.0 * a,
+            p[1] as f32 / 255.0 * a,
+            p[2] as f32 / 255.0 * a,
+            a,
+        ])
+    });
+    let resized = image::imageops::resize(&premultiplied, width, height, FilterType::Triangle);
+    RgbaImage::from_fn(width, height, |x, y| {
+        let p = resized.get_pixel(x, y);
+        let a = p[3];
+        let unpremultiply = |c: f32| {
+            let c = if a > 0.0 { c / a } else { c };
+            (c * 255.0).round().clamp(0.0, 255.0) as u8
+        };
+        Rgba([
+            unpremultiply(p[0]),
+            unpremultiply(p[1]),
+            unpremultiply(p[2]),
+            (a * 255.0).round().clamp(0.0, 255.0) as u8,
+        ])
+    })
+}
+
+/// Scales alpha so the fraction of texels passing the alpha test matches `target`.
+fn scale_to_coverage(image: &RgbaImage, cutoff: f32, target: f32) -> RgbaImage {
+    // Coverage only grows with the scale, so a binary search finds the smallest scale that
+    // reaches the target
+    let (mut low, mut high) = (0.0, 4.0);
+    for _ in 0..16 {
+        let mid = (low + high) * 0.5;
+        if scaled_coverage(image, cutoff, mid) < target {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    let mut scaled = image.clone();
+    for p in scaled.pixels_mut() {
+        p[3] = (p[3] as f32 * high).round().min(255.0) as u8;
+    }
+    scaled
+}
+
+pub struct CoverageLevel {
+    pub image: RgbaImage,
+    /// Coverage of a plain downsampled mip.
+    pub naive: f32,
+    /// Coverage after scaling the alpha.
+    pub preserved: f32,
+}
+
+/// Builds the mip chain down to 1x1, or until the largest side would drop below
+/// `min_mip_size`. Each level is downsampled from the unscaled previous level, then its alpha is
+/// scaled to keep the coverage of the base level, so alpha tested foliage doesn't thin out in
+/// the distance.
+pub fn coverage_mip_chain(base: RgbaImage, cutoff: f32, min_mip_size: u32) -> Vec<CoverageLevel> {
+    let target = coverage(&base, cutoff);
+    let mut previous = base.clone();
+    let mut levels = vec![CoverageLevel {
+        image: base,
+        naive: target,
+        preserved: target,
+    }];
+    while previous.width() > 1 || previous.height() > 1 {
+        let width = (previous.width() / 2).max(1);
+        let height = (previous.height() / 2).max(1);
+        if width.max(height) < min_mip_size.max(1) {
+            break;
+        }
+        let next = resize(&previous, width, height);
+        let scaled = scale_to_coverage(&next, cutoff, target);
+        levels.push(CoverageLevel {
+            naive: coverage(&next, cutoff),
+            preserved: coverage(&scaled, cutoff),
+            image: scaled,
+        });
+        previous = next;
+    }
+    levels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Alpha spread evenly over 0..=255 with no structure, like the noisy edges of leaf cards.
+    fn noise(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            let mut h = x.wrapping_mul(374_761_393) ^ y.wrapping_mul(668_265_263);
+            h = (h ^ (h >> 13)).wrapping_mul(1_274_126_177);
+            h ^= h >> 16;
+            Rgba([40, 120, 30, h as u8])
+        })
+    }
+
+    #[test]
+    fn mip_chain_keeps_the_base_coverage() {
+        let cutoff = 0.75;
+        let levels = coverage_mip_chain(noise(64, 64), cutoff, 8);
+        let sizes = levels
+            .iter()
+            .map(|level| level.image.dimensions())
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, [(64, 64), (32, 32), (16, 16), (8, 8)]);
+        let target = levels[0].preserved;
+        assert!((0.2..0.3).contains(&target), "{target}");
+        for level in &levels[1..] {
+            assert!(
+                (level.preserved - target).abs() < 0.05,
+                "{:?}: {} vs {target}",
+                level.image.dimensions(),
+                level.preserved
+            );
+            assert_eq!(level.preserved, coverage(&level.image, cutoff));
+        }
+        // Averaging pulls the alpha towards the middle, plain mips lose most of the coverage
+        assert!(levels[3].naive < target / 2.0, "{}", levels[3].naive);
+    }
+
+    #[test]
+    fn mip_chain_stops_at_one_texel() {
+        let levels = coverage_mip_chain(noise(8, 2), 0.5, 1);
+        let sizes = levels
+            .iter()
+            .map(|level| level.image.dimensions())
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, [(8, 2), (4, 1), (2, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn premultiply_keeps_opaque_texels() {
+        let mut image = RgbaImage::from_fn(4, 1, |x, _| match x {
+            0 => Rgba([200, 100, 7, 255]),
+            1 => Rgba([255, 255, 255, 255]),
+            2 => Rgba([200, 100, 8, 128]),
+            _ => Rgba([200, 100, 7, 0]),
+        });
+        premultiply(&mut image);
+        assert_eq!(image.get_pixel(0, 0), &Rgba([200, 100, 7, 255]));
+        assert_eq!(image.get_pixel(1, 0), &Rgba([255, 255, 255, 255]));
+        assert_eq!(image.get_pixel(2, 0), &Rgba([100, 50, 4, 128]));
+        assert_eq!(image.get_pixel(3, 0), &Rgba([0, 0, 0, 0]));
+    }
+}
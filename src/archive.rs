@@ -0,0 +1,684 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+use bevy::{
+    asset::io::{
+        file::FileAssetReader, AssetReader, AssetReaderError, PathStream, Reader, VecReader,
+    },
+    tasks::futures_lite::stream,
+    utils::{HashMap, HashSet},
+};
+use flate2::read::DeflateDecoder;
+
+#[derive(Clone, Copy, Debug)]
+enum Compression {
+    Stored,
+    Deflate,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Entry {
+    /// Where the file data starts, for zip where its local header starts.
+    offset: u64,
+    size: u64,
+    uncompressed_size: u64,
+    compression: Compression,
+    zip: bool,
+}
+
+/// The files of a .zip or .tar, read straight from it. Only the index is kept in memory.
+pub struct Archive {
+    path: PathBuf,
+    entries: HashMap<PathBuf, Entry>,
+    dirs: HashSet<PathBuf>,
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+fn read_at(file: &mut File, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![0; len];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Relative path without `.` or `..`, directories end in `/` in both formats.
+fn entry_path(name: &str) -> Option<PathBuf> {
+    let path = Path::new(name.trim_start_matches("./"));
+    path.components()
+        .all(|component| matches!(component, Component::Normal(_)))
+        .then(|| path.to_path_buf())
+        .filter(|path| !name.ends_with('/') && path.components().count() > 0)
+}
+
+fn index_zip(file: &mut File) -> io::Result<Vec<(String, Entry)>> {
+    // The end of central directory record is followed by a comment of up to 64KB
+    let len = file.seek(SeekFrom::End(0))?;
+    let tail_len = len.min(22 + 65535);
+    let tail = read_at(file, len - tail_len, tail_len as usize)?;
+    let eocd = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&i| u32_at(&tail, i) == 0x06054b50)
+        .ok_or_else(|| invalid("no zip end of central directory record"))?;
+    let mut count = u16_at(&tail, eocd + 10) as u64;
+    let mut directory_offset = u32_at(&tail, eocd + 16) as u64;
+    if count == 0xFFFF || directory_offset == 0xFFFFFFFF {
+        // Zip64, the locator is right before the record
+        let locator = eocd
+            .checked_sub(20)
+            .filter(|&i| u32_at(&tail, i) == 0x07064b50)
+            .ok_or_else(|| invalid("no zip64 end of central directory locator"))?;
+        let record = read_at(file, u64_at(&tail, locator + 8), 56)?;
+        if u32_at(&record, 0) != 0x06064b50 {
+            return Err(invalid("no zip64 end of central directory record"));
+        }
+        count = u64_at(&record, 32);
+        directory_offset = u64_at(&record, 48);
+    }
+
+    let mut reader = io::BufReader::new(&mut *file);
+    reader.seek(SeekFrom::Start(directory_offset))?;
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let mut header = [0; 46];
+        reader.read_exact(&mut header)?;
+        if u32_at(&header, 0) != 0x02014b50 {
+            return Err(invalid("broken zip central directory"));
+        }
+        let mut variable = vec![
+            0;
+            u16_at(&header, 28) as usize
+                + u16_at(&header, 30) as usize
+                + u16_at(&header, 32) as usize
+        ];
+        reader.read_exact(&mut variable)?;
+        let name_len = u16_at(&header, 28) as usize;
+        let name = String::from_utf8_lossy(&variable[..name_len]).to_string();
+        let extra = &variable[name_len..name_len + u16_at(&header, 30) as usize];
+
+        let mut uncompressed_size = u32_at(&header, 24) as u64;
+        let mut size = u32_at(&header, 20) as u64;
+        let mut offset = u32_at(&header, 42) as u64;
+        // The zip64 extra field has the values that didn't fit, in this order
+        let mut at = 0;
+        while at + 4 <= extra.len() {
+            let (id, len) = (u16_at(extra, at), u16_at(extra, at + 2) as usize);
+            let data = &extra[(at + 4).min(extra.len())..(at + 4 + len).min(extra.len())];
+            if id == 0x0001 {
+                let mut values = data.chunks_exact(8).map(|c| u64_at(c, 0));
+                for value in [&mut uncompressed_size, &mut size, &mut offset] {
+                    if *value == 0xFFFFFFFF {
+                        *value = values.next().ok_or_else(|| invalid("short zip64 field"))?;
+                    }
+                }
+            }
+            at += 4 + len;
+        }
+
+        if u16_at(&header, 8) & 1 != 0 {
+            return Err(invalid(format!("{name} is encrypted")));
+        }
+        let compression = match u16_at(&header, 10) {
+            0 => Compression::Stored,
+            8 => Compression::Deflate,
+            method => {
+                return Err(invalid(format!(
+                    "{name} uses compression method {method}, only stored and deflate are supported"
+                )))
+            }
+        };
+        entries.push((
+            name,
+            Entry {
+                offset,
+                size,
+                uncompressed_size,
+                compression,
+                zip: true,
+            },
+        ));
+    }
+    Ok(entries)
+}
+
+/// Octal, or base-256 for the sizes over 8GB.
+fn tar_number(field: &[u8]) -> io::Result<u64> {
+    if field[0] & 0x80 != 0 {
+        return Ok(field[1..]
+            .iter()
+            .fold(u64::from(field[0] & 0x7f), |n, b| n << 8 | u64::from(*b)));
+    }
+    let text = String::from_utf8_lossy(field);
+    let text = text.trim_matches(|c: char| c == '\0' || c == ' ');
+    if text.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(text, 8).map_err(|_| invalid(format!("bad tar number {text:?}")))
+}
+
+/// GNU long names and pax headers are read into memory, anything this large is a broken archive.
+const MAX_TAR_EXTENSION_LEN: u64 = 1 << 20;
+
+fn tar_string(field: &[u8]) -> String {
+    let end = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).to_string()
+}
+
+fn index_tar(file: &mut File) -> io::Result<Vec<(String, Entry)>> {
+    let mut entries = Vec::new();
+    let mut long_name = None;
+    let mut offset = 0;
+    let mut reader = io::BufReader::new(&mut *file);
+    loop {
+        let mut header = [0; 512];
+        reader.seek(SeekFrom::Start(offset))?;
+        if reader.read_exact(&mut header).is_err() || header.iter().all(|b| *b == 0) {
+            break;
+        }
+        let size = tar_number(&header[124..136])?;
+        let data_offset = offset + 512;
+        offset = data_offset + size.div_ceil(512) * 512;
+        match header[156] {
+            // GNU long name, and pax headers which may carry the path
+            b'L' | b'x' => {
+                if size > MAX_TAR_EXTENSION_LEN {
+                    return Err(invalid(format!(
+                        "{size} byte tar extension header, the limit is {MAX_TAR_EXTENSION_LEN}"
+                    )));
+                }
+                let mut data = vec![0; size as usize];
+                reader.read_exact(&mut data)?;
+                long_name = if header[156] == b'L' {
+                    Some(tar_string(&data))
+                } else {
+                    String::from_utf8_lossy(&data)
+                        .lines()
+                        .find_map(|record| record.split_once(" path="))
+                        .map(|(_, path)| path.to_string())
+                };
+            }
+            b'0' | b'\0' | b'7' => {
+                let name = long_name.take().unwrap_or_else(|| {
+                    let (prefix, name) =
+                        (tar_string(&header[345..500]), tar_string(&header[..100]));
+                    if &header[257..262] == b"ustar" && !prefix.is_empty() {
+                        format!("{prefix}/{name}")
+                    } else {
+                        name
+                    }
+                });
+                entries.push((
+                    name,
+                    Entry {
+                        offset: data_offset,
+                        size,
+                        uncompressed_size: size,
+                        compression: Compression::Stored,
+                        zip: false,
+                    },
+                ));
+            }
+            _ => long_name = None,
+        }
+    }
+    Ok(entries)
+}
+
+impl Archive {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let mut file =
+            File::open(path).map_err(|e| format!("Failed to open assets archive {path:?}: {e}"))?;
+        let name = path.to_string_lossy().to_lowercase();
+        let entries = if name.ends_with(".zip") {
+            index_zip(&mut file)
+        } else if name.ends_with(".tar") {
+            index_tar(&mut file)
+        } else {
+            return Err(format!(
+                "Unsupported assets archive {path:?}, expected a .zip or an uncompressed .tar"
+            ));
+        }
+        .map_err(|e| format!("Failed to read assets archive {path:?}: {e}"))?;
+        // The sizes are allocated when reading, so a broken header can't ask for more than the file
+        let len = file
+            .metadata()
+            .map_err(|e| format!("Failed to read assets archive {path:?}: {e}"))?
+            .len();
+        if let Some((name, entry)) = entries.iter().find(|(_, entry)| {
+            entry
+                .offset
+                .checked_add(entry.size)
+                .is_none_or(|end| end > len)
+        }) {
+            return Err(format!(
+                "Failed to read assets archive {path:?}: {name} claims {} bytes at {}, past the end of the {len} byte file",
+                entry.size, entry.offset
+            ));
+        }
+
+        let mut files = entries
+            .into_iter()
+            .filter_map(|(name, entry)| Some((entry_path(&name)?, entry)))
+            .collect::<Vec<_>>();
+        // Downloads usually have everything in one top level directory
+        let top_level = files
+            .first()
+            .and_then(|(path, _)| path.components().next())
+            .map(|component| component.as_os_str().to_owned());
+        if let Some(top_level) = top_level.filter(|top_level| {
+            files.iter().all(|(path, _)| {
+                path.components().count() > 1 && path.starts_with(Path::new(top_level))
+            })
+        }) {
+            for (path, _) in &mut files {
+                *path = path.strip_prefix(&top_level).unwrap().to_path_buf();
+            }
+        }
+
+        let mut dirs = HashSet::new();
+        for (path, _) in &files {
+            dirs.extend(path.ancestors().skip(1).map(Path::to_path_buf));
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            entries: files.into_iter().collect(),
+            dirs,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.entries.contains_key(path)
+    }
+
+    pub fn is_dir(&self, path: &Path) -> bool {
+        self.dirs.contains(path)
+    }
+
+    /// Opens the archive again for every read, so reads can run in parallel.
+    pub fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let entry = self.entries.get(path).ok_or(io::ErrorKind::NotFound)?;
+        let mut file = File::open(&self.path)?;
+        let mut offset = entry.offset;
+        if entry.zip {
+            let header = read_at(&mut file, offset, 30)?;
+            if u32_at(&header, 0) != 0x04034b50 {
+                return Err(invalid(format!("broken zip local header for {path:?}")));
+            }
+            offset += 30 + u16_at(&header, 26) as u64 + u16_at(&header, 28) as u64;
+        }
+        let data = read_at(&mut file, offset, entry.size as usize)?;
+        match entry.compression {
+            Compression::Stored => Ok(data),
+            Compression::Deflate => {
+                // Not bounded by the file length, deflate can compress a lot
+                let mut bytes = Vec::new();
+                bytes
+                    .try_reserve(entry.uncompressed_size as usize)
+                    .map_err(|e| {
+                        invalid(format!(
+                            "{path:?} claims {} uncompressed bytes: {e}",
+                            entry.uncompressed_size
+                        ))
+                    })?;
+                DeflateDecoder::new(&data[..])
+                    .take(entry.uncompressed_size)
+                    .read_to_end(&mut bytes)?;
+                Ok(bytes)
+            }
+        }
+    }
+
+    /// The files right under `dir`.
+    pub fn files_in(&self, dir: &Path) -> Vec<PathBuf> {
+        self.entries
+            .keys()
+            .filter(|path| path.parent() == Some(dir))
+            .cloned()
+            .collect()
+    }
+
+    /// The files and directories right under `dir`.
+    fn children(&self, dir: &Path) -> Vec<PathBuf> {
+        self.entries
+            .keys()
+            .chain(&self.dirs)
+            .filter(|path| path.parent() == Some(dir))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Reads from `--assets-archive`, and from the assets directory what isn't in it, like the
+/// environment maps and the fake GI that come with this repo.
+pub struct ArchiveAssetReader {
+    archive: Arc<Archive>,
+    fallback: FileAssetReader,
+}
+
+impl ArchiveAssetReader {
+    pub fn new(archive: Arc<Archive>, fallback_dir: &Path) -> Self {
+        Self {
+            archive,
+            fallback: FileAssetReader::new(fallback_dir),
+        }
+    }
+
+    fn read_entry<'a>(&self, path: &Path) -> Result<Box<Reader<'a>>, AssetReaderError> {
+        let bytes = self.archive.read(path).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => AssetReaderError::NotFound(path.to_path_buf()),
+            _ => AssetReaderError::Io(Arc::new(e)),
+        })?;
+        Ok(Box::new(VecReader::new(bytes)))
+    }
+}
+
+fn meta_path(path: &Path) -> PathBuf {
+    let mut meta = path.as_os_str().to_owned();
+    meta.push(".meta");
+    meta.into()
+}
+
+impl AssetReader for ArchiveAssetReader {
+    async fn read<'a>(&'a self, path: &'a Path) -> Result<Box<Reader<'a>>, AssetReaderError> {
+        if self.archive.contains(path) {
+            self.read_entry(path)
+        } else {
+            self.fallback.read(path).await
+        }
+    }
+
+    async fn read_meta<'a>(&'a self, path: &'a Path) -> Result<Box<Reader<'a>>, AssetReaderError> {
+        let meta = meta_path(path);
+        if self.archive.contains(&meta) {
+            self.read_entry(&meta)
+        } else {
+            self.fallback.read_meta(path).await
+        }
+    }
+
+    async fn read_directory<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> Result<Box<PathStream>, AssetReaderError> {
+        if self.archive.is_dir(path) {
+            Ok(Box::new(stream::iter(self.archive.children(path))))
+        } else {
+            self.fallback.read_directory(path).await
+        }
+    }
+
+    async fn is_directory<'a>(&'a self, path: &'a Path) -> Result<bool, AssetReaderError> {
+        if self.archive.is_dir(path) {
+            Ok(true)
+        } else {
+            self.fallback.is_directory(path).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Write};
+
+    use flate2::write::DeflateEncoder;
+
+    use super::*;
+    use crate::test_dir::TempDir;
+
+    fn u16_le(out: &mut Vec<u8>, value: u16) {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32_le(out: &mut Vec<u8>, value: u32) {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64_le(out: &mut Vec<u8>, value: u64) {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// A zip of `(name, data, deflate)` files. With `zip64` the sizes and offsets only come from
+    /// the zip64 extra fields and the count from the zip64 end of central directory record.
+    fn zip(files: &[(&str, &[u8], bool)], zip64: bool) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut directory = Vec::new();
+        for (name, data, deflate) in files {
+            let stored = if *deflate {
+                let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            } else {
+                data.to_vec()
+            };
+            let method = if *deflate { 8 } else { 0 };
+            let offset = out.len() as u64;
+            u32_le(&mut out, 0x04034b50);
+            out.extend_from_slice(&[0; 4]);
+            u16_le(&mut out, method);
+            out.extend_from_slice(&[0; 16]);
+            u16_le(&mut out, name.len() as u16);
+            u16_le(&mut out, 0);
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(&stored);
+
+            let (size, uncompressed_size) = (stored.len() as u64, data.len() as u64);
+            let small = |value: u64| if zip64 { 0xFFFFFFFF } else { value as u32 };
+            u32_le(&mut directory, 0x02014b50);
+            directory.extend_from_slice(&[0; 6]);
+            u16_le(&mut directory, method);
+            directory.extend_from_slice(&[0; 8]);
+            u32_le(&mut directory, small(size));
+            u32_le(&mut directory, small(uncompressed_size));
+            u16_le(&mut directory, name.len() as u16);
+            u16_le(&mut directory, if zip64 { 28 } else { 0 });
+            directory.extend_from_slice(&[0; 10]);
+            u32_le(&mut directory, small(offset));
+            directory.extend_from_slice(name.as_bytes());
+            if zip64 {
+                u16_le(&mut directory, 0x0001);
+                u16_le(&mut directory, 24);
+                u64_le(&mut directory, uncompressed_size);
+                u64_le(&mut directory, size);
+                u64_le(&mut directory, offset);
+            }
+        }
+        let directory_offset = out.len() as u64;
+        out.extend_from_slice(&directory);
+        if zip64 {
+            let record = out.len() as u64;
+            u32_le(&mut out, 0x06064b50);
+            out.extend_from_slice(&[0; 28]);
+            u64_le(&mut out, files.len() as u64);
+            u64_le(&mut out, directory.len() as u64);
+            u64_le(&mut out, directory_offset);
+            u32_le(&mut out, 0x07064b50);
+            u32_le(&mut out, 0);
+            u64_le(&mut out, record);
+            u32_le(&mut out, 1);
+        }
+        u32_le(&mut out, 0x06054b50);
+        out.extend_from_slice(&[0; 6]);
+        u16_le(&mut out, if zip64 { 0xFFFF } else { files.len() as u16 });
+        u32_le(&mut out, directory.len() as u32);
+        u32_le(
+            &mut out,
+            if zip64 {
+                0xFFFFFFFF
+            } else {
+                directory_offset as u32
+            },
+        );
+        u16_le(&mut out, 0);
+        out
+    }
+
+    fn tar_header(name: &str, prefix: &str, kind: u8, size: usize) -> [u8; 512] {
+        let mut header = [0; 512];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[124..135].copy_from_slice(format!("{size:011o}").as_bytes());
+        header[156] = kind;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+        header
+    }
+
+    fn tar_entry(out: &mut Vec<u8>, header: [u8; 512], data: &[u8]) {
+        out.extend_from_slice(&header);
+        out.extend_from_slice(data);
+        out.resize(out.len().next_multiple_of(512), 0);
+    }
+
+    fn open(dir: &TempDir, name: &str, bytes: &[u8]) -> Result<Archive, String> {
+        let path = dir.join(name);
+        fs::write(&path, bytes).unwrap();
+        Archive::open(&path)
+    }
+
+    #[test]
+    fn zip_paths_and_contents() {
+        let dir = TempDir::new("archive_zip");
+        let texture = [7; 300];
+        for zip64 in [false, true] {
+            let bytes = zip(
+                &[
+                    ("Bistro_v5_2/", b"", false),
+                    ("Bistro_v5_2/scene.gltf", b"{}", false),
+                    ("Bistro_v5_2/textures/leaf.png", &texture, true),
+                ],
+                zip64,
+            );
+            let archive = open(&dir, "assets.zip", &bytes).unwrap();
+            // The shared top level directory is stripped, directories aren't files
+            assert_eq!(archive.len(), 2, "zip64 {zip64}");
+            assert!(archive.is_dir(Path::new("textures")));
+            assert_eq!(archive.read(Path::new("scene.gltf")).unwrap(), b"{}");
+            assert_eq!(
+                archive.read(Path::new("textures/leaf.png")).unwrap(),
+                texture
+            );
+            assert_eq!(
+                archive.read(Path::new("missing.png")).unwrap_err().kind(),
+                io::ErrorKind::NotFound
+            );
+        }
+    }
+
+    #[test]
+    fn tar_paths_and_contents() {
+        let dir = TempDir::new("archive_tar");
+        let long_name = format!("textures/{}.png", "a".repeat(120));
+        let pax_name = "textures/pax name.png";
+        let mut bytes = Vec::new();
+        tar_entry(&mut bytes, tar_header("scene.gltf", "", b'0', 2), b"{}");
+        tar_entry(&mut bytes, tar_header("textures/", "", b'5', 0), b"");
+        tar_entry(
+            &mut bytes,
+            tar_header("leaf.png", "textures", b'0', 3),
+            b"abc",
+        );
+        let long = format!("{long_name}\0");
+        tar_entry(
+            &mut bytes,
+            tar_header("././@LongLink", "", b'L', long.len()),
+            long.as_bytes(),
+        );
+        tar_entry(&mut bytes, tar_header("truncated", "", b'0', 4), b"long");
+        // Each record starts with its own length
+        let pax = format!("22 mtime=1700000000.0\n30 path={pax_name}\n");
+        tar_entry(
+            &mut bytes,
+            tar_header("PaxHeaders/x", "", b'x', pax.len()),
+            pax.as_bytes(),
+        );
+        tar_entry(&mut bytes, tar_header("pax", "", b'0', 3), b"pax");
+        bytes.extend_from_slice(&[0; 1024]);
+
+        let archive = open(&dir, "assets.tar", &bytes).unwrap();
+        // scene.gltf is at the top level, nothing is stripped
+        assert_eq!(archive.len(), 4);
+        assert_eq!(archive.read(Path::new("scene.gltf")).unwrap(), b"{}");
+        assert_eq!(
+            archive.read(Path::new("textures/leaf.png")).unwrap(),
+            b"abc"
+        );
+        assert_eq!(archive.read(Path::new(&long_name)).unwrap(), b"long");
+        assert_eq!(archive.read(Path::new(pax_name)).unwrap(), b"pax");
+        assert!(archive.is_dir(Path::new("textures")));
+    }
+
+    #[test]
+    fn huge_tar_extension_headers_are_errors() {
+        let dir = TempDir::new("archive_huge");
+        let mut bytes = Vec::new();
+        tar_entry(
+            &mut bytes,
+            tar_header("././@LongLink", "", b'L', 1 << 30),
+            b"",
+        );
+        bytes.extend_from_slice(&[0; 1024]);
+        let e = open(&dir, "assets.tar", &bytes).err().unwrap();
+        assert!(e.contains("tar extension header"), "{e}");
+    }
+
+    #[test]
+    fn sizes_past_the_end_are_errors() {
+        let dir = TempDir::new("archive_past_end");
+        let mut bytes = Vec::new();
+        tar_entry(
+            &mut bytes,
+            tar_header("scene.gltf", "", b'0', 1 << 32),
+            b"{}",
+        );
+        let e = open(&dir, "assets.tar", &bytes).err().unwrap();
+        assert!(e.contains("past the end"), "{e}");
+
+        let mut bytes = zip(&[("scene.gltf", b"{}", false)], false);
+        // The compressed size in the central directory entry, right after the local file
+        let size = 30 + "scene.gltf".len() + 2 + 20;
+        bytes[size..size + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let e = open(&dir, "assets.zip", &bytes).err().unwrap();
+        assert!(e.contains("past the end"), "{e}");
+    }
+
+    #[test]
+    fn huge_uncompressed_sizes_are_errors() {
+        let dir = TempDir::new("archive_huge_uncompressed");
+        let mut bytes = zip(&[("scene.gltf", b"{}", true)], true);
+        // The zip64 uncompressed size is the first value of the extra field, after the name
+        let directory = bytes
+            .windows(4)
+            .position(|window| window == 0x02014b50u32.to_le_bytes())
+            .unwrap();
+        let size = directory + 46 + "scene.gltf".len() + 4;
+        bytes[size..size + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        let archive = open(&dir, "assets.zip", &bytes).unwrap();
+        let e = archive.read(Path::new("scene.gltf")).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,496 @@
+use std::path::Path;
+
+use bevy::{
+    core_pipeline::{bloom::BloomSettings, experimental::taa::TemporalAntiAliasSettings},
+    diagnostic::DiagnosticsStore,
+    pbr::ScreenSpaceAmbientOcclusionSettings,
+    prelude::*,
+    window::{PresentMode, PrimaryWindow},
+};
+
+use crate::{
+    effects,
+    mipmap_generator::MipmapGeneratorPaused,
+    msaa_compat::MsaaConflict,
+    present_mode::{likely_vsync_limited, present_mode_report},
+    single_mut_or_warn, Args, BenchmarkRunning, CameraBookmarks, GrifLight, MainCamera, StepSizing,
+};
+
+/// Frames rendered after switching effects before measuring, so pipeline compilation doesn't end
+/// up in the frame times.
+const WARMUP_FRAMES: u32 = 30;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Effect {
+    Bloom,
+    Ssao,
+    Taa,
+    Shadows,
+}
+
+impl Effect {
+    const ALL: [Effect; 4] = [Effect::Bloom, Effect::Ssao, Effect::Taa, Effect::Shadows];
+
+    fn name(self) -> &'static str {
+        match self {
+            Effect::Bloom => "bloom",
+            Effect::Ssao => "ssao",
+            Effect::Taa => "taa",
+            Effect::Shadows => "shadows",
+        }
+    }
+}
+
+pub fn parse_effects(value: &str) -> Result<Vec<Effect>, String> {
+    if value == "all" {
+        return Ok(Effect::ALL.to_vec());
+    }
+    let mut effects = Vec::new();
+    for name in value.split(',').map(str::trim) {
+        let Some(effect) = Effect::ALL.into_iter().find(|e| e.name() == name) else {
+            return Err(format!(
+                "Unknown effect {name}, expected all or a comma separated list of bloom, ssao, \
+                taa and shadows"
+            ));
+        };
+        if !effects.contains(&effect) {
+            effects.push(effect);
+        }
+    }
+    Ok(effects)
+}
+
+/// Which of the permuted effects are on, indexed like `MatrixRun::effects`.
+type Config = Vec<bool>;
+
+struct MatrixRow {
+    config: Config,
+    frames: usize,
+    avg_ms: f32,
+    p50_ms: f32,
+    p95_ms: f32,
+    p99_ms: f32,
+    max_ms: f32,
+}
+
+impl MatrixRow {
+    fn new(config: Config, mut frame_times_ms: Vec<f32>) -> Self {
+        frame_times_ms.sort_by(f32::total_cmp);
+        let frames = frame_times_ms.len();
+        // Nearest rank
+        let percentile = |p: f32| {
+            let rank = ((p / 100.0 * frames as f32).ceil() as usize).clamp(1, frames.max(1));
+            frame_times_ms.get(rank - 1).copied().unwrap_or(0.0)
+        };
+        Self {
+            avg_ms: frame_times_ms.iter().sum::<f32>() / frames.max(1) as f32,
+            p50_ms: percentile(50.0),
+            p95_ms: percentile(95.0),
+            p99_ms: percentile(99.0),
+            max_ms: frame_times_ms.last().copied().unwrap_or(0.0),
+            config,
+            frames,
+        }
+    }
+}
+
+/// State of a `--bench-matrix` run.
+pub struct MatrixRun {
+    effects: Vec<Effect>,
+    /// The effects as they were before the run, restored at the end.
+    initial: Config,
+    configs: Vec<Config>,
+    current: usize,
+    frame: u32,
+    /// 0 until `StepSizing` is done.
+    count_per_step: u32,
+    frame_times_ms: Vec<f32>,
+    rows: Vec<MatrixRow>,
+}
+
+/// With `--bench-matrix`, B runs the benchmark once per on/off permutation of the chosen effects
+/// and writes the frame time stats of each to `--bench-matrix-out`. Effects not in the matrix
+/// keep their current state.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn bench_matrix(
+    mut commands: Commands,
+    input: Res<ButtonInput<KeyCode>>,
+    mut camera: Query<
+        (
+            Entity,
+            &mut Transform,
+            Has<BloomSettings>,
+            Has<ScreenSpaceAmbientOcclusionSettings>,
+            Has<TemporalAntiAliasSettings>,
+        ),
+        With<MainCamera>,
+    >,
+    mut sun: Query<&mut DirectionalLight, With<GrifLight>>,
+    msaa: Res<Msaa>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    time: Res<Time>,
+    diagnostics: Res<DiagnosticsStore>,
+    args: Res<Args>,
+    bookmarks: Res<CameraBookmarks>,
+    mut running: ResMut<BenchmarkRunning>,
+    mut mipmaps_paused: ResMut<MipmapGeneratorPaused>,
+    mut run: Local<Option<MatrixRun>>,
+    mut sizing: Local<StepSizing>,
+    mut warned: Local<bool>,
+) {
+    let Some(effects) = &args.bench_matrix else {
+        return;
+    };
+    let Some((entity, mut transform, bloom, ssao, taa)) =
+        single_mut_or_warn(&mut camera, &mut warned, "bench_matrix")
+    else {
+        return;
+    };
+    let shadows = sun.iter().any(|light| light.shadows_enabled);
+
+    if run.is_none() && input.just_pressed(KeyCode::KeyB) && !bookmarks.ready {
+        println!("The benchmark views are set once the scene has loaded, try again then");
+        return;
+    }
+    if run.is_none() && input.just_pressed(KeyCode::KeyB) {
+        let effects = effects
+            .iter()
+            .copied()
+            .filter(|effect| {
+                let conflict = match effect {
+                    Effect::Ssao => MsaaConflict::Ssao.allowed_with(*msaa),
+                    Effect::Taa => MsaaConflict::Taa.allowed_with(*msaa),
+                    Effect::Bloom | Effect::Shadows => Ok(()),
+                };
+                if let Err(e) = &conflict {
+                    println!("Leaving {} out of the matrix: {e}", effect.name());
+                }
+                conflict.is_ok()
+            })
+            .collect::<Vec<_>>();
+        // All on first, all off last
+        let configs = (0..1u32 << effects.len())
+            .rev()
+            .map(|mask| (0..effects.len()).map(|i| mask & (1 << i) != 0).collect())
+            .collect::<Vec<Config>>();
+        let initial = effects
+            .iter()
+            .map(|effect| match effect {
+                Effect::Bloom => bloom,
+                Effect::Ssao => ssao,
+                Effect::Taa => taa,
+                Effect::Shadows => shadows,
+            })
+            .collect();
+        running.0 = true;
+        mipmaps_paused.0 = true;
+        *sizing = StepSizing::default();
+        *run = Some(MatrixRun {
+            effects,
+            initial,
+            configs,
+            current: 0,
+            frame: 0,
+            count_per_step: 0,
+            frame_times_ms: Vec::new(),
+            rows: Vec::new(),
+        });
+    }
+    let Some(matrix) = run.as_mut() else {
+        return;
+    };
+    if matrix.count_per_step == 0 {
+        let Some(count_per_step) = sizing.frames_per_step(&diagnostics, &args) else {
+            return;
+        };
+        matrix.count_per_step = count_per_step;
+        println!(
+            "Starting benchmark matrix: {} configurations of {} with {} frames per step",
+            matrix.configs.len(),
+            matrix
+                .effects
+                .iter()
+                .map(|effect| effect.name())
+                .collect::<Vec<_>>()
+                .join(", "),
+            count_per_step
+        );
+    }
+
+    if matrix.frame == 0 {
+        let config = &matrix.configs[matrix.current];
+        apply_config(
+            &mut commands,
+            entity,
+            &mut sun,
+            &matrix.effects,
+            config,
+            *msaa,
+        );
+        println!(
+            "Benchmark matrix {}/{}: {}",
+            matrix.current + 1,
+            matrix.configs.len(),
+            describe(&matrix.effects, config)
+        );
+        *transform = bookmarks.views[0];
+    }
+    let step = matrix.frame.saturating_sub(WARMUP_FRAMES);
+    if matrix.frame > WARMUP_FRAMES {
+        matrix.frame_times_ms.push(time.delta_seconds() * 1000.0);
+    }
+    if step == matrix.count_per_step {
+        *transform = bookmarks.views[1];
+    } else if step == matrix.count_per_step * 2 {
+        *transform = bookmarks.views[2];
+    } else if step == matrix.count_per_step * 3 {
+        let config = matrix.configs[matrix.current].clone();
+        let frame_times_ms = std::mem::take(&mut matrix.frame_times_ms);
+        matrix.rows.push(MatrixRow::new(config, frame_times_ms));
+        matrix.current += 1;
+        matrix.frame = 0;
+        if matrix.current < matrix.configs.len() {
+            return;
+        }
+
+        let present_mode = windows
+            .get_single()
+            .map_or(PresentMode::Immediate, |window| window.present_mode);
+        print_rows(&matrix.effects, &matrix.rows, present_mode);
+        if let Err(e) = write_rows(
+            &args.bench_matrix_out,
+            &matrix.effects,
+            &matrix.rows,
+            present_mode,
+        ) {
+            println!(
+                "Failed to write benchmark matrix to {:?}: {e}",
+                args.bench_matrix_out
+            );
+        } else {
+            println!("Wrote benchmark matrix to {:?}", args.bench_matrix_out);
+        }
+        apply_config(
+            &mut commands,
+            entity,
+            &mut sun,
+            &matrix.effects,
+            &matrix.initial,
+            *msaa,
+        );
+        *transform = bookmarks.views[0];
+        running.0 = false;
+        mipmaps_paused.0 = false;
+        *run = None;
+        return;
+    }
+    matrix.frame += 1;
+}
+
+fn apply_config(
+    commands: &mut Commands,
+    camera: Entity,
+    sun: &mut Query<&mut DirectionalLight, With<GrifLight>>,
+    effects: &[Effect],
+    config: &Config,
+    msaa: Msaa,
+) {
+    let mut camera = commands.entity(camera);
+    let mut needs_prepass = false;
+    for (effect, on) in effects.iter().zip(config) {
+        // SSAO and TAA were left out of the matrix if they conflict with MSAA
+        match effect {
+            Effect::Bloom => effects::set_bloom(&mut camera, *on),
+            Effect::Ssao => effects::set_ssao(&mut camera, *on, msaa).unwrap(),
+            Effect::Taa => effects::set_taa(&mut camera, *on, msaa).unwrap(),
+            Effect::Shadows => effects::set_shadows(sun.iter_mut(), *on),
+        }
+        needs_prepass |= *on && matches!(effect, Effect::Ssao | Effect::Taa);
+    }
+    // Only drop the prepasses when both their users are part of the matrix, otherwise one of
+    // them may still be on outside of it
+    let permutes_prepass_users = [Effect::Ssao, Effect::Taa]
+        .iter()
+        .all(|effect| effects.contains(effect));
+    if permutes_prepass_users && !needs_prepass {
+        effects::remove_prepasses(&mut camera);
+    }
+}
+
+fn describe(effects: &[Effect], config: &Config) -> String {
+    effects
+        .iter()
+        .zip(config)
+        .map(|(effect, on)| format!("{} {}", effect.name(), if *on { "on" } else { "off" }))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn print_rows(effects: &[Effect], rows: &[MatrixRow], present_mode: PresentMode) {
+    println!("Benchmark matrix frame times (ms):");
+    for row in rows {
+        println!(
+            "    avg {:6.2} p50 {:6.2} p95 {:6.2} p99 {:6.2} max {:6.2}  {}{}",
+            row.avg_ms,
+            row.p50_ms,
+            row.p95_ms,
+            row.p99_ms,
+            row.max_ms,
+            describe(effects, &row.config),
+            if likely_vsync_limited(present_mode, row.avg_ms).is_some() {
+                " (likely vsync limited)"
+            } else {
+                ""
+            }
+        );
+    }
+    // The note for the fastest configuration, the one most likely to hit the refresh rate
+    let fastest_ms = rows.iter().map(|row| row.avg_ms).fold(f32::MAX, f32::min);
+    println!("{}", present_mode_report(present_mode, fastest_ms));
+}
+
+/// Writes JSON if `path` ends in .json, CSV otherwise. `likely_vsync_limited` is a heuristic,
+/// see `present_mode::likely_vsync_limited`.
+fn write_rows(
+    path: &Path,
+    effects: &[Effect],
+    rows: &[MatrixRow],
+    present_mode: PresentMode,
+) -> std::io::Result<()> {
+    let vsync_limited = |row: &MatrixRow| likely_vsync_limited(present_mode, row.avg_ms).is_some();
+    let contents = if path.extension().is_some_and(|ext| ext == "json") {
+        let rows = rows
+            .iter()
+            .map(|row| {
+                let config = effects
+                    .iter()
+                    .zip(&row.config)
+                    .map(|(effect, on)| (effect.name().to_string(), serde_json::Value::from(*on)))
+                    .collect::<serde_json::Map<_, _>>();
+                serde_json::json!({
+                    "config": config,
+                    "frames": row.frames,
+                    "avg_ms": row.avg_ms,
+                    "p50_ms": row.p50_ms,
+                    "p95_ms": row.p95_ms,
+                    "p99_ms": row.p99_ms,
+                    "max_ms": row.max_ms,
+                    "present_mode": format!("{present_mode:?}"),
+                    "likely_vsync_limited": vsync_limited(row),
+                })
+            })
+            .collect::<Vec<_>>();
+        serde_json::to_string_pretty(&rows).unwrap()
+    } else {
+        let mut csv = effects
+            .iter()
+            .map(|effect| effect.name())
+            .chain([
+                "frames",
+                "avg_ms",
+                "p50_ms",
+                "p95_ms",
+                "p99_ms",
+                "max_ms",
+                "present_mode",
+                "likely_vsync_limited",
+            ])
+            .collect::<Vec<_>>()
+            .join(",");
+        csv.push('\n');
+        for row in rows {
+            let config = row.config.iter().map(|on| if *on { "on" } else { "off" });
+            let stats = [
+                row.frames.to_string(),
+                format!("{:.3}", row.avg_ms),
+                format!("{:.3}", row.p50_ms),
+                format!("{:.3}", row.p95_ms),
+                format!("{:.3}", row.p99_ms),
+                format!("{:.3}", row.max_ms),
+                format!("{present_mode:?}"),
+                vsync_limited(row).to_string(),
+            ];
+            let line = config
+                .map(str::to_string)
+                .chain(stats)
+                .collect::<Vec<_>>()
+                .join(",");
+            csv.push_str(&line);
+            csv.push('\n');
+        }
+        csv
+    };
+    std::fs::write(path, contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TempDir;
+
+    fn row() -> MatrixRow {
+        // 1 to 100 ms, out of order
+        let frame_times = (1..=100).rev().map(|ms| ms as f32).collect();
+        MatrixRow::new(vec![true, false], frame_times)
+    }
+
+    #[test]
+    fn percentiles_use_the_nearest_rank() {
+        let row = row();
+        assert_eq!(row.frames, 100);
+        assert_eq!(row.avg_ms, 50.5);
+        assert_eq!(row.p50_ms, 50.0);
+        assert_eq!(row.p95_ms, 95.0);
+        assert_eq!(row.p99_ms, 99.0);
+        assert_eq!(row.max_ms, 100.0);
+
+        let row = MatrixRow::new(Vec::new(), vec![4.0, 1.0, 3.0, 2.0]);
+        assert_eq!((row.p50_ms, row.p95_ms, row.p99_ms), (2.0, 4.0, 4.0));
+        let row = MatrixRow::new(Vec::new(), Vec::new());
+        assert_eq!(
+            (row.frames, row.avg_ms, row.p50_ms, row.max_ms),
+            (0, 0.0, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn csv_has_a_column_per_effect_and_stat() {
+        let dir = TempDir::new("bench_matrix_csv");
+        let path = dir.join("matrix.csv");
+        let effects = [Effect::Bloom, Effect::Taa];
+        write_rows(&path, &effects, &[row()], PresentMode::AutoNoVsync).unwrap();
+        let csv = std::fs::read_to_string(&path).unwrap();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                "bloom,taa,frames,avg_ms,p50_ms,p95_ms,p99_ms,max_ms,present_mode,likely_vsync_limited",
+                "on,off,100,50.500,50.000,95.000,99.000,100.000,AutoNoVsync,false",
+            ]
+        );
+    }
+
+    #[test]
+    fn json_rows_name_the_effects() {
+        let dir = TempDir::new("bench_matrix_json");
+        let path = dir.join("matrix.json");
+        write_rows(
+            &path,
+            &[Effect::Ssao, Effect::Shadows],
+            &[row()],
+            PresentMode::Fifo,
+        )
+        .unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let row = &json[0];
+        assert_eq!(
+            row["config"],
+            serde_json::json!({ "ssao": true, "shadows": false })
+        );
+        assert_eq!(row["frames"], 100);
+        assert_eq!(row["p95_ms"], 95.0);
+        assert_eq!(row["present_mode"], "Fifo");
+        assert_eq!(row["likely_vsync_limited"], false);
+    }
+}
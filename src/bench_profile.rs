@@ -0,0 +1,133 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    log::{
+        tracing_subscriber::{layer::Context, registry::LookupSpan, Layer},
+        BoxedLayer,
+    },
+    utils::{
+        tracing::{
+            field::{Field, Visit},
+            span, Subscriber,
+        },
+        HashMap,
+    },
+};
+
+/// Whether system runs are being timed, only while a `--bench-profile` benchmark runs.
+static RECORDING: AtomicBool = AtomicBool::new(false);
+static TIMINGS: Mutex<Option<HashMap<String, SystemTiming>>> = Mutex::new(None);
+
+#[derive(Default, Clone, Copy)]
+struct SystemTiming {
+    total: Duration,
+    runs: u32,
+}
+
+struct SystemName(String);
+struct RunStart(Instant);
+
+/// With the `trace` feature, bevy opens a `system` span around every system run, including the
+/// render world's. This layer adds up the time spent in each of them.
+struct SystemTimingLayer;
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for SystemTimingLayer {
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        if attrs.metadata().name() != "system" {
+            return;
+        }
+        let mut visitor = NameVisitor(None);
+        attrs.record(&mut visitor);
+        if let (Some(name), Some(span)) = (visitor.0, ctx.span(id)) {
+            span.extensions_mut().replace(SystemName(name));
+        }
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        if !RECORDING.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Some(span) = ctx.span(id) {
+            let mut extensions = span.extensions_mut();
+            if extensions.get_mut::<SystemName>().is_some() {
+                extensions.replace(RunStart(Instant::now()));
+            }
+        }
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        if !RECORDING.load(Ordering::Relaxed) {
+            return;
+        }
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        let Some(RunStart(start)) = extensions.remove::<RunStart>() else {
+            return;
+        };
+        let Some(SystemName(name)) = extensions.get_mut::<SystemName>() else {
+            return;
+        };
+        let mut timings = TIMINGS.lock().unwrap();
+        let timing = timings
+            .get_or_insert_with(HashMap::default)
+            .entry_ref(name.as_str())
+            .or_default();
+        timing.total += start.elapsed();
+        timing.runs += 1;
+    }
+}
+
+struct NameVisitor(Option<String>);
+
+impl Visit for NameVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "name" {
+            self.0 = Some(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "name" {
+            self.0 = Some(format!("{value:?}").trim_matches('"').to_string());
+        }
+    }
+}
+
+/// For `LogPlugin::custom_layer`.
+pub fn system_timing_layer(_app: &mut bevy::app::App) -> Option<BoxedLayer> {
+    Some(Box::new(SystemTimingLayer))
+}
+
+pub fn start_recording() {
+    *TIMINGS.lock().unwrap() = None;
+    RECORDING.store(true, Ordering::Relaxed);
+}
+
+/// Stops recording and prints the systems that took the most time per frame.
+pub fn print_top_systems(frames: u32, count: usize) {
+    RECORDING.store(false, Ordering::Relaxed);
+    let Some(timings) = TIMINGS.lock().unwrap().take() else {
+        println!("No system timings were recorded, --bench-profile needs the trace feature");
+        return;
+    };
+    let mut timings = timings.into_iter().collect::<Vec<_>>();
+    timings.sort_by_key(|(_, timing)| std::cmp::Reverse(timing.total));
+    let frames = frames.max(1) as f64;
+    println!("Top {count} systems by CPU time over {frames} frames:");
+    println!("    ms/frame  runs/frame  system");
+    for (name, timing) in timings.iter().take(count) {
+        println!(
+            "    {:8.3}  {:10.1}  {name}",
+            timing.total.as_secs_f64() * 1000.0 / frames,
+            timing.runs as f64 / frames,
+        );
+    }
+}
@@ -5,6 +5,11 @@ use bevy::{
     prelude::*,
 };
 
+use crate::{
+    help::{key_name, KeyBindings},
+    single_mut_or_warn, MainCamera,
+};
+
 /// Provides basic movement functionality to the attached camera
 #[derive(Component, Clone)]
 pub struct CameraController {
@@ -22,6 +27,8 @@ pub struct CameraController {
     pub keyboard_key_enable_mouse: KeyCode,
     pub walk_speed: f32,
     pub run_speed: f32,
+    /// Scales the walk and run speed, changed with the scroll wheel outside of orbit mode.
+    pub speed_multiplier: f32,
     pub friction: f32,
     pub pitch: f32,
     pub yaw: f32,
@@ -33,33 +40,75 @@ pub struct CameraController {
 }
 
 impl CameraController {
+    /// The controls with their current keys, printed by `print_controls` and listed in the help.
+    pub fn controls(&self) -> Vec<(String, String)> {
+        let keys = [
+            (self.key_forward, "Forward"),
+            (self.key_back, "Backward"),
+            (self.key_left, "Left"),
+            (self.key_right, "Right"),
+            (self.key_up, "Up"),
+            (self.key_down, "Down"),
+            (self.key_run, "Run"),
+        ];
+        let mut controls = keys
+            .into_iter()
+            .map(|(key, action)| (key_name(key), action.to_string()))
+            .collect::<Vec<_>>();
+        controls.push((
+            format!(
+                "{:?} mouse/{}",
+                self.mouse_key_enable_mouse,
+                key_name(self.keyboard_key_enable_mouse)
+            ),
+            "EnableMouse".to_string(),
+        ));
+        controls.push((
+            "Scroll".to_string(),
+            format!("Speed ({} walk, {} run)", self.walk_speed, self.run_speed),
+        ));
+        controls
+    }
+
     pub fn print_controls(self) -> Self {
         println!(
             "
 ===============================
 ======= Camera Controls =======
-===============================
-    {:?} - Forward
-    {:?} - Backward
-    {:?} - Left
-    {:?} - Right
-    {:?} - Up
-    {:?} - Down
-    {:?} - Run
-    {:?}/{:?} - EnableMouse
-",
-            self.key_forward,
-            self.key_back,
-            self.key_left,
-            self.key_right,
-            self.key_up,
-            self.key_down,
-            self.key_run,
-            self.mouse_key_enable_mouse,
-            self.keyboard_key_enable_mouse,
+==============================="
         );
+        for (key, action) in self.controls() {
+            println!("    {key} - {action}");
+        }
+        println!();
         self
     }
+
+    /// Turns the camera towards `target` without roll and stores the matching pitch and yaw, so
+    /// the next mouse movement continues from the new view. `target` becomes the orbit focus.
+    pub fn look_at(&mut self, transform: &mut Transform, target: Vec3) {
+        let Some(dir) = (target - transform.translation).try_normalize() else {
+            return;
+        };
+        // The controller builds the rotation as Quat::from_euler(EulerRot::ZYX, 0.0, yaw, pitch),
+        // which turns -Z into (-cos(pitch) sin(yaw), sin(pitch), -cos(pitch) cos(yaw))
+        let pitch = dir.y.clamp(-1.0, 1.0).asin().clamp(
+            -0.99 * std::f32::consts::FRAC_PI_2,
+            0.99 * std::f32::consts::FRAC_PI_2,
+        );
+        // Looking straight up or down the yaw is undefined, keep the current one
+        let yaw = if dir.x.abs() + dir.z.abs() > 1e-6 {
+            (-dir.x).atan2(-dir.z)
+        } else {
+            self.yaw
+        };
+        transform.rotation = Quat::from_euler(EulerRot::ZYX, 0.0, yaw, pitch);
+        self.pitch = pitch;
+        self.yaw = yaw;
+        self.orbit_focus = target;
+        self.velocity = Vec3::ZERO;
+        self.initialized = true;
+    }
 }
 
 impl Default for CameraController {
@@ -79,6 +128,7 @@ impl Default for CameraController {
             keyboard_key_enable_mouse: KeyCode::KeyM,
             walk_speed: 5.0,
             run_speed: 15.0,
+            speed_multiplier: 1.0,
             friction: 0.5,
             pitch: 0.0,
             yaw: 0.0,
@@ -91,6 +141,15 @@ impl Default for CameraController {
     }
 }
 
+const MIN_SPEED_MULTIPLIER: f32 = 0.05;
+const MAX_SPEED_MULTIPLIER: f32 = 20.0;
+
+/// Set by UI systems to turn off the controller while the user interacts with them, e.g. while
+/// dragging a slider. The controller is also off while the pointer is over an `Interaction` node.
+#[derive(Resource, Default)]
+pub struct CameraControllerBlocked(pub bool);
+
+#[allow(clippy::too_many_arguments)]
 pub fn camera_controller(
     time: Res<Time>,
     mut mouse_events: EventReader<MouseMotion>,
@@ -99,10 +158,15 @@ pub fn camera_controller(
     key_input: Res<ButtonInput<KeyCode>>,
     mut move_toggled: Local<bool>,
     mut query: Query<(&mut Transform, &mut CameraController), With<Camera>>,
+    mut warned: Local<bool>,
+    blocked: Res<CameraControllerBlocked>,
+    interactions: Query<&Interaction>,
 ) {
     let dt = time.delta_seconds();
 
-    if let Ok((mut transform, mut options)) = query.get_single_mut() {
+    if let Some((mut transform, mut options)) =
+        single_mut_or_warn(&mut query, &mut warned, "camera_controller")
+    {
         if !options.initialized {
             let (_roll, yaw, pitch) = transform.rotation.to_euler(EulerRot::ZYX);
             options.yaw = yaw;
@@ -112,6 +176,13 @@ pub fn camera_controller(
         if !options.enabled {
             return;
         }
+        if blocked.0 || interactions.iter().any(|i| *i != Interaction::None) {
+            // Drop the input so it isn't applied all at once when the UI lets go
+            mouse_events.clear();
+            scroll_evr.clear();
+            options.velocity = Vec3::ZERO;
+            return;
+        }
 
         let mut scroll_distance = 0.0;
 
@@ -125,6 +196,17 @@ pub fn camera_controller(
             }
         }
 
+        if !options.orbit_mode && scroll_distance != 0.0 {
+            options.speed_multiplier = (options.speed_multiplier * 1.2f32.powf(scroll_distance))
+                .clamp(MIN_SPEED_MULTIPLIER, MAX_SPEED_MULTIPLIER);
+            println!(
+                "Camera speed: {:.2}x ({:.1} m/s walk, {:.1} m/s run)",
+                options.speed_multiplier,
+                options.walk_speed * options.speed_multiplier,
+                options.run_speed * options.speed_multiplier
+            );
+        }
+
         // Handle key input
         let mut axis_input = Vec3::ZERO;
         if key_input.pressed(options.key_forward) {
@@ -156,7 +238,7 @@ pub fn camera_controller(
             } else {
                 options.walk_speed
             };
-            options.velocity = axis_input.normalize() * max_speed;
+            options.velocity = axis_input.normalize() * max_speed * options.speed_multiplier;
         } else {
             let friction = options.friction.clamp(0.0, 1.0);
             options.velocity *= 1.0 - friction;
@@ -229,8 +311,21 @@ pub fn camera_controller(
 #[derive(Default)]
 pub struct CameraControllerPlugin;
 
+/// The main camera's controls for the help, from its controller.
+pub fn register_controller_keys(
+    controllers: Query<&CameraController, With<MainCamera>>,
+    mut bindings: ResMut<KeyBindings>,
+) {
+    for controller in &controllers {
+        for (key, action) in controller.controls() {
+            bindings.add("Camera", key, action);
+        }
+    }
+}
+
 impl Plugin for CameraControllerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, camera_controller);
+        app.init_resource::<CameraControllerBlocked>()
+            .add_systems(Update, camera_controller);
     }
 }
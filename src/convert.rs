@@ -1,61 +1,2774 @@
+use bevy::render::{
+    render_asset::RenderAssetUsages,
+    render_resource::{Extent3d, TextureDimension, TextureFormat},
+    texture::{
+        ktx2_get_texture_format, CompressedImageFormats, Image, ImageSampler, ImageType,
+        TextureError,
+    },
+};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::{de::DeserializeOwned, Deserialize};
 use threadpool::ThreadPool;
 
-use std::{fs, io::Write, process::Command, thread::available_parallelism};
-
-pub fn change_gltf_to_use_ktx2() {
-    for path in [
-        "./assets/bistro_exterior/BistroExterior.gltf",
-        "./assets/bistro_interior_wine/BistroInterior_Wine.gltf",
-    ] {
-        let contents = fs::read_to_string(path).unwrap();
-        let new = contents
-            .replace("\"mimeType\":\"image/png\",", "")
-            .replace(".png", ".ktx2");
-        let mut file = fs::OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(path)
-            .unwrap();
-        let _ = file.write(new.as_bytes()).unwrap();
-    }
-}
-
-pub fn convert_images_to_ktx2() {
-    for path in ["./assets/bistro_exterior", "./assets/bistro_interior_wine"] {
-        let pool = ThreadPool::new(available_parallelism().unwrap().get());
-        for path in fs::read_dir(path).unwrap() {
-            pool.execute(move || {
-                if let Ok(path) = path {
-                    let path = path.path();
-                    if path.is_file() && path.extension().unwrap() == "png" {
-                        let path_string = path.to_string_lossy().to_string();
-                        let new_path_string =
-                            path.with_extension("ktx2").to_string_lossy().to_string();
-                        let name = path.file_stem().unwrap().to_string_lossy().to_lowercase();
-                        let nor = name.contains("Normal");
-
-                        let mut cmd = Command::new("kram");
-                        cmd.arg("encode").arg("-f");
-                        // should be able to use bc5 for nor and rough+metal, but they looked bad
-                        cmd.arg("bc7");
-                        if nor {
-                            cmd.arg("-normal");
+use crate::{
+    alpha_coverage,
+    glb::{read_gltf_json, write_gltf_json},
+    ktx2_strict,
+};
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread::available_parallelism,
+    time::{Duration, Instant},
+};
+
+/// Converted when no `--convert-path` is given, relative to the assets directory.
+pub const BISTRO_DIRS: [&str; 2] = ["bistro_exterior", "bistro_interior_wine"];
+
+/// KTX2 supercompression applied by the encoder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Supercompression {
+    None,
+    /// zstd level, 0 lets the encoder pick its default level.
+    Zstd(u32),
+}
+
+impl Default for Supercompression {
+    fn default() -> Self {
+        Supercompression::Zstd(0)
+    }
+}
+
+/// Written as a level or `"off"` in `convert_overrides.ron`, like `--convert-zstd`.
+impl<'de> Deserialize<'de> for Supercompression {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Level {
+            Level(u32),
+            Name(String),
+        }
+        match Level::deserialize(deserializer)? {
+            Level::Level(level) => Ok(Supercompression::Zstd(level)),
+            Level::Name(name) => name.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+impl FromStr for Supercompression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("off") {
+            return Ok(Supercompression::None);
+        }
+        s.parse()
+            .map(Supercompression::Zstd)
+            .map_err(|_| format!("Expected a zstd level or \"off\", got {s}"))
+    }
+}
+
+/// Which encoder and family of formats the converter produces.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConvertFormat {
+    /// BC formats through kram, bc7 unless an override picks another one.
+    #[default]
+    Bc,
+    /// ETC1S/BasisLZ through toktx, a lot smaller but needs the `basis-universal` feature to
+    /// transcode on load.
+    Etc1s,
+}
+
+impl FromStr for ConvertFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bc" | "bc7" => Ok(ConvertFormat::Bc),
+            "etc1s" => Ok(ConvertFormat::Etc1s),
+            _ => Err(format!("Expected bc7 or etc1s, got {s}")),
+        }
+    }
+}
+
+/// The file type written by the converter, independent of the texture format inside it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Container {
+    #[default]
+    Ktx2,
+    /// For other engines, bevy only loads these with the `dds` feature.
+    Dds,
+}
+
+impl Container {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Container::Ktx2 => "ktx2",
+            Container::Dds => "dds",
+        }
+    }
+}
+
+impl FromStr for Container {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ktx2" => Ok(Container::Ktx2),
+            "dds" => Ok(Container::Dds),
+            _ => Err(format!("Expected ktx2 or dds, got {s}")),
+        }
+    }
+}
+
+/// Encoder options shared by all the images being converted.
+/// The defaults reproduce the original `kram` invocation.
+#[derive(Clone, Debug)]
+pub struct ConvertSettings {
+    pub format: ConvertFormat,
+    pub container: Container,
+    /// ETC1S quality level from 1 to 255.
+    pub etc1s_quality: u32,
+    /// Textures larger than this are downscaled by powers of two until they fit.
+    pub max_size: Option<u32>,
+    pub supercompression: Supercompression,
+    /// Generate the full mip chain, otherwise only the base level is written.
+    pub mips: bool,
+    /// Smallest mip level size, uses the encoder default if `None`.
+    pub min_mip_size: Option<u32>,
+    /// Scale the alpha of each mip of alpha masked base color textures so the same fraction of
+    /// texels passes the material's alpha cutoff as in the base level.
+    pub alpha_coverage: bool,
+    /// Multiply the color by alpha before encoding.
+    pub premultiply: bool,
+    pub overrides: Vec<ConvertOverride>,
+    /// Total number of encoder threads, one per image encoded at once (toktx gets `--threads 1`),
+    /// defaults to `available_parallelism`.
+    pub threads: Option<usize>,
+    /// Time reading and decoding the sources and the outputs for the report.
+    pub measure_decode: bool,
+    /// Rewrite BC outputs to spec-clean KTX2 and check for it, see `ktx2_strict::make_strict`.
+    pub strict: bool,
+    /// Where the report is written besides being printed, `None` to only print it.
+    pub report: Option<PathBuf>,
+}
+
+impl Default for ConvertSettings {
+    fn default() -> Self {
+        Self {
+            format: ConvertFormat::default(),
+            container: Container::default(),
+            etc1s_quality: 128,
+            max_size: None,
+            supercompression: Supercompression::default(),
+            mips: true,
+            min_mip_size: None,
+            alpha_coverage: true,
+            premultiply: false,
+            overrides: Vec::new(),
+            threads: None,
+            measure_decode: false,
+            strict: false,
+            report: None,
+        }
+    }
+}
+
+impl ConvertSettings {
+    /// Rejects combinations the encoders can't produce.
+    pub fn check(&self) -> Result<(), String> {
+        if self.format == ConvertFormat::Etc1s && self.container != Container::Ktx2 {
+            return Err("etc1s can only be written as ktx2".to_string());
+        }
+        if self.format == ConvertFormat::Etc1s && !cfg!(feature = "basis-universal") {
+            return Err(
+                "etc1s textures only load with the basis-universal feature, build with --features basis-universal"
+                    .to_string(),
+            );
+        }
+        if self.container == Container::Dds && !cfg!(feature = "dds") {
+            return Err(
+                "dds textures only load with the dds feature, build with --features dds"
+                    .to_string(),
+            );
+        }
+        if self.strict && self.container != Container::Ktx2 {
+            return Err("--ktx-strict only applies to ktx2".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// The images to encode and the glTF files to rewrite, resolved from the `--convert-path` inputs.
+#[derive(Default, Debug)]
+pub struct ConvertInputs {
+    pub images: Vec<PathBuf>,
+    pub gltfs: Vec<PathBuf>,
+    /// Already converted textures, used by `--validate-only`.
+    pub ktx2s: Vec<PathBuf>,
+    /// How the glTFs use each image, keyed by `role_key`.
+    pub roles: HashMap<PathBuf, Vec<ImageRole>>,
+    /// Alpha cutoff of the masked materials using each image as their base color, keyed by
+    /// `role_key`.
+    pub alpha_cutoffs: HashMap<PathBuf, f32>,
+    /// The `--convert-filter` globs the images were selected with, empty for all images.
+    pub filter: Vec<String>,
+}
+
+impl ConvertInputs {
+    /// A directory contributes every source image inside it (recursively) and every .gltf and
+    /// .glb found in it. A single glTF contributes itself and exactly the source images it
+    /// references. Images embedded in a .glb are only picked up once they are extracted.
+    /// With a `filter`, only the source images whose file name matches it are included.
+    pub fn from_paths(paths: &[PathBuf], filter: &[String]) -> Self {
+        let mut inputs = ConvertInputs {
+            filter: filter.to_vec(),
+            ..Default::default()
+        };
+        let mut unfiltered = HashSet::new();
+        for path in paths {
+            if path.is_dir() {
+                walk_dir(path, &mut |file| {
+                    if is_source_image(file) {
+                        unfiltered.insert(file.to_path_buf());
+                        if matches_filter(filter, file) {
+                            inputs.images.push(file.to_path_buf());
                         }
-                        cmd.arg("-type")
-                            .arg("2d")
-                            .arg("-srgb")
-                            .arg("-zstd")
-                            .arg("0")
-                            .arg("-i")
-                            .arg(path_string)
-                            .arg("-o")
-                            .arg(new_path_string);
-                        dbg!(&cmd);
-                        cmd.output().expect("ls command failed to start");
+                    } else if is_gltf(file) {
+                        inputs.gltfs.push(file.to_path_buf());
+                    } else if has_extension(file, "ktx2") {
+                        inputs.ktx2s.push(file.to_path_buf());
+                    }
+                });
+            } else if is_gltf(path) {
+                let images = match gltf_image_paths(path) {
+                    Ok(images) => images,
+                    Err(e) => {
+                        println!("Skipping {e}");
+                        continue;
+                    }
+                };
+                for image in images {
+                    if is_source_image(&image) {
+                        unfiltered.insert(image.clone());
+                        if !matches_filter(filter, &image) {
+                            continue;
+                        }
+                        inputs.ktx2s.push(image.with_extension("ktx2"));
+                        inputs.images.push(image);
+                    } else if has_extension(&image, "ktx2") {
+                        inputs.ktx2s.push(image);
                     }
                 }
+                inputs.gltfs.push(path.clone());
+            } else {
+                println!(
+                    "Skipping {:?}, expected a directory or a .gltf or .glb file",
+                    path
+                );
+            }
+        }
+        inputs.images.sort();
+        inputs.images.dedup();
+        if !filter.is_empty() {
+            println!(
+                "{} of {} source images match --convert-filter {}",
+                inputs.images.len(),
+                unfiltered.len(),
+                filter.join(" ")
+            );
+        }
+        inputs.gltfs.sort();
+        inputs.gltfs.dedup();
+        inputs.ktx2s.retain(|path| path.exists());
+        inputs.ktx2s.sort();
+        inputs.ktx2s.dedup();
+        // A glTF that can't be read is left out, rewriting it after the encode would fail
+        let mut gltfs = Vec::new();
+        for gltf in std::mem::take(&mut inputs.gltfs) {
+            let (roles, masked) = match gltf_image_roles(&gltf)
+                .and_then(|roles| Ok((roles, masked_base_colors(&gltf)?)))
+            {
+                Ok(read) => read,
+                Err(e) => {
+                    println!("Skipping {e}");
+                    continue;
+                }
+            };
+            for (image, role) in roles {
+                let roles = inputs.roles.entry(role_key(&image)).or_default();
+                if !roles.contains(&role) {
+                    roles.push(role);
+                    roles.sort();
+                }
+            }
+            for (image, _, cutoff) in masked {
+                // The lowest cutoff keeps the most texels of any material sharing the image
+                let entry = inputs
+                    .alpha_cutoffs
+                    .entry(role_key(&image))
+                    .or_insert(cutoff);
+                *entry = entry.min(cutoff);
+            }
+            gltfs.push(gltf);
+        }
+        inputs.gltfs = gltfs;
+        inputs
+    }
+
+    /// Every role the image is used as, empty if no glTF references it.
+    pub fn roles_of(&self, path: &Path) -> &[ImageRole] {
+        self.roles
+            .get(&role_key(path))
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    /// The alpha cutoff if the image is the base color of an alpha masked material.
+    pub fn alpha_cutoff(&self, path: &Path) -> Option<f32> {
+        self.alpha_cutoffs.get(&role_key(path)).copied()
+    }
+}
+
+/// How a material uses an image. Ordered by precedence, the first role of an image used in
+/// several ways decides its settings.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageRole {
+    Normal,
+    /// Occlusion, roughness and metallic.
+    Orm,
+    Emissive,
+    BaseColor,
+    /// Not referenced, or only by extensions.
+    Other,
+}
+
+impl ImageRole {
+    pub const ALL: [ImageRole; 5] = [
+        ImageRole::BaseColor,
+        ImageRole::Emissive,
+        ImageRole::Normal,
+        ImageRole::Orm,
+        ImageRole::Other,
+    ];
+
+    /// Color data is stored with the sRGB transfer function, everything else is linear.
+    pub fn is_srgb(self) -> bool {
+        matches!(
+            self,
+            ImageRole::BaseColor | ImageRole::Emissive | ImageRole::Other
+        )
+    }
+}
+
+impl fmt::Display for ImageRole {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ImageRole::Normal => "normal",
+            ImageRole::Orm => "orm",
+            ImageRole::Emissive => "emissive",
+            ImageRole::BaseColor => "base_color",
+            ImageRole::Other => "other",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Identifies an image independent of its extension and of how the path was written, so a
+/// source, its .ktx2 and the glTF URI of either all map to the same key.
+fn role_key(path: &Path) -> PathBuf {
+    let parent = path.parent().unwrap_or(Path::new("."));
+    let parent = fs::canonicalize(parent).unwrap_or_else(|_| parent.to_path_buf());
+    parent.join(path.file_stem().unwrap_or_default())
+}
+
+/// The images each material texture slot of the glTF points at.
+fn gltf_image_roles(gltf_path: &Path) -> Result<Vec<(PathBuf, ImageRole)>, String> {
+    let json = read_gltf_json(gltf_path)?;
+    let base = gltf_path.parent().unwrap_or(Path::new("."));
+    let image_path = |texture_info: &serde_json::Value| {
+        let texture = texture_info["index"].as_u64()?;
+        let source = json["textures"][texture as usize]["source"].as_u64()?;
+        let uri = json["images"][source as usize]["uri"].as_str()?;
+        (!uri.starts_with("data:")).then(|| uri_to_path(base, uri))
+    };
+    let mut roles = Vec::new();
+    for material in json["materials"].as_array().into_iter().flatten() {
+        let pbr = &material["pbrMetallicRoughness"];
+        for (texture_info, role) in [
+            (&pbr["baseColorTexture"], ImageRole::BaseColor),
+            (&pbr["metallicRoughnessTexture"], ImageRole::Orm),
+            (&material["occlusionTexture"], ImageRole::Orm),
+            (&material["normalTexture"], ImageRole::Normal),
+            (&material["emissiveTexture"], ImageRole::Emissive),
+        ] {
+            if let Some(path) = image_path(texture_info) {
+                roles.push((path, role));
+            }
+        }
+    }
+    Ok(roles)
+}
+
+/// .gltf and .glb files, the JSON of either is read by `read_gltf_json`.
+pub fn is_gltf(path: &Path) -> bool {
+    has_extension(path, "gltf") || has_extension(path, "glb")
+}
+
+fn has_extension(path: &Path, ext: &str) -> bool {
+    path.extension()
+        .map(|e| e.eq_ignore_ascii_case(ext))
+        .unwrap_or(false)
+}
+
+/// Image formats the converter picks up, matched case-insensitively.
+pub const SOURCE_EXTENSIONS: [&str; 4] = ["png", "jpg", "jpeg", "tga"];
+
+pub fn is_source_image(path: &Path) -> bool {
+    SOURCE_EXTENSIONS.iter().any(|ext| has_extension(path, ext))
+}
+
+/// Formats that can't store alpha, these are read as fully opaque.
+fn has_no_alpha_channel(path: &Path) -> bool {
+    has_extension(path, "jpg") || has_extension(path, "jpeg")
+}
+
+/// The source image a .ktx2 was converted from, if it's still next to it.
+fn source_for_ktx2(path: &Path) -> Option<PathBuf> {
+    SOURCE_EXTENSIONS
+        .iter()
+        .flat_map(|ext| [ext.to_string(), ext.to_uppercase()])
+        .map(|ext| path.with_extension(ext))
+        .find(|source| source.exists())
+}
+
+/// Whether the file name matches any of the `--convert-filter` globs. A glob without wildcards
+/// matches file names containing it. An empty filter matches everything.
+pub fn matches_filter(filter: &[String], path: &Path) -> bool {
+    if filter.is_empty() {
+        return true;
+    }
+    let Some(name) = path.file_name() else {
+        return false;
+    };
+    let name = name.to_string_lossy();
+    filter.iter().any(|glob| {
+        if glob.contains(['*', '?']) {
+            glob_match(glob, &name)
+        } else {
+            name.contains(glob.as_str())
+        }
+    })
+}
+
+/// Calls `f` for every file under `dir`. Symlinked directories aren't followed, so a link loop
+/// can't recurse forever, and directories that can't be read are skipped.
+fn walk_dir(dir: &Path, f: &mut impl FnMut(&Path)) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("Skipping {:?}: {e}", dir);
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            // Sources archived by --convert-prune-archive
+            if path.file_name().is_some_and(|name| name == ARCHIVE_DIR) {
+                continue;
+            }
+            walk_dir(&path, f);
+        } else if path.is_file() {
+            f(&path);
+        }
+    }
+}
+
+/// glTF URIs are percent-encoded (a space is `%20`) and relative to the glTF's directory.
+pub fn uri_to_path(base: &Path, uri: &str) -> PathBuf {
+    base.join(&*percent_decode_str(uri).decode_utf8_lossy())
+}
+
+/// Characters left as-is when encoding a relative path back into a URI.
+pub const URI_PATH: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'/')
+    .remove(b'.')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Image paths referenced by the glTF's `images` array, resolved relative to the glTF's directory.
+/// Embedded (data:) and bufferView images are skipped.
+pub fn gltf_image_paths(gltf_path: &Path) -> Result<Vec<PathBuf>, String> {
+    let json = read_gltf_json(gltf_path)?;
+    let base = gltf_path.parent().unwrap_or(Path::new("."));
+    Ok(json["images"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|image| image["uri"].as_str())
+        .filter(|uri| !uri.starts_with("data:"))
+        .map(|uri| uri_to_path(base, uri))
+        .collect())
+}
+
+/// The same path however it was written, relative to the glTF or to the convert path.
+fn source_key(path: &Path) -> PathBuf {
+    let parent = path.parent().unwrap_or(Path::new("."));
+    let parent = fs::canonicalize(parent).unwrap_or_else(|_| parent.to_path_buf());
+    parent.join(path.file_name().unwrap_or_default())
+}
+
+/// The image URIs a glTF rewrite changes, as (old, new) pairs.
+type UriRewrites = Vec<(String, String)>;
+
+/// Points the source image URIs in the glTF's `images` array at the converted file next to them
+/// and drops the now wrong `mimeType`. Only the `converted` sources are rewritten, the URIs of
+/// filtered out, skipped and failed images keep pointing at their source. Buffers and everything
+/// else are left alone. Returns the changed URIs as (old, new) pairs.
+fn rewrite_image_uris(
+    json: &mut serde_json::Value,
+    base: &Path,
+    container: Container,
+    converted: &[PathBuf],
+) -> UriRewrites {
+    let extension = container.extension();
+    let converted = converted
+        .iter()
+        .map(|path| source_key(path))
+        .collect::<HashSet<_>>();
+    let mut rewrites = Vec::new();
+    let Some(images) = json["images"].as_array_mut() else {
+        return rewrites;
+    };
+    for image in images.iter_mut().filter_map(|image| image.as_object_mut()) {
+        let Some(uri) = image.get("uri").and_then(|uri| uri.as_str()) else {
+            continue;
+        };
+        if uri.starts_with("data:") {
+            continue;
+        }
+        let decoded = percent_decode_str(uri).decode_utf8_lossy();
+        if !is_source_image(Path::new(&*decoded))
+            || !converted.contains(&source_key(&base.join(&*decoded)))
+        {
+            continue;
+        }
+        let new = if is_source_image(Path::new(uri)) {
+            // Only the extension changes, keep the rest of the URI exactly as it was written
+            let (stem, _) = uri.rsplit_once('.').unwrap();
+            format!("{stem}.{extension}")
+        } else {
+            let (stem, _) = decoded.rsplit_once('.').unwrap();
+            utf8_percent_encode(&format!("{stem}.{extension}"), URI_PATH).to_string()
+        };
+        rewrites.push((uri.to_string(), new.clone()));
+        image.insert("uri".to_string(), new.into());
+        image.remove("mimeType");
+    }
+    rewrites
+}
+
+/// Points the glTFs at the `converted` sources' output. Stops at the first glTF that can't be
+/// rewritten, so the sources it references aren't pruned.
+pub fn change_gltf_to_use_ktx2(
+    gltfs: &[PathBuf],
+    container: Container,
+    converted: &[PathBuf],
+) -> Result<(), String> {
+    for path in gltfs {
+        let mut json = read_gltf_json(path)?;
+        let base = path.parent().unwrap_or(Path::new("."));
+        if rewrite_image_uris(&mut json, base, container, converted).is_empty() {
+            continue;
+        }
+        write_gltf_json(path, &json)?;
+    }
+    Ok(())
+}
+
+/// Base color images of alpha masked materials, as (image, material name, alpha cutoff).
+fn masked_base_colors(gltf_path: &Path) -> Result<Vec<(PathBuf, String, f32)>, String> {
+    let json = read_gltf_json(gltf_path)?;
+    let base = gltf_path.parent().unwrap_or(Path::new("."));
+    let mut found = Vec::new();
+    for material in json["materials"].as_array().into_iter().flatten() {
+        if material["alphaMode"].as_str() != Some("MASK") {
+            continue;
+        }
+        let Some(uri) = material["pbrMetallicRoughness"]["baseColorTexture"]["index"]
+            .as_u64()
+            .and_then(|texture| json["textures"][texture as usize]["source"].as_u64())
+            .and_then(|source| json["images"][source as usize]["uri"].as_str())
+        else {
+            continue;
+        };
+        let name = material["name"].as_str().unwrap_or("unnamed");
+        // 0.5 is the glTF default
+        let cutoff = material["alphaCutoff"].as_f64().unwrap_or(0.5) as f32;
+        found.push((uri_to_path(base, uri), name.to_string(), cutoff));
+    }
+    Ok(found)
+}
+
+/// jpg images used as the base color of alpha masked materials, as (image, material name).
+/// Without an alpha channel the mask reads as fully opaque, before and after conversion.
+pub fn opaque_masked_images(gltfs: &[PathBuf]) -> Result<Vec<(PathBuf, String)>, String> {
+    let mut found = Vec::new();
+    for path in gltfs {
+        for (image, name, _) in masked_base_colors(path)? {
+            if has_no_alpha_channel(&image) {
+                found.push((image, name));
+            }
+        }
+    }
+    found.sort();
+    found.dedup();
+    Ok(found)
+}
+
+pub fn report_opaque_masked_images(gltfs: &[PathBuf]) {
+    let found = match opaque_masked_images(gltfs) {
+        Ok(found) => found,
+        Err(e) => {
+            println!("Failed to read glTF: {e}");
+            return;
+        }
+    };
+    for (image, material) in found {
+        println!(
+            "Warning: {:?} has no alpha channel but is the base color of alpha masked material {:?}, it will be fully opaque",
+            image, material
+        );
+    }
+}
+
+/// Per-texture settings from `convert_overrides.ron`. Every entry whose glob matches the file
+/// name (and role, if set) is applied in order, so later entries win. Unset fields keep the
+/// CLI-level defaults.
+///
+/// ```ron
+/// [
+///     (role: emissive, format: "bc1"),
+///     (glob: "*Decal*", premultiply: true),
+///     (glob: "*Foliage*", max_size: 1024),
+///     (glob: "Chalkboard_*", format: "bc3", srgb: true),
+///     (glob: "*_Unused.png", skip: true),
+///     (glob: "*_UI_*", mips: false, zstd: "off"),
+/// ]
+/// ```
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ConvertOverride {
+    /// Matched against the file name, supports `*` and `?` wildcards. Empty matches every file.
+    pub glob: String,
+    /// Only match images with this role.
+    pub role: Option<ImageRole>,
+    /// kram encode format, e.g. bc7, bc5, bc3.
+    pub format: Option<String>,
+    pub srgb: Option<bool>,
+    pub normal: Option<bool>,
+    pub max_size: Option<u32>,
+    pub premultiply: Option<bool>,
+    /// Force alpha coverage preserving mips on or off, on uses a cutoff of 0.5 for images that
+    /// aren't the base color of a masked material.
+    pub alpha_coverage: Option<bool>,
+    pub mips: Option<bool>,
+    pub min_mip_size: Option<u32>,
+    /// zstd level, or `"off"`.
+    pub zstd: Option<Supercompression>,
+    pub skip: bool,
+}
+
+pub const DEFAULT_OVERRIDES_PATH: &str = "convert_overrides.ron";
+pub const REPORT_FILE_NAME: &str = "convert_report.txt";
+
+/// Loads a list from one of the `.ron` config files, an empty list if the file doesn't exist.
+/// Unset `Option` fields can be written without `Some(..)`.
+pub fn load_ron_list<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>, String> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    ron::Options::default()
+        .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+        .from_str(&contents)
+        .map_err(|e| format!("Failed to parse {:?}: {}", path, e))
+}
+
+/// Case sensitive glob match where `*` matches any run of characters and `?` any single one.
+pub fn glob_match(glob: &str, name: &str) -> bool {
+    let glob = glob.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    let (mut g, mut n) = (0, 0);
+    // Position of the last `*` and the name position it was tried at, for backtracking
+    let mut star = None;
+    while n < name.len() {
+        if g < glob.len() && (glob[g] == '?' || glob[g] == name[n]) {
+            g += 1;
+            n += 1;
+        } else if g < glob.len() && glob[g] == '*' {
+            star = Some((g, n));
+            g += 1;
+        } else if let Some((star_g, star_n)) = star {
+            g = star_g + 1;
+            n = star_n + 1;
+            star = Some((star_g, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    glob[g..].iter().all(|c| *c == '*')
+}
+
+/// The resolved encoder settings for a single image.
+#[derive(Clone, Debug)]
+struct ImageSettings {
+    role: ImageRole,
+    format: String,
+    srgb: bool,
+    normal: bool,
+    max_size: Option<u32>,
+    /// Cutoff to preserve the alpha coverage at, if enabled for this image.
+    alpha_cutoff: Option<f32>,
+    premultiply: bool,
+    mips: bool,
+    min_mip_size: Option<u32>,
+    supercompression: Supercompression,
+    skip: bool,
+}
+
+impl ImageSettings {
+    fn resolve(
+        path: &Path,
+        roles: &[ImageRole],
+        alpha_cutoff: Option<f32>,
+        settings: &ConvertSettings,
+        matched: &mut [bool],
+    ) -> Self {
+        let name = path.file_stem().unwrap().to_string_lossy().to_lowercase();
+        let role = match roles.first() {
+            Some(role) => *role,
+            // Unreferenced images fall back to the file name
+            None if name.contains("normal") => ImageRole::Normal,
+            None => ImageRole::Other,
+        };
+        let mut image_settings = ImageSettings {
+            role,
+            // should be able to use bc5 for nor and rough+metal, but they looked bad
+            format: "bc7".to_string(),
+            srgb: role.is_srgb(),
+            normal: role == ImageRole::Normal,
+            max_size: settings.max_size,
+            alpha_cutoff: alpha_cutoff.filter(|_| settings.alpha_coverage),
+            premultiply: settings.premultiply,
+            mips: settings.mips,
+            min_mip_size: settings.min_mip_size,
+            supercompression: settings.supercompression,
+            skip: false,
+        };
+        let file_name = path.file_name().unwrap().to_string_lossy();
+        for (i, o) in settings.overrides.iter().enumerate() {
+            if !o.glob.is_empty() && !glob_match(&o.glob, &file_name) {
+                continue;
+            }
+            if o.role.is_some_and(|r| r != role) {
+                continue;
+            }
+            matched[i] = true;
+            if let Some(format) = &o.format {
+                image_settings.format = format.clone();
+            }
+            if let Some(srgb) = o.srgb {
+                image_settings.srgb = srgb;
+            }
+            if let Some(normal) = o.normal {
+                image_settings.normal = normal;
+            }
+            if let Some(max_size) = o.max_size {
+                image_settings.max_size = Some(max_size);
+            }
+            if let Some(premultiply) = o.premultiply {
+                image_settings.premultiply = premultiply;
+            }
+            match o.alpha_coverage {
+                Some(true) => {
+                    image_settings.alpha_cutoff = Some(alpha_cutoff.unwrap_or(0.5));
+                }
+                Some(false) => image_settings.alpha_cutoff = None,
+                None => (),
+            }
+            if let Some(mips) = o.mips {
+                image_settings.mips = mips;
+            }
+            if let Some(min_mip_size) = o.min_mip_size {
+                image_settings.min_mip_size = Some(min_mip_size);
+            }
+            if let Some(supercompression) = o.zstd {
+                image_settings.supercompression = supercompression;
+            }
+            image_settings.skip |= o.skip;
+        }
+        image_settings
+    }
+}
+
+/// What a converted file should look like, derived from the source image and settings.
+#[derive(Clone, Debug)]
+pub struct Expected {
+    pub width: u32,
+    pub height: u32,
+    /// `None` if only an upper bound (the full chain) is known.
+    pub level_count: Option<u32>,
+    pub srgb: bool,
+}
+
+fn full_mip_chain_len(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+fn expected_level_count(width: u32, height: u32, settings: &ImageSettings) -> Option<u32> {
+    if !settings.mips {
+        Some(1)
+    } else if settings.min_mip_size.is_none() {
+        Some(full_mip_chain_len(width, height))
+    } else {
+        None
+    }
+}
+
+/// Checks that the file parses as KTX2 and can be loaded by Bevy's ktx2 loader. `strict` also
+/// checks what `--ktx-strict` writes.
+pub fn validate_ktx2(path: &Path, expected: Option<&Expected>, strict: bool) -> Result<(), String> {
+    let bytes = fs::read(path).map_err(|e| format!("could not read: {e}"))?;
+    let reader = ktx2::Reader::new(&bytes[..]).map_err(|e| format!("invalid header: {e:?}"))?;
+    let header = reader.header();
+
+    match header.supercompression_scheme {
+        None
+        | Some(ktx2::SupercompressionScheme::Zstandard)
+        | Some(ktx2::SupercompressionScheme::BasisLZ) => (),
+        Some(scheme) => return Err(format!("unsupported supercompression {scheme:?}")),
+    }
+
+    let srgb = expected.map(|e| e.srgb).unwrap_or(true);
+    match ktx2_get_texture_format(&reader, srgb) {
+        // Basis files are transcoded on load by the basis-universal feature
+        Ok(_) | Err(TextureError::FormatRequiresTranscodingError(_)) => (),
+        Err(e) => return Err(format!("unsupported format: {e}")),
+    }
+
+    let levels = reader.levels().collect::<Vec<_>>();
+    if levels.len() != header.level_count.max(1) as usize {
+        return Err(format!(
+            "header lists {} levels but {} are present",
+            header.level_count,
+            levels.len()
+        ));
+    }
+    if levels.iter().any(|level| level.is_empty()) {
+        return Err("empty mip level".to_string());
+    }
+    let full_chain = full_mip_chain_len(header.pixel_width, header.pixel_height);
+    if header.level_count > full_chain {
+        return Err(format!(
+            "{} levels is more than the full chain of {}",
+            header.level_count, full_chain
+        ));
+    }
+
+    if let Some(expected) = expected {
+        if (header.pixel_width, header.pixel_height) != (expected.width, expected.height) {
+            return Err(format!(
+                "expected {}x{}, got {}x{}",
+                expected.width, expected.height, header.pixel_width, header.pixel_height
+            ));
+        }
+        if let Some(level_count) = expected.level_count {
+            if header.level_count.max(1) != level_count {
+                return Err(format!(
+                    "expected {} levels, got {}",
+                    level_count, header.level_count
+                ));
+            }
+        }
+    }
+    if strict {
+        ktx2_strict::check_strict(&bytes)?;
+    }
+    Ok(())
+}
+
+/// Checks the DDS header: a BC format, and the dimensions and level count.
+pub fn validate_dds(path: &Path, expected: Option<&Expected>) -> Result<(), String> {
+    let bytes = fs::read(path).map_err(|e| format!("could not read: {e}"))?;
+    if bytes.len() < 128 || &bytes[0..4] != b"DDS " {
+        return Err("invalid header".to_string());
+    }
+    let u32_at = |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+    let (height, width, level_count) = (u32_at(12), u32_at(16), u32_at(28).max(1));
+    let four_cc = &bytes[84..88];
+    if four_cc == b"DX10" {
+        if bytes.len() < 148 {
+            return Err("truncated DX10 header".to_string());
+        }
+        // BC1 through BC7, typeless, unorm and srgb variants
+        let dxgi_format = u32_at(128);
+        if !(70..=84).contains(&dxgi_format) && !(94..=99).contains(&dxgi_format) {
+            return Err(format!("unsupported DXGI format {dxgi_format}"));
+        }
+    } else if ![
+        b"DXT1", b"DXT3", b"DXT5", b"ATI1", b"ATI2", b"BC4U", b"BC5U",
+    ]
+    .iter()
+    .any(|cc| &cc[..] == four_cc)
+    {
+        return Err(format!(
+            "unsupported format {}",
+            String::from_utf8_lossy(four_cc)
+        ));
+    }
+    let full_chain = full_mip_chain_len(width, height);
+    if level_count > full_chain {
+        return Err(format!(
+            "{} levels is more than the full chain of {}",
+            level_count, full_chain
+        ));
+    }
+    if let Some(expected) = expected {
+        if (width, height) != (expected.width, expected.height) {
+            return Err(format!(
+                "expected {}x{}, got {}x{}",
+                expected.width, expected.height, width, height
+            ));
+        }
+        if expected
+            .level_count
+            .is_some_and(|count| count != level_count)
+        {
+            return Err(format!(
+                "expected {} levels, got {}",
+                expected.level_count.unwrap(),
+                level_count
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn report_failures(failed: &[(PathBuf, String)]) {
+    if failed.is_empty() {
+        return;
+    }
+    println!("{} textures failed:", failed.len());
+    for (path, e) in failed {
+        println!("    {:?}: {}", path, e);
+    }
+}
+
+/// Checks existing .ktx2 files without re-encoding. If the source image is still next to the
+/// file, its dimensions are checked too. Returns the number of failed files.
+pub fn validate_existing_ktx2(inputs: &ConvertInputs, settings: &ConvertSettings) -> usize {
+    let mut matched = vec![false; settings.overrides.len()];
+    let failed = inputs
+        .ktx2s
+        .iter()
+        .filter_map(|path| {
+            let source = source_for_ktx2(path);
+            let expected = source.and_then(|source| {
+                let (w, h) = image::image_dimensions(&source).ok()?;
+                let roles = inputs.roles_of(&source);
+                let cutoff = inputs.alpha_cutoff(&source);
+                let image_settings =
+                    ImageSettings::resolve(&source, roles, cutoff, settings, &mut matched);
+                let (width, height) = image_settings
+                    .max_size
+                    .and_then(|max_size| downscaled_size(w, h, max_size))
+                    .unwrap_or((w, h));
+                Some(Expected {
+                    width,
+                    height,
+                    level_count: expected_level_count(width, height, &image_settings),
+                    srgb: image_settings.srgb,
+                })
+            });
+            validate_ktx2(path, expected.as_ref(), settings.strict)
+                .err()
+                .map(|e| (path.clone(), e))
+        })
+        .collect::<Vec<_>>();
+    println!("Validated {} ktx2 files", inputs.ktx2s.len());
+    report_failures(&failed);
+    failed.len()
+}
+
+/// Halves the size until the largest side fits in `max_size`, which keeps the aspect ratio and
+/// power-of-two-ness. Returns `None` if the image already fits.
+fn downscaled_size(width: u32, height: u32, max_size: u32) -> Option<(u32, u32)> {
+    let (mut w, mut h) = (width, height);
+    while w.max(h) > max_size.max(1) {
+        w = (w / 2).max(1);
+        h = (h / 2).max(1);
+    }
+    ((w, h) != (width, height)).then_some((w, h))
+}
+
+/// A single image encode, with everything resolved up front so it can be printed or executed.
+#[derive(Clone, Debug)]
+pub struct EncodeJob {
+    pub input: PathBuf,
+    pub role: ImageRole,
+    pub output: PathBuf,
+    pub program: String,
+    pub args: Vec<String>,
+    pub expected: Option<Expected>,
+    /// Source and target size if the image is downscaled.
+    pub downscale: Option<((u32, u32), (u32, u32))>,
+    /// kram only reads png, other formats are decoded to this temporary png first.
+    pub staged_input: Option<PathBuf>,
+    pub premultiply: bool,
+    /// Build the mip chain here instead of in the encoder. `args` then encode a single level
+    /// from `level_path(0, "png")` to `level_path(0, "ktx2")`.
+    pub alpha_coverage: Option<AlphaCoverage>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AlphaCoverage {
+    pub cutoff: f32,
+    pub min_mip_size: u32,
+}
+
+/// Alpha test coverage of one mip level, with plain and with coverage preserving mips.
+#[derive(Clone, Debug)]
+pub struct LevelCoverage {
+    pub width: u32,
+    pub height: u32,
+    pub naive: f32,
+    pub preserved: f32,
+}
+
+impl EncodeJob {
+    /// Writes the temporary png for non-png sources, or when premultiplying. Alpha is filled in
+    /// as opaque for formats without it, so kram sees the same RGBA layout for every input.
+    pub fn stage_input(&self) -> Result<(), String> {
+        let Some(staged) = &self.staged_input else {
+            return Ok(());
+        };
+        let image = image::open(&self.input).map_err(|e| format!("could not decode: {e}"))?;
+        let mut image = image.to_rgba8();
+        if self.premultiply {
+            alpha_coverage::premultiply(&mut image);
+        }
+        image
+            .save(staged)
+            .map_err(|e| format!("could not write {:?}: {e}", staged))
+    }
+
+    /// Temporary file for a single mip level of an alpha coverage job.
+    pub fn level_path(&self, level: usize, extension: &str) -> PathBuf {
+        self.output
+            .with_extension(format!("mip{level}.{extension}"))
+    }
+
+    /// Builds the coverage preserving mip chain, encodes every level on its own and joins them
+    /// into the output file.
+    pub fn encode_coverage_levels(
+        &self,
+        coverage: AlphaCoverage,
+    ) -> Result<Vec<LevelCoverage>, String> {
+        let image = image::open(&self.input).map_err(|e| format!("could not decode: {e}"))?;
+        let mut base = image.to_rgba8();
+        if let Some((_, (width, height))) = self.downscale {
+            base = alpha_coverage::resize(&base, width, height);
+        }
+        let levels =
+            alpha_coverage::coverage_mip_chain(base, coverage.cutoff, coverage.min_mip_size);
+
+        let template_input = self.level_path(0, "png").to_string_lossy().to_string();
+        let template_output = self.level_path(0, "ktx2").to_string_lossy().to_string();
+        let mut stats = Vec::new();
+        let mut encoded = Vec::new();
+        for (i, mut level) in levels.into_iter().enumerate() {
+            stats.push(LevelCoverage {
+                width: level.image.width(),
+                height: level.image.height(),
+                naive: level.naive,
+                preserved: level.preserved,
             });
+            if self.premultiply {
+                alpha_coverage::premultiply(&mut level.image);
+            }
+            let (png, ktx2) = (self.level_path(i, "png"), self.level_path(i, "ktx2"));
+            let args = self.args.iter().map(|arg| {
+                if *arg == template_input {
+                    png.to_string_lossy().to_string()
+                } else if *arg == template_output {
+                    ktx2.to_string_lossy().to_string()
+                } else {
+                    arg.clone()
+                }
+            });
+            let result = level
+                .image
+                .save(&png)
+                .map_err(|e| format!("could not write {:?}: {e}", png))
+                .and_then(|()| run_encoder(Command::new(&self.program).args(args), &self.program))
+                .and_then(|()| fs::read(&ktx2).map_err(|e| format!("could not read: {e}")));
+            let _ = fs::remove_file(&png);
+            let _ = fs::remove_file(&ktx2);
+            encoded.push(result.map_err(|e| format!("mip {i}: {e}"))?);
+        }
+        let joined = join_ktx2_levels(&encoded)?;
+        fs::write(&self.output, joined).map_err(|e| format!("could not write: {e}"))?;
+        Ok(stats)
+    }
+
+    pub fn command(&self) -> Command {
+        let mut cmd = Command::new(&self.program);
+        cmd.args(&self.args);
+        cmd
+    }
+
+    /// The command as it would be typed in a shell.
+    pub fn command_line(&self) -> String {
+        let quote = |arg: &str| {
+            if arg.contains(char::is_whitespace) {
+                format!("'{arg}'")
+            } else {
+                arg.to_string()
+            }
+        };
+        std::iter::once(self.program.as_str())
+            .chain(self.args.iter().map(String::as_str))
+            .map(quote)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+fn run_encoder(cmd: &mut Command, program: &str) -> Result<(), String> {
+    let output = cmd
+        .output()
+        .map_err(|e| format!("could not start {program}: {e}"))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} exited with {}: {}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+/// Joins single level KTX2 files, largest level first, into one file with a mip chain.
+/// The format, data format descriptor and key/value data are taken from the first level.
+pub fn join_ktx2_levels(levels: &[Vec<u8>]) -> Result<Vec<u8>, String> {
+    const HEADER_LEN: usize = 80;
+    const LEVEL_INDEX_ENTRY_LEN: usize = 24;
+    let first = levels.first().ok_or("no levels")?;
+    let mut level_data = Vec::new();
+    let mut uncompressed_lens = Vec::new();
+    for (i, bytes) in levels.iter().enumerate() {
+        let header = ktx2::Reader::new(&bytes[..])
+            .map_err(|e| format!("mip {i}: invalid header: {e:?}"))?
+            .header();
+        if header.level_count > 1 {
+            return Err(format!("mip {i} has {} levels", header.level_count));
+        }
+        let u64_at = |offset: usize| {
+            u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap()) as usize
+        };
+        if u64_at(72) != 0 {
+            return Err(format!("mip {i} has supercompression global data"));
+        }
+        let (offset, len) = (u64_at(HEADER_LEN), u64_at(HEADER_LEN + 8));
+        level_data.push(&bytes[offset..offset + len]);
+        uncompressed_lens.push(u64_at(HEADER_LEN + 16) as u64);
+    }
+
+    let u32_at =
+        |offset: usize| u32::from_le_bytes(first[offset..offset + 4].try_into().unwrap()) as usize;
+    let dfd = &first[u32_at(48)..u32_at(48) + u32_at(52)];
+    let kvd = first
+        .get(u32_at(56)..u32_at(56) + u32_at(60))
+        .ok_or("mip 0: key/value data past the end of the file")?;
+
+    let mut out = first[..HEADER_LEN].to_vec();
+    out[40..44].copy_from_slice(&(levels.len() as u32).to_le_bytes());
+    let index_start = out.len();
+    out.resize(index_start + LEVEL_INDEX_ENTRY_LEN * levels.len(), 0);
+
+    let dfd_offset = out.len() as u32;
+    out.extend_from_slice(dfd);
+    let kvd_offset = if kvd.is_empty() { 0 } else { out.len() as u32 };
+    out.extend_from_slice(kvd);
+    out[48..52].copy_from_slice(&dfd_offset.to_le_bytes());
+    out[56..60].copy_from_slice(&kvd_offset.to_le_bytes());
+    out[64..80].fill(0);
+
+    // The spec stores the smallest level first. Without supercompression the levels are aligned
+    // to the 16 byte BC block size.
+    let supercompressed = u32_at(44) != 0;
+    let alignment = if supercompressed { 1 } else { 16 };
+    for (i, data) in level_data.iter().enumerate().rev() {
+        out.resize(out.len().next_multiple_of(alignment), 0);
+        let entry = index_start + i * LEVEL_INDEX_ENTRY_LEN;
+        let offset = out.len() as u64;
+        out[entry..entry + 8].copy_from_slice(&offset.to_le_bytes());
+        out[entry + 8..entry + 16].copy_from_slice(&(data.len() as u64).to_le_bytes());
+        out[entry + 16..entry + 24].copy_from_slice(&uncompressed_lens[i].to_le_bytes());
+        out.extend_from_slice(data);
+    }
+    Ok(out)
+}
+
+#[derive(Default, Debug)]
+pub struct ConvertPlan {
+    pub jobs: Vec<EncodeJob>,
+    pub skipped: Vec<PathBuf>,
+    /// Images no glTF references, only filled when there are glTFs to check.
+    pub unreferenced: Vec<PathBuf>,
+    /// Images used in roles that need different settings, with every role they are used as.
+    pub conflicting_roles: Vec<(PathBuf, Vec<ImageRole>)>,
+    /// Globs from the overrides file that didn't match any image.
+    pub unmatched_overrides: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// Resolves the per-image settings and builds the encoder commands without running anything.
+pub fn plan_conversion(inputs: &ConvertInputs, settings: &ConvertSettings) -> ConvertPlan {
+    let mut plan = ConvertPlan::default();
+    let mut matched = vec![false; settings.overrides.len()];
+    let mut unsupported_coverage = 0;
+    for path in &inputs.images {
+        let roles = inputs.roles_of(path);
+        if roles.is_empty() && !inputs.gltfs.is_empty() {
+            plan.unreferenced.push(path.clone());
+        }
+        let role_settings = |r: &ImageRole| (r.is_srgb(), *r == ImageRole::Normal);
+        if roles
+            .iter()
+            .any(|r| role_settings(r) != role_settings(&roles[0]))
+        {
+            plan.conflicting_roles.push((path.clone(), roles.to_vec()));
+        }
+        let cutoff = inputs.alpha_cutoff(path);
+        let image_settings = ImageSettings::resolve(path, roles, cutoff, settings, &mut matched);
+        if image_settings.skip {
+            plan.skipped.push(path.clone());
+            continue;
+        }
+        let source_size = image::image_dimensions(path).ok();
+        let resize = image_settings
+            .max_size
+            .zip(source_size)
+            .and_then(|(max_size, (width, height))| downscaled_size(width, height, max_size));
+        let expected = resize.or(source_size).map(|(width, height)| Expected {
+            width,
+            height,
+            level_count: expected_level_count(width, height, &image_settings),
+            srgb: image_settings.srgb,
+        });
+        let output = path.with_extension(settings.container.extension());
+        let premultiply = image_settings.premultiply && !has_no_alpha_channel(path);
+        // Images without alpha always pass the alpha test, there is no coverage to preserve
+        let alpha_coverage = image_settings
+            .alpha_cutoff
+            .filter(|_| !has_no_alpha_channel(path))
+            .map(|cutoff| AlphaCoverage {
+                cutoff,
+                min_mip_size: image_settings.min_mip_size.unwrap_or(1),
+            });
+        let coverage_supported = settings.format == ConvertFormat::Bc
+            && settings.container == Container::Ktx2
+            && image_settings.mips;
+        if alpha_coverage.is_some() && !coverage_supported {
+            unsupported_coverage += 1;
+        }
+        let alpha_coverage = alpha_coverage.filter(|_| coverage_supported);
+        let staged_input = (alpha_coverage.is_none()
+            && (premultiply || !has_extension(path, "png")))
+        .then(|| output.with_extension("staged.png"));
+
+        let input = staged_input.as_ref().unwrap_or(path);
+        let (program, args) = match settings.format {
+            ConvertFormat::Bc if alpha_coverage.is_some() => {
+                let level_settings = ImageSettings {
+                    mips: false,
+                    ..image_settings.clone()
+                };
+                let level = |extension: &str| output.with_extension(format!("mip0.{extension}"));
+                (
+                    "kram",
+                    kram_args(&level("png"), &level("ktx2"), &level_settings, None),
+                )
+            }
+            ConvertFormat::Bc => ("kram", kram_args(input, &output, &image_settings, resize)),
+            ConvertFormat::Etc1s => (
+                "toktx",
+                toktx_args(
+                    input,
+                    &output,
+                    &image_settings,
+                    resize,
+                    resize.or(source_size),
+                    settings,
+                ),
+            ),
+        };
+
+        plan.jobs.push(EncodeJob {
+            input: path.clone(),
+            role: image_settings.role,
+            output,
+            program: program.to_string(),
+            args,
+            expected,
+            downscale: source_size.zip(resize),
+            staged_input,
+            premultiply,
+            alpha_coverage,
+        });
+    }
+    if unsupported_coverage > 0 {
+        plan.warnings.push(format!(
+            "{} alpha masked textures use plain mips, alpha coverage preserving mips need bc7, ktx2 and mips",
+            unsupported_coverage
+        ));
+    }
+    plan.unmatched_overrides = settings
+        .overrides
+        .iter()
+        .zip(matched)
+        .filter(|(_, matched)| !matched)
+        .map(|(o, _)| o.glob.clone())
+        .collect();
+    plan
+}
+
+fn kram_args(
+    input: &Path,
+    output: &Path,
+    image_settings: &ImageSettings,
+    resize: Option<(u32, u32)>,
+) -> Vec<String> {
+    let mut args = vec!["encode".to_string(), "-f".to_string()];
+    args.push(image_settings.format.clone());
+    if image_settings.normal {
+        args.push("-normal".to_string());
+    }
+    // kram resamples before encoding and renormalizes when -normal is set
+    if let Some((width, height)) = resize {
+        args.push("-resize".to_string());
+        args.push(format!("{width}x{height}"));
+    }
+    if !image_settings.mips {
+        args.push("-mipnone".to_string());
+    } else if let Some(min_mip_size) = image_settings.min_mip_size {
+        args.push("-mipmin".to_string());
+        args.push(min_mip_size.to_string());
+    }
+    args.push("-type".to_string());
+    args.push("2d".to_string());
+    if image_settings.srgb {
+        args.push("-srgb".to_string());
+    }
+    if let Supercompression::Zstd(level) = image_settings.supercompression {
+        args.push("-zstd".to_string());
+        args.push(level.to_string());
+    }
+    args.push("-i".to_string());
+    args.push(input.to_string_lossy().to_string());
+    args.push("-o".to_string());
+    args.push(output.to_string_lossy().to_string());
+    args
+}
+
+fn toktx_args(
+    input: &Path,
+    output: &Path,
+    image_settings: &ImageSettings,
+    resize: Option<(u32, u32)>,
+    size: Option<(u32, u32)>,
+    settings: &ConvertSettings,
+) -> Vec<String> {
+    let mut args = vec!["--t2".to_string(), "--encode".to_string()];
+    args.push("etc1s".to_string());
+    args.push("--qlevel".to_string());
+    args.push(settings.etc1s_quality.clamp(1, 255).to_string());
+    if image_settings.mips {
+        args.push("--genmipmap".to_string());
+        // toktx has no minimum mip size, only a level count
+        if let Some(((width, height), min_mip_size)) = size.zip(image_settings.min_mip_size) {
+            let mut levels = 1;
+            let mut side = width.max(height);
+            while side / 2 >= min_mip_size.max(1) {
+                side /= 2;
+                levels += 1;
+            }
+            args.push("--levels".to_string());
+            args.push(levels.to_string());
+        }
+    }
+    if let Some((width, height)) = resize {
+        args.push("--resize".to_string());
+        args.push(format!("{width}x{height}"));
+    }
+    // The pool already runs one encoder per thread, more would oversubscribe the cores
+    args.push("--threads".to_string());
+    args.push("1".to_string());
+    args.push("--assign_oetf".to_string());
+    args.push(
+        if image_settings.srgb {
+            "srgb"
+        } else {
+            "linear"
+        }
+        .to_string(),
+    );
+    args.push(output.to_string_lossy().to_string());
+    args.push(input.to_string_lossy().to_string());
+    args
+}
+
+/// The image URIs of each glTF that the rewrite would change if all the planned `jobs` succeed,
+/// as (old, new) pairs.
+pub fn plan_gltf_rewrites(
+    gltfs: &[PathBuf],
+    container: Container,
+    jobs: &[EncodeJob],
+) -> Result<Vec<(PathBuf, UriRewrites)>, String> {
+    let sources = jobs.iter().map(|job| job.input.clone()).collect::<Vec<_>>();
+    gltfs
+        .iter()
+        .map(|path| {
+            let mut json = read_gltf_json(path)?;
+            let base = path.parent().unwrap_or(Path::new("."));
+            let mut rewrites = rewrite_image_uris(&mut json, base, container, &sources);
+            rewrites.sort();
+            Ok((path.clone(), rewrites))
+        })
+        .collect()
+}
+
+/// Per-role count and size table, followed by the images that may need an override.
+/// The ktx2 size only includes the `converted` images.
+fn report_roles(plan: &ConvertPlan, converted: &[PathBuf], settings: &ConvertSettings) {
+    let file_size = |path: &Path| fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    let mb = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+    println!(
+        "{:<12} {:>6} {:>12} {:>12}",
+        "role", "count", "source MB", "output MB"
+    );
+    for role in ImageRole::ALL {
+        let jobs = plan.jobs.iter().filter(|job| job.role == role);
+        let (mut count, mut before, mut after) = (0, 0, 0);
+        for job in jobs {
+            count += 1;
+            before += file_size(&job.input);
+            if converted.binary_search(&job.input).is_ok() {
+                after += file_size(&job.output);
+            }
+        }
+        if count > 0 {
+            println!(
+                "{:<12} {:>6} {:>12.1} {:>12.1}",
+                role.to_string(),
+                count,
+                mb(before),
+                mb(after)
+            );
+        }
+    }
+    let normal_maps = plan
+        .jobs
+        .iter()
+        .filter(|job| job.role == ImageRole::Normal)
+        .count();
+    if settings.format == ConvertFormat::Etc1s && normal_maps > 0 {
+        println!(
+            "Warning: {} normal maps are encoded as ETC1S and will lose quality, consider a bc5 or bc7 override",
+            normal_maps
+        );
+    }
+    if !plan.unreferenced.is_empty() {
+        println!(
+            "{} images are not referenced by any glTF material:",
+            plan.unreferenced.len()
+        );
+        for path in &plan.unreferenced {
+            println!("    {:?}", path);
+        }
+    }
+    if !plan.conflicting_roles.is_empty() {
+        println!(
+            "{} images are used in roles that need different settings, the first role is used:",
+            plan.conflicting_roles.len()
+        );
+        for (path, roles) in &plan.conflicting_roles {
+            let roles = roles.iter().map(ImageRole::to_string).collect::<Vec<_>>();
+            println!("    {:?}: {}", path, roles.join(", "));
+        }
+    }
+}
+
+/// Prints the full plan, sorted so the output can be diffed between runs.
+pub fn print_dry_run(inputs: &ConvertInputs, settings: &ConvertSettings) {
+    let plan = plan_conversion(inputs, settings);
+    println!("Dry run, nothing will be executed or modified");
+    println!("{} images to encode:", plan.jobs.len());
+    for job in &plan.jobs {
+        println!("  {:?}", job.input);
+        if let Some(staged) = &job.staged_input {
+            println!(
+                "    decode{} to {:?}",
+                if job.premultiply {
+                    " and premultiply"
+                } else {
+                    ""
+                },
+                staged
+            );
+        }
+        if let Some(coverage) = job.alpha_coverage {
+            println!(
+                "    alpha coverage mips at cutoff {}{}, each level encoded with:",
+                coverage.cutoff,
+                if job.premultiply {
+                    ", premultiplied"
+                } else {
+                    ""
+                }
+            );
+        }
+        println!("    {}", job.command_line());
+    }
+    if !plan.skipped.is_empty() {
+        println!("{} images skipped by overrides:", plan.skipped.len());
+        for path in &plan.skipped {
+            println!("  {:?}", path);
+        }
+    }
+    for glob in &plan.unmatched_overrides {
+        println!("Override {:?} did not match any file", glob);
+    }
+    for warning in &plan.warnings {
+        println!("Warning: {warning}");
+    }
+    report_roles(&plan, &[], settings);
+    report_opaque_masked_images(&inputs.gltfs);
+    let rewrites = match plan_gltf_rewrites(&inputs.gltfs, settings.container, &plan.jobs) {
+        Ok(rewrites) => rewrites,
+        Err(e) => {
+            println!("Failed to read glTF: {e}");
+            return;
+        }
+    };
+    for (path, rewrites) in rewrites {
+        println!(
+            "{:?}: {} image URIs would be rewritten",
+            path,
+            rewrites.len()
+        );
+        for (old, new) in rewrites {
+            println!("    {old} -> {new}");
+        }
+    }
+}
+
+/// Returns the source images whose output was written and passed validation.
+pub fn convert_images_to_ktx2(inputs: &ConvertInputs, settings: &ConvertSettings) -> Vec<PathBuf> {
+    let plan = plan_conversion(inputs, settings);
+    for path in &plan.skipped {
+        println!("Skipping {:?}", path);
+    }
+
+    // kram encode has no thread count option and toktx is limited to one thread, so the pool
+    // size is the total number of encoder threads
+    let threads = settings
+        .threads
+        .unwrap_or_else(|| available_parallelism().unwrap().get())
+        .max(1);
+    let pool = ThreadPool::new(threads);
+    println!(
+        "Converting {} images on {} threads",
+        plan.jobs.len(),
+        threads
+    );
+    let total = plan.jobs.len();
+    let done = Arc::new(AtomicUsize::new(0));
+    let failed = Arc::new(Mutex::new(Vec::new()));
+    let converted = Arc::new(Mutex::new(Vec::new()));
+    let coverage = Arc::new(Mutex::new(Vec::new()));
+    let strict = settings.strict;
+    for job in plan.jobs.iter().cloned() {
+        let done = done.clone();
+        let failed = failed.clone();
+        let converted = converted.clone();
+        let coverage = coverage.clone();
+        let progress_pool = pool.clone();
+        pool.execute(move || {
+            let encoded = match job.alpha_coverage {
+                Some(alpha_coverage) => job.encode_coverage_levels(alpha_coverage).map(|levels| {
+                    coverage.lock().unwrap().push((
+                        job.input.clone(),
+                        alpha_coverage.cutoff,
+                        levels,
+                    ))
+                }),
+                None => job
+                    .stage_input()
+                    .and_then(|()| run_encoder(&mut job.command(), &job.program)),
+            };
+            let encoded = encoded.and_then(|()| {
+                if !strict {
+                    return Ok(());
+                }
+                let bytes = fs::read(&job.output).map_err(|e| format!("could not read: {e}"))?;
+                let strict = ktx2_strict::make_strict(&bytes, job.premultiply)?;
+                fs::write(&job.output, strict).map_err(|e| format!("could not write: {e}"))
+            });
+            let result = encoded.and_then(|()| {
+                if has_extension(&job.output, "dds") {
+                    validate_dds(&job.output, job.expected.as_ref())
+                } else {
+                    validate_ktx2(&job.output, job.expected.as_ref(), strict)
+                }
+            });
+            if let Some(staged) = &job.staged_input {
+                let _ = fs::remove_file(staged);
+            }
+            match result {
+                Ok(()) => converted.lock().unwrap().push(job.input.clone()),
+                Err(e) => {
+                    // Don't leave a broken or stale file around to mask the problem
+                    let _ = fs::remove_file(&job.output);
+                    failed.lock().unwrap().push((job.input.clone(), e));
+                }
+            }
+            let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+            println!(
+                "[{done}/{total}] {} in flight, {} queued",
+                // This job is still counted as active
+                progress_pool.active_count() - 1,
+                progress_pool.queued_count()
+            );
+        });
+    }
+    pool.join();
+
+    report_failures(&failed.lock().unwrap());
+
+    for glob in &plan.unmatched_overrides {
+        println!("Override {:?} did not match any file", glob);
+    }
+    for warning in &plan.warnings {
+        println!("Warning: {warning}");
+    }
+
+    let mut converted = converted.lock().unwrap().clone();
+    converted.sort();
+    report_roles(&plan, &converted, settings);
+
+    let mut coverage = coverage.lock().unwrap().clone();
+    coverage.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut report = savings_report(&plan, &converted, settings.measure_decode);
+    report.push_str(&coverage_report(&coverage));
+    print!("{report}");
+    if let Some(path) = &settings.report {
+        match fs::write(path, &report) {
+            Ok(()) => println!("Report written to {:?}", path),
+            Err(e) => println!("Could not write {:?}: {e}", path),
+        }
+    }
+
+    let downscaled = plan
+        .jobs
+        .iter()
+        .filter_map(|job| job.downscale.map(|(from, to)| (&job.input, from, to)))
+        .collect::<Vec<_>>();
+    if !downscaled.is_empty() {
+        // BC7 is 1 byte per texel, the mip chain adds another third
+        let bc7_size = |(w, h): (u32, u32)| w as u64 * h as u64 * 4 / 3;
+        let mut saved = 0;
+        println!("Downscaled {} textures:", downscaled.len());
+        for (path, from, to) in &downscaled {
+            println!("    {:?} {}x{} -> {}x{}", path, from.0, from.1, to.0, to.1);
+            saved += bc7_size(*from) - bc7_size(*to);
+        }
+        println!(
+            "Estimated BC7 size saved: {:.1} MB",
+            saved as f64 / (1024.0 * 1024.0)
+        );
+    }
+    converted
+}
+
+/// Per mip alpha test coverage of the images encoded with coverage preserving mips.
+fn coverage_report(coverage: &[(PathBuf, f32, Vec<LevelCoverage>)]) -> String {
+    use std::fmt::Write;
+
+    let mut report = String::new();
+    if coverage.is_empty() {
+        return report;
+    }
+    writeln!(
+        report,
+        "Alpha coverage of {} masked textures, plain mips -> preserved:",
+        coverage.len()
+    )
+    .unwrap();
+    for (path, cutoff, levels) in coverage {
+        writeln!(report, "  {:?} at cutoff {}", path, cutoff).unwrap();
+        for (i, level) in levels.iter().enumerate() {
+            writeln!(
+                report,
+                "    mip {:>2} {:>5}x{:<5} {:>5.1}% -> {:>5.1}%",
+                i,
+                level.width,
+                level.height,
+                level.naive * 100.0,
+                level.preserved * 100.0
+            )
+            .unwrap();
+        }
+    }
+    report
+}
+
+/// Size before and after of the converted images, and optionally how long they take to decode.
+fn savings_report(plan: &ConvertPlan, converted: &[PathBuf], measure_decode: bool) -> String {
+    use std::fmt::Write;
+
+    let file_size = |path: &Path| fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    let mb = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+    let jobs = plan
+        .jobs
+        .iter()
+        .filter(|job| converted.binary_search(&job.input).is_ok())
+        .map(|job| (job, file_size(&job.input), file_size(&job.output)))
+        .collect::<Vec<_>>();
+    let before = jobs.iter().map(|(_, before, _)| before).sum::<u64>();
+    let after = jobs.iter().map(|(_, _, after)| after).sum::<u64>();
+
+    let mut report = String::new();
+    let _ = writeln!(report, "Converted {} images", jobs.len());
+    let _ = writeln!(report, "    source: {:.1} MB", mb(before));
+    let _ = writeln!(
+        report,
+        "    output: {:.1} MB ({:.0}% of source)",
+        mb(after),
+        after as f64 / before.max(1) as f64 * 100.0
+    );
+
+    let mut wins = jobs
+        .iter()
+        .filter(|(_, before, after)| after < before)
+        .collect::<Vec<_>>();
+    wins.sort_by_key(|(_, before, after)| std::cmp::Reverse(before - after));
+    if !wins.is_empty() {
+        let _ = writeln!(report, "Biggest savings:");
+        for (job, before, after) in wins.iter().take(10) {
+            let _ = writeln!(
+                report,
+                "    {:?} {:.2} MB -> {:.2} MB",
+                job.input,
+                mb(*before),
+                mb(*after)
+            );
+        }
+    }
+    let larger = jobs
+        .iter()
+        .filter(|(_, before, after)| after > before)
+        .collect::<Vec<_>>();
+    if !larger.is_empty() {
+        let _ = writeln!(report, "{} files got larger:", larger.len());
+        for (job, before, after) in larger {
+            let _ = writeln!(
+                report,
+                "    {:?} {} bytes -> {} bytes",
+                job.input, before, after
+            );
+        }
+    }
+
+    if measure_decode {
+        let (mut count, mut source_time, mut output_time) = (0, Duration::ZERO, Duration::ZERO);
+        for (job, _, _) in &jobs {
+            let srgb = job.expected.as_ref().map(|e| e.srgb).unwrap_or(true);
+            let (Some(source), Some(output)) = (
+                time_decode(&job.input, srgb),
+                time_decode(&job.output, srgb),
+            ) else {
+                continue;
+            };
+            count += 1;
+            source_time += source;
+            output_time += output;
+        }
+        let _ = writeln!(
+            report,
+            "Decode time: {:.1}s -> {:.1}s ({} textures, file read and decode only, not the asset server, GPU upload or runtime mipmap generation)",
+            source_time.as_secs_f32(),
+            output_time.as_secs_f32(),
+            count
+        );
+    }
+    report
+}
+
+/// Reads and decodes the file into an `Image`. Sources are decoded to rgba8 with the `image`
+/// crate like bevy's image loader does, so jpeg and tga are timed without those bevy features.
+fn time_decode(path: &Path, srgb: bool) -> Option<Duration> {
+    let start = Instant::now();
+    let bytes = fs::read(path).ok()?;
+    if is_source_image(path) {
+        let format = image::ImageFormat::from_path(path).ok()?;
+        let image = image::load_from_memory_with_format(&bytes, format)
+            .ok()?
+            .into_rgba8();
+        Image::new(
+            Extent3d {
+                width: image.width(),
+                height: image.height(),
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            image.into_raw(),
+            if srgb {
+                TextureFormat::Rgba8UnormSrgb
+            } else {
+                TextureFormat::Rgba8Unorm
+            },
+            RenderAssetUsages::default(),
+        );
+    } else {
+        Image::from_buffer(
+            &bytes,
+            ImageType::Extension(&path.extension()?.to_string_lossy()),
+            // The formats the GPU supports don't matter here, nothing is uploaded
+            CompressedImageFormats::all(),
+            srgb,
+            ImageSampler::Default,
+            RenderAssetUsages::default(),
+        )
+        .ok()?;
+    }
+    Some(start.elapsed())
+}
+
+/// What `--convert-prune` (`Delete`) and `--convert-prune-archive` (`Archive`) do with the source
+/// images of successful conversions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PruneMode {
+    Delete,
+    /// Move into an `_original/` directory next to the source.
+    Archive,
+}
+
+pub const ARCHIVE_DIR: &str = "_original";
+
+/// Deletes or archives the given source images. Only pass sources whose output passed validation
+/// and whose glTFs were rewritten. A source is still kept if its output has gone missing or no
+/// longer validates since, or if one of the `gltfs` still references it.
+pub fn prune_sources(
+    converted: &[PathBuf],
+    gltfs: &[PathBuf],
+    mode: PruneMode,
+    container: Container,
+) {
+    let mut referenced = HashSet::new();
+    for gltf in gltfs {
+        match gltf_image_paths(gltf) {
+            Ok(images) => referenced.extend(images.iter().map(|image| source_key(image))),
+            Err(e) => {
+                println!("Not pruning any source images, failed to read glTF: {e}");
+                return;
+            }
+        }
+    }
+    let mut count = 0;
+    let mut bytes = 0;
+    for path in converted {
+        let output = path.with_extension(container.extension());
+        let valid = match container {
+            Container::Ktx2 => validate_ktx2(&output, None, false),
+            Container::Dds => validate_dds(&output, None),
+        };
+        if let Err(e) = valid {
+            println!(
+                "Keeping {:?}, its {} is not valid: {e}",
+                path,
+                container.extension()
+            );
+            continue;
+        }
+        if referenced.contains(&source_key(path)) {
+            println!("Keeping {:?}, a glTF still references it", path);
+            continue;
+        }
+        let Ok(metadata) = fs::metadata(path) else {
+            continue;
+        };
+        let result = match mode {
+            PruneMode::Delete => fs::remove_file(path),
+            PruneMode::Archive => {
+                let dir = path.parent().unwrap_or(Path::new(".")).join(ARCHIVE_DIR);
+                fs::create_dir_all(&dir)
+                    .and_then(|()| fs::rename(path, dir.join(path.file_name().unwrap())))
+            }
+        };
+        match result {
+            Ok(()) => {
+                count += 1;
+                bytes += metadata.len();
+            }
+            Err(e) => println!("Could not prune {:?}: {}", path, e),
+        }
+    }
+    let mb = bytes as f64 / (1024.0 * 1024.0);
+    match mode {
+        PruneMode::Delete => println!("Deleted {count} source images, {mb:.1} MB reclaimed"),
+        PruneMode::Archive => {
+            println!("Moved {count} source images ({mb:.1} MB) into {ARCHIVE_DIR}/ directories")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TempDir;
+
+    #[test]
+    fn glob_star_and_question_mark() {
+        assert!(glob_match("*.png", "Bistro_Leaf_BaseColor.png"));
+        assert!(glob_match("*Leaf*", "Bistro_Leaf_BaseColor.png"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "aXXbYYc"));
+        assert!(glob_match("a*b", "abab"));
+        assert!(glob_match("tex_??.png", "tex_01.png"));
+        assert!(!glob_match("tex_??.png", "tex_1.png"));
+        assert!(!glob_match("tex_?.png", "tex_01.png"));
+        assert!(!glob_match("*.png", "leaf.jpg"));
+        assert!(!glob_match("*Leaf*", "Bistro_Trunk.png"));
+    }
+
+    #[test]
+    fn glob_literal() {
+        assert!(glob_match("leaf.png", "leaf.png"));
+        assert!(!glob_match("leaf.png", "leaf.png.bak"));
+        assert!(!glob_match("leaf.png", "my_leaf.png"));
+        assert!(!glob_match("leaf", ""));
+        assert!(!glob_match("", "leaf"));
+    }
+
+    #[test]
+    fn glob_is_case_sensitive() {
+        assert!(!glob_match("*leaf*", "Bistro_Leaf.png"));
+        assert!(!glob_match("*.PNG", "leaf.png"));
+        assert!(glob_match("*Leaf*", "Bistro_Leaf.png"));
+    }
+
+    #[test]
+    fn bad_overrides_are_an_error() {
+        let dir = TempDir::new("overrides");
+        let path = dir.join("convert_overrides.ron");
+        assert!(load_ron_list::<ConvertOverride>(&path).unwrap().is_empty());
+        fs::write(&path, "[(glob: ").unwrap();
+        let e = load_ron_list::<ConvertOverride>(&path).unwrap_err();
+        assert!(e.starts_with("Failed to parse"), "{e}");
+        assert!(load_ron_list::<ConvertOverride>(&dir).is_err());
+    }
+
+    #[test]
+    fn filter_matching() {
+        let path = Path::new("textures/Bistro_Leaf_BaseColor.png");
+        assert!(matches_filter(&[], path));
+        // Without wildcards a filter matches names containing it
+        assert!(matches_filter(&["Leaf".to_string()], path));
+        assert!(matches_filter(&["*Color.png".to_string()], path));
+        assert!(matches_filter(
+            &["Trunk".to_string(), "*BaseColor*".to_string()],
+            path
+        ));
+        assert!(!matches_filter(&["Trunk".to_string()], path));
+        assert!(!matches_filter(&["leaf".to_string()], path));
+        // Only the file name is matched, not the directories
+        assert!(!matches_filter(&["textures".to_string()], path));
+        assert!(!matches_filter(&["*".to_string()], Path::new("/")));
+    }
+
+    #[test]
+    fn uri_to_path_decodes_percent_escapes() {
+        let base = Path::new("assets/bistro_exterior");
+        assert_eq!(
+            uri_to_path(base, "Textures/Paris%20Wall/Brick%20Wall.png"),
+            base.join("Textures/Paris Wall/Brick Wall.png")
+        );
+        assert_eq!(
+            uri_to_path(base, "Textures/leaf.png"),
+            base.join("Textures/leaf.png")
+        );
+        // Decoded once, `%2520` is a literal `%20` in the file name
+        assert_eq!(
+            uri_to_path(base, "Textures/100%2520.png"),
+            base.join("Textures/100%20.png")
+        );
+    }
+
+    fn images_json() -> serde_json::Value {
+        serde_json::json!({
+            "images": [
+                { "uri": "Textures/Paris%20Wall/Brick%20Wall.png", "mimeType": "image/png" },
+                { "uri": "textures/sub/dir/leaf.jpg" },
+                { "uri": "Textures/My%20Odd%2Epng" },
+                { "uri": "data:image/png;base64,iVBORw0KGgo=", "mimeType": "image/png" },
+                { "bufferView": 0, "mimeType": "image/png" },
+                { "uri": "Textures/done.ktx2", "mimeType": "image/ktx2" }
+            ],
+            "buffers": [{ "uri": "scene.bin", "byteLength": 4 }]
+        })
+    }
+
+    #[test]
+    fn gltf_image_paths_resolves_uris() {
+        let dir = TempDir::new("uris");
+        let gltf = dir.join("scene.gltf");
+        fs::write(&gltf, images_json().to_string()).unwrap();
+        let paths = gltf_image_paths(&gltf).unwrap();
+        assert_eq!(
+            paths,
+            [
+                dir.join("Textures/Paris Wall/Brick Wall.png"),
+                dir.join("textures/sub/dir/leaf.jpg"),
+                dir.join("Textures/My Odd.png"),
+                dir.join("Textures/done.ktx2"),
+            ]
+        );
+    }
+
+    #[test]
+    fn rewrite_image_uris_swaps_extensions() {
+        let mut json = images_json();
+        let base = Path::new("scene");
+        let converted = [
+            base.join("Textures/Paris Wall/Brick Wall.png"),
+            base.join("Textures/My Odd.png"),
+        ];
+        let rewrites = rewrite_image_uris(&mut json, base, Container::Ktx2, &converted);
+        assert_eq!(
+            rewrites,
+            [
+                (
+                    "Textures/Paris%20Wall/Brick%20Wall.png".to_string(),
+                    "Textures/Paris%20Wall/Brick%20Wall.ktx2".to_string()
+                ),
+                // The extension was encoded, so the URI is encoded again from the decoded path
+                (
+                    "Textures/My%20Odd%2Epng".to_string(),
+                    "Textures/My%20Odd.ktx2".to_string()
+                ),
+            ]
+        );
+        let images = &json["images"];
+        assert_eq!(images[0]["uri"], "Textures/Paris%20Wall/Brick%20Wall.ktx2");
+        assert!(images[0].get("mimeType").is_none());
+        // Not converted
+        assert_eq!(images[1]["uri"], "textures/sub/dir/leaf.jpg");
+        assert_eq!(images[2]["uri"], "Textures/My%20Odd.ktx2");
+        // Embedded, bufferView and already converted images are left alone
+        assert_eq!(images[3]["uri"], "data:image/png;base64,iVBORw0KGgo=");
+        assert_eq!(images[3]["mimeType"], "image/png");
+        assert_eq!(images[4]["mimeType"], "image/png");
+        assert_eq!(images[5]["uri"], "Textures/done.ktx2");
+        assert_eq!(json["buffers"][0]["uri"], "scene.bin");
+    }
+
+    #[test]
+    fn rewrite_image_uris_only_converted() {
+        let mut json = images_json();
+        let base = Path::new("scene");
+        let converted = [base.join("Textures/Paris Wall/Brick Wall.png")];
+        let rewrites = rewrite_image_uris(&mut json, base, Container::Dds, &converted);
+        assert_eq!(
+            rewrites,
+            [(
+                "Textures/Paris%20Wall/Brick%20Wall.png".to_string(),
+                "Textures/Paris%20Wall/Brick%20Wall.dds".to_string()
+            )]
+        );
+        assert_eq!(json["images"][1]["uri"], "textures/sub/dir/leaf.jpg");
+        let mut empty = serde_json::json!({});
+        assert!(rewrite_image_uris(&mut empty, base, Container::Ktx2, &converted).is_empty());
+    }
+
+    #[test]
+    fn failed_jobs_keep_their_uris() {
+        let dir = TempDir::new("failed");
+        fs::create_dir_all(dir.join("textures")).unwrap();
+        let gltf = dir.join("scene.gltf");
+        let json = serde_json::json!({
+            "images": [
+                { "uri": "textures/wall.png" },
+                { "uri": "textures/broken%20floor.png" }
+            ]
+        });
+        fs::write(&gltf, json.to_string()).unwrap();
+        // Only the wall converted, the floor's output was deleted after its encode failed
+        let converted = [dir.join("textures/wall.png")];
+        change_gltf_to_use_ktx2(std::slice::from_ref(&gltf), Container::Ktx2, &converted).unwrap();
+        let json = read_gltf_json(&gltf).unwrap();
+        assert_eq!(json["images"][0]["uri"], "textures/wall.ktx2");
+        assert_eq!(json["images"][1]["uri"], "textures/broken%20floor.png");
+    }
+
+    #[test]
+    fn skipped_images_keep_their_uris() {
+        let dir = TempDir::new("skip");
+        fs::create_dir_all(dir.join("textures")).unwrap();
+        for name in ["wall.png", "sign.png"] {
+            image::RgbaImage::new(4, 4)
+                .save(dir.join("textures").join(name))
+                .unwrap();
+        }
+        let gltf = dir.join("scene.gltf");
+        let json = serde_json::json!({
+            "images": [{ "uri": "textures/wall.png" }, { "uri": "textures/sign.png" }]
+        });
+        fs::write(&gltf, json.to_string()).unwrap();
+        let inputs = ConvertInputs::from_paths(std::slice::from_ref(&gltf), &[]);
+        let settings = ConvertSettings {
+            overrides: vec![ConvertOverride {
+                glob: "sign.png".to_string(),
+                skip: true,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let plan = plan_conversion(&inputs, &settings);
+        let rewrites = plan_gltf_rewrites(&inputs.gltfs, settings.container, &plan.jobs).unwrap();
+        assert_eq!(plan.skipped.len(), 1);
+        assert_eq!(
+            rewrites[0].1,
+            [(
+                "textures/wall.png".to_string(),
+                "textures/wall.ktx2".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn unreadable_gltfs_are_skipped() {
+        let dir = TempDir::new("unreadable");
+        let good = dir.join("good.gltf");
+        fs::write(&good, images_json().to_string()).unwrap();
+        let truncated = dir.join("truncated.gltf");
+        fs::write(&truncated, r#"{ "images": [{ "uri": "#).unwrap();
+        fs::write(dir.join("truncated.glb"), b"glTF\x02\x00\x00\x00").unwrap();
+        let inputs = ConvertInputs::from_paths(&[dir.to_path_buf()], &[]);
+        assert_eq!(inputs.gltfs, std::slice::from_ref(&good));
+        let inputs = ConvertInputs::from_paths(std::slice::from_ref(&truncated), &[]);
+        assert!(inputs.gltfs.is_empty());
+        assert!(gltf_image_paths(&truncated).is_err());
+        assert!(plan_gltf_rewrites(&[good, truncated], Container::Ktx2, &[]).is_err());
+    }
+
+    #[test]
+    fn walk_dir_skips_unreadable_and_linked_dirs() {
+        let dir = TempDir::new("walk");
+        fs::create_dir_all(dir.join("a/b")).unwrap();
+        fs::create_dir_all(dir.join("locked")).unwrap();
+        fs::write(dir.join("top.png"), b"").unwrap();
+        fs::write(dir.join("a/b/nested.png"), b"").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&*dir, dir.join("a/loop")).unwrap();
+        let locked = dir.join("locked");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        }
+        let mut found = Vec::new();
+        walk_dir(&dir, &mut |file| found.push(file.to_path_buf()));
+        // Unreadable to anyone but root, put back so the directory can be removed
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        found.sort();
+        assert_eq!(found, [dir.join("a/b/nested.png"), dir.join("top.png")]);
+    }
+
+    #[test]
+    fn missing_encoder_is_an_error() {
+        let program = "bistro-no-such-encoder";
+        let e = run_encoder(&mut Command::new(program), program).unwrap_err();
+        assert!(
+            e.starts_with("could not start bistro-no-such-encoder: "),
+            "{e}"
+        );
+    }
+
+    fn resolved(path: &str, role: ImageRole, settings: &ConvertSettings) -> ImageSettings {
+        let mut matched = vec![false; settings.overrides.len()];
+        ImageSettings::resolve(Path::new(path), &[role], None, settings, &mut matched)
+    }
+
+    fn args_string(args: &[String]) -> String {
+        args.join(" ")
+    }
+
+    #[test]
+    fn default_kram_args_match_the_original_invocation() {
+        let settings = ConvertSettings::default();
+        let image_settings = resolved("leaf.png", ImageRole::BaseColor, &settings);
+        let args = kram_args(
+            Path::new("leaf.png"),
+            Path::new("leaf.ktx2"),
+            &image_settings,
+            None,
+        );
+        // The arguments the converter passed to kram before any of the encoder options existed
+        assert_eq!(
+            args,
+            [
+                "encode",
+                "-f",
+                "bc7",
+                "-type",
+                "2d",
+                "-srgb",
+                "-zstd",
+                "0",
+                "-i",
+                "leaf.png",
+                "-o",
+                "leaf.ktx2",
+            ]
+        );
+    }
+
+    #[test]
+    fn normal_map_role_uses_linear_normal_args() {
+        let settings = ConvertSettings::default();
+        let image_settings = resolved("leaf_normal.png", ImageRole::Normal, &settings);
+        let args = kram_args(
+            Path::new("leaf_normal.png"),
+            Path::new("leaf_normal.ktx2"),
+            &image_settings,
+            None,
+        );
+        assert_eq!(
+            args_string(&args),
+            "encode -f bc7 -normal -type 2d -zstd 0 -i leaf_normal.png -o leaf_normal.ktx2"
+        );
+    }
+
+    #[test]
+    fn kram_mip_and_zstd_args() {
+        let input = Path::new("leaf.png");
+        let output = Path::new("leaf.ktx2");
+        let settings = ConvertSettings {
+            mips: false,
+            min_mip_size: Some(8),
+            supercompression: Supercompression::None,
+            ..Default::default()
+        };
+        let image_settings = resolved("leaf.png", ImageRole::BaseColor, &settings);
+        let args = kram_args(input, output, &image_settings, None);
+        assert_eq!(
+            args_string(&args),
+            "encode -f bc7 -mipnone -type 2d -srgb -i leaf.png -o leaf.ktx2"
+        );
+        let settings = ConvertSettings {
+            min_mip_size: Some(8),
+            supercompression: Supercompression::Zstd(19),
+            ..Default::default()
+        };
+        let image_settings = resolved("leaf.png", ImageRole::BaseColor, &settings);
+        let args = kram_args(input, output, &image_settings, Some((512, 256)));
+        assert_eq!(
+            args_string(&args),
+            "encode -f bc7 -resize 512x256 -mipmin 8 -type 2d -srgb -zstd 19 -i leaf.png -o leaf.ktx2"
+        );
+    }
+
+    #[test]
+    fn toktx_mip_args() {
+        let input = Path::new("leaf.png");
+        let output = Path::new("leaf.ktx2");
+        let settings = ConvertSettings {
+            format: ConvertFormat::Etc1s,
+            ..Default::default()
+        };
+        let image_settings = resolved("leaf.png", ImageRole::BaseColor, &settings);
+        let args = toktx_args(
+            input,
+            output,
+            &image_settings,
+            None,
+            Some((256, 64)),
+            &settings,
+        );
+        assert_eq!(
+            args_string(&args),
+            "--t2 --encode etc1s --qlevel 128 --genmipmap --threads 1 --assign_oetf srgb leaf.ktx2 leaf.png"
+        );
+        // 256 -> 128 -> 64 -> 32 -> 16
+        let settings = ConvertSettings {
+            min_mip_size: Some(16),
+            ..settings
+        };
+        let image_settings = resolved("leaf.png", ImageRole::BaseColor, &settings);
+        let args = toktx_args(
+            input,
+            output,
+            &image_settings,
+            None,
+            Some((256, 64)),
+            &settings,
+        );
+        assert_eq!(
+            args_string(&args),
+            "--t2 --encode etc1s --qlevel 128 --genmipmap --levels 5 --threads 1 --assign_oetf srgb leaf.ktx2 leaf.png"
+        );
+        let settings = ConvertSettings {
+            mips: false,
+            ..settings
+        };
+        let image_settings = resolved("leaf_normal.png", ImageRole::Normal, &settings);
+        let args = toktx_args(
+            input,
+            output,
+            &image_settings,
+            None,
+            Some((256, 64)),
+            &settings,
+        );
+        assert_eq!(
+            args_string(&args),
+            "--t2 --encode etc1s --qlevel 128 --threads 1 --assign_oetf linear leaf.ktx2 leaf.png"
+        );
+    }
+
+    #[test]
+    fn override_mip_and_zstd_args() {
+        let dir = TempDir::new("mip_overrides");
+        let path = dir.join("convert_overrides.ron");
+        fs::write(
+            &path,
+            r#"[
+                (glob: "*_UI_*", mips: false, zstd: "off"),
+                (role: normal, min_mip_size: 4, zstd: 19),
+            ]"#,
+        )
+        .unwrap();
+        let settings = ConvertSettings {
+            overrides: load_ron_list(&path).unwrap(),
+            ..Default::default()
+        };
+        let kram = |name: &str, role: ImageRole| {
+            let image_settings = resolved(name, role, &settings);
+            args_string(&kram_args(
+                Path::new(name),
+                Path::new("out.ktx2"),
+                &image_settings,
+                None,
+            ))
+        };
+        assert_eq!(
+            kram("Menu_UI_Icons.png", ImageRole::BaseColor),
+            "encode -f bc7 -mipnone -type 2d -srgb -i Menu_UI_Icons.png -o out.ktx2"
+        );
+        assert_eq!(
+            kram("wall_normal.png", ImageRole::Normal),
+            "encode -f bc7 -normal -mipmin 4 -type 2d -zstd 19 -i wall_normal.png -o out.ktx2"
+        );
+        // Unmatched images keep the defaults
+        assert_eq!(
+            kram("wall.png", ImageRole::BaseColor),
+            "encode -f bc7 -type 2d -srgb -zstd 0 -i wall.png -o out.ktx2"
+        );
+        let settings = ConvertSettings {
+            format: ConvertFormat::Etc1s,
+            ..settings
+        };
+        let image_settings = resolved("wall_normal.png", ImageRole::Normal, &settings);
+        let args = toktx_args(
+            Path::new("wall_normal.png"),
+            Path::new("out.ktx2"),
+            &image_settings,
+            None,
+            Some((32, 32)),
+            &settings,
+        );
+        // 32 -> 16 -> 8 -> 4
+        assert_eq!(
+            args_string(&args),
+            "--t2 --encode etc1s --qlevel 128 --genmipmap --levels 4 --threads 1 --assign_oetf linear out.ktx2 wall_normal.png"
+        );
+        fs::write(&path, r#"[(glob: "*", zstd: "fast")]"#).unwrap();
+        assert!(load_ron_list::<ConvertOverride>(&path).is_err());
+    }
+
+    #[test]
+    fn dry_run_plan_is_sorted() {
+        let dir = TempDir::new("plan");
+        fs::create_dir_all(dir.join("textures/sub")).unwrap();
+        // Written out of order so the plan can't just follow the creation order
+        for name in [
+            "wall.png",
+            "sub/leaf.png",
+            "sign.png",
+            "floor.png",
+            "unused.png",
+        ] {
+            image::RgbaImage::new(4, 4)
+                .save(dir.join("textures").join(name))
+                .unwrap();
+        }
+        image::RgbImage::new(4, 4)
+            .save(dir.join("textures/brick.jpg"))
+            .unwrap();
+        let json = serde_json::json!({
+            "images": [
+                { "uri": "textures/wall.png" },
+                { "uri": "textures/sub/leaf.png" },
+                { "uri": "textures/sign.png" },
+                { "uri": "textures/floor.png" },
+                { "uri": "textures/brick.jpg" }
+            ],
+            "textures": [
+                { "source": 0 },
+                { "source": 1 },
+                { "source": 2 },
+                { "source": 3 },
+                { "source": 4 }
+            ],
+            "materials": [
+                {
+                    "pbrMetallicRoughness": {
+                        "baseColorTexture": { "index": 0 },
+                        "metallicRoughnessTexture": { "index": 3 }
+                    },
+                    "normalTexture": { "index": 1 }
+                },
+                {
+                    "pbrMetallicRoughness": { "baseColorTexture": { "index": 2 } },
+                    "emissiveTexture": { "index": 4 }
+                }
+            ]
+        });
+        fs::write(dir.join("scene.gltf"), json.to_string()).unwrap();
+        let settings = ConvertSettings {
+            overrides: vec![
+                ConvertOverride {
+                    glob: "sign.png".to_string(),
+                    skip: true,
+                    ..Default::default()
+                },
+                ConvertOverride {
+                    glob: "*Typo*".to_string(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let inputs = ConvertInputs::from_paths(&[dir.to_path_buf()], &[]);
+        let plan = plan_conversion(&inputs, &settings);
+        let textures = dir.join("textures");
+        let inputs_of = |plan: &ConvertPlan| {
+            plan.jobs
+                .iter()
+                .map(|job| job.input.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            inputs_of(&plan),
+            [
+                textures.join("brick.jpg"),
+                textures.join("floor.png"),
+                textures.join("sub/leaf.png"),
+                textures.join("unused.png"),
+                textures.join("wall.png"),
+            ]
+        );
+        assert_eq!(plan.skipped, [textures.join("sign.png")]);
+        assert_eq!(plan.unreferenced, [textures.join("unused.png")]);
+        assert_eq!(plan.unmatched_overrides, ["*Typo*"]);
+        let roles = plan.jobs.iter().map(|job| job.role).collect::<Vec<_>>();
+        assert_eq!(
+            roles,
+            [
+                ImageRole::Emissive,
+                ImageRole::Orm,
+                ImageRole::Normal,
+                ImageRole::Other,
+                ImageRole::BaseColor,
+            ]
+        );
+        // The jpg is decoded to a png for kram first
+        assert_eq!(
+            plan.jobs[0].staged_input,
+            Some(textures.join("brick.staged.png"))
+        );
+
+        let again = plan_conversion(
+            &ConvertInputs::from_paths(&[dir.to_path_buf()], &[]),
+            &settings,
+        );
+        assert_eq!(inputs_of(&again), inputs_of(&plan));
+        let command_lines = |plan: &ConvertPlan| {
+            plan.jobs
+                .iter()
+                .map(EncodeJob::command_line)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(command_lines(&again), command_lines(&plan));
+    }
+
+    /// A BC7 file with a single level of zeroed blocks and a DFD that only holds its size.
+    fn bc7_level(width: u32, height: u32) -> Vec<u8> {
+        let mut out = vec![0; 80 + 24];
+        out[..12].copy_from_slice(&[
+            0xab, 0x4b, 0x54, 0x58, 0x20, 0x32, 0x30, 0xbb, 0x0d, 0x0a, 0x1a, 0x0a,
+        ]);
+        // vkFormat BC7_UNORM, typeSize, width, height, faceCount, levelCount, DFD offset and length
+        for (offset, value) in [
+            (12, 145),
+            (16, 1),
+            (20, width),
+            (24, height),
+            (36, 1),
+            (40, 1),
+            (48, 104),
+            (52, 4),
+        ] {
+            out[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        out.extend_from_slice(&4u32.to_le_bytes());
+        out.resize(112, 0);
+        let len = (width.div_ceil(4) * height.div_ceil(4) * 16) as u64;
+        out[80..88].copy_from_slice(&112u64.to_le_bytes());
+        out[88..96].copy_from_slice(&len.to_le_bytes());
+        out[96..104].copy_from_slice(&len.to_le_bytes());
+        out.resize(out.len() + len as usize, 0);
+        out
+    }
+
+    #[test]
+    fn validate_ktx2_checks_the_expected_size_and_levels() {
+        let dir = TempDir::new("validate_ktx2");
+        let path = dir.join("wall.ktx2");
+        fs::write(&path, bc7_level(8, 8)).unwrap();
+        let expected = |width, height, level_count| Expected {
+            width,
+            height,
+            level_count,
+            srgb: true,
+        };
+        validate_ktx2(&path, None, false).unwrap();
+        validate_ktx2(&path, Some(&expected(8, 8, Some(1))), false).unwrap();
+        validate_ktx2(&path, Some(&expected(8, 8, None)), false).unwrap();
+        let e = validate_ktx2(&path, Some(&expected(16, 8, Some(1))), false).unwrap_err();
+        assert_eq!(e, "expected 16x8, got 8x8");
+        let e = validate_ktx2(&path, Some(&expected(8, 8, Some(4))), false).unwrap_err();
+        assert_eq!(e, "expected 4 levels, got 1");
+    }
+
+    #[test]
+    fn validate_ktx2_rejects_broken_files() {
+        let dir = TempDir::new("broken_ktx2");
+        let path = dir.join("wall.ktx2");
+        let e = validate_ktx2(&path, None, false).unwrap_err();
+        assert!(e.starts_with("could not read: "), "{e}");
+
+        let mut bad_magic = bc7_level(8, 8);
+        bad_magic[1] = b'J';
+        fs::write(&path, bad_magic).unwrap();
+        let e = validate_ktx2(&path, None, false).unwrap_err();
+        assert_eq!(e, "invalid header: BadMagic");
+
+        let mut truncated = bc7_level(8, 8);
+        truncated.truncate(truncated.len() - 1);
+        fs::write(&path, truncated).unwrap();
+        let e = validate_ktx2(&path, None, false).unwrap_err();
+        assert_eq!(e, "invalid header: UnexpectedEnd");
+
+        // Two levels in the header but only room for the index of one
+        let mut missing_level = bc7_level(8, 8);
+        missing_level[40..44].copy_from_slice(&2u32.to_le_bytes());
+        fs::write(&path, missing_level).unwrap();
+        assert!(validate_ktx2(&path, None, false).is_err());
+
+        let mut unknown_format = bc7_level(8, 8);
+        unknown_format[12..16].copy_from_slice(&0u32.to_le_bytes());
+        fs::write(&path, unknown_format).unwrap();
+        let e = validate_ktx2(&path, None, false).unwrap_err();
+        assert!(e.starts_with("unsupported format: "), "{e}");
+    }
+
+    #[test]
+    fn join_ktx2_levels_builds_a_mip_chain() {
+        let levels = [
+            bc7_level(8, 8),
+            bc7_level(4, 4),
+            bc7_level(2, 2),
+            bc7_level(1, 1),
+        ];
+        let joined = join_ktx2_levels(&levels).unwrap();
+        let reader = ktx2::Reader::new(&joined[..]).unwrap();
+        assert_eq!(reader.header().level_count, 4);
+        assert_eq!(
+            (reader.header().pixel_width, reader.header().pixel_height),
+            (8, 8)
+        );
+        let lens = reader.levels().map(<[u8]>::len).collect::<Vec<_>>();
+        assert_eq!(lens, [64, 16, 16, 16]);
+
+        let dir = TempDir::new("join_ktx2");
+        let path = dir.join("wall.ktx2");
+        fs::write(&path, &joined).unwrap();
+        let expected = Expected {
+            width: 8,
+            height: 8,
+            level_count: Some(4),
+            srgb: true,
+        };
+        validate_ktx2(&path, Some(&expected), false).unwrap();
+    }
+
+    #[test]
+    fn join_ktx2_levels_rejects_broken_levels() {
+        assert_eq!(join_ktx2_levels(&[]).unwrap_err(), "no levels");
+
+        let mut bad_magic = bc7_level(4, 4);
+        bad_magic[0] = 0;
+        let e = join_ktx2_levels(&[bc7_level(8, 8), bad_magic]).unwrap_err();
+        assert_eq!(e, "mip 1: invalid header: BadMagic");
+
+        let mut truncated = bc7_level(4, 4);
+        truncated.truncate(100);
+        let e = join_ktx2_levels(&[bc7_level(8, 8), truncated]).unwrap_err();
+        assert_eq!(e, "mip 1: invalid header: UnexpectedEnd");
+
+        let joined = join_ktx2_levels(&[bc7_level(8, 8), bc7_level(4, 4)]).unwrap();
+        let e = join_ktx2_levels(&[joined]).unwrap_err();
+        assert_eq!(e, "mip 0 has 2 levels");
+
+        // Key/value data claimed past the end of the file
+        let mut bad_kvd = bc7_level(8, 8);
+        bad_kvd[56..60].copy_from_slice(&108u32.to_le_bytes());
+        bad_kvd[60..64].copy_from_slice(&1000u32.to_le_bytes());
+        assert!(join_ktx2_levels(&[bad_kvd]).is_err());
+    }
+
+    #[test]
+    fn prune_keeps_referenced_and_unconverted_sources() {
+        let dir = TempDir::new("prune");
+        for name in ["wall", "floor", "sign", "broken", "missing"] {
+            fs::write(dir.join(format!("{name}.png")), b"png").unwrap();
+        }
+        for name in ["wall", "floor", "sign"] {
+            fs::write(dir.join(format!("{name}.ktx2")), bc7_level(4, 4)).unwrap();
+        }
+        fs::write(dir.join("broken.ktx2"), b"not a ktx2").unwrap();
+        // The sign's URI wasn't rewritten
+        let gltfs = [dir.join("scene.gltf")];
+        let json = serde_json::json!({
+            "images": [{ "uri": "wall.ktx2" }, { "uri": "floor.ktx2" }, { "uri": "sign.png" }]
+        });
+        fs::write(&gltfs[0], json.to_string()).unwrap();
+        let converted = ["wall", "floor", "sign", "broken", "missing"]
+            .map(|name| dir.join(format!("{name}.png")));
+        prune_sources(&converted, &gltfs, PruneMode::Delete, Container::Ktx2);
+        assert!(!dir.join("wall.png").exists());
+        assert!(!dir.join("floor.png").exists());
+        assert!(dir.join("sign.png").exists());
+        assert!(dir.join("broken.png").exists());
+        assert!(dir.join("missing.png").exists());
+        assert!(dir.join("wall.ktx2").exists());
+
+        // Not converted at all, so never passed in
+        fs::write(dir.join("unconverted.png"), b"png").unwrap();
+        prune_sources(&[], &gltfs, PruneMode::Delete, Container::Ktx2);
+        assert!(dir.join("unconverted.png").exists());
+
+        // Nothing is pruned if a glTF can't be read
+        fs::write(dir.join("wall.png"), b"png").unwrap();
+        fs::write(&gltfs[0], "{").unwrap();
+        prune_sources(&converted[..1], &gltfs, PruneMode::Delete, Container::Ktx2);
+        assert!(dir.join("wall.png").exists());
+    }
+
+    #[test]
+    fn prune_archive_moves_sources() {
+        let dir = TempDir::new("prune_archive");
+        fs::write(dir.join("wall.png"), b"png").unwrap();
+        fs::write(dir.join("wall.ktx2"), bc7_level(4, 4)).unwrap();
+        prune_sources(
+            &[dir.join("wall.png")],
+            &[],
+            PruneMode::Archive,
+            Container::Ktx2,
+        );
+        assert!(!dir.join("wall.png").exists());
+        assert_eq!(
+            fs::read(dir.join(ARCHIVE_DIR).join("wall.png")).unwrap(),
+            b"png"
+        );
+    }
+
+    #[test]
+    fn toktx_uses_one_thread_per_job() {
+        // The pool runs `threads` jobs at once, more threads per job would multiply that
+        let settings = ConvertSettings {
+            format: ConvertFormat::Etc1s,
+            threads: Some(8),
+            ..Default::default()
+        };
+        let image_settings = resolved("leaf.png", ImageRole::BaseColor, &settings);
+        let args = toktx_args(
+            Path::new("leaf.png"),
+            Path::new("leaf.ktx2"),
+            &image_settings,
+            None,
+            None,
+            &settings,
+        );
+        let threads = args.iter().position(|arg| arg == "--threads").unwrap();
+        assert_eq!(args[threads + 1], "1");
+        assert_eq!(args.iter().filter(|arg| *arg == "--threads").count(), 1);
+    }
+
+    #[test]
+    fn decode_is_timed_for_every_source_extension() {
+        let dir = TempDir::new("decode_time");
+        for extension in SOURCE_EXTENSIONS {
+            let path = dir.join(format!("source.{extension}"));
+            image::RgbImage::new(4, 4).save(&path).unwrap();
+            assert!(time_decode(&path, true).is_some(), "{extension}");
         }
-        pool.join();
+        fs::write(dir.join("broken.png"), b"not a png").unwrap();
+        assert!(time_decode(&dir.join("broken.png"), true).is_none());
     }
 }
@@ -0,0 +1,51 @@
+use bevy::{asset::LoadState, prelude::*};
+
+/// The glTF files of the `--scene`s still being loaded, to report the ones that fail.
+#[derive(Resource, Default)]
+pub struct CustomScenes(Vec<(String, Handle<Gltf>)>);
+
+impl CustomScenes {
+    pub fn push(&mut self, path: String, gltf_h: Handle<Gltf>) {
+        self.0.push((path, gltf_h));
+    }
+}
+
+/// The file of a `--scene` path, without its label.
+pub fn gltf_path(path: &str) -> &str {
+    path.split('#').next().unwrap_or(path)
+}
+
+/// The first scene of the glTF unless the path already names one, e.g. `sponza.gltf#Scene1`.
+pub fn scene_asset_path(path: &str) -> String {
+    if path.contains('#') {
+        path.to_string()
+    } else {
+        format!("{path}#Scene0")
+    }
+}
+
+/// A glTF that fails to load only fails its own handle, the scene handle spawned from it would
+/// stay loading and leave an empty world. Exits with the error instead.
+pub fn check_custom_scenes(
+    mut custom_scenes: ResMut<CustomScenes>,
+    asset_server: Res<AssetServer>,
+    gltfs: Res<Assets<Gltf>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    custom_scenes.0.retain(|(path, gltf_h)| {
+        let error = match asset_server.load_state(gltf_h) {
+            LoadState::Failed(e) => e.to_string(),
+            LoadState::Loaded => match gltfs.get(gltf_h) {
+                Some(gltf) if gltf.scenes.is_empty() => "it has no scenes".to_string(),
+                _ => {
+                    println!("Loaded scene {path}");
+                    return false;
+                }
+            },
+            LoadState::NotLoaded | LoadState::Loading => return true,
+        };
+        println!("Failed to load scene {path}: {error}");
+        exit.send(AppExit::error());
+        false
+    });
+}
@@ -0,0 +1,168 @@
+use bevy::{color::Mix, prelude::*, utils::HashMap};
+
+use crate::{
+    help::KeyBindings,
+    sun::{self, SunAngles, MAX_SUN_ILLUMINANCE, SUN_COLOR},
+    Args, GrifLight,
+};
+
+/// The sun color at the horizon, it's `SUN_COLOR` from `HORIZON_ELEVATION` up.
+const HORIZON_SUN_COLOR: Color = Color::srgb(1.0, 0.45, 0.15);
+const HORIZON_ELEVATION: f32 = 30.0;
+/// The sun fades out between these elevations.
+const TWILIGHT_ELEVATIONS: (f32, f32) = (-6.0, 6.0);
+/// How much of the ambient light and clear color are left at night.
+const NIGHT_AMBIENT: f32 = 0.15;
+const NIGHT_SKY: f32 = 0.03;
+/// How much brighter the lamps get at night.
+const LAMP_NIGHT_GAIN: f32 = 4.0;
+/// The lamp materials are only changed once the gain moved this much, every change prepares
+/// them again.
+const LAMP_GAIN_STEP: f32 = 0.05;
+const SCRUB_HOURS_PER_SECOND: f32 = 3.0;
+
+pub fn parse_day_length(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(seconds) if seconds.is_finite() && seconds > 0.0 => Ok(seconds),
+        _ => Err(format!(
+            "Expected a positive number of seconds per day, got {value}"
+        )),
+    }
+}
+
+/// State of `--day-cycle`.
+#[derive(Resource)]
+pub struct DayCycle {
+    pub seconds_per_day: f32,
+    /// 0 to 1 from midnight, noon is 0.5 where the sun is at `--sun-azimuth` and
+    /// `--sun-elevation`.
+    pub time_of_day: f32,
+    pub paused: bool,
+    ambient_scale: f32,
+}
+
+impl DayCycle {
+    pub fn new(seconds_per_day: f32) -> Self {
+        Self {
+            seconds_per_day,
+            time_of_day: 0.5,
+            paused: false,
+            ambient_scale: 1.0,
+        }
+    }
+
+    fn print_time(&self) {
+        let minutes = (self.time_of_day * 24.0 * 60.0) as u32;
+        println!(
+            "Time of day {:02}:{:02}{}",
+            minutes / 60,
+            minutes % 60,
+            if self.paused { " (paused)" } else { "" }
+        );
+    }
+}
+
+/// The materials with an emissive color, the Bistro lamps, with that color. Gathered by
+/// `proc_scene`, brightened at night by `--day-cycle`.
+#[derive(Resource, Default)]
+pub struct LampMaterials {
+    pub materials: HashMap<AssetId<StandardMaterial>, LinearRgba>,
+    gain: f32,
+    /// How many of `materials` have `gain` applied, the scene may still be loading.
+    applied: usize,
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+pub fn register_keys(mut bindings: ResMut<KeyBindings>) {
+    bindings
+        .add("Lighting", "K", "Pause or resume the day cycle")
+        .add("Lighting", "X/Z (hold)", "Scrub the day forward or back");
+}
+
+// K: pause/resume the day cycle, hold X/Z to scrub forward/back
+pub fn day_cycle_input(input: Res<ButtonInput<KeyCode>>, mut cycle: ResMut<DayCycle>) {
+    if input.just_pressed(KeyCode::KeyK) {
+        cycle.paused = !cycle.paused;
+        cycle.print_time();
+    }
+    if input.any_just_released([KeyCode::KeyX, KeyCode::KeyZ]) {
+        cycle.print_time();
+    }
+}
+
+/// Moves the sun along its path of the day and fades the lights and sky with it.
+#[allow(clippy::too_many_arguments)]
+pub fn day_cycle(
+    input: Res<ButtonInput<KeyCode>>,
+    time: Res<Time>,
+    args: Res<Args>,
+    mut cycle: ResMut<DayCycle>,
+    mut sun: Query<(&mut Transform, &mut DirectionalLight), With<GrifLight>>,
+    mut angles: ResMut<SunAngles>,
+    mut clear_color: ResMut<ClearColor>,
+    mut ambient: ResMut<AmbientLight>,
+    mut lamps: ResMut<LampMaterials>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let mut days = if cycle.paused {
+        0.0
+    } else {
+        time.delta_seconds() / cycle.seconds_per_day
+    };
+    let scrub = SCRUB_HOURS_PER_SECOND / 24.0 * time.delta_seconds();
+    if input.pressed(KeyCode::KeyX) {
+        days += scrub;
+    }
+    if input.pressed(KeyCode::KeyZ) {
+        days -= scrub;
+    }
+    cycle.time_of_day = (cycle.time_of_day + days).rem_euclid(1.0);
+
+    // Rises at 6:00 a quarter turn before --sun-azimuth, sets at 18:00 a quarter turn after
+    let noon_elevation = args.sun_elevation.clamp(0.0, 90.0);
+    let elevation = noon_elevation * ((cycle.time_of_day - 0.25) * std::f32::consts::TAU).sin();
+    *angles = SunAngles::new(
+        args.sun_azimuth + (cycle.time_of_day - 0.5) * 360.0,
+        elevation,
+    );
+    let daylight = smoothstep(TWILIGHT_ELEVATIONS.0, TWILIGHT_ELEVATIONS.1, elevation);
+    let illuminance = args.sun_illuminance.clamp(0.0, MAX_SUN_ILLUMINANCE) * daylight;
+    let color = HORIZON_SUN_COLOR.to_srgba().mix(
+        &SUN_COLOR.to_srgba(),
+        smoothstep(0.0, HORIZON_ELEVATION, elevation),
+    );
+    for (mut transform, mut light) in &mut sun {
+        transform.rotation = angles.rotation();
+        light.illuminance = illuminance;
+        light.color = color.into();
+    }
+
+    // Scaled relative to the last frame so ] and [ still work
+    let ambient_scale = NIGHT_AMBIENT + (1.0 - NIGHT_AMBIENT) * daylight;
+    ambient.brightness = ambient.brightness / cycle.ambient_scale * ambient_scale;
+    cycle.ambient_scale = ambient_scale;
+    ambient.color = sun::ambient_color(angles.sky_tint());
+    clear_color.0 = sun::clear_color(
+        &args,
+        angles.sky_tint() * (NIGHT_SKY + (1.0 - NIGHT_SKY) * daylight),
+    );
+
+    let gain = 1.0 + (LAMP_NIGHT_GAIN - 1.0) * (1.0 - daylight);
+    let settled = gain == 1.0 || gain == LAMP_NIGHT_GAIN;
+    if (gain - lamps.gain).abs() >= LAMP_GAIN_STEP
+        || (settled && gain != lamps.gain)
+        || lamps.applied != lamps.materials.len()
+    {
+        lamps.gain = gain;
+        lamps.applied = lamps.materials.len();
+        for (id, emissive) in &lamps.materials {
+            if let Some(mat) = materials.get_mut(*id) {
+                mat.emissive = *emissive * gain;
+            }
+        }
+    }
+}
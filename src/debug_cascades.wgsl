@@ -0,0 +1,48 @@
+// Fragment shader of the F11 shadow cascade debug view: the lit color tinted by the cascade of the
+// sun's shadow map the fragment samples. Red, green, blue and yellow for the first four cascades,
+// grey past the last one where there are no shadows.
+
+#import bevy_pbr::{
+    forward_io::VertexOutput,
+    mesh_view_bindings as view_bindings,
+    mesh_view_types::DIRECTIONAL_LIGHT_FLAGS_SHADOWS_ENABLED_BIT,
+    pbr_fragment::pbr_input_from_standard_material,
+    pbr_functions::{alpha_discard, apply_pbr_lighting},
+    shadows::get_cascade_index,
+}
+
+@fragment
+fn fragment(in: VertexOutput, @builtin(front_facing) is_front: bool) -> @location(0) vec4<f32> {
+    var pbr_input = pbr_input_from_standard_material(in, is_front);
+    pbr_input.material.base_color = alpha_discard(pbr_input.material, pbr_input.material.base_color);
+    let lit = apply_pbr_lighting(pbr_input).rgb;
+
+    let view_z = dot(vec4<f32>(
+        view_bindings::view.view_from_world[0].z,
+        view_bindings::view.view_from_world[1].z,
+        view_bindings::view.view_from_world[2].z,
+        view_bindings::view.view_from_world[3].z
+    ), in.world_position);
+
+    // The first directional light with shadows, that's the sun
+    var cascade = 4u;
+    for (var i = 0u; i < view_bindings::lights.n_directional_lights; i = i + 1u) {
+        let light = &view_bindings::lights.directional_lights[i];
+        if ((*light).flags & DIRECTIONAL_LIGHT_FLAGS_SHADOWS_ENABLED_BIT) != 0u {
+            let index = get_cascade_index(i, view_z);
+            cascade = select(4u, index, index < (*light).num_cascades);
+            break;
+        }
+    }
+
+    var colors = array<vec3<f32>, 5>(
+        vec3(1.0, 0.15, 0.15),
+        vec3(0.15, 1.0, 0.15),
+        vec3(0.2, 0.35, 1.0),
+        vec3(1.0, 0.9, 0.1),
+        vec3(0.5, 0.5, 0.5),
+    );
+    // Keeps some of the shading so the shadows stay visible
+    let brightness = saturate(dot(lit, vec3(0.2126, 0.7152, 0.0722)));
+    return vec4(colors[cascade] * (0.25 + 0.75 * brightness), 1.0);
+}
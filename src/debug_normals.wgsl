@@ -0,0 +1,16 @@
+// Fragment shader of the F7 normals debug view: the world space normal after normal mapping and
+// the double sided flip, mapped to 0..1.
+
+#import bevy_pbr::{
+    forward_io::VertexOutput,
+    pbr_fragment::pbr_input_from_standard_material,
+    pbr_functions::alpha_discard,
+}
+
+@fragment
+fn fragment(in: VertexOutput, @builtin(front_facing) is_front: bool) -> @location(0) vec4<f32> {
+    let pbr_input = pbr_input_from_standard_material(in, is_front);
+    // Alpha masked texels are discarded like in the regular view
+    alpha_discard(pbr_input.material, pbr_input.material.base_color);
+    return vec4(pbr_input.N * 0.5 + 0.5, 1.0);
+}
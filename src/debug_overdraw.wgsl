@@ -0,0 +1,9 @@
+// Fragment shader of the F8 overdraw debug view. Drawn additively without a depth test, so
+// every layer covering a pixel makes it brighter.
+
+#import bevy_pbr::forward_io::VertexOutput
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4(0.04, 0.015, 0.005, 1.0);
+}
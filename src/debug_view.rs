@@ -0,0 +1,354 @@
+use bevy::{
+    asset::load_internal_asset,
+    pbr::{
+        wireframe::{WireframeConfig, WireframePlugin},
+        CascadeShadowConfig, ExtendedMaterial, MaterialExtension, MaterialExtensionKey,
+        MaterialExtensionPipeline, MaterialPipeline, MaterialPipelineKey,
+    },
+    prelude::*,
+    render::{
+        mesh::MeshVertexBufferLayoutRef,
+        render_resource::{
+            AsBindGroup, CompareFunction, RenderPipelineDescriptor, ShaderRef,
+            SpecializedMeshPipelineError,
+        },
+        renderer::RenderDevice,
+        settings::WgpuFeatures,
+    },
+};
+
+use crate::{extended_materials::ExtendedMaterials, help::KeyBindings, GrifLight};
+
+const NORMALS_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(0x51a6_0e3c_94d2_4b7f_8c15_e2a9_6d40_b3f1);
+const OVERDRAW_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(0x0b9e_47d5_2f61_4c8a_a3d7_91e4_5c28_f06b);
+const CASCADES_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(0x7d31_c8e2_0a5f_4e96_b4c1_3f87_a2d6_591e);
+
+/// Names of the cascade colors in `debug_cascades.wgsl`.
+const CASCADE_COLORS: [&str; 4] = ["red", "green", "blue", "yellow"];
+
+pub type NormalsMaterial = ExtendedMaterial<StandardMaterial, NormalsExtension>;
+
+/// Shows the normals of a `StandardMaterial`, with its normal map and alpha mask.
+#[derive(Asset, AsBindGroup, Reflect, Debug, Clone)]
+pub struct NormalsExtension {}
+
+impl MaterialExtension for NormalsExtension {
+    fn fragment_shader() -> ShaderRef {
+        NORMALS_SHADER_HANDLE.into()
+    }
+
+    fn specialize(
+        _pipeline: &MaterialExtensionPipeline,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayoutRef,
+        _key: MaterialExtensionKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        // The normals are the output, they shouldn't be blended with what's behind
+        if let Some(fragment) = &mut descriptor.fragment {
+            for target in fragment.targets.iter_mut().flatten() {
+                target.blend = None;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub type CascadesMaterial = ExtendedMaterial<StandardMaterial, CascadesExtension>;
+
+/// Tints a lit `StandardMaterial` by the sun shadow cascade it samples.
+#[derive(Asset, AsBindGroup, Reflect, Debug, Clone)]
+pub struct CascadesExtension {}
+
+impl MaterialExtension for CascadesExtension {
+    fn fragment_shader() -> ShaderRef {
+        CASCADES_SHADER_HANDLE.into()
+    }
+
+    fn specialize(
+        _pipeline: &MaterialExtensionPipeline,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayoutRef,
+        _key: MaterialExtensionKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        // Glass and liquids get a solid color too, otherwise what's behind them shows through
+        if let Some(fragment) = &mut descriptor.fragment {
+            for target in fragment.targets.iter_mut().flatten() {
+                target.blend = None;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Adds a little color for every triangle covering a pixel, whether it's hidden or not.
+#[derive(Asset, AsBindGroup, TypePath, Debug, Clone, Default)]
+pub struct OverdrawMaterial {}
+
+impl Material for OverdrawMaterial {
+    fn fragment_shader() -> ShaderRef {
+        OVERDRAW_SHADER_HANDLE.into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Add
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayoutRef,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        if let Some(depth_stencil) = &mut descriptor.depth_stencil {
+            depth_stencil.depth_write_enabled = false;
+            depth_stencil.depth_compare = CompareFunction::Always;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum MaterialView {
+    #[default]
+    Off,
+    Normals,
+    Overdraw,
+    Cascades,
+}
+
+/// F6 toggles the wireframe, F7 the normals, F8 the overdraw and F11 the shadow cascade view. The
+/// wireframe is drawn on top of any material view.
+#[derive(Resource, Default)]
+pub struct DebugView {
+    pub wireframe: bool,
+    pub material: MaterialView,
+}
+
+/// A mesh whose material was swapped for a debug view, restored when the view is turned off.
+#[derive(Component)]
+pub struct DebugViewOriginal(pub Handle<StandardMaterial>);
+
+/// The materials of the views, the overdraw one doesn't depend on the original material.
+#[derive(Resource, Default)]
+struct DebugMaterials {
+    normals: ExtendedMaterials<NormalsExtension>,
+    overdraw: Option<Handle<OverdrawMaterial>>,
+    cascades: ExtendedMaterials<CascadesExtension>,
+}
+
+pub struct DebugViewPlugin;
+
+impl Plugin for DebugViewPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            NORMALS_SHADER_HANDLE,
+            "debug_normals.wgsl",
+            Shader::from_wgsl
+        );
+        load_internal_asset!(
+            app,
+            OVERDRAW_SHADER_HANDLE,
+            "debug_overdraw.wgsl",
+            Shader::from_wgsl
+        );
+        load_internal_asset!(
+            app,
+            CASCADES_SHADER_HANDLE,
+            "debug_cascades.wgsl",
+            Shader::from_wgsl
+        );
+        app.add_plugins((
+            WireframePlugin,
+            MaterialPlugin::<NormalsMaterial>::default(),
+            MaterialPlugin::<CascadesMaterial>::default(),
+            MaterialPlugin::<OverdrawMaterial> {
+                prepass_enabled: false,
+                shadows_enabled: false,
+                ..default()
+            },
+        ))
+        .init_resource::<DebugView>()
+        .init_resource::<DebugMaterials>()
+        .init_resource::<KeyBindings>()
+        .add_systems(Startup, register_keys)
+        .add_systems(
+            Update,
+            (
+                debug_view_input,
+                swap_debug_materials.after(debug_view_input),
+                report_cascades.after(debug_view_input),
+            ),
+        );
+    }
+}
+
+fn register_keys(mut bindings: ResMut<KeyBindings>) {
+    bindings
+        .add("Debug", "F6", "Toggle the wireframe")
+        .add("Debug", "F7", "Toggle the normals view")
+        .add("Debug", "F8", "Toggle the overdraw view")
+        .add("Debug", "F11", "Toggle the shadow cascade view");
+}
+
+fn debug_view_input(
+    input: Res<ButtonInput<KeyCode>>,
+    mut debug_view: ResMut<DebugView>,
+    mut wireframe: ResMut<WireframeConfig>,
+    render_device: Option<Res<RenderDevice>>,
+) {
+    if input.just_pressed(KeyCode::F6) {
+        // Drawing lines needs a native backend, WebGPU and some mobile GPUs don't have it
+        let supported = render_device
+            .is_some_and(|device| device.features().contains(WgpuFeatures::POLYGON_MODE_LINE));
+        if supported {
+            debug_view.wireframe = !debug_view.wireframe;
+            wireframe.global = debug_view.wireframe;
+            println!(
+                "Wireframe {}",
+                if debug_view.wireframe { "on" } else { "off" }
+            );
+        } else {
+            println!("Wireframe isn't supported by this GPU");
+        }
+    }
+    for (key, view) in [
+        (KeyCode::F7, MaterialView::Normals),
+        (KeyCode::F8, MaterialView::Overdraw),
+        (KeyCode::F11, MaterialView::Cascades),
+    ] {
+        if input.just_pressed(key) {
+            debug_view.material = if debug_view.material == view {
+                MaterialView::Off
+            } else {
+                view
+            };
+            println!("Debug view: {:?}", debug_view.material);
+        }
+    }
+}
+
+/// Swaps the materials when the view changes, and those of meshes spawned while a view is on.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn swap_debug_materials(
+    mut commands: Commands,
+    debug_view: Res<DebugView>,
+    mut current: Local<MaterialView>,
+    unswapped: Query<(Entity, &Handle<StandardMaterial>), Without<DebugViewOriginal>>,
+    swapped: Query<(Entity, &DebugViewOriginal)>,
+    standard_materials: Res<Assets<StandardMaterial>>,
+    mut normals_materials: ResMut<Assets<NormalsMaterial>>,
+    mut overdraw_materials: ResMut<Assets<OverdrawMaterial>>,
+    mut cascades_materials: ResMut<Assets<CascadesMaterial>>,
+    mut debug_materials: ResMut<DebugMaterials>,
+) {
+    let view = debug_view.material;
+    let changed = *current != view;
+    *current = view;
+    if changed {
+        for (entity, original) in &swapped {
+            commands
+                .entity(entity)
+                .remove::<(
+                    DebugViewOriginal,
+                    Handle<NormalsMaterial>,
+                    Handle<OverdrawMaterial>,
+                    Handle<CascadesMaterial>,
+                )>()
+                .insert(original.0.clone());
+        }
+    }
+    if view == MaterialView::Off {
+        return;
+    }
+    let DebugMaterials {
+        normals,
+        overdraw,
+        cascades,
+    } = &mut *debug_materials;
+    if changed {
+        // Made again from the current originals, they may have changed since the last time
+        normals.clear();
+        cascades.clear();
+    }
+    let originals = unswapped.iter().chain(
+        swapped
+            .iter()
+            .filter(|_| changed)
+            .map(|(entity, original)| (entity, &original.0)),
+    );
+    for (entity, material_h) in originals {
+        let mut entity = commands.entity(entity);
+        match view {
+            MaterialView::Normals => {
+                let Some(material) = standard_materials.get(material_h) else {
+                    continue;
+                };
+                let normals_h =
+                    normals.get_or_add(material_h.id(), material, &mut normals_materials, || {
+                        NormalsExtension {}
+                    });
+                entity.insert(normals_h);
+            }
+            MaterialView::Overdraw => {
+                let overdraw_h = overdraw
+                    .get_or_insert_with(|| overdraw_materials.add(OverdrawMaterial::default()));
+                entity.insert(overdraw_h.clone());
+            }
+            MaterialView::Cascades => {
+                let Some(material) = standard_materials.get(material_h) else {
+                    continue;
+                };
+                let cascades_h =
+                    cascades.get_or_add(material_h.id(), material, &mut cascades_materials, || {
+                        CascadesExtension {}
+                    });
+                entity.insert(cascades_h);
+            }
+            MaterialView::Off => unreachable!(),
+        }
+        entity
+            .remove::<Handle<StandardMaterial>>()
+            .insert(DebugViewOriginal(material_h.clone()));
+    }
+}
+
+/// Prints the sun's cascade bounds with their colors while the cascade view is on, again whenever
+/// they change, e.g. once `--fit-camera` sized them to the scene. The arguments to get the same
+/// cascades on the next run are printed with them.
+fn report_cascades(
+    debug_view: Res<DebugView>,
+    sun: Query<(&DirectionalLight, &CascadeShadowConfig), With<GrifLight>>,
+    mut reported: Local<Option<(bool, Vec<f32>)>>,
+) {
+    if debug_view.material != MaterialView::Cascades {
+        *reported = None;
+        return;
+    }
+    let Some((light, config)) = sun.iter().next() else {
+        return;
+    };
+    let current = (light.shadows_enabled, config.bounds.clone());
+    if reported.as_ref() == Some(&current) {
+        return;
+    }
+    if !light.shadows_enabled {
+        println!("Sun shadows are off, everything is past the last cascade (grey)");
+    }
+    let mut near = config.minimum_distance;
+    for (bound, color) in config.bounds.iter().zip(CASCADE_COLORS) {
+        println!("Cascade {color}: {near:.1}m to {bound:.1}m");
+        near = *bound;
+    }
+    println!("Past {near:.1}m: grey, no shadows");
+    if let (Some(first), Some(last)) = (config.bounds.first(), config.bounds.last()) {
+        println!(
+            "--cascades {} --shadow-distance {last:.1} --first-cascade-bound {first:.1}",
+            config.bounds.len()
+        );
+    }
+    *reported = Some(current);
+}
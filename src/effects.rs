@@ -0,0 +1,132 @@
+use bevy::{
+    core_pipeline::{
+        bloom::BloomSettings,
+        experimental::taa::{TemporalAntiAliasBundle, TemporalAntiAliasSettings},
+        prepass::{DepthPrepass, MotionVectorPrepass, NormalPrepass},
+    },
+    ecs::system::EntityCommands,
+    pbr::{ScreenSpaceAmbientOcclusionBundle, ScreenSpaceAmbientOcclusionSettings},
+    prelude::*,
+    render::camera::TemporalJitter,
+};
+
+use crate::{help::KeyBindings, msaa_compat::MsaaConflict, GrifLight, MainCamera};
+
+pub const BLOOM_INTENSITY: f32 = 0.02;
+
+// Shared by the keys, the session restore and the benchmark matrix. Turning an effect off leaves
+// the prepasses, the other effect may still need them, see `remove_prepasses`.
+
+pub fn set_bloom(camera: &mut EntityCommands, on: bool) {
+    if on {
+        camera.insert(BloomSettings {
+            intensity: BLOOM_INTENSITY,
+            ..default()
+        });
+    } else {
+        camera.remove::<BloomSettings>();
+    }
+}
+
+/// Errors without changing anything if SSAO can't be turned on with `msaa`.
+pub fn set_ssao(camera: &mut EntityCommands, on: bool, msaa: Msaa) -> Result<(), String> {
+    if on {
+        MsaaConflict::Ssao.allowed_with(msaa)?;
+        camera.insert(ScreenSpaceAmbientOcclusionBundle::default());
+    } else {
+        camera.remove::<ScreenSpaceAmbientOcclusionSettings>();
+    }
+    Ok(())
+}
+
+/// Errors without changing anything if TAA can't be turned on with `msaa`. The inserted settings
+/// start with `reset`, so the history from before it was turned off isn't blended in.
+pub fn set_taa(camera: &mut EntityCommands, on: bool, msaa: Msaa) -> Result<(), String> {
+    if on {
+        MsaaConflict::Taa.allowed_with(msaa)?;
+        camera.insert(TemporalAntiAliasBundle::default());
+    } else {
+        camera.remove::<(TemporalAntiAliasSettings, TemporalJitter)>();
+    }
+    Ok(())
+}
+
+pub fn set_shadows<'a>(sun: impl Iterator<Item = Mut<'a, DirectionalLight>>, on: bool) {
+    for mut light in sun {
+        light.shadows_enabled = on;
+    }
+}
+
+/// The prepasses SSAO and TAA added, once neither of them is on.
+pub fn remove_prepasses(camera: &mut EntityCommands) {
+    camera.remove::<(DepthPrepass, NormalPrepass, MotionVectorPrepass)>();
+}
+
+fn on_off(on: bool) -> &'static str {
+    if on {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+pub fn register_keys(mut bindings: ResMut<KeyBindings>) {
+    bindings
+        .add("Effects", "F1", "Toggle the sun shadows")
+        .add("Effects", "F2", "Toggle SSAO")
+        .add("Effects", "F3", "Toggle TAA")
+        .add("Effects", "F4", "Toggle bloom");
+}
+
+// F1: sun shadows, F2: SSAO, F3: TAA, F4: bloom
+#[allow(clippy::type_complexity)]
+pub fn effect_toggle_input(
+    mut commands: Commands,
+    input: Res<ButtonInput<KeyCode>>,
+    cameras: Query<
+        (
+            Entity,
+            Has<BloomSettings>,
+            Has<ScreenSpaceAmbientOcclusionSettings>,
+            Has<TemporalAntiAliasSettings>,
+        ),
+        With<MainCamera>,
+    >,
+    mut sun: Query<&mut DirectionalLight, With<GrifLight>>,
+    msaa: Res<Msaa>,
+) {
+    if input.just_pressed(KeyCode::F1) {
+        let on = !sun.iter().any(|light| light.shadows_enabled);
+        set_shadows(sun.iter_mut(), on);
+        println!("Sun shadows {}", on_off(on));
+    }
+    for (entity, bloom, ssao, taa) in &cameras {
+        let mut camera = commands.entity(entity);
+        let (mut ssao_after, mut taa_after) = (ssao, taa);
+        if input.just_pressed(KeyCode::F2) {
+            match set_ssao(&mut camera, !ssao, *msaa) {
+                Ok(()) => {
+                    ssao_after = !ssao;
+                    println!("SSAO {}", on_off(ssao_after));
+                }
+                Err(e) => println!("{e}"),
+            }
+        }
+        if input.just_pressed(KeyCode::F3) {
+            match set_taa(&mut camera, !taa, *msaa) {
+                Ok(()) => {
+                    taa_after = !taa;
+                    println!("TAA {}", on_off(taa_after));
+                }
+                Err(e) => println!("{e}"),
+            }
+        }
+        if (ssao || taa) && !ssao_after && !taa_after {
+            remove_prepasses(&mut camera);
+        }
+        if input.just_pressed(KeyCode::F4) {
+            set_bloom(&mut camera, !bloom);
+            println!("Bloom {}", on_off(!bloom));
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use std::{collections::BTreeSet, path::Path};
+
+use bevy::prelude::*;
+
+use crate::archive::Archive;
+
+pub const ENVIRONMENT_MAPS_DIR: &str = "environment_maps";
+pub const DEFAULT_ENVIRONMENT_MAP: &str = "san_giuseppe_bridge_4k";
+
+/// A diffuse/specular pair from `environment_maps/`, paths relative to the assets directory.
+#[derive(Clone, Debug)]
+pub struct EnvironmentMapPair {
+    pub name: String,
+    pub diffuse: String,
+    pub specular: String,
+}
+
+/// The environment maps found in the assets directory or archive and the one the cameras use, N cycles
+/// through them.
+#[derive(Resource, Clone, Debug)]
+pub struct EnvironmentMaps {
+    pub available: Vec<EnvironmentMapPair>,
+    pub current: usize,
+    pub intensity: f32,
+}
+
+impl EnvironmentMaps {
+    /// Finds the pairs in `<assets_dir>/environment_maps`, and in `environment_maps/` of the
+    /// `--assets-archive`, and selects `name`. A pair is a `<name>_diffuse*.ktx2` file with a
+    /// matching `<name>_specular*.ktx2` next to it.
+    pub fn find(
+        assets_dir: &Path,
+        archive: Option<&Archive>,
+        name: &str,
+        intensity: f32,
+    ) -> Result<Self, String> {
+        let mut files = BTreeSet::new();
+        if let Ok(entries) = std::fs::read_dir(assets_dir.join(ENVIRONMENT_MAPS_DIR)) {
+            files.extend(
+                entries
+                    .flatten()
+                    .map(|entry| entry.file_name().to_string_lossy().to_string()),
+            );
+        }
+        if let Some(archive) = archive {
+            files.extend(
+                archive
+                    .files_in(Path::new(ENVIRONMENT_MAPS_DIR))
+                    .iter()
+                    .filter_map(|path| path.file_name())
+                    .map(|file_name| file_name.to_string_lossy().to_string()),
+            );
+        }
+        let mut available = Vec::new();
+        for file_name in &files {
+            let Some((name, _)) = file_name.split_once("_diffuse") else {
+                continue;
+            };
+            if !file_name.ends_with(".ktx2") {
+                continue;
+            }
+            let specular = file_name.replacen("_diffuse", "_specular", 1);
+            if !files.contains(&specular) {
+                println!("Ignoring environment map {file_name}, {specular} is missing");
+                continue;
+            }
+            available.push(EnvironmentMapPair {
+                name: name.to_string(),
+                diffuse: format!("{ENVIRONMENT_MAPS_DIR}/{file_name}"),
+                specular: format!("{ENVIRONMENT_MAPS_DIR}/{specular}"),
+            });
+        }
+        available.sort_by(|a, b| a.name.cmp(&b.name));
+        let Some(current) = available.iter().position(|pair| pair.name == name) else {
+            let names = available
+                .iter()
+                .map(|pair| pair.name.as_str())
+                .collect::<Vec<_>>();
+            return Err(format!(
+                "Environment map {name} not found, expected {ENVIRONMENT_MAPS_DIR}/{name}_diffuse.ktx2 \
+                and {ENVIRONMENT_MAPS_DIR}/{name}_specular.ktx2 in {:?}{}\n\
+                Available: {}",
+                assets_dir,
+                if archive.is_some() {
+                    " or the assets archive"
+                } else {
+                    ""
+                },
+                if names.is_empty() {
+                    "none".to_string()
+                } else {
+                    names.join(", ")
+                }
+            ));
+        };
+        Ok(Self {
+            available,
+            current,
+            intensity,
+        })
+    }
+
+    pub fn current(&self) -> &EnvironmentMapPair {
+        &self.available[self.current]
+    }
+
+    pub fn light(&self, asset_server: &AssetServer) -> EnvironmentMapLight {
+        let pair = self.current();
+        EnvironmentMapLight {
+            diffuse_map: asset_server.load(&pair.diffuse),
+            specular_map: asset_server.load(&pair.specular),
+            intensity: self.intensity,
+        }
+    }
+}
+
+// N: next environment map, Shift+N: previous
+pub fn environment_map_input(
+    input: Res<ButtonInput<KeyCode>>,
+    asset_server: Res<AssetServer>,
+    mut environment_maps: ResMut<EnvironmentMaps>,
+    mut cameras: Query<&mut EnvironmentMapLight>,
+) {
+    if !input.just_pressed(KeyCode::KeyN) || environment_maps.available.len() < 2 {
+        return;
+    }
+    let count = environment_maps.available.len();
+    environment_maps.current = if input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        (environment_maps.current + count - 1) % count
+    } else {
+        (environment_maps.current + 1) % count
+    };
+    println!("Environment map: {}", environment_maps.current().name);
+    for mut light in &mut cameras {
+        *light = environment_maps.light(&asset_server);
+    }
+}
@@ -0,0 +1,50 @@
+use bevy::{
+    pbr::{ExtendedMaterial, MaterialExtension},
+    prelude::*,
+    utils::HashMap,
+};
+
+/// The `ExtendedMaterial`s that replaced the `StandardMaterial` of some meshes, by the id of the
+/// material they were made from. Meshes that shared a material still share one after the swap.
+#[derive(Resource)]
+pub struct ExtendedMaterials<E: MaterialExtension>(
+    HashMap<AssetId<StandardMaterial>, Handle<ExtendedMaterial<StandardMaterial, E>>>,
+);
+
+impl<E: MaterialExtension> Default for ExtendedMaterials<E> {
+    fn default() -> Self {
+        Self(HashMap::new())
+    }
+}
+
+impl<E: MaterialExtension> ExtendedMaterials<E> {
+    /// The material made from `original`, made with `extension` the first time.
+    pub fn get_or_add(
+        &mut self,
+        id: AssetId<StandardMaterial>,
+        original: &StandardMaterial,
+        materials: &mut Assets<ExtendedMaterial<StandardMaterial, E>>,
+        extension: impl FnOnce() -> E,
+    ) -> Handle<ExtendedMaterial<StandardMaterial, E>> {
+        self.0
+            .entry(id)
+            .or_insert_with(|| {
+                materials.add(ExtendedMaterial {
+                    base: original.clone(),
+                    extension: extension(),
+                })
+            })
+            .clone()
+    }
+
+    pub fn get(
+        &self,
+        id: AssetId<StandardMaterial>,
+    ) -> Option<&Handle<ExtendedMaterial<StandardMaterial, E>>> {
+        self.0.get(&id)
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
@@ -0,0 +1,254 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+
+const MAGIC: &[u8; 4] = b"glTF";
+const CHUNK_JSON: u32 = 0x4e4f_534a;
+const CHUNK_BIN: u32 = 0x004e_4942;
+
+/// A binary glTF split into its JSON and (optional) BIN chunk.
+pub struct Glb {
+    pub json: Value,
+    pub bin: Option<Vec<u8>>,
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().unwrap(),
+    ))
+}
+
+pub fn is_glb(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+impl Glb {
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        if !is_glb(bytes) || u32_at(bytes, 4) != Some(2) {
+            return Err("not a version 2 binary glTF".to_string());
+        }
+        let len = (u32_at(bytes, 8).ok_or("truncated header")? as usize).min(bytes.len());
+        let mut json = None;
+        let mut bin = None;
+        let mut offset = 12;
+        while let (Some(chunk_len), Some(chunk_type)) =
+            (u32_at(bytes, offset), u32_at(bytes, offset + 4))
+        {
+            let data = bytes
+                .get(offset + 8..offset + 8 + chunk_len as usize)
+                .filter(|_| offset + 8 + chunk_len as usize <= len)
+                .ok_or("chunk runs past the end of the file")?;
+            match chunk_type {
+                CHUNK_JSON => {
+                    json = Some(serde_json::from_slice(data).map_err(|e| e.to_string())?);
+                }
+                // Only the first BIN chunk is the buffer, later unknown chunks are ignored
+                CHUNK_BIN if bin.is_none() => bin = Some(data.to_vec()),
+                _ => (),
+            }
+            offset += 8 + chunk_len as usize;
+        }
+        Ok(Glb {
+            json: json.ok_or("no JSON chunk")?,
+            bin,
+        })
+    }
+
+    /// Chunks are padded to 4 bytes, the JSON with spaces and the BIN with zeros.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut json = serde_json::to_vec(&self.json).unwrap();
+        json.resize(json.len().next_multiple_of(4), b' ');
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&2u32.to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes());
+        let chunks = [(CHUNK_JSON, Some(json)), (CHUNK_BIN, self.bin.clone())];
+        for (chunk_type, data) in chunks {
+            let Some(mut data) = data else {
+                continue;
+            };
+            data.resize(data.len().next_multiple_of(4), 0);
+            out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            out.extend_from_slice(&chunk_type.to_le_bytes());
+            out.extend_from_slice(&data);
+        }
+        let len = out.len() as u32;
+        out[8..12].copy_from_slice(&len.to_le_bytes());
+        out
+    }
+}
+
+/// The JSON of a .gltf or .glb, told apart by the binary glTF magic rather than the extension.
+pub fn read_gltf_json(path: &Path) -> Result<Value, String> {
+    let bytes = fs::read(path).map_err(|e| format!("{:?}: {e}", path))?;
+    if is_glb(&bytes) {
+        Glb::parse(&bytes)
+            .map(|glb| glb.json)
+            .map_err(|e| format!("{:?}: {e}", path))
+    } else {
+        serde_json::from_slice(&bytes).map_err(|e| format!("{:?}: {e}", path))
+    }
+}
+
+/// Replaces the JSON of a .gltf, or the JSON chunk of a .glb keeping its BIN chunk as it was.
+pub fn write_gltf_json(path: &Path, json: &Value) -> Result<(), String> {
+    let bytes = fs::read(path).map_err(|e| format!("{:?}: {e}", path))?;
+    let out = if is_glb(&bytes) {
+        let mut glb = Glb::parse(&bytes).map_err(|e| format!("{:?}: {e}", path))?;
+        glb.json = json.clone();
+        glb.to_bytes()
+    } else {
+        serde_json::to_vec(json).unwrap()
+    };
+    fs::write(path, out).map_err(|e| format!("{:?}: {e}", path))
+}
+
+fn embedded_extension(mime_type: &str) -> Option<&'static str> {
+    match mime_type {
+        "image/png" => Some("png"),
+        "image/jpeg" => Some("jpg"),
+        _ => None,
+    }
+}
+
+/// The images stored in the BIN chunk of a .glb, as (image index, file they'd be extracted to).
+/// Named after the glb and the image name or index, next to the glb. The bufferView images of a
+/// .gltf, and of a .glb with an external buffer, are in a .bin file and skipped like data: URIs.
+pub fn embedded_images(path: &Path) -> Result<Vec<(usize, PathBuf)>, String> {
+    let bytes = fs::read(path).map_err(|e| format!("{:?}: {e}", path))?;
+    if !is_glb(&bytes) {
+        return Ok(Vec::new());
+    }
+    let json = Glb::parse(&bytes)
+        .map_err(|e| format!("{:?}: {e}", path))?
+        .json;
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let base = path.parent().unwrap_or(Path::new("."));
+    let images = json["images"].as_array().into_iter().flatten().enumerate();
+    Ok(images
+        .filter(|(i, image)| {
+            let Some(view) = image["bufferView"].as_u64() else {
+                return false;
+            };
+            // Only the first buffer without a URI is the BIN chunk
+            let buffer = json["bufferViews"][view as usize]["buffer"]
+                .as_u64()
+                .unwrap_or(0);
+            let in_bin = buffer == 0 && json["buffers"][0]["uri"].is_null();
+            if !in_bin {
+                println!("{:?}: skipping image {i}, it isn't in the BIN chunk", path);
+            }
+            in_bin
+        })
+        .filter_map(|(i, image)| {
+            let extension = embedded_extension(image["mimeType"].as_str()?)?;
+            // The name may be anything or shared by several images, only its safe characters are
+            // kept after the index
+            let name = image["name"]
+                .as_str()
+                .map(|name| name.replace(|c: char| !c.is_alphanumeric() && c != '_', "_"))
+                .map(|name| format!("_{name}"))
+                .unwrap_or_default();
+            Some((i, base.join(format!("{stem}_{i}{name}.{extension}"))))
+        })
+        .collect())
+}
+
+/// Writes the embedded png and jpg images of a .glb next to it and points the images at those
+/// files, so they are converted and rewritten like the images of a .gltf. Their bytes stay in
+/// the BIN chunk, only the references change. Returns the extracted files.
+pub fn extract_embedded_images(path: &Path) -> Result<Vec<PathBuf>, String> {
+    let extracted = embedded_images(path)?;
+    if extracted.is_empty() {
+        return Ok(Vec::new());
+    }
+    let bytes = fs::read(path).map_err(|e| format!("{:?}: {e}", path))?;
+    let mut glb = Glb::parse(&bytes).map_err(|e| format!("{:?}: {e}", path))?;
+    let bin = glb.bin.as_deref().unwrap_or_default();
+    for (i, file) in &extracted {
+        let view = glb.json["images"][*i]["bufferView"].as_u64().unwrap() as usize;
+        let view = &glb.json["bufferViews"][view];
+        let offset = view["byteOffset"].as_u64().unwrap_or(0) as usize;
+        let len = view["byteLength"].as_u64().unwrap_or(0) as usize;
+        let data = bin
+            .get(offset..offset + len)
+            .ok_or_else(|| format!("{:?}: image {i} runs past the BIN chunk", path))?;
+        fs::write(file, data).map_err(|e| format!("{:?}: {e}", file))?;
+        let image = glb.json["images"][*i].as_object_mut().unwrap();
+        image.remove("bufferView");
+        image.remove("mimeType");
+        let uri = file.file_name().unwrap().to_string_lossy();
+        image.insert(
+            "uri".to_string(),
+            percent_encoding::utf8_percent_encode(&uri, crate::convert::URI_PATH)
+                .to_string()
+                .into(),
+        );
+    }
+    fs::write(path, glb.to_bytes()).map_err(|e| format!("{:?}: {e}", path))?;
+    Ok(extracted.into_iter().map(|(_, file)| file).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_dir::TempDir;
+
+    fn image_json(buffer: Value) -> Value {
+        json!({
+            "images": [{ "bufferView": 0, "mimeType": "image/png", "name": "Leaf" }],
+            "bufferViews": [{ "buffer": 0, "byteOffset": 0, "byteLength": 4 }],
+            "buffers": [buffer]
+        })
+    }
+
+    #[test]
+    fn extracts_bin_chunk_images() {
+        let dir = TempDir::new("glb_bin");
+        let glb = dir.join("scene.glb");
+        let bytes = Glb {
+            json: image_json(json!({ "byteLength": 4 })),
+            bin: Some(b"\x89PNG".to_vec()),
+        };
+        fs::write(&glb, bytes.to_bytes()).unwrap();
+        let extracted = extract_embedded_images(&glb).unwrap();
+        assert_eq!(extracted, [dir.join("scene_0_Leaf.png")]);
+        assert_eq!(fs::read(&extracted[0]).unwrap(), b"\x89PNG");
+        let json = read_gltf_json(&glb).unwrap();
+        assert_eq!(json["images"][0]["uri"], "scene_0_Leaf.png");
+        assert!(json["images"][0].get("bufferView").is_none());
+    }
+
+    #[test]
+    fn gltf_buffer_view_images_are_skipped() {
+        let dir = TempDir::new("glb_gltf");
+        let gltf = dir.join("scene.gltf");
+        let json = image_json(json!({ "uri": "scene.bin", "byteLength": 4 }));
+        fs::write(&gltf, json.to_string()).unwrap();
+        fs::write(dir.join("scene.bin"), b"\x89PNG").unwrap();
+        assert!(embedded_images(&gltf).unwrap().is_empty());
+        assert!(extract_embedded_images(&gltf).unwrap().is_empty());
+        assert_eq!(read_gltf_json(&gltf).unwrap(), json);
+    }
+
+    #[test]
+    fn glb_external_buffer_images_are_skipped() {
+        let dir = TempDir::new("glb_external");
+        let glb = dir.join("scene.glb");
+        let bytes = Glb {
+            json: image_json(json!({ "uri": "scene.bin", "byteLength": 4 })),
+            bin: None,
+        }
+        .to_bytes();
+        fs::write(&glb, &bytes).unwrap();
+        fs::write(dir.join("scene.bin"), b"\x89PNG").unwrap();
+        assert!(embedded_images(&glb).unwrap().is_empty());
+        assert!(extract_embedded_images(&glb).unwrap().is_empty());
+        assert_eq!(fs::read(&glb).unwrap(), bytes);
+    }
+}
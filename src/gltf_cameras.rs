@@ -0,0 +1,94 @@
+use bevy::prelude::*;
+
+use crate::{camera_controller::CameraController, help::KeyBindings, CameraTransition, MainCamera};
+
+/// Cameras `proc_scene` never despawns, like `GrifLight` for lights. The app's own cameras have
+/// it, add it to a camera placed under a scene to keep it without `--keep-gltf-cameras`.
+#[derive(Component)]
+pub struct KeepCamera;
+
+/// A camera that came with a glTF, kept with `--keep-gltf-cameras`. Inactive, V moves the main
+/// camera to its view instead so the effects set up on the main camera still apply.
+#[derive(Component)]
+pub struct GltfCamera;
+
+/// The main camera's view before V first moved it, and which glTF camera it's at.
+#[derive(Default)]
+pub struct CameraCycle {
+    free_view: Option<(Transform, Projection)>,
+    current: usize,
+}
+
+pub fn register_keys(mut bindings: ResMut<KeyBindings>) {
+    bindings.add("Views", "V", "Move to the next glTF camera, then back");
+}
+
+// V: move the main camera to the next glTF camera, after the last one back to where it was
+#[allow(clippy::type_complexity)]
+pub fn cycle_gltf_cameras(
+    mut commands: Commands,
+    input: Res<ButtonInput<KeyCode>>,
+    gltf_cameras: Query<(Entity, &GlobalTransform, &Projection, Option<&Name>), With<GltfCamera>>,
+    mut camera: Query<
+        (
+            Entity,
+            &mut Transform,
+            &mut Projection,
+            Option<&mut CameraController>,
+        ),
+        (With<MainCamera>, Without<GltfCamera>),
+    >,
+    mut cycle: Local<CameraCycle>,
+) {
+    if !input.just_pressed(KeyCode::KeyV) {
+        return;
+    }
+    if gltf_cameras.is_empty() {
+        println!("No glTF cameras, keep them with --keep-gltf-cameras");
+        return;
+    }
+    let Ok((entity, mut transform, mut projection, controller)) = camera.get_single_mut() else {
+        return;
+    };
+    // Sorted so the order doesn't change between presses
+    let mut views = gltf_cameras.iter().collect::<Vec<_>>();
+    views.sort_by_key(|(entity, ..)| *entity);
+    commands.entity(entity).remove::<CameraTransition>();
+
+    let (free_transform, free_projection) = cycle
+        .free_view
+        .get_or_insert_with(|| (*transform, projection.clone()))
+        .clone();
+    let (target, target_projection) = if cycle.current < views.len() {
+        let (gltf_entity, global_transform, gltf_projection, name) = views[cycle.current];
+        println!(
+            "glTF camera {}/{}: {}",
+            cycle.current + 1,
+            views.len(),
+            name.map_or_else(|| gltf_entity.to_string(), |name| name.to_string())
+        );
+        let mut target_projection = free_projection;
+        if let (Projection::Perspective(target), Projection::Perspective(gltf)) =
+            (&mut target_projection, gltf_projection)
+        {
+            target.fov = gltf.fov;
+        }
+        cycle.current += 1;
+        (global_transform.compute_transform(), target_projection)
+    } else {
+        println!("Back to the free camera");
+        cycle.free_view = None;
+        cycle.current = 0;
+        (free_transform, free_projection)
+    };
+    transform.translation = target.translation;
+    *projection = target_projection;
+    match controller {
+        // The controller has no roll, only the direction is kept
+        Some(mut controller) => {
+            let look = target.translation + target.forward() * 1.0;
+            controller.look_at(&mut transform, look);
+        }
+        None => transform.rotation = target.rotation,
+    }
+}
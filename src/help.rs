@@ -0,0 +1,177 @@
+use bevy::prelude::*;
+
+use crate::{BenchmarkRunning, MainCamera};
+
+/// How long the help is shown at startup.
+const STARTUP_SECONDS: f32 = 8.0;
+const FONT_SIZE: f32 = 15.0;
+const CATEGORY_COLOR: Color = Color::srgb(1.0, 0.8, 0.3);
+/// The order of the categories, others come after them.
+const CATEGORIES: [&str; 7] = [
+    "Camera", "Views", "Effects", "Lighting", "Debug", "Tools", "Help",
+];
+
+pub struct KeyBinding {
+    pub category: &'static str,
+    pub key: String,
+    pub description: String,
+}
+
+/// Every key binding in use, for the H help overlay. Systems add theirs at startup, only when
+/// they are added themselves, so the help lists what actually works in this run.
+#[derive(Resource, Default)]
+pub struct KeyBindings(pub Vec<KeyBinding>);
+
+impl KeyBindings {
+    pub fn add(
+        &mut self,
+        category: &'static str,
+        key: impl Into<String>,
+        description: impl Into<String>,
+    ) -> &mut Self {
+        self.0.push(KeyBinding {
+            category,
+            key: key.into(),
+            description: description.into(),
+        });
+        self
+    }
+}
+
+/// `KeyW` as W and `Digit1` as 1.
+pub fn key_name(key: KeyCode) -> String {
+    let name = format!("{key:?}");
+    name.strip_prefix("Key")
+        .or_else(|| name.strip_prefix("Digit"))
+        .unwrap_or(&name)
+        .to_string()
+}
+
+pub fn register_keys(mut bindings: ResMut<KeyBindings>) {
+    bindings.add("Help", "H", "Show or hide this help");
+}
+
+#[derive(Component)]
+pub struct HelpOverlay;
+
+pub fn spawn_help(mut commands: Commands, camera: Query<Entity, With<MainCamera>>) {
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(8.0),
+                left: Val::Px(8.0),
+                padding: UiRect::all(Val::Px(10.0)),
+                column_gap: Val::Px(24.0),
+                ..default()
+            },
+            // Dark enough to read over the bright sky
+            background_color: Color::srgba(0.0, 0.0, 0.0, 0.75).into(),
+            ..default()
+        },
+        TargetCamera(camera),
+        HelpOverlay,
+    ));
+}
+
+/// The bindings grouped by category, split into two columns so the list fits on the screen.
+fn help_columns(bindings: &KeyBindings) -> [Vec<TextSection>; 2] {
+    let mut categories = Vec::<(&str, String, usize)>::new();
+    for binding in &bindings.0 {
+        let line = format!("  {} - {}\n", binding.key, binding.description);
+        match categories
+            .iter_mut()
+            .find(|(category, ..)| *category == binding.category)
+        {
+            Some((_, lines, count)) => {
+                lines.push_str(&line);
+                *count += 1;
+            }
+            None => categories.push((binding.category, line, 1)),
+        }
+    }
+    // Stable, the bindings of a category stay in the order they were added
+    categories.sort_by_key(|(category, ..)| {
+        CATEGORIES
+            .iter()
+            .position(|known| known == category)
+            .unwrap_or(CATEGORIES.len())
+    });
+    let total = categories
+        .iter()
+        .map(|(.., count)| count + 2)
+        .sum::<usize>();
+    let mut columns = [Vec::new(), Vec::new()];
+    let mut first_column_lines = 0;
+    for (category, lines, count) in categories {
+        let column = usize::from(first_column_lines >= total / 2);
+        if column == 0 {
+            first_column_lines += count + 2;
+        }
+        let sections = &mut columns[column];
+        sections.push(TextSection::new(
+            format!(
+                "{}{category}\n",
+                if sections.is_empty() { "" } else { "\n" }
+            ),
+            TextStyle {
+                font_size: FONT_SIZE + 2.0,
+                color: CATEGORY_COLOR,
+                ..default()
+            },
+        ));
+        sections.push(TextSection::new(
+            lines,
+            TextStyle {
+                font_size: FONT_SIZE,
+                color: Color::WHITE,
+                ..default()
+            },
+        ));
+    }
+    columns
+}
+
+// H: show or hide the key bindings. Shown for the first seconds after startup, and hidden while
+// the benchmark runs.
+#[allow(clippy::too_many_arguments)]
+pub fn update_help(
+    mut commands: Commands,
+    input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    running: Res<BenchmarkRunning>,
+    time: Res<Time>,
+    mut help: Query<(Entity, &mut Visibility), With<HelpOverlay>>,
+    mut hidden: Local<bool>,
+    mut startup_over: Local<bool>,
+) {
+    let Ok((entity, mut visibility)) = help.get_single_mut() else {
+        return;
+    };
+    if bindings.is_changed() {
+        let columns = help_columns(&bindings);
+        commands
+            .entity(entity)
+            .despawn_descendants()
+            .with_children(|parent| {
+                for sections in columns {
+                    parent.spawn(TextBundle::from_sections(sections));
+                }
+            });
+    }
+    if input.just_pressed(KeyCode::KeyH) {
+        *hidden = !*hidden;
+        *startup_over = true;
+    } else if !*startup_over && time.elapsed_seconds() > STARTUP_SECONDS {
+        *hidden = true;
+        *startup_over = true;
+    }
+    visibility.set_if_neq(if *hidden || running.0 {
+        Visibility::Hidden
+    } else {
+        Visibility::Inherited
+    });
+}
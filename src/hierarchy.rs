@@ -0,0 +1,139 @@
+use std::fmt::Write;
+
+use bevy::{pbr::TransmittedShadowReceiver, prelude::*};
+
+use crate::{help::KeyBindings, scene_bounds::SceneBoundsRoot, wind::Foliage, PostProcScene};
+
+type NodeData = (
+    Option<&'static Name>,
+    Option<&'static Handle<Mesh>>,
+    Option<&'static Handle<StandardMaterial>>,
+    Has<PointLight>,
+    Has<SpotLight>,
+    Has<DirectionalLight>,
+    Has<Camera>,
+    Has<Foliage>,
+    Has<TransmittedShadowReceiver>,
+);
+
+pub fn register_keys(mut bindings: ResMut<KeyBindings>) {
+    bindings.add("Debug", "F9", "Print the entity tree of the scenes");
+}
+
+/// F9 prints the entity tree of every loaded scene with what `proc_scene` looks at: names, meshes,
+/// materials (alpha mode, sidedness, normal map flip), lights, cameras and the markers it adds.
+/// Children are printed under their parent, unlike the order `all_children` visits them in.
+pub fn dump_hierarchy(
+    input: Res<ButtonInput<KeyCode>>,
+    roots: Query<(Entity, Has<PostProcScene>), With<SceneBoundsRoot>>,
+    children_query: Query<&Children>,
+    nodes: Query<NodeData>,
+    materials: Res<Assets<StandardMaterial>>,
+) {
+    if !input.just_pressed(KeyCode::F9) {
+        return;
+    }
+    let mut tree = String::new();
+    for (root, unprocessed) in &roots {
+        let mut count = 0;
+        if let Ok(children) = children_query.get(root) {
+            crate::all_children(children, &children_query, &mut |_| count += 1);
+        }
+        let _ = writeln!(
+            tree,
+            "Scene root {root}: {count} entities{}",
+            if unprocessed {
+                ", not processed by proc_scene yet"
+            } else {
+                ""
+            }
+        );
+        write_node(&mut tree, root, 1, &children_query, &nodes, &materials);
+    }
+    if tree.is_empty() {
+        println!("No scenes to dump yet");
+    } else {
+        print!("{tree}");
+    }
+}
+
+fn write_node(
+    tree: &mut String,
+    entity: Entity,
+    depth: usize,
+    children_query: &Query<&Children>,
+    nodes: &Query<NodeData>,
+    materials: &Assets<StandardMaterial>,
+) {
+    let Ok(children) = children_query.get(entity) else {
+        return;
+    };
+    for child in children {
+        let _ = writeln!(
+            tree,
+            "{}{}",
+            "  ".repeat(depth),
+            describe(*child, nodes, materials)
+        );
+        write_node(tree, *child, depth + 1, children_query, nodes, materials);
+    }
+}
+
+fn describe(
+    entity: Entity,
+    nodes: &Query<NodeData>,
+    materials: &Assets<StandardMaterial>,
+) -> String {
+    let mut line = entity.to_string();
+    let Ok((name, mesh_h, mat_h, point, spot, directional, camera, foliage, transmitted)) =
+        nodes.get(entity)
+    else {
+        return line;
+    };
+    if let Some(name) = name {
+        let _ = write!(line, " \"{name}\"");
+    }
+    if let Some(mesh_h) = mesh_h {
+        let _ = write!(line, " mesh: {}", handle_label(mesh_h));
+    }
+    if let Some(mat_h) = mat_h {
+        let _ = write!(line, " material: {}", handle_label(mat_h));
+        if let Some(mat) = materials.get(mat_h) {
+            let _ = write!(
+                line,
+                " ({:?}, {}{})",
+                mat.alpha_mode,
+                if mat.double_sided {
+                    "double sided"
+                } else {
+                    "single sided"
+                },
+                if mat.flip_normal_map_y {
+                    ", normal map y flipped"
+                } else {
+                    ""
+                }
+            );
+        }
+    }
+    for (has, marker) in [
+        (point, "PointLight"),
+        (spot, "SpotLight"),
+        (directional, "DirectionalLight"),
+        (camera, "Camera"),
+        (foliage, "Foliage"),
+        (transmitted, "TransmittedShadowReceiver"),
+    ] {
+        if has {
+            let _ = write!(line, " [{marker}]");
+        }
+    }
+    line
+}
+
+/// The asset path with its label, e.g. `BistroExterior.gltf#Mesh12/Primitive0`, or the id.
+fn handle_label<A: Asset>(handle: &Handle<A>) -> String {
+    handle
+        .path()
+        .map_or_else(|| handle.id().to_string(), |path| path.to_string())
+}
@@ -0,0 +1,448 @@
+use std::io::Read;
+
+const HEADER_LEN: usize = 80;
+const LEVEL_INDEX_ENTRY_LEN: usize = 24;
+/// Keys kept in the key/value data, every other entry is dropped.
+const STANDARD_KEYS: [&str; 3] = ["KTXorientation", "KTXwriter", "KTXwriterScParams"];
+
+const MODEL_BC1A: u8 = 128;
+const MODEL_BC2: u8 = 129;
+const MODEL_BC3: u8 = 130;
+const MODEL_BC4: u8 = 131;
+const MODEL_BC5: u8 = 132;
+const MODEL_BC6H: u8 = 133;
+const MODEL_BC7: u8 = 134;
+const PRIMARIES_BT709: u8 = 1;
+const TRANSFER_LINEAR: u8 = 1;
+const TRANSFER_SRGB: u8 = 2;
+const FLAG_ALPHA_PREMULTIPLIED: u8 = 1;
+const CHANNEL_COLOR: u8 = 0;
+const CHANNEL_BC1A_ALPHAPRESENT: u8 = 1;
+const CHANNEL_GREEN: u8 = 1;
+const CHANNEL_ALPHA: u8 = 15;
+const QUALIFIER_SIGNED: u8 = 0x40;
+const QUALIFIER_FLOAT: u8 = 0x80;
+const UNORM: (u32, u32) = (0, u32::MAX);
+const SNORM: (u32, u32) = (0x8000_0000, 0x7fff_ffff);
+
+struct Sample {
+    bit_offset: u16,
+    bit_length: u8,
+    channel: u8,
+    lower: u32,
+    upper: u32,
+}
+
+impl Sample {
+    fn new(bit_offset: u16, channel: u8, (lower, upper): (u32, u32)) -> Self {
+        Self {
+            bit_offset,
+            bit_length: 64,
+            channel,
+            lower,
+            upper,
+        }
+    }
+
+    fn block(channel: u8, (lower, upper): (u32, u32)) -> Self {
+        Self {
+            bit_length: 128,
+            ..Self::new(0, channel, (lower, upper))
+        }
+    }
+}
+
+/// The data format descriptor of a BC `vkFormat`, `None` for any other format.
+fn canonical_dfd(vk_format: u32, premultiplied: bool) -> Option<Vec<u8>> {
+    let signed = |channel: u8| channel | QUALIFIER_SIGNED;
+    let (model, samples) = match vk_format {
+        // BC1_RGB_UNORM/SRGB
+        131 | 132 => (MODEL_BC1A, vec![Sample::new(0, CHANNEL_COLOR, UNORM)]),
+        // BC1_RGBA_UNORM/SRGB
+        133 | 134 => (
+            MODEL_BC1A,
+            vec![Sample::new(0, CHANNEL_BC1A_ALPHAPRESENT, UNORM)],
+        ),
+        // BC2 and BC3 store the alpha block first
+        135 | 136 => (
+            MODEL_BC2,
+            vec![
+                Sample::new(0, CHANNEL_ALPHA, UNORM),
+                Sample::new(64, CHANNEL_COLOR, UNORM),
+            ],
+        ),
+        137 | 138 => (
+            MODEL_BC3,
+            vec![
+                Sample::new(0, CHANNEL_ALPHA, UNORM),
+                Sample::new(64, CHANNEL_COLOR, UNORM),
+            ],
+        ),
+        139 => (MODEL_BC4, vec![Sample::new(0, CHANNEL_COLOR, UNORM)]),
+        140 => (
+            MODEL_BC4,
+            vec![Sample::new(0, signed(CHANNEL_COLOR), SNORM)],
+        ),
+        141 => (
+            MODEL_BC5,
+            vec![
+                Sample::new(0, CHANNEL_COLOR, UNORM),
+                Sample::new(64, CHANNEL_GREEN, UNORM),
+            ],
+        ),
+        142 => (
+            MODEL_BC5,
+            vec![
+                Sample::new(0, signed(CHANNEL_COLOR), SNORM),
+                Sample::new(64, signed(CHANNEL_GREEN), SNORM),
+            ],
+        ),
+        // The float bounds are 0.0 or -1.0 to 1.0
+        143 => (
+            MODEL_BC6H,
+            vec![Sample::block(
+                CHANNEL_COLOR | QUALIFIER_FLOAT,
+                (0, 0x3f80_0000),
+            )],
+        ),
+        144 => (
+            MODEL_BC6H,
+            vec![Sample::block(
+                signed(CHANNEL_COLOR) | QUALIFIER_FLOAT,
+                (0xbf80_0000, 0x3f80_0000),
+            )],
+        ),
+        145 | 146 => (MODEL_BC7, vec![Sample::block(CHANNEL_COLOR, UNORM)]),
+        _ => return None,
+    };
+    let srgb = matches!(vk_format, 132 | 134 | 136 | 138 | 146);
+    let block_bytes = samples.iter().map(|s| s.bit_length as u32).sum::<u32>() / 8;
+    let block_size = 24 + 16 * samples.len() as u32;
+
+    let mut dfd = Vec::new();
+    let mut push = |word: u32| dfd.extend_from_slice(&word.to_le_bytes());
+    push(4 + block_size);
+    // Khronos vendor, basic descriptor type
+    push(0);
+    // Version 1.3 of the spec is descriptor version 2
+    push(2 | (block_size << 16));
+    push(u32::from_le_bytes([
+        model,
+        PRIMARIES_BT709,
+        if srgb { TRANSFER_SRGB } else { TRANSFER_LINEAR },
+        if premultiplied {
+            FLAG_ALPHA_PREMULTIPLIED
+        } else {
+            0
+        },
+    ]));
+    // 4x4 texel blocks, dimensions are stored minus one
+    push(u32::from_le_bytes([3, 3, 0, 0]));
+    push(block_bytes);
+    push(0);
+    for sample in &samples {
+        push(
+            sample.bit_offset as u32
+                | ((sample.bit_length as u32 - 1) << 16)
+                | ((sample.channel as u32) << 24),
+        );
+        push(0);
+        push(sample.lower);
+        push(sample.upper);
+    }
+    Some(dfd)
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> usize {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> usize {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap()) as usize
+}
+
+/// The `len` bytes at `offset`, an error rather than a panic if they run past the end.
+fn section<'a>(bytes: &'a [u8], offset: usize, len: usize, name: &str) -> Result<&'a [u8], String> {
+    offset
+        .checked_add(len)
+        .and_then(|end| bytes.get(offset..end))
+        .ok_or_else(|| format!("{name} runs past the end of the file"))
+}
+
+/// The key/value entries as (key, entry without its padding).
+fn key_values(kvd: &[u8]) -> Result<Vec<(String, &[u8])>, String> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while offset + 4 <= kvd.len() {
+        let len = u32_at(kvd, offset);
+        let entry = kvd
+            .get(offset..offset + 4 + len)
+            .ok_or("key/value entry runs past the end of the data")?;
+        let key_len = entry[4..]
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or("key without a terminating null")?;
+        let key = std::str::from_utf8(&entry[4..4 + key_len])
+            .map_err(|_| "key is not valid UTF-8")?
+            .to_string();
+        entries.push((key, entry));
+        offset = (offset + 4 + len).next_multiple_of(4);
+    }
+    Ok(entries)
+}
+
+/// For `--ktx-strict`. kram writes a data format descriptor and key/value data that Bevy doesn't
+/// care about but that strict parsers like libktx may reject. This rewrites a BC file with the
+/// descriptor the KTX2 spec derives from the Vulkan format, only the standard keys and levels
+/// aligned to the block size. zstd levels are decompressed, since not every reader supports it.
+/// Other formats are returned unchanged.
+pub fn make_strict(bytes: &[u8], premultiplied: bool) -> Result<Vec<u8>, String> {
+    ktx2::Reader::new(bytes).map_err(|e| format!("invalid header: {e:?}"))?;
+    let Some(dfd) = canonical_dfd(u32_at(bytes, 12) as u32, premultiplied) else {
+        return Ok(bytes.to_vec());
+    };
+    if u64_at(bytes, 72) != 0 {
+        return Err("BC format with supercompression global data".to_string());
+    }
+    let supercompression = u32_at(bytes, 44);
+    let zstd = supercompression == 2;
+    let kvd = section(
+        bytes,
+        u32_at(bytes, 56),
+        u32_at(bytes, 60),
+        "key/value data",
+    )?;
+    let mut entries = key_values(kvd)?;
+    // The zstd parameters don't apply once it's decompressed
+    entries.retain(|(key, _)| {
+        STANDARD_KEYS.contains(&key.as_str()) && !(zstd && key == "KTXwriterScParams")
+    });
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    let level_count = u32_at(bytes, 40).max(1);
+    let levels = (0..level_count)
+        .map(|i| {
+            let entry = HEADER_LEN + i * LEVEL_INDEX_ENTRY_LEN;
+            let (offset, len) = (u64_at(bytes, entry), u64_at(bytes, entry + 8));
+            let data = section(bytes, offset, len, &format!("level {i}"))?;
+            if !zstd {
+                return Ok((data.to_vec(), u64_at(bytes, entry + 16)));
+            }
+            let mut level = Vec::new();
+            ruzstd::StreamingDecoder::new(data)
+                .map_err(|e| format!("level {i}: {e}"))?
+                .read_to_end(&mut level)
+                .map_err(|e| format!("level {i}: {e}"))?;
+            let len = level.len();
+            Ok((level, len))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let mut out = bytes[..HEADER_LEN].to_vec();
+    if zstd {
+        out[44..48].fill(0);
+    }
+    let index_start = out.len();
+    out.resize(index_start + LEVEL_INDEX_ENTRY_LEN * level_count, 0);
+    let dfd_offset = out.len() as u32;
+    out.extend_from_slice(&dfd);
+    out[48..52].copy_from_slice(&dfd_offset.to_le_bytes());
+    out[52..56].copy_from_slice(&(dfd.len() as u32).to_le_bytes());
+    let kvd_start = out.len();
+    for (_, entry) in &entries {
+        out.extend_from_slice(entry);
+        out.resize(out.len().next_multiple_of(4), 0);
+    }
+    let kvd_offset = if entries.is_empty() { 0 } else { kvd_start };
+    out[56..60].copy_from_slice(&(kvd_offset as u32).to_le_bytes());
+    let kvd_len = (out.len() - kvd_start) as u32;
+    out[60..64].copy_from_slice(&kvd_len.to_le_bytes());
+    out[64..80].fill(0);
+
+    // Smallest level first, aligned to the 8 or 16 byte block unless still supercompressed
+    let alignment = if supercompression != 0 && !zstd {
+        1
+    } else {
+        u32_at(&dfd, 20)
+    };
+    for (i, (data, uncompressed_len)) in levels.iter().enumerate().rev() {
+        out.resize(out.len().next_multiple_of(alignment), 0);
+        let entry = index_start + i * LEVEL_INDEX_ENTRY_LEN;
+        let offset = out.len() as u64;
+        out[entry..entry + 8].copy_from_slice(&offset.to_le_bytes());
+        out[entry + 8..entry + 16].copy_from_slice(&(data.len() as u64).to_le_bytes());
+        out[entry + 16..entry + 24].copy_from_slice(&(*uncompressed_len as u64).to_le_bytes());
+        out.extend_from_slice(data);
+    }
+    Ok(out)
+}
+
+/// The checks `--ktx-strict` adds to the regular validation: no zstd, the canonical data format
+/// descriptor for BC formats, only standard keys in sorted order and block aligned levels.
+pub fn check_strict(bytes: &[u8]) -> Result<(), String> {
+    ktx2::Reader::new(bytes).map_err(|e| format!("invalid header: {e:?}"))?;
+    let supercompression = u32_at(bytes, 44);
+    if supercompression == 2 {
+        return Err("zstd supercompression, not all KTX2 readers support it".to_string());
+    }
+    let dfd = section(
+        bytes,
+        u32_at(bytes, 48),
+        u32_at(bytes, 52),
+        "data format descriptor",
+    )?;
+    let Some(canonical) = canonical_dfd(u32_at(bytes, 12) as u32, false) else {
+        // BasisLZ from toktx, which already writes spec-clean files
+        return Ok(());
+    };
+    // The premultiplied alpha flag is the only part that may differ
+    // Last byte of the third word of the block, after the total size
+    let flags = 15;
+    let mut dfd = dfd.to_vec();
+    if let Some(flag) = dfd.get_mut(flags) {
+        *flag &= !FLAG_ALPHA_PREMULTIPLIED;
+    }
+    if dfd != canonical {
+        return Err("non-standard data format descriptor".to_string());
+    }
+    let kvd = section(
+        bytes,
+        u32_at(bytes, 56),
+        u32_at(bytes, 60),
+        "key/value data",
+    )?;
+    let entries = key_values(kvd)?;
+    if let Some((key, _)) = entries
+        .iter()
+        .find(|(key, _)| !STANDARD_KEYS.contains(&key.as_str()))
+    {
+        return Err(format!("non-standard key {key:?}"));
+    }
+    if entries.windows(2).any(|pair| pair[0].0 > pair[1].0) {
+        return Err("keys are not sorted".to_string());
+    }
+    let alignment = u32_at(&canonical, 20);
+    // `is_multiple_of` needs Rust 1.87
+    #[allow(clippy::manual_is_multiple_of)]
+    for i in 0..u32_at(bytes, 40).max(1) {
+        let entry = HEADER_LEN + i * LEVEL_INDEX_ENTRY_LEN;
+        if supercompression == 0 && alignment != 0 && u64_at(bytes, entry) % alignment != 0 {
+            return Err(format!("level {i} is not aligned to {alignment} bytes"));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BC7_UNORM: u32 = 145;
+
+    /// A zstd frame storing `data` as a single raw block.
+    fn zstd_frame(data: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0x28, 0xb5, 0x2f, 0xfd];
+        // Single segment with a one byte content size
+        frame.extend_from_slice(&[0x20, data.len() as u8]);
+        // Last block, raw
+        let block = 1 | (data.len() as u32) << 3;
+        frame.extend_from_slice(&block.to_le_bytes()[..3]);
+        frame.extend_from_slice(data);
+        frame
+    }
+
+    fn set(out: &mut [u8], offset: usize, value: usize) {
+        out[offset..offset + 4].copy_from_slice(&(value as u32).to_le_bytes());
+    }
+
+    /// A 4x4 BC7 file with one level, `misalign` bytes past a 16 byte boundary.
+    fn bc7(
+        supercompression: u32,
+        dfd: &[u8],
+        keys: &[&str],
+        level: &[u8],
+        misalign: usize,
+    ) -> Vec<u8> {
+        let mut out = vec![0; HEADER_LEN + LEVEL_INDEX_ENTRY_LEN];
+        out[..12].copy_from_slice(&[
+            0xab, 0x4b, 0x54, 0x58, 0x20, 0x32, 0x30, 0xbb, 0x0d, 0x0a, 0x1a, 0x0a,
+        ]);
+        // vkFormat, typeSize, width, height, faceCount, levelCount and supercompression
+        for (offset, value) in [(12, BC7_UNORM), (16, 1), (20, 4), (24, 4), (36, 1), (40, 1)] {
+            set(&mut out, offset, value as usize);
+        }
+        set(&mut out, 44, supercompression as usize);
+        let dfd_offset = out.len();
+        set(&mut out, 48, dfd_offset);
+        set(&mut out, 52, dfd.len());
+        out.extend_from_slice(dfd);
+        let kvd_start = out.len();
+        for key in keys {
+            let entry = format!("{key}\0value\0");
+            out.extend_from_slice(&(entry.len() as u32).to_le_bytes());
+            out.extend_from_slice(entry.as_bytes());
+            out.resize(out.len().next_multiple_of(4), 0);
+        }
+        set(&mut out, 56, kvd_start);
+        let kvd_len = out.len() - kvd_start;
+        set(&mut out, 60, kvd_len);
+        out.resize(out.len().next_multiple_of(16) + misalign, 0);
+        let offset = out.len() as u64;
+        out[80..88].copy_from_slice(&offset.to_le_bytes());
+        out[88..96].copy_from_slice(&(level.len() as u64).to_le_bytes());
+        out[96..104].copy_from_slice(&16u64.to_le_bytes());
+        out.extend_from_slice(level);
+        out
+    }
+
+    #[test]
+    fn make_strict_round_trip() {
+        // kram's own descriptor, unspecified primaries
+        let mut dfd = canonical_dfd(BC7_UNORM, false).unwrap();
+        dfd[13] = 0;
+        let block = [7; 16];
+        let keys = ["KTXwriter", "kram", "KTXwriterScParams", "KTXorientation"];
+        let bytes = bc7(2, &dfd, &keys, &zstd_frame(&block), 0);
+        assert!(check_strict(&bytes).is_err());
+
+        let strict = make_strict(&bytes, false).unwrap();
+        let reader = ktx2::Reader::new(&strict[..]).unwrap();
+        assert_eq!(reader.header().supercompression_scheme, None);
+        assert_eq!(reader.levels().collect::<Vec<_>>(), [&block[..]]);
+        let kvd = section(&strict, u32_at(&strict, 56), u32_at(&strict, 60), "").unwrap();
+        let keys = key_values(kvd).unwrap();
+        assert_eq!(
+            keys.iter().map(|(key, _)| key.as_str()).collect::<Vec<_>>(),
+            ["KTXorientation", "KTXwriter"]
+        );
+        assert_eq!(check_strict(&strict), Ok(()));
+    }
+
+    #[test]
+    fn check_strict_rejects_unsorted_keys() {
+        let dfd = canonical_dfd(BC7_UNORM, false).unwrap();
+        let bytes = bc7(0, &dfd, &["KTXwriter", "KTXorientation"], &[7; 16], 0);
+        assert_eq!(check_strict(&bytes), Err("keys are not sorted".to_string()));
+        assert_eq!(check_strict(&make_strict(&bytes, false).unwrap()), Ok(()));
+    }
+
+    #[test]
+    fn check_strict_rejects_misaligned_levels() {
+        let dfd = canonical_dfd(BC7_UNORM, false).unwrap();
+        let bytes = bc7(0, &dfd, &["KTXorientation"], &[7; 16], 4);
+        assert_eq!(
+            check_strict(&bytes),
+            Err("level 0 is not aligned to 16 bytes".to_string())
+        );
+        assert_eq!(check_strict(&make_strict(&bytes, false).unwrap()), Ok(()));
+    }
+
+    #[test]
+    fn broken_sections_are_errors() {
+        let dfd = canonical_dfd(BC7_UNORM, false).unwrap();
+        let mut bytes = bc7(0, &dfd, &["KTXorientation"], &[7; 16], 0);
+        bytes[60..64].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(check_strict(&bytes).is_err());
+        assert!(make_strict(&bytes, false).is_err());
+        assert!(check_strict(&bytes[..40]).is_err());
+    }
+}
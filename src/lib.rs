@@ -0,0 +1,2575 @@
+//! The Bistro scene as a plugin, see [`BistroScenePlugin`]. The `bistro` binary adds it with the
+//! command line arguments.
+
+use std::{
+    ops::{Add, Mul, Sub},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+pub mod alpha_coverage;
+pub mod archive;
+pub mod bench_matrix;
+pub mod bench_profile;
+pub mod camera_controller;
+pub mod convert;
+pub mod custom_scene;
+pub mod day_cycle;
+pub mod debug_view;
+pub mod effects;
+pub mod environment_map;
+pub mod extended_materials;
+pub mod glb;
+pub mod gltf_cameras;
+pub mod help;
+pub mod hierarchy;
+pub mod ktx2_strict;
+pub mod lights;
+pub mod material_overrides;
+pub mod mesh_diagnostics;
+pub mod mipmap_cache;
+pub mod mipmap_generator;
+pub mod mipmap_gpu;
+pub mod msaa_compat;
+pub mod orm_pack;
+pub mod overlay;
+pub mod picking;
+pub mod present_mode;
+pub mod scene_bounds;
+pub mod screenshot;
+pub mod session;
+pub mod shadows;
+pub mod split_screen;
+pub mod sun;
+#[cfg(test)]
+mod test_dir;
+pub mod wind;
+
+use archive::Archive;
+use argh::FromArgs;
+use bevy::{
+    asset::io::file::FileAssetReader,
+    core_pipeline::{
+        contrast_adaptive_sharpening::ContrastAdaptiveSharpeningSettings,
+        core_3d::ScreenSpaceTransmissionQuality,
+        experimental::taa::{TemporalAntiAliasPlugin, TemporalAntiAliasSettings},
+        fxaa::Fxaa,
+        tonemapping::Tonemapping,
+    },
+    diagnostic::{DiagnosticsStore, EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin},
+    ecs::query::{QueryData, QueryFilter, QuerySingleError},
+    pbr::{CascadeShadowConfig, DirectionalLightShadowMap, TransmittedShadowReceiver},
+    prelude::*,
+    render::{
+        camera::Exposure,
+        diagnostic::RenderDiagnosticsPlugin,
+        mesh::{Indices, PrimitiveTopology},
+        render_resource::Face,
+        view::NoFrustumCulling,
+    },
+    utils::{HashMap, HashSet},
+    window::{PresentMode, PrimaryWindow, WindowMode, WindowResolution},
+};
+use camera_controller::{register_controller_keys, CameraController, CameraControllerPlugin};
+use custom_scene::{check_custom_scenes, gltf_path, scene_asset_path, CustomScenes};
+use day_cycle::{day_cycle, day_cycle_input, parse_day_length, DayCycle, LampMaterials};
+use gltf_cameras::{cycle_gltf_cameras, GltfCamera, KeepCamera};
+use help::KeyBindings;
+use mipmap_generator::{
+    mipmap_systems, MipFilter, MipOverride, MipmapGeneratorPaused, MipmapGeneratorPlugin,
+    MipmapGeneratorSettings, DEFAULT_MIP_OVERRIDES_PATH,
+};
+use msaa_compat::{enforce_msaa_compatibility, MsaaConflict};
+use present_mode::PresentModeFallbackPlugin;
+use scene_bounds::{compute_scene_bounds, fit_camera_to_bounds, SceneBounds, SceneBoundsRoot};
+use shadows::{ShadowSettings, DEFAULT_CASCADES, DEFAULT_SHADOW_MAP_SIZE};
+use split_screen::{sync_comparison_camera, update_split_viewports, ComparisonCamera};
+use sun::{
+    sun_input, SunAngles, DEFAULT_SUN_AZIMUTH, DEFAULT_SUN_ELEVATION, MAX_SUN_ILLUMINANCE,
+    SUN_COLOR,
+};
+use wind::{Foliage, WindPlugin};
+
+use crate::bench_matrix::{bench_matrix, parse_effects, Effect};
+use crate::convert::{
+    load_ron_list, Container, ConvertFormat, ConvertSettings, PruneMode, Supercompression,
+    BISTRO_DIRS, DEFAULT_OVERRIDES_PATH, REPORT_FILE_NAME,
+};
+use crate::environment_map::{environment_map_input, EnvironmentMaps, DEFAULT_ENVIRONMENT_MAP};
+use crate::light_consts::lux;
+use crate::material_overrides::{
+    MaterialNames, MaterialOverrides, DEFAULT_MATERIAL_OVERRIDES_PATH,
+};
+
+#[derive(FromArgs, Resource, Clone)]
+/// Config
+pub struct Args {
+    /// convert gltf to use ktx
+    #[argh(switch)]
+    pub convert: bool,
+
+    /// directory, .gltf or .glb file to convert, can be repeated (defaults to the Bistro directories
+    /// of the assets directory)
+    #[argh(option)]
+    pub convert_path: Vec<PathBuf>,
+
+    /// converted texture format: bc7 (kram) or etc1s (toktx, needs the basis-universal feature)
+    #[argh(option, default = "ConvertFormat::default()")]
+    pub convert_format: ConvertFormat,
+
+    /// converted texture container: ktx2 or dds (dds needs the dds feature to load)
+    #[argh(option, default = "Container::default()")]
+    pub convert_container: Container,
+
+    /// etc1s quality level from 1 to 255 (default 128)
+    #[argh(option, default = "128")]
+    pub convert_quality: u32,
+
+    /// downscale converted textures so neither side exceeds this many pixels
+    #[argh(option)]
+    pub convert_max_size: Option<u32>,
+
+    /// zstd supercompression level for converted textures, or "off" (default 0)
+    #[argh(option, default = "Supercompression::default()")]
+    pub convert_zstd: Supercompression,
+
+    /// only write the base level of converted textures, without a mip chain
+    #[argh(switch)]
+    pub convert_no_mips: bool,
+
+    /// smallest mip level size of converted textures
+    #[argh(option)]
+    pub convert_min_mip: Option<u32>,
+
+    /// don't rescale the alpha of each mip of alpha masked base color textures to keep their
+    /// coverage at the material's alphaCutoff
+    #[argh(switch)]
+    pub convert_no_alpha_coverage: bool,
+
+    /// premultiply the color by alpha before encoding textures with an alpha channel
+    #[argh(switch)]
+    pub convert_premultiply: bool,
+
+    /// before converting, pack separate occlusion and metallic/roughness maps into one ORM
+    /// texture per material and point the glTF at it
+    #[argh(switch)]
+    pub convert_pack_orm: bool,
+
+    /// per-texture conversion overrides file (default convert_overrides.ron, if it exists)
+    #[argh(option, default = "PathBuf::from(DEFAULT_OVERRIDES_PATH)")]
+    pub convert_overrides: PathBuf,
+
+    /// total encoder threads, one per texture encoded at once (defaults to all cores)
+    #[argh(option)]
+    pub convert_threads: Option<usize>,
+
+    /// after converting, delete the source images that converted successfully
+    #[argh(switch)]
+    pub convert_prune: bool,
+
+    /// after converting, move the source images that converted successfully into an _original/
+    /// directory next to them instead of deleting them
+    #[argh(switch)]
+    pub convert_prune_archive: bool,
+
+    /// only convert the source images whose file name matches this glob (or contains it, without
+    /// wildcards), e.g. "*Normal*", can be repeated
+    #[argh(option)]
+    pub convert_filter: Vec<String>,
+
+    /// print the conversion plan (encoder commands, glTF URI rewrites) without running it
+    #[argh(switch)]
+    pub convert_dry_run: bool,
+
+    /// time reading and decoding the sources and the converted files for the conversion report
+    #[argh(switch)]
+    pub measure_decode: bool,
+
+    /// where to write the conversion report (default convert_report.txt in the assets directory)
+    #[argh(option)]
+    pub convert_report: Option<PathBuf>,
+
+    /// write spec-clean, uncompressed KTX2 for strict parsers like libktx, at the cost of size,
+    /// also checked by --validate-only
+    #[argh(switch)]
+    pub ktx_strict: bool,
+
+    /// only check the existing .ktx2 files of the convert paths, then exit
+    #[argh(switch)]
+    pub validate_only: bool,
+
+    /// directory to load the scenes from instead of ./assets, also where --convert looks by default
+    #[argh(option)]
+    pub assets_dir: Option<PathBuf>,
+
+    /// load the assets from this .zip or uncompressed .tar without extracting it, what isn't in
+    /// it still comes from the assets directory
+    #[argh(option)]
+    pub assets_archive: Option<PathBuf>,
+
+    /// disable glTF lights
+    #[argh(switch)]
+    pub no_gltf_lights: bool,
+
+    /// keep the cameras that come with the glTF scenes, V cycles the view through them
+    #[argh(switch)]
+    pub keep_gltf_cameras: bool,
+
+    /// asset path of a glTF scene to load instead of the Bistro scenes, can be repeated. The
+    /// camera frames it once it has loaded
+    #[argh(option)]
+    pub scene: Vec<String>,
+
+    /// only load the exterior scene (and its fake GI lights)
+    #[argh(switch)]
+    pub exterior_only: bool,
+
+    /// only load the interior scene
+    #[argh(switch)]
+    pub interior_only: bool,
+
+    /// position of the interior scene as X,Y,Z, to line it up with the exterior (default
+    /// 0,0.3,-0.2)
+    #[argh(option)]
+    pub interior_offset: Option<String>,
+
+    /// generate the missing mipmaps on the GPU instead of the CPU
+    #[argh(switch)]
+    pub gpu_mipmaps: bool,
+
+    /// filter for the generated mipmaps: box, triangle or lanczos3 (default triangle)
+    #[argh(option, default = "MipFilter::Triangle", from_str_fn(parse_mip_filter))]
+    pub mip_filter: MipFilter,
+
+    /// store the mipmaps generated on the CPU in this directory and load them from it on later
+    /// runs
+    #[argh(option)]
+    pub mip_cache: Option<PathBuf>,
+
+    /// most mip levels to generate including the full size one (default the whole chain)
+    #[argh(option)]
+    pub mip_max_levels: Option<u32>,
+
+    /// smallest mip level to generate, in pixels on the shorter side (default 1)
+    #[argh(option, default = "1")]
+    pub mip_min_size: u32,
+
+    /// per-image mip limits file (default mip_overrides.ron, if it exists)
+    #[argh(option, default = "PathBuf::from(DEFAULT_MIP_OVERRIDES_PATH)")]
+    pub mip_overrides: PathBuf,
+
+    /// per-material double sided, cull mode and transmission overrides file (default
+    /// material_overrides.ron, if it exists)
+    #[argh(option, default = "PathBuf::from(DEFAULT_MATERIAL_OVERRIDES_PATH)")]
+    pub material_overrides: PathBuf,
+
+    /// keep only the N glTF point and spot lights that are brightest from the camera, despawning
+    /// the rest
+    #[argh(option)]
+    pub max_lights: Option<usize>,
+
+    /// antialiasing: taa, fxaa, msaa4 (same as off with --msaa 4) or off (default taa, or off
+    /// with --msaa)
+    #[argh(option, from_str_fn(parse_anti_aliasing))]
+    pub aa: Option<AntiAliasing>,
+
+    /// MSAA sample count: off, 2, 4 or 8 (default off). Turns the default TAA and SSAO off, both
+    /// conflict with it
+    #[argh(option, from_str_fn(parse_msaa))]
+    pub msaa: Option<Msaa>,
+
+    /// disable bloom, AO, AA, shadows
+    #[argh(switch)]
+    pub minimal: bool,
+
+    /// window width in logical pixels (default 1920)
+    #[argh(option, default = "1920.0")]
+    pub width: f32,
+
+    /// window height in logical pixels (default 1080)
+    #[argh(option, default = "1080.0")]
+    pub height: f32,
+
+    /// scale factor of the window, 0 uses the one of the display (default 1)
+    #[argh(option, default = "1.0")]
+    pub scale_factor: f32,
+
+    /// present mode: immediate, mailbox or fifo (vsync), falls back to a supported one (default
+    /// immediate)
+    #[argh(
+        option,
+        default = "PresentMode::Immediate",
+        from_str_fn(parse_present_mode)
+    )]
+    pub present_mode: PresentMode,
+
+    /// start in borderless fullscreen on the current display
+    #[argh(switch)]
+    pub fullscreen: bool,
+
+    /// whether to disable frustum culling.
+    #[argh(switch)]
+    pub no_frustum_culling: bool,
+
+    /// screen space specular transmission steps, 0 disables transmission.
+    #[argh(option, default = "0")]
+    pub transmission_steps: usize,
+
+    /// screen space specular transmission quality: low, medium, high or ultra.
+    #[argh(
+        option,
+        default = "ScreenSpaceTransmissionQuality::Low",
+        from_str_fn(parse_transmission_quality)
+    )]
+    pub transmission_quality: ScreenSpaceTransmissionQuality,
+
+    /// make B benchmark every on/off permutation of these effects instead: all, or a comma
+    /// separated list of bloom, ssao, taa and shadows
+    #[argh(option, from_str_fn(parse_effects))]
+    pub bench_matrix: Option<Vec<Effect>>,
+
+    /// where --bench-matrix writes the frame time stats of each permutation, JSON if it ends in
+    /// .json, CSV otherwise (default bench_matrix.csv)
+    #[argh(option, default = "PathBuf::from(\"bench_matrix.csv\")")]
+    pub bench_matrix_out: PathBuf,
+
+    /// restore the camera, effects and tuning from this RON file on startup, and save them to it
+    /// on exit and with F5
+    #[argh(option)]
+    pub session: Option<PathBuf>,
+
+    /// print the systems that took the most CPU time after the benchmark, needs the trace
+    /// feature
+    #[argh(switch)]
+    pub bench_profile: bool,
+
+    /// seconds each of the three benchmark steps should take, measured from the frame time when
+    /// B is pressed (default 2)
+    #[argh(option, default = "2.0")]
+    pub bench_seconds: f32,
+
+    /// the fewest frames a benchmark step renders, however slow they are (default 30)
+    #[argh(option, default = "30")]
+    pub bench_min_frames: u32,
+
+    /// fail the benchmark if the average frame time exceeds this many milliseconds
+    #[argh(option)]
+    pub max_frame_ms: Option<f32>,
+
+    /// fail the benchmark if the estimated texture and mesh memory exceeds this many MB
+    #[argh(option)]
+    pub max_vram_mb: Option<f32>,
+
+    /// camera walking speed in m/s, the scroll wheel scales it at runtime (default 5)
+    #[argh(option, default = "5.0")]
+    pub walk_speed: f32,
+
+    /// camera speed in m/s while holding shift (default 15)
+    #[argh(option, default = "15.0")]
+    pub run_speed: f32,
+
+    /// seconds the camera takes to move to a bookmark (1, 2, 3), 0 jumps instantly
+    #[argh(option, default = "0.75")]
+    pub bookmark_transition: f32,
+
+    /// time constant in seconds the animated camera (Space) follows its path with, 0 follows it
+    /// exactly (default 0.16)
+    #[argh(option, default = "ANIM_SMOOTHING")]
+    pub anim_smoothing: f32,
+
+    /// start the camera at X,Y,Z instead of the default position
+    #[argh(option)]
+    pub cam_pos: Option<String>,
+
+    /// point the starting camera at X,Y,Z instead of the default target
+    #[argh(option)]
+    pub cam_look: Option<String>,
+
+    /// move the camera to frame the scene once it has loaded, and size the shadow cascades to it
+    #[argh(switch)]
+    pub fit_camera: bool,
+
+    /// render the right half of the window with a second camera without post processing,
+    /// following the main camera, to compare the effects
+    #[argh(switch)]
+    pub split_screen: bool,
+
+    /// move the sun through a day and night every this many seconds, starting at noon
+    #[argh(option, from_str_fn(parse_day_length))]
+    pub day_cycle: Option<f32>,
+
+    /// sway the alpha masked foliage in the wind, 1 is a light breeze
+    #[argh(option)]
+    pub wind: Option<f32>,
+
+    /// only sway foliage whose mesh name or base color texture file name matches this glob, can
+    /// be repeated
+    #[argh(option)]
+    pub wind_filter: Vec<String>,
+
+    /// start with the F11 shadow cascade debug view on, to tune --cascades, --shadow-distance and
+    /// --first-cascade-bound
+    #[argh(switch)]
+    pub show_cascades: bool,
+
+    /// start with the FPS overlay (F10) visible, this also turns on the GPU pass timings it shows
+    #[argh(switch)]
+    pub overlay: bool,
+
+    /// limit the frame rate while inspecting the scene, off during the benchmark
+    #[argh(option)]
+    pub fps_cap: Option<f32>,
+
+    /// background color as R,G,B sRGB values, can go above 1 (default 1.75,1.9,1.99)
+    #[argh(option, default = "[1.75, 1.9, 1.99]", from_str_fn(parse_rgb))]
+    pub clear_color: [f32; 3],
+
+    /// ambient light brightness (default 0.02)
+    #[argh(option, default = "0.02")]
+    pub ambient: f32,
+
+    /// sun direction around the vertical axis in degrees, 0 is towards -Z and 90 towards -X
+    /// (default 136.37)
+    #[argh(option, default = "DEFAULT_SUN_AZIMUTH")]
+    pub sun_azimuth: f32,
+
+    /// sun height above the horizon in degrees, 0 to 90 (default 54.86)
+    #[argh(option, default = "DEFAULT_SUN_ELEVATION")]
+    pub sun_elevation: f32,
+
+    /// sun illuminance in lux (default 20000)
+    #[argh(option, default = "lux::FULL_DAYLIGHT")]
+    pub sun_illuminance: f32,
+
+    /// sun shadow map resolution per cascade, 256 to 8192 (default 2048)
+    #[argh(option, default = "DEFAULT_SHADOW_MAP_SIZE")]
+    pub shadow_map_size: usize,
+
+    /// number of sun shadow cascades, 1 to 4 (default 4)
+    #[argh(option, default = "DEFAULT_CASCADES")]
+    pub cascades: usize,
+
+    /// how far the sun shadows reach in meters (default 100, or the scene size with
+    /// --fit-camera)
+    #[argh(option)]
+    pub shadow_distance: Option<f32>,
+
+    /// far bound of the first, sharpest shadow cascade in meters (default 5)
+    #[argh(option)]
+    pub first_cascade_bound: Option<f32>,
+
+    /// sun shadow depth bias, raise it against shadow acne (default 0.2)
+    #[argh(option, default = "0.2")]
+    pub shadow_depth_bias: f32,
+
+    /// sun shadow normal bias, lower it if shadows detach from their casters (default 0.2)
+    #[argh(option, default = "0.2")]
+    pub shadow_normal_bias: f32,
+
+    /// environment map loaded from environment_maps/<name>_diffuse.ktx2 and _specular.ktx2
+    /// (default san_giuseppe_bridge_4k)
+    #[argh(option)]
+    pub env: Option<String>,
+
+    /// environment map intensity (default 600)
+    #[argh(option, default = "600.0")]
+    pub env_intensity: f32,
+
+    /// tonemapper: none, reinhard, reinhard_luminance, aces_fitted, agx,
+    /// somewhat_boring_display_transform, tony_mc_mapface or blender_filmic (default
+    /// tony_mc_mapface)
+    #[argh(
+        option,
+        default = "Tonemapping::TonyMcMapface",
+        from_str_fn(parse_tonemapping)
+    )]
+    pub tonemapper: Tonemapping,
+
+    /// camera exposure as EV100, higher is darker (default 9.7)
+    #[argh(option, default = "Exposure::EV100_BLENDER")]
+    pub ev100: f32,
+}
+
+impl Args {
+    /// `--cam-pos`, `None` if it's missing or not a vector.
+    pub fn cam_pos(&self) -> Option<Vec3> {
+        self.cam_pos.as_deref().and_then(|v| parse_vec3(v).ok())
+    }
+
+    /// `--cam-look`, `None` if it's missing or not a vector.
+    pub fn cam_look(&self) -> Option<Vec3> {
+        self.cam_look.as_deref().and_then(|v| parse_vec3(v).ok())
+    }
+
+    /// `--interior-offset`, the default if it's missing or not a vector.
+    pub fn interior_offset(&self) -> Vec3 {
+        self.interior_offset
+            .as_deref()
+            .and_then(|v| parse_vec3(v).ok())
+            .unwrap_or(DEFAULT_INTERIOR_OFFSET)
+    }
+
+    /// The primary window from `--width`, `--height`, `--scale-factor`, `--present-mode` and
+    /// `--fullscreen`.
+    pub fn window(&self) -> Window {
+        let mut resolution = WindowResolution::new(self.width.max(1.0), self.height.max(1.0));
+        if self.scale_factor > 0.0 {
+            resolution = resolution.with_scale_factor_override(self.scale_factor);
+        }
+        Window {
+            present_mode: self.present_mode,
+            resolution,
+            mode: if self.fullscreen {
+                WindowMode::BorderlessFullscreen
+            } else {
+                WindowMode::Windowed
+            },
+            ..default()
+        }
+    }
+
+    /// The post process antialiasing (never `Msaa4`) and MSAA to use, with what had to give way.
+    /// MSAA conflicts with TAA: an explicit `--aa taa` keeps MSAA off, otherwise `--msaa` turns
+    /// the default TAA off. `--minimal` leaves out the post process antialiasing.
+    pub fn anti_aliasing(&self) -> (AntiAliasing, Msaa, Option<&'static str>) {
+        let msaa_on = self.msaa.is_some_and(|msaa| msaa != Msaa::Off);
+        match (self.aa, self.msaa) {
+            (_, msaa) if self.minimal => (AntiAliasing::Off, msaa.unwrap_or(Msaa::Off), None),
+            (Some(AntiAliasing::Msaa4), msaa) => {
+                (AntiAliasing::Off, msaa.unwrap_or(Msaa::Sample4), None)
+            }
+            (Some(AntiAliasing::Taa), _) if msaa_on => (
+                AntiAliasing::Taa,
+                Msaa::Off,
+                Some("MSAA stays off, TAA was asked for and conflicts with it"),
+            ),
+            (None, Some(msaa)) if msaa_on => (
+                AntiAliasing::Off,
+                msaa,
+                Some("TAA is off, it conflicts with MSAA"),
+            ),
+            (aa, msaa) => (
+                aa.unwrap_or(AntiAliasing::Taa),
+                msaa.unwrap_or(Msaa::Off),
+                None,
+            ),
+        }
+    }
+
+    /// An error for the MSAA conflicts asked for explicitly on the command line, the ones
+    /// `anti_aliasing` doesn't resolve.
+    pub fn check_anti_aliasing(&self) -> Result<(), String> {
+        let (_, msaa, _) = self.anti_aliasing();
+        if self.transmission_steps > 0 {
+            MsaaConflict::Transmission
+                .allowed_with(msaa)
+                .map_err(|_| {
+                    format!(
+                        "--transmission-steps {} doesn't work with --msaa, use --transmission-steps 0 or --msaa off",
+                        self.transmission_steps
+                    )
+                })?;
+        }
+        Ok(())
+    }
+
+    /// The errors `BistroScenePlugin` exits with, to report them before the app is built.
+    pub fn check(&self) -> Result<(), String> {
+        self.scenes()?;
+        self.check_anti_aliasing()?;
+        load_ron_list::<MipOverride>(&self.mip_overrides)?;
+        MaterialOverrides::load(&self.material_overrides)?;
+        Ok(())
+    }
+
+    /// Which of the exterior and interior scenes to load, an error if that's neither. Neither is
+    /// loaded with `--scene`.
+    pub fn scenes(&self) -> Result<(bool, bool), String> {
+        if !self.scene.is_empty() {
+            return Ok((false, false));
+        }
+        if self.exterior_only && self.interior_only {
+            return Err(
+                "--exterior-only and --interior-only together would load no scene".to_string(),
+            );
+        }
+        Ok((!self.interior_only, !self.exterior_only))
+    }
+
+    /// What `--convert-prune` and `--convert-prune-archive` do with the converted sources.
+    pub fn prune_mode(&self) -> Option<PruneMode> {
+        if self.convert_prune_archive {
+            Some(PruneMode::Archive)
+        } else if self.convert_prune {
+            Some(PruneMode::Delete)
+        } else {
+            None
+        }
+    }
+
+    /// `--assets-dir`, or ./assets.
+    fn assets_dir_or_default(&self) -> PathBuf {
+        self.assets_dir
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("./assets"))
+    }
+
+    pub fn convert_paths(&self) -> Vec<PathBuf> {
+        if !self.convert_path.is_empty() {
+            return self.convert_path.clone();
+        }
+        let assets_dir = self.assets_dir_or_default();
+        BISTRO_DIRS.iter().map(|dir| assets_dir.join(dir)).collect()
+    }
+
+    pub fn convert_settings(&self) -> Result<ConvertSettings, String> {
+        Ok(ConvertSettings {
+            format: self.convert_format,
+            container: self.convert_container,
+            etc1s_quality: self.convert_quality,
+            max_size: self.convert_max_size,
+            // Not every KTX2 reader supports zstd
+            supercompression: if self.ktx_strict {
+                Supercompression::None
+            } else {
+                self.convert_zstd
+            },
+            mips: !self.convert_no_mips,
+            min_mip_size: self.convert_min_mip,
+            alpha_coverage: !self.convert_no_alpha_coverage,
+            premultiply: self.convert_premultiply,
+            overrides: load_ron_list(&self.convert_overrides)?,
+            threads: self.convert_threads,
+            measure_decode: self.measure_decode,
+            strict: self.ktx_strict,
+            report: Some(
+                self.convert_report
+                    .clone()
+                    .unwrap_or_else(|| self.assets_dir_or_default().join(REPORT_FILE_NAME)),
+            ),
+        })
+    }
+}
+
+const BISTRO_EXTERIOR: &str = "bistro_exterior/BistroExterior.gltf";
+const BISTRO_INTERIOR: &str = "bistro_interior_wine/BistroInterior_Wine.gltf";
+/// Lines the Bistro download's interior up with its exterior.
+const DEFAULT_INTERIOR_OFFSET: Vec3 = Vec3::new(0.0, 0.3, -0.2);
+// These are in this repo's assets directory, not in the Bistro download
+const FAKE_GI: &str = "BistroExteriorFakeGI.gltf";
+
+/// Resolves `--assets-dir` to an absolute path, since bevy resolves relative asset paths
+/// against the executable or manifest directory rather than the working directory.
+pub fn check_assets_dir(dir: &Path, args: &Args) -> Result<PathBuf, String> {
+    let dir = dir
+        .canonicalize()
+        .map_err(|e| format!("Assets directory {:?} not found: {e}", dir))?;
+    // With --assets-archive the files can be in the archive, see check_assets_archive
+    let required = if args.assets_archive.is_some() {
+        Vec::new()
+    } else {
+        required_assets(args)?
+    };
+    let missing = required
+        .into_iter()
+        .filter(|file| !dir.join(file).exists())
+        .collect::<Vec<_>>();
+    if missing.is_empty() {
+        Ok(dir)
+    } else {
+        Err(format!(
+            "Missing from assets directory {:?}: {}\n\
+            {FAKE_GI} comes from this repo's assets directory",
+            dir,
+            missing.join(", ")
+        ))
+    }
+}
+
+/// Like `check_assets_dir` for `--assets-archive`, the files can be in the archive or in the
+/// assets directory.
+pub fn check_assets_archive(
+    archive: &Archive,
+    fallback_dir: &Path,
+    args: &Args,
+) -> Result<(), String> {
+    let missing = required_assets(args)?
+        .into_iter()
+        .filter(|file| !archive.contains(Path::new(file)) && !fallback_dir.join(file).exists())
+        .collect::<Vec<_>>();
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Missing from assets archive {:?} and assets directory {:?}: {}",
+            args.assets_archive.as_deref().unwrap_or(Path::new("")),
+            fallback_dir,
+            missing.join(", ")
+        ))
+    }
+}
+
+/// The files of the scenes that will be loaded.
+fn required_assets(args: &Args) -> Result<Vec<&str>, String> {
+    let (exterior, interior) = args.scenes()?;
+    let mut required = Vec::new();
+    if exterior {
+        required.push(BISTRO_EXTERIOR);
+        if !args.no_gltf_lights {
+            required.push(FAKE_GI);
+        }
+    }
+    if interior {
+        required.push(BISTRO_INTERIOR);
+    }
+    required.extend(args.scene.iter().map(|path| gltf_path(path)));
+    Ok(required)
+}
+
+fn parse_rgb(value: &str) -> Result<[f32; 3], String> {
+    let channels = value
+        .split(',')
+        .map(|c| c.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Invalid color {value}: {e}"))?;
+    match channels[..] {
+        [r, g, b] if channels.iter().all(|c| c.is_finite()) => Ok([r, g, b]),
+        _ => Err(format!("Expected a color as R,G,B, got {value}")),
+    }
+}
+
+fn parse_vec3(value: &str) -> Result<Vec3, String> {
+    let components = value
+        .split(',')
+        .map(|c| c.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Invalid vector {value}: {e}"))?;
+    match components[..] {
+        [x, y, z] if components.iter().all(|c| c.is_finite()) => Ok(Vec3::new(x, y, z)),
+        _ => Err(format!("Expected a vector as X,Y,Z, got {value}")),
+    }
+}
+
+const DEFAULT_CAM_POS: Vec3 = Vec3::new(-10.5, 1.7, -1.0);
+const DEFAULT_CAM_LOOK: Vec3 = Vec3::new(0.0, 3.5, 0.0);
+
+/// The starting camera transform from `--cam-pos` and `--cam-look`. Errors if the camera would
+/// look at its own position or straight up or down, where `looking_at` has no sensible roll.
+fn initial_camera_transform(args: &Args) -> Result<Transform, String> {
+    let position = args.cam_pos().unwrap_or(DEFAULT_CAM_POS);
+    let target = args.cam_look().unwrap_or(DEFAULT_CAM_LOOK);
+    let direction = (target - position).normalize_or_zero();
+    if direction == Vec3::ZERO || direction.y.abs() > 0.999 {
+        return Err(format!(
+            "Can't look from {position} to {target}, using the default camera transform"
+        ));
+    }
+    Ok(Transform::from_translation(position).looking_at(target, Vec3::Y))
+}
+
+const MAX_CLEAR_COLOR: f32 = 100.0;
+const MAX_AMBIENT: f32 = 100_000.0;
+const MAX_CAMERA_SPEED: f32 = 1000.0;
+const MAX_SHADOW_BIAS: f32 = 10.0;
+const SHADOW_BIAS_STEP: f32 = 0.05;
+
+fn clamp_logged(name: &str, value: f32, max: f32) -> f32 {
+    let clamped = if value.is_nan() {
+        0.0
+    } else {
+        value.clamp(0.0, max)
+    };
+    if clamped != value {
+        println!("{name} {value} is out of range, clamped to {clamped}");
+    }
+    clamped
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AntiAliasing {
+    Taa,
+    Fxaa,
+    Msaa4,
+    Off,
+}
+
+fn parse_anti_aliasing(value: &str) -> Result<AntiAliasing, String> {
+    match value {
+        "taa" => Ok(AntiAliasing::Taa),
+        "fxaa" => Ok(AntiAliasing::Fxaa),
+        "msaa4" => Ok(AntiAliasing::Msaa4),
+        "off" => Ok(AntiAliasing::Off),
+        _ => Err(format!(
+            "Unknown antialiasing {value}, expected taa, fxaa, msaa4 or off"
+        )),
+    }
+}
+
+fn parse_msaa(value: &str) -> Result<Msaa, String> {
+    match value {
+        "off" | "1" => Ok(Msaa::Off),
+        "2" => Ok(Msaa::Sample2),
+        "4" => Ok(Msaa::Sample4),
+        "8" => Ok(Msaa::Sample8),
+        _ => Err(format!("Unknown MSAA {value}, expected off, 2, 4 or 8")),
+    }
+}
+
+fn parse_present_mode(value: &str) -> Result<PresentMode, String> {
+    match value {
+        "immediate" => Ok(PresentMode::Immediate),
+        "mailbox" => Ok(PresentMode::Mailbox),
+        "fifo" => Ok(PresentMode::Fifo),
+        _ => Err(format!(
+            "Unknown present mode {value}, expected immediate, mailbox or fifo"
+        )),
+    }
+}
+
+const TONEMAPPERS: [(Tonemapping, &str); 8] = [
+    (Tonemapping::None, "none"),
+    (Tonemapping::Reinhard, "reinhard"),
+    (Tonemapping::ReinhardLuminance, "reinhard_luminance"),
+    (Tonemapping::AcesFitted, "aces_fitted"),
+    (Tonemapping::AgX, "agx"),
+    (
+        Tonemapping::SomewhatBoringDisplayTransform,
+        "somewhat_boring_display_transform",
+    ),
+    (Tonemapping::TonyMcMapface, "tony_mc_mapface"),
+    (Tonemapping::BlenderFilmic, "blender_filmic"),
+];
+
+fn parse_tonemapping(value: &str) -> Result<Tonemapping, String> {
+    TONEMAPPERS
+        .iter()
+        .find(|(_, name)| *name == value)
+        .map(|(tonemapping, _)| *tonemapping)
+        .ok_or_else(|| {
+            format!(
+                "Unknown tonemapper {value}, expected {}",
+                TONEMAPPERS.map(|(_, name)| name).join(", ")
+            )
+        })
+}
+
+fn parse_mip_filter(value: &str) -> Result<MipFilter, String> {
+    match value {
+        "box" => Ok(MipFilter::Box),
+        "triangle" => Ok(MipFilter::Triangle),
+        "lanczos3" => Ok(MipFilter::Lanczos3),
+        _ => Err(format!(
+            "Unknown mip filter {value}, expected box, triangle or lanczos3"
+        )),
+    }
+}
+
+fn parse_transmission_quality(value: &str) -> Result<ScreenSpaceTransmissionQuality, String> {
+    match value.to_lowercase().as_str() {
+        "low" => Ok(ScreenSpaceTransmissionQuality::Low),
+        "medium" => Ok(ScreenSpaceTransmissionQuality::Medium),
+        "high" => Ok(ScreenSpaceTransmissionQuality::High),
+        "ultra" => Ok(ScreenSpaceTransmissionQuality::Ultra),
+        _ => Err(format!(
+            "Unknown transmission quality {value}, expected low, medium, high or ultra"
+        )),
+    }
+}
+
+impl Default for Args {
+    /// The values the binary runs with when no arguments are passed.
+    fn default() -> Self {
+        Args::from_args(&["bistro"], &[]).unwrap()
+    }
+}
+
+/// The Bistro scene with everything the `bistro` binary runs: loading and fixing up the scenes,
+/// the camera, its controller and key bindings, the mipmap generator, the debug views, session
+/// saving and the benchmark. Configured with [`Args`], which can also be built with
+/// `Args::from_args`. Needs `DefaultPlugins` and an [`EnvironmentMaps`] resource, which is found
+/// in the default `assets` directory if it wasn't inserted before the plugin is added.
+pub struct BistroScenePlugin {
+    args: Args,
+    benchmark: bool,
+    animation: bool,
+}
+
+impl Default for BistroScenePlugin {
+    fn default() -> Self {
+        Self::new(Args::default())
+    }
+}
+
+impl BistroScenePlugin {
+    pub fn new(args: Args) -> Self {
+        Self {
+            args,
+            benchmark: true,
+            animation: true,
+        }
+    }
+
+    /// Leaves out B and `--bench-matrix`, for apps that don't need them or use B themselves.
+    pub fn without_benchmark(mut self) -> Self {
+        self.benchmark = false;
+        self
+    }
+
+    /// Leaves out the camera path Space toggles.
+    pub fn without_animation(mut self) -> Self {
+        self.animation = false;
+        self
+    }
+}
+
+impl Plugin for BistroScenePlugin {
+    fn build(&self, app: &mut App) {
+        let args = &self.args;
+        // `main` reports these before the window opens, other apps exit with the error here
+        let config = args.scenes().and_then(|scenes| {
+            args.check_anti_aliasing()?;
+            let environment_maps = if app.world().contains_resource::<EnvironmentMaps>() {
+                None
+            } else {
+                let assets_root = FileAssetReader::new(AssetPlugin::default().file_path)
+                    .root_path()
+                    .clone();
+                Some(EnvironmentMaps::find(
+                    &assets_root,
+                    None,
+                    args.env.as_deref().unwrap_or(DEFAULT_ENVIRONMENT_MAP),
+                    args.env_intensity,
+                )?)
+            };
+            Ok((
+                scenes,
+                environment_maps,
+                load_ron_list::<MipOverride>(&args.mip_overrides)?,
+                MaterialOverrides::load(&args.material_overrides)?,
+            ))
+        });
+        let ((exterior, interior), environment_maps, mip_overrides, material_overrides) =
+            match config {
+                Ok(config) => config,
+                Err(e) => {
+                    error!("{e}");
+                    app.world_mut().send_event(AppExit::error());
+                    return;
+                }
+            };
+        if args.mip_overrides.exists() {
+            println!(
+                "{} mip overrides from {:?}",
+                mip_overrides.len(),
+                args.mip_overrides
+            );
+        }
+        if args.material_overrides.exists() {
+            println!(
+                "{} material overrides from {:?}",
+                material_overrides.0.len(),
+                args.material_overrides
+            );
+        }
+        let [r, g, b] = args
+            .clear_color
+            .map(|c| clamp_logged("Clear color channel", c, MAX_CLEAR_COLOR));
+        let ambient = clamp_logged("Ambient brightness", args.ambient, MAX_AMBIENT);
+        println!("Clear color: {r}, {g}, {b} Ambient brightness: {ambient}");
+        if args.sun_elevation != args.sun_elevation.clamp(0.0, 90.0) {
+            println!(
+                "Sun elevation {} is out of range, clamped to 0-90",
+                args.sun_elevation
+            );
+        }
+        let sun_angles = SunAngles::new(args.sun_azimuth, args.sun_elevation);
+        let shadow_settings = ShadowSettings::from_args(args);
+        if !args.scene.is_empty() {
+            if args.exterior_only || args.interior_only {
+                println!("Ignoring --exterior-only and --interior-only with --scene");
+            }
+            println!("Loading {}", args.scene.join(", "));
+        } else if !exterior || !interior {
+            println!(
+                "Loading only the {} scene",
+                if exterior { "exterior" } else { "interior" }
+            );
+        }
+        // Typos in a vector shouldn't keep the scene from starting
+        for (name, value) in [
+            ("--cam-pos", &args.cam_pos),
+            ("--cam-look", &args.cam_look),
+            ("--interior-offset", &args.interior_offset),
+        ] {
+            if let Some(Err(e)) = value.as_deref().map(parse_vec3) {
+                warn!("{e}, ignoring {name} and using the default");
+            }
+        }
+        if args.cam_pos().is_some() || args.cam_look().is_some() {
+            match initial_camera_transform(args) {
+                Ok(transform) => println!("Starting camera:\n{}", transform_const(&transform)),
+                Err(e) => println!("{e}"),
+            }
+        }
+        match args.fps_cap {
+            Some(fps) if fps > 0.0 => println!("Frame cap: {fps} fps, off while benchmarking"),
+            Some(fps) => println!("Ignoring frame cap of {fps} fps, expected more than 0"),
+            None => println!("Frame cap: off"),
+        }
+
+        if let Some(environment_maps) = environment_maps {
+            app.insert_resource(environment_maps);
+        }
+        // The benchmark reads the frame times from it
+        if !app.is_plugin_added::<FrameTimeDiagnosticsPlugin>() {
+            app.add_plugins(FrameTimeDiagnosticsPlugin);
+        }
+        if !app.is_plugin_added::<EntityCountDiagnosticsPlugin>() {
+            app.add_plugins(EntityCountDiagnosticsPlugin);
+        }
+        // The GPU timestamp queries cost a little, only with --overlay
+        if args.overlay && !app.is_plugin_added::<RenderDiagnosticsPlugin>() {
+            app.add_plugins(RenderDiagnosticsPlugin);
+        }
+        if !app.is_plugin_added::<TemporalAntiAliasPlugin>() {
+            app.add_plugins(TemporalAntiAliasPlugin);
+        }
+        if !app.is_plugin_added::<PresentModeFallbackPlugin>() {
+            app.add_plugins(PresentModeFallbackPlugin);
+        }
+
+        let (aa, msaa, aa_note) = args.anti_aliasing();
+        if let Some(note) = aa_note {
+            println!("{note}");
+        }
+        println!(
+            "{}",
+            anti_aliasing_description(aa == AntiAliasing::Taa, aa == AntiAliasing::Fxaa, msaa)
+        );
+
+        app.insert_resource(args.clone())
+            .insert_resource(material_overrides)
+            .insert_resource(msaa)
+            .insert_resource(ClearColor(sun::clear_color(args, sun_angles.sky_tint())))
+            .insert_resource(AmbientLight {
+                color: sun::ambient_color(sun_angles.sky_tint()),
+                brightness: ambient,
+            })
+            .insert_resource(sun_angles)
+            .insert_resource(shadow_settings)
+            .insert_resource(shadow_settings.shadow_map())
+            .init_resource::<LampMaterials>()
+            // Generating mipmaps takes a minute on the CPU, a couple of seconds with --gpu-mipmaps.
+            // Converted ktx2 textures are compressed and come with mips, they are skipped.
+            .insert_resource(MipmapGeneratorSettings {
+                anisotropic_filtering: 16,
+                gpu: args.gpu_mipmaps,
+                filter: args.mip_filter,
+                cache_dir: args.mip_cache.clone(),
+                min_mip_size: args.mip_min_size,
+                max_mip_levels: args.mip_max_levels,
+                overrides: mip_overrides,
+                ..default()
+            })
+            .add_plugins((
+                CameraControllerPlugin,
+                MipmapGeneratorPlugin,
+                debug_view::DebugViewPlugin,
+            ))
+            .init_resource::<SceneBounds>()
+            .insert_resource(CameraBookmarks {
+                ready: args.scene.is_empty(),
+                ..default()
+            })
+            .init_resource::<BenchmarkRunning>()
+            .init_resource::<KeyBindings>()
+            .add_systems(
+                Startup,
+                (
+                    setup,
+                    session::restore_session.after(setup),
+                    overlay::spawn_overlay.after(setup),
+                    help::spawn_help.after(setup),
+                    register_controller_keys.after(setup),
+                    register_keys,
+                    effects::register_keys,
+                    gltf_cameras::register_keys,
+                    picking::register_keys,
+                    hierarchy::register_keys,
+                    overlay::register_keys,
+                    screenshot::register_keys,
+                    session::register_keys,
+                    sun::register_keys.run_if(|args: Res<Args>| args.day_cycle.is_none()),
+                    help::register_keys,
+                ),
+            )
+            .add_systems(Last, session::save_session)
+            .add_systems(
+                Update,
+                (
+                    mipmap_systems::<StandardMaterial>(),
+                    proc_scene.run_if(any_with_component::<PostProcScene>),
+                    // After the glTF lights proc_scene despawns are gone
+                    lights::report_lights.after(proc_scene),
+                    generate_missing_tangents.run_if(benchmark_not_running),
+                    mesh_diagnostics::report_mesh_problems.run_if(benchmark_not_running),
+                    input,
+                    run_camera_transition.after(input),
+                    transmission_input,
+                    ambient_input,
+                    shadow_bias_input,
+                    sun_input.run_if(|args: Res<Args>| args.day_cycle.is_none()),
+                    interior_offset_input,
+                    environment_map_input,
+                    aa_input,
+                    enforce_msaa_compatibility.after(aa_input),
+                    picking::pick_info,
+                    picking::frame_picked.after(input),
+                    compute_scene_bounds.run_if(benchmark_not_running),
+                    fit_camera_to_bounds.after(compute_scene_bounds),
+                ),
+            );
+        if self.benchmark {
+            app.add_systems(
+                Update,
+                (
+                    benchmark.run_if(|args: Res<Args>| args.bench_matrix.is_none()),
+                    bench_matrix,
+                ),
+            )
+            .add_systems(
+                Startup,
+                |args: Res<Args>, mut bindings: ResMut<KeyBindings>| {
+                    let description = if args.bench_matrix.is_some() {
+                        "Run the benchmark matrix"
+                    } else {
+                        "Run the benchmark"
+                    };
+                    bindings.add("Tools", "B", description);
+                },
+            );
+        }
+        if self.animation {
+            app.add_systems(Update, run_animation).add_systems(
+                Startup,
+                |mut bindings: ResMut<KeyBindings>| {
+                    bindings.add("Views", "Space", "Animate the camera along the path");
+                },
+            );
+        }
+        app.add_systems(
+            Update,
+            (
+                hierarchy::dump_hierarchy,
+                effects::effect_toggle_input,
+                tonemapping_input,
+                cycle_gltf_cameras.after(input),
+                screenshot::screenshot_input,
+                overlay::update_overlay,
+                help::update_help,
+            ),
+        );
+        if args.show_cascades {
+            app.insert_resource(debug_view::DebugView {
+                material: debug_view::MaterialView::Cascades,
+                ..default()
+            });
+        }
+        if let Some(seconds_per_day) = args.day_cycle {
+            println!("Day cycle: {seconds_per_day} seconds per day, K to pause, hold X/Z to scrub");
+            app.insert_resource(DayCycle::new(seconds_per_day))
+                .add_systems(Update, (day_cycle_input, day_cycle.after(day_cycle_input)))
+                .add_systems(Startup, day_cycle::register_keys);
+        }
+        if !args.scene.is_empty() {
+            app.add_systems(Update, check_custom_scenes);
+        }
+        if args.no_frustum_culling {
+            app.add_systems(Update, add_no_frustum_culling);
+        }
+        if args.fps_cap.is_some_and(|fps| fps > 0.0) {
+            app.add_systems(Last, frame_cap);
+        }
+        match args.wind {
+            Some(strength) if strength > 0.0 => {
+                println!("Wind strength {strength}");
+                app.add_plugins(WindPlugin);
+            }
+            Some(strength) => println!("Ignoring wind strength {strength}, expected more than 0"),
+            None => (),
+        }
+        if args.split_screen {
+            app.add_systems(
+                PostUpdate,
+                (update_split_viewports, sync_comparison_camera)
+                    .before(bevy::transform::TransformSystem::TransformPropagate),
+            );
+        }
+    }
+}
+
+/// Fixups `proc_scene` applies to the hierarchy of a loaded scene. Removed once applied.
+#[derive(Component, Clone)]
+pub struct PostProcScene {
+    /// Flip the green channel of the normal maps.
+    pub flip_normal_map_y: bool,
+    /// Make alpha masked materials double sided with diffuse transmission.
+    pub enable_foliage_transmission: bool,
+    /// Despawn the lights that came with the glTF.
+    pub despawn_gltf_lights: bool,
+    /// Despawn the cameras that came with the glTF.
+    pub despawn_cameras: bool,
+}
+
+impl Default for PostProcScene {
+    fn default() -> Self {
+        Self {
+            flip_normal_map_y: true,
+            enable_foliage_transmission: true,
+            despawn_gltf_lights: false,
+            despawn_cameras: true,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct GrifLight;
+
+/// The camera the controls, bookmarks, animation and benchmark drive.
+#[derive(Component)]
+pub struct MainCamera;
+
+/// `get_single_mut` that logs a warning, once per `warned`, instead of silently doing nothing
+/// when more than one entity matches.
+pub fn single_mut_or_warn<'a, D: QueryData, F: QueryFilter>(
+    query: &'a mut Query<D, F>,
+    warned: &mut bool,
+    system: &str,
+) -> Option<D::Item<'a>> {
+    match query.get_single_mut() {
+        Ok(item) => Some(item),
+        Err(QuerySingleError::NoEntities(_)) => None,
+        Err(QuerySingleError::MultipleEntities(_)) => {
+            if !*warned {
+                *warned = true;
+                warn!("{system}: more than one camera matches, it only drives a single camera");
+            }
+            None
+        }
+    }
+}
+
+fn camera_bundle(
+    asset_server: &AssetServer,
+    args: &Args,
+    environment_maps: &EnvironmentMaps,
+) -> (Camera3dBundle, EnvironmentMapLight) {
+    (
+        Camera3dBundle {
+            camera_3d: Camera3d {
+                screen_space_specular_transmission_steps: args.transmission_steps,
+                screen_space_specular_transmission_quality: args.transmission_quality,
+                ..default()
+            },
+            camera: Camera {
+                hdr: true,
+                ..default()
+            },
+            transform: initial_camera_transform(args).unwrap_or_else(|_| {
+                Transform::from_translation(DEFAULT_CAM_POS).looking_at(DEFAULT_CAM_LOOK, Vec3::Y)
+            }),
+            projection: Projection::Perspective(PerspectiveProjection {
+                fov: std::f32::consts::PI / 3.0,
+                near: 0.1,
+                far: 1000.0,
+                aspect_ratio: 1.0,
+            }),
+            tonemapping: args.tonemapper,
+            exposure: Exposure { ev100: args.ev100 },
+            ..default()
+        },
+        environment_maps.light(asset_server),
+    )
+}
+
+pub fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    args: Res<Args>,
+    environment_maps: Res<EnvironmentMaps>,
+    sun_angles: Res<SunAngles>,
+    shadow_settings: Res<ShadowSettings>,
+) {
+    println!("Loading models, generating mipmaps");
+
+    let bistro_proc = PostProcScene {
+        despawn_gltf_lights: args.no_gltf_lights,
+        despawn_cameras: !args.keep_gltf_cameras,
+        ..default()
+    };
+
+    // Checked when the plugin was built
+    let (exterior, interior) = args.scenes().unwrap();
+
+    let mut custom_scenes = CustomScenes::default();
+    for path in &args.scene {
+        commands.spawn((
+            SceneBundle {
+                scene: asset_server.load(scene_asset_path(path)),
+                ..default()
+            },
+            bistro_proc.clone(),
+            SceneBoundsRoot,
+        ));
+        custom_scenes.push(path.clone(), asset_server.load(gltf_path(path).to_string()));
+    }
+    commands.insert_resource(custom_scenes);
+
+    if exterior {
+        commands.spawn((
+            SceneBundle {
+                scene: asset_server.load(format!("{BISTRO_EXTERIOR}#Scene0")),
+                ..default()
+            },
+            bistro_proc.clone(),
+            SceneBoundsRoot,
+        ));
+    }
+
+    if interior {
+        commands.spawn((
+            SceneBundle {
+                scene: asset_server.load(format!("{BISTRO_INTERIOR}#Scene0")),
+                transform: Transform::from_translation(args.interior_offset()),
+                ..default()
+            },
+            bistro_proc,
+            SceneBoundsRoot,
+            InteriorScene,
+        ));
+    }
+
+    // The fake GI lights the exterior
+    if exterior && !args.no_gltf_lights {
+        // In Repo glTF
+        commands.spawn(SceneBundle {
+            scene: asset_server.load(format!("{FAKE_GI}#Scene0")),
+            ..default()
+        });
+    }
+
+    // Sun
+    commands
+        .spawn(DirectionalLightBundle {
+            transform: Transform::from_rotation(sun_angles.rotation()),
+            directional_light: DirectionalLight {
+                color: SUN_COLOR,
+                illuminance: clamp_logged(
+                    "Sun illuminance",
+                    args.sun_illuminance,
+                    MAX_SUN_ILLUMINANCE,
+                ),
+                shadows_enabled: !args.minimal,
+                shadow_depth_bias: clamp_logged(
+                    "Shadow depth bias",
+                    args.shadow_depth_bias,
+                    MAX_SHADOW_BIAS,
+                ),
+                shadow_normal_bias: clamp_logged(
+                    "Shadow normal bias",
+                    args.shadow_normal_bias,
+                    MAX_SHADOW_BIAS,
+                ),
+            },
+            cascade_shadow_config: shadow_settings.cascades(None),
+            ..default()
+        })
+        .insert(GrifLight);
+
+    if args.split_screen {
+        let (mut camera, environment_map) = camera_bundle(&asset_server, &args, &environment_maps);
+        camera.camera.order = 1;
+        commands.spawn((camera, environment_map, ComparisonCamera, KeepCamera));
+        println!("Split screen: main camera on the left, no post processing on the right");
+    }
+
+    // Camera
+    let mut cam = commands.spawn((
+        camera_bundle(&asset_server, &args, &environment_maps),
+        CameraController {
+            walk_speed: clamp_logged("Walk speed", args.walk_speed, MAX_CAMERA_SPEED),
+            run_speed: clamp_logged("Run speed", args.run_speed, MAX_CAMERA_SPEED),
+            ..default()
+        }
+        .print_controls(),
+        MainCamera,
+        KeepCamera,
+    ));
+    if !args.minimal {
+        effects::set_bloom(&mut cam, true);
+        cam.insert(ContrastAdaptiveSharpeningSettings {
+            enabled: false,
+            ..default()
+        });
+        let (aa, msaa, _) = args.anti_aliasing();
+        match aa {
+            AntiAliasing::Taa => {
+                // anti_aliasing keeps MSAA off with TAA
+                effects::set_taa(&mut cam, true, msaa).unwrap();
+            }
+            AntiAliasing::Fxaa => {
+                cam.insert(Fxaa::default());
+            }
+            AntiAliasing::Msaa4 | AntiAliasing::Off => (),
+        }
+        // Left off with MSAA
+        let _ = effects::set_ssao(&mut cam, true, msaa);
+    }
+}
+
+pub fn all_children<F: FnMut(Entity)>(
+    children: &Children,
+    children_query: &Query<&Children>,
+    closure: &mut F,
+) {
+    for child in children {
+        if let Ok(children) = children_query.get(*child) {
+            all_children(children, children_query, closure);
+        }
+        closure(*child);
+    }
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn proc_scene(
+    mut commands: Commands,
+    post_proc_query: Query<(Entity, &PostProcScene)>,
+    children_query: Query<&Children>,
+    has_std_mat: Query<&Handle<StandardMaterial>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    material_overrides: Res<MaterialOverrides>,
+    gltfs: Res<Assets<Gltf>>,
+    lights: Query<
+        Entity,
+        (
+            Or<(With<PointLight>, With<DirectionalLight>, With<SpotLight>)>,
+            Without<GrifLight>,
+        ),
+    >,
+    mut cameras: Query<&mut Camera, Without<KeepCamera>>,
+    mut lamps: ResMut<LampMaterials>,
+) {
+    let mut material_names = None;
+    for (entity, post_proc) in post_proc_query.iter() {
+        let Ok(children) = children_query.get(entity) else {
+            continue;
+        };
+        // The entities of each material, so the materials Bistro's thousands of meshes share are
+        // only changed once
+        let mut users =
+            HashMap::<AssetId<StandardMaterial>, (&Handle<StandardMaterial>, Vec<Entity>)>::new();
+        // Despawned after the components are added, a mesh can be under a glTF light or camera
+        let mut despawn = Vec::new();
+        all_children(children, &children_query, &mut |entity| {
+            if let Ok(mat_h) = has_std_mat.get(entity) {
+                users
+                    .entry(mat_h.id())
+                    .or_insert_with(|| (mat_h, Vec::new()))
+                    .1
+                    .push(entity);
+            }
+
+            // Bistro has a bunch of lights by default
+            if post_proc.despawn_gltf_lights && lights.get(entity).is_ok() {
+                despawn.push(entity);
+            }
+
+            // Bistro has a bunch of cameras by default
+            if let Ok(mut camera) = cameras.get_mut(entity) {
+                if post_proc.despawn_cameras {
+                    despawn.push(entity);
+                } else {
+                    // Only the main camera renders, V moves it to the kept ones
+                    camera.is_active = false;
+                    commands.entity(entity).insert(GltfCamera);
+                }
+            }
+        });
+
+        for (mat_h, entities) in users.values() {
+            let Some(mat) = materials.get_mut(*mat_h) else {
+                continue;
+            };
+            if post_proc.flip_normal_map_y {
+                mat.flip_normal_map_y = true;
+            }
+            let foliage = matches!(mat.alpha_mode, AlphaMode::Mask(_));
+            if mat.emissive != LinearRgba::BLACK {
+                lamps.materials.entry(mat_h.id()).or_insert(mat.emissive);
+            }
+            // Whether the entities get a TransmittedShadowReceiver, None leaves them as they are
+            let mut transmitted_shadows = None;
+            match mat.alpha_mode {
+                AlphaMode::Mask(_) if post_proc.enable_foliage_transmission => {
+                    mat.diffuse_transmission = 0.6;
+                    mat.double_sided = true;
+                    mat.cull_mode = None;
+                    mat.thickness = 0.2;
+                    transmitted_shadows = Some(true);
+                }
+                AlphaMode::Opaque => {
+                    mat.double_sided = false;
+                    mat.cull_mode = Some(Face::Back);
+                }
+                _ => (),
+            }
+            if !material_overrides.0.is_empty() {
+                let names = material_names.get_or_insert_with(|| MaterialNames::new(&gltfs));
+                if material_overrides.apply(mat, mat_h.path(), names) {
+                    transmitted_shadows = Some(mat.diffuse_transmission > 0.0);
+                }
+            }
+
+            for &entity in entities {
+                let mut entity = commands.entity(entity);
+                if foliage {
+                    entity.insert(Foliage);
+                }
+                match transmitted_shadows {
+                    Some(true) => {
+                        entity.insert(TransmittedShadowReceiver);
+                    }
+                    Some(false) => {
+                        entity.remove::<TransmittedShadowReceiver>();
+                    }
+                    None => (),
+                }
+            }
+        }
+
+        for entity in despawn {
+            commands.entity(entity).despawn_recursive();
+        }
+        commands.entity(entity).remove::<PostProcScene>();
+    }
+}
+
+/// Normal mapping needs tangents, some glTF exports don't include them. New meshes wait in
+/// `pending` until their material and mesh are loaded.
+#[allow(clippy::type_complexity)]
+pub fn generate_missing_tangents(
+    new_meshes: Query<Entity, (Added<Handle<Mesh>>, With<Handle<StandardMaterial>>)>,
+    handles: Query<(&Handle<Mesh>, &Handle<StandardMaterial>)>,
+    materials: Res<Assets<StandardMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut pending: Local<Vec<Entity>>,
+    mut checked: Local<HashSet<AssetId<Mesh>>>,
+) {
+    pending.extend(&new_meshes);
+    let mut fixed = 0;
+    pending.retain(|entity| {
+        // Despawned meshes are dropped
+        let Ok((mesh_h, mat_h)) = handles.get(*entity) else {
+            return false;
+        };
+        let Some(mat) = materials.get(mat_h) else {
+            return true;
+        };
+        if mat.normal_map_texture.is_none() || checked.contains(&mesh_h.id()) {
+            return false;
+        }
+        let Some(mesh) = meshes.get(mesh_h) else {
+            return true;
+        };
+        checked.insert(mesh_h.id());
+        if mesh.contains_attribute(Mesh::ATTRIBUTE_TANGENT) {
+            return false;
+        }
+        match meshes.get_mut(mesh_h).unwrap().generate_tangents() {
+            Ok(()) => fixed += 1,
+            Err(e) => warn!("Could not generate tangents for {:?}: {}", mesh_h.path(), e),
+        }
+        false
+    });
+    if fixed > 0 {
+        println!("Generated tangents for {fixed} meshes");
+    }
+}
+
+/// Where the bookmark keys (1, 2, 3) move the camera, also the three benchmark views. Bistro's
+/// `CAM_POS_*`, or with `--scene` views of its bounds once they are known.
+#[derive(Resource, Clone, Copy)]
+pub struct CameraBookmarks {
+    pub views: [Transform; 3],
+    /// False until the views of a `--scene` are set, the benchmark waits for it.
+    pub ready: bool,
+}
+
+impl Default for CameraBookmarks {
+    fn default() -> Self {
+        Self {
+            views: [CAM_POS_1, CAM_POS_2, CAM_POS_3],
+            ready: true,
+        }
+    }
+}
+
+const CAM_POS_1: Transform = Transform {
+    translation: Vec3::new(-10.5, 1.7, -1.0),
+    rotation: Quat::from_array([-0.05678932, 0.7372272, -0.062454797, -0.670351]),
+    scale: Vec3::ONE,
+};
+
+const CAM_POS_2: Transform = Transform {
+    translation: Vec3::new(56.23809, 2.9985719, 28.96291),
+    rotation: Quat::from_array([0.0020175162, 0.35272083, -0.0007605003, 0.93572617]),
+    scale: Vec3::ONE,
+};
+
+const CAM_POS_3: Transform = Transform {
+    translation: Vec3::new(5.7861176, 3.3475509, -8.821455),
+    rotation: Quat::from_array([-0.0049382094, -0.98193514, -0.025878597, 0.18737496]),
+    scale: Vec3::ONE,
+};
+
+const ANIM_SPEED: f32 = 0.2;
+/// Blends 10% of the way to the path per frame at 60 fps, like the fixed factor it replaced.
+const ANIM_SMOOTHING: f32 = 0.16;
+
+const ANIM_CAM: [Transform; 3] = [
+    Transform {
+        translation: Vec3::new(-6.414026, 8.179898, -23.550516),
+        rotation: Quat::from_array([-0.016413536, -0.88136566, -0.030704278, 0.4711502]),
+        scale: Vec3::ONE,
+    },
+    Transform {
+        translation: Vec3::new(-14.752817, 6.279289, 5.691277),
+        rotation: Quat::from_array([-0.031593435, -0.516736, -0.019086324, 0.8553488]),
+        scale: Vec3::ONE,
+    },
+    Transform {
+        translation: Vec3::new(5.1539426, 8.142523, 16.436222),
+        rotation: Quat::from_array([-0.07907656, -0.07581916, -0.006031934, 0.99396276]),
+        scale: Vec3::ONE,
+    },
+];
+
+/// The transform as a `const` that can be pasted next to `CAM_POS_1`. The floats use their
+/// Debug formatting, which is the shortest representation that parses back to the same value.
+fn transform_const(transform: &Transform) -> String {
+    let [x, y, z] = transform.translation.to_array();
+    let [qx, qy, qz, qw] = transform.rotation.to_array();
+    format!(
+        "const CAM_POS: Transform = Transform {{
+    translation: Vec3::new({x:?}, {y:?}, {z:?}),
+    rotation: Quat::from_array([{qx:?}, {qy:?}, {qz:?}, {qw:?}]),
+    scale: Vec3::ONE,
+}};"
+    )
+}
+
+/// The root of the interior scene, moved by `--interior-offset` and `interior_offset_input`.
+#[derive(Component)]
+pub struct InteriorScene;
+
+const INTERIOR_NUDGE: f32 = 0.05;
+const INTERIOR_NUDGE_FINE: f32 = 0.01;
+
+// Alt+arrows: nudge the interior along X/Z, Alt+PageUp/PageDown along Y, add Shift for smaller
+// steps. The axes are drawn at its origin while Alt is held.
+fn interior_offset_input(
+    input: Res<ButtonInput<KeyCode>>,
+    mut interiors: Query<&mut Transform, With<InteriorScene>>,
+    mut gizmos: Gizmos,
+) {
+    if !input.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]) {
+        return;
+    }
+    for transform in &interiors {
+        gizmos.axes(*transform, 1.0);
+    }
+    let step = if input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        INTERIOR_NUDGE_FINE
+    } else {
+        INTERIOR_NUDGE
+    };
+    let mut nudge = Vec3::ZERO;
+    for (key, direction) in [
+        (KeyCode::ArrowLeft, Vec3::NEG_X),
+        (KeyCode::ArrowRight, Vec3::X),
+        (KeyCode::ArrowUp, Vec3::NEG_Z),
+        (KeyCode::ArrowDown, Vec3::Z),
+        (KeyCode::PageUp, Vec3::Y),
+        (KeyCode::PageDown, Vec3::NEG_Y),
+    ] {
+        if input.just_pressed(key) {
+            nudge += direction * step;
+        }
+    }
+    if nudge == Vec3::ZERO {
+        return;
+    }
+    for mut transform in &mut interiors {
+        transform.translation += nudge;
+        let Vec3 { x, y, z } = transform.translation;
+        println!("Interior offset: --interior-offset {x:.2},{y:.2},{z:.2}");
+    }
+}
+
+/// Moves the camera from `start` to `target` over `duration` seconds.
+#[derive(Component)]
+pub struct CameraTransition {
+    start: Transform,
+    target: Transform,
+    elapsed: f32,
+    duration: f32,
+}
+
+/// The keys of the input systems in this file, for the help.
+fn register_keys(mut bindings: ResMut<KeyBindings>) {
+    bindings
+        .add(
+            "Views",
+            "1/2/3",
+            "Camera bookmarks, Shift to jump instantly",
+        )
+        .add("Views", "O", "Look at the world origin")
+        .add(
+            "Views",
+            "I",
+            "Print the camera transform, Shift for Debug formatting",
+        )
+        .add("Effects", "Y", "Toggle TAA")
+        .add("Effects", "R", "Reset the TAA history")
+        .add("Effects", "U/J", "More or less sharpening")
+        .add("Effects", "C/Shift+C", "Next or previous tonemapper")
+        .add("Effects", "9/0", "Exposure darker or brighter")
+        .add(
+            "Effects",
+            "Period/Comma",
+            "More or fewer transmission steps",
+        )
+        .add("Effects", "T", "Cycle the transmission quality")
+        .add("Lighting", "]/[", "Ambient light brighter or darker")
+        .add(
+            "Lighting",
+            "=/-",
+            "Shadow depth bias, Shift for the normal bias",
+        )
+        .add("Lighting", "N/Shift+N", "Next or previous environment map")
+        .add(
+            "Lighting",
+            "Alt+arrows/PageUp/PageDown",
+            "Nudge the interior, Shift for 1cm",
+        );
+}
+
+// Hold shift while pressing a bookmark key to jump instantly, O looks at the world origin
+fn input(
+    mut commands: Commands,
+    input: Res<ButtonInput<KeyCode>>,
+    mut camera: Query<(Entity, &mut Transform, Option<&mut CameraController>), With<MainCamera>>,
+    args: Res<Args>,
+    bookmarks: Res<CameraBookmarks>,
+    mut warned: Local<bool>,
+) {
+    let Some((entity, mut transform, controller)) =
+        single_mut_or_warn(&mut camera, &mut warned, "input")
+    else {
+        return;
+    };
+    if input.just_pressed(KeyCode::KeyI) {
+        if input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+            info!("{:?}", transform);
+        } else {
+            println!("{}", transform_const(&transform));
+        }
+    }
+    if input.just_pressed(KeyCode::KeyO) {
+        commands.entity(entity).remove::<CameraTransition>();
+        match controller {
+            Some(mut controller) => controller.look_at(&mut transform, Vec3::ZERO),
+            None => transform.look_at(Vec3::ZERO, Vec3::Y),
+        }
+        return;
+    }
+    let target = if input.just_pressed(KeyCode::Digit1) {
+        bookmarks.views[0]
+    } else if input.just_pressed(KeyCode::Digit2) {
+        bookmarks.views[1]
+    } else if input.just_pressed(KeyCode::Digit3) {
+        bookmarks.views[2]
+    } else {
+        return;
+    };
+    let instant = input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if instant || args.bookmark_transition <= 0.0 {
+        commands.entity(entity).remove::<CameraTransition>();
+        *transform = target;
+        if let Some(mut controller) = controller {
+            // Pick up the new pitch/yaw
+            controller.initialized = false;
+        }
+    } else {
+        commands.entity(entity).insert(CameraTransition {
+            start: *transform,
+            target,
+            elapsed: 0.0,
+            duration: args.bookmark_transition,
+        });
+    }
+}
+
+fn run_camera_transition(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut camera: Query<(
+        Entity,
+        &mut Transform,
+        &mut CameraTransition,
+        Option<&mut CameraController>,
+    )>,
+) {
+    for (entity, mut transform, mut transition, controller) in &mut camera {
+        transition.elapsed += time.delta_seconds();
+        let t = (transition.elapsed / transition.duration).clamp(0.0, 1.0);
+        // Smoothstep so the camera eases in and out
+        let t = t * t * (3.0 - 2.0 * t);
+        let (start, target) = (transition.start, transition.target);
+        transform.translation = start.translation.lerp(target.translation, t);
+        transform.rotation = start.rotation.slerp(target.rotation, t);
+        if transition.elapsed >= transition.duration {
+            *transform = target;
+            commands.entity(entity).remove::<CameraTransition>();
+            if let Some(mut controller) = controller {
+                controller.initialized = false;
+            }
+        }
+    }
+}
+
+// Period/Comma: more/fewer transmission steps, T: cycle transmission quality
+fn transmission_input(
+    input: Res<ButtonInput<KeyCode>>,
+    mut cameras: Query<&mut Camera3d>,
+    msaa: Res<Msaa>,
+) {
+    if !input.any_just_pressed([KeyCode::Period, KeyCode::Comma, KeyCode::KeyT]) {
+        return;
+    }
+    if input.just_pressed(KeyCode::Period) {
+        if let Err(e) = MsaaConflict::Transmission.allowed_with(*msaa) {
+            println!("{e}");
+            return;
+        }
+    }
+    for mut camera_3d in &mut cameras {
+        let steps = camera_3d.screen_space_specular_transmission_steps;
+        if input.just_pressed(KeyCode::Period) {
+            camera_3d.screen_space_specular_transmission_steps = steps + 1;
+        }
+        if input.just_pressed(KeyCode::Comma) {
+            camera_3d.screen_space_specular_transmission_steps = steps.saturating_sub(1);
+        }
+        if input.just_pressed(KeyCode::KeyT) {
+            camera_3d.screen_space_specular_transmission_quality =
+                match camera_3d.screen_space_specular_transmission_quality {
+                    ScreenSpaceTransmissionQuality::Low => ScreenSpaceTransmissionQuality::Medium,
+                    ScreenSpaceTransmissionQuality::Medium => ScreenSpaceTransmissionQuality::High,
+                    ScreenSpaceTransmissionQuality::High => ScreenSpaceTransmissionQuality::Ultra,
+                    ScreenSpaceTransmissionQuality::Ultra => ScreenSpaceTransmissionQuality::Low,
+                };
+        }
+        println!("{}", transmission_settings(&camera_3d));
+    }
+}
+
+fn anti_aliasing_description(taa: bool, fxaa: bool, msaa: Msaa) -> String {
+    let post = match (taa, fxaa) {
+        (true, _) => "TAA",
+        (false, true) => "FXAA",
+        (false, false) => "no post process AA",
+    };
+    match msaa {
+        Msaa::Off => format!("Antialiasing: {post}, MSAA off"),
+        msaa => format!("Antialiasing: {post}, MSAA {}x", msaa.samples()),
+    }
+}
+
+fn transmission_settings(camera_3d: &Camera3d) -> String {
+    format!(
+        "Transmission: {} steps, {:?} quality",
+        camera_3d.screen_space_specular_transmission_steps,
+        camera_3d.screen_space_specular_transmission_quality
+    )
+}
+
+// ]/[: brighter/darker ambient light
+fn ambient_input(input: Res<ButtonInput<KeyCode>>, mut ambient: ResMut<AmbientLight>) {
+    let brightness = if input.just_pressed(KeyCode::BracketRight) {
+        (ambient.brightness * 1.25).max(0.001)
+    } else if input.just_pressed(KeyCode::BracketLeft) {
+        let darker = ambient.brightness / 1.25;
+        if darker < 0.001 {
+            0.0
+        } else {
+            darker
+        }
+    } else {
+        return;
+    };
+    ambient.brightness = brightness.min(MAX_AMBIENT);
+    println!("Ambient brightness: {}", ambient.brightness);
+}
+
+// =/-: more/less sun shadow depth bias, with Shift the normal bias instead
+fn shadow_bias_input(
+    input: Res<ButtonInput<KeyCode>>,
+    mut sun: Query<&mut DirectionalLight, With<GrifLight>>,
+) {
+    let step = if input.just_pressed(KeyCode::Equal) {
+        SHADOW_BIAS_STEP
+    } else if input.just_pressed(KeyCode::Minus) {
+        -SHADOW_BIAS_STEP
+    } else {
+        return;
+    };
+    let normal = input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    for mut light in &mut sun {
+        let bias = if normal {
+            &mut light.shadow_normal_bias
+        } else {
+            &mut light.shadow_depth_bias
+        };
+        *bias = (*bias + step).clamp(0.0, MAX_SHADOW_BIAS);
+        println!(
+            "Shadow bias: depth {:.2} normal {:.2}",
+            light.shadow_depth_bias, light.shadow_normal_bias
+        );
+    }
+}
+
+const EXPOSURE_STEP: f32 = 0.5;
+
+// C/Shift+C: next/previous tonemapper, 9/0: darker/brighter by half a stop. Only the tonemapping
+// and exposure components change, the other effects stay as they are. Applies to every camera so
+// the split screen halves stay comparable.
+fn tonemapping_input(
+    input: Res<ButtonInput<KeyCode>>,
+    mut cameras: Query<(&mut Tonemapping, &mut Exposure)>,
+) {
+    if input.just_pressed(KeyCode::KeyC) {
+        let back = input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+        for (mut tonemapping, _) in &mut cameras {
+            let current = TONEMAPPERS
+                .iter()
+                .position(|(t, _)| *t == *tonemapping)
+                .unwrap_or(0);
+            let next = if back {
+                (current + TONEMAPPERS.len() - 1) % TONEMAPPERS.len()
+            } else {
+                (current + 1) % TONEMAPPERS.len()
+            };
+            *tonemapping = TONEMAPPERS[next].0;
+        }
+        if let Some((tonemapping, _)) = cameras.iter().next() {
+            let (_, name) = TONEMAPPERS
+                .iter()
+                .find(|(t, _)| *t == *tonemapping)
+                .unwrap();
+            println!("Tonemapper: --tonemapper {name}");
+        }
+    }
+    let step = if input.just_pressed(KeyCode::Digit9) {
+        EXPOSURE_STEP
+    } else if input.just_pressed(KeyCode::Digit0) {
+        -EXPOSURE_STEP
+    } else {
+        return;
+    };
+    for (_, mut exposure) in &mut cameras {
+        exposure.ev100 += step;
+    }
+    if let Some((_, exposure)) = cameras.iter().next() {
+        println!("Exposure: --ev100 {}", exposure.ev100);
+    }
+}
+
+// Y: toggle TAA, R: reset TAA history, U/J: more/less sharpening (0 disables it)
+#[allow(clippy::type_complexity)]
+fn aa_input(
+    mut commands: Commands,
+    input: Res<ButtonInput<KeyCode>>,
+    mut cameras: Query<
+        (
+            Entity,
+            Option<&mut TemporalAntiAliasSettings>,
+            Option<&mut ContrastAdaptiveSharpeningSettings>,
+        ),
+        With<MainCamera>,
+    >,
+    msaa: Res<Msaa>,
+) {
+    for (entity, taa, cas) in &mut cameras {
+        if input.just_pressed(KeyCode::KeyY) {
+            // The prepasses stay, SSAO needs the depth prepass
+            match effects::set_taa(&mut commands.entity(entity), taa.is_none(), *msaa) {
+                Ok(()) if taa.is_some() => println!("TAA off"),
+                Ok(()) => println!("TAA on"),
+                Err(e) => println!("{e}"),
+            }
+        } else if input.just_pressed(KeyCode::KeyR) {
+            if let Some(mut taa) = taa {
+                taa.reset = true;
+                println!("TAA history reset");
+            }
+        }
+
+        let Some(mut cas) = cas else {
+            continue;
+        };
+        let strength = if input.just_pressed(KeyCode::KeyU) {
+            cas.sharpening_strength + 0.1
+        } else if input.just_pressed(KeyCode::KeyJ) {
+            cas.sharpening_strength - 0.1
+        } else {
+            continue;
+        };
+        cas.sharpening_strength = strength.clamp(0.0, 1.0);
+        cas.enabled = cas.sharpening_strength > 0.0;
+        println!("Sharpening: {:.1}", cas.sharpening_strength);
+    }
+}
+
+fn lerp<T>(a: T, b: T, t: f32) -> T
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T>,
+{
+    a + (b - a) * t
+}
+
+fn follow_path(points: &[Transform], progress: f32) -> Transform {
+    let total_segments = (points.len() - 1) as f32;
+    let progress = progress.clamp(0.0, 1.0);
+    let mut segment_progress = progress * total_segments;
+    let segment_index = segment_progress.floor() as usize;
+    segment_progress -= segment_index as f32;
+    let a = points[segment_index];
+    let b = points[(segment_index + 1).min(points.len() - 1)];
+    Transform {
+        translation: lerp(a.translation, b.translation, segment_progress),
+        rotation: lerp(a.rotation, b.rotation, segment_progress),
+        scale: lerp(a.scale, b.scale, segment_progress),
+    }
+}
+
+fn run_animation(
+    time: Res<Time>,
+    input: Res<ButtonInput<KeyCode>>,
+    args: Res<Args>,
+    mut animation_active: Local<bool>,
+    mut camera: Query<&mut Transform, With<MainCamera>>,
+    mut warned: Local<bool>,
+) {
+    let Some(mut cam_tr) = single_mut_or_warn(&mut camera, &mut warned, "run_animation") else {
+        return;
+    };
+    if input.just_pressed(KeyCode::Space) {
+        *animation_active = !*animation_active;
+    }
+    if !*animation_active {
+        return;
+    }
+    let progress = (time.elapsed_seconds() * ANIM_SPEED).fract();
+    let cycle = 1.0 - (progress * 2.0 - 1.0).abs();
+    let path_state = follow_path(&ANIM_CAM, cycle);
+    // Exponential low pass filter, the same smoothing at any frame rate
+    let t = if args.anim_smoothing > 0.0 {
+        1.0 - (-time.delta_seconds() / args.anim_smoothing).exp()
+    } else {
+        1.0
+    };
+    cam_tr.translation = cam_tr.translation.lerp(path_state.translation, t);
+    cam_tr.rotation = cam_tr.rotation.slerp(path_state.rotation, t);
+}
+
+/// Set while the benchmark runs so background work doesn't end up in the frame times.
+/// `generate_missing_tangents` and `compute_scene_bounds` don't run, and `generate_mipmaps` and
+/// `apply_mipmaps` are paused through `MipmapGeneratorPaused`. Asset loading itself isn't paused.
+#[derive(Resource, Default)]
+pub struct BenchmarkRunning(pub bool);
+
+fn benchmark_not_running(running: Res<BenchmarkRunning>) -> bool {
+    !running.0
+}
+
+/// Sleeps at the end of the frame so frames start at most `--fps-cap` times per second. Runs in
+/// `Last` and is skipped while `BenchmarkRunning` is set, so it never adds to benchmark frames.
+fn frame_cap(
+    args: Res<Args>,
+    running: Res<BenchmarkRunning>,
+    mut last_frame: Local<Option<Instant>>,
+    mut was_running: Local<bool>,
+) {
+    if running.0 != *was_running {
+        *was_running = running.0;
+        println!(
+            "Frame cap {}",
+            if running.0 {
+                "off for the benchmark"
+            } else {
+                "back on"
+            }
+        );
+    }
+    if !running.0 {
+        if let (Some(fps), Some(last_frame)) = (args.fps_cap, *last_frame) {
+            let frame_time = Duration::from_secs_f32(1.0 / fps);
+            if let Some(remaining) = frame_time.checked_sub(last_frame.elapsed()) {
+                std::thread::sleep(remaining);
+            }
+        }
+    }
+    *last_frame = Some(Instant::now());
+}
+
+/// Frames timed with `--fps-cap` lifted before a benchmark sizes its steps.
+const UNCAPPED_WARMUP_FRAMES: u32 = 30;
+
+/// Sizes the steps of a benchmark that just set `BenchmarkRunning`. Without `--fps-cap` that's
+/// right away, from the frame time history. With it the history is full of capped frames, so the
+/// frames rendered after the cap was lifted are timed first.
+#[derive(Default)]
+pub struct StepSizing {
+    started: Option<Instant>,
+    frames: u32,
+}
+
+impl StepSizing {
+    /// Call every frame from the one that started the benchmark, `None` until the steps are
+    /// sized.
+    pub fn frames_per_step(&mut self, diagnostics: &DiagnosticsStore, args: &Args) -> Option<u32> {
+        if !args.fps_cap.is_some_and(|fps| fps > 0.0) {
+            let frame_ms = diagnostics
+                .get(&FrameTimeDiagnosticsPlugin::FRAME_TIME)
+                .and_then(|frame_time| frame_time.average());
+            return Some(frames_per_step(frame_ms, args));
+        }
+        // Timed from the frame that started the benchmark, `frame_cap` doesn't sleep after it
+        let started = *self.started.get_or_insert_with(|| {
+            println!(
+                "Timing {UNCAPPED_WARMUP_FRAMES} frames without the frame cap to size the benchmark steps"
+            );
+            Instant::now()
+        });
+        if self.frames < UNCAPPED_WARMUP_FRAMES {
+            self.frames += 1;
+            return None;
+        }
+        let frame_ms = started.elapsed().as_secs_f64() * 1000.0 / self.frames as f64;
+        Some(frames_per_step(Some(frame_ms), args))
+    }
+}
+
+/// Frames to render per benchmark step to take around `--bench-seconds`, at least
+/// `--bench-min-frames`. `frame_ms` is an average, a single delta is too noisy at high or spiky
+/// frame rates with `PresentMode::Immediate`.
+fn frames_per_step(frame_ms: Option<f64>, args: &Args) -> u32 {
+    let min_frames = args.bench_min_frames.max(1);
+    let Some(frame_ms) = frame_ms else {
+        // No frame times yet, `--bench-min-frames` (30 by default)
+        return min_frames;
+    };
+    // Frames count as at least 0.1ms, so a step is at most 10000 frames per second of
+    // `--bench-seconds`
+    ((args.bench_seconds.max(0.0) as f64 * 1000.0 / frame_ms.max(0.1)) as u32).max(min_frames)
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn benchmark(
+    input: Res<ButtonInput<KeyCode>>,
+    mut camera: Query<
+        (
+            &mut Transform,
+            &Camera3d,
+            Has<TemporalAntiAliasSettings>,
+            Has<Fxaa>,
+        ),
+        With<MainCamera>,
+    >,
+    materials: Res<Assets<StandardMaterial>>,
+    meshes: Res<Assets<Mesh>>,
+    instances: Query<(Option<&Handle<Mesh>>, Has<Handle<StandardMaterial>>)>,
+    images: Res<Assets<Image>>,
+    bookmarks: Res<CameraBookmarks>,
+    mut bench_started: Local<Option<Instant>>,
+    mut bench_frame: Local<u32>,
+    (mut count_per_step, mut sizing): (Local<u32>, Local<StepSizing>),
+    diagnostics: Res<DiagnosticsStore>,
+    args: Res<Args>,
+    (windows, msaa, sun, shadow_map): (
+        Query<&Window, With<PrimaryWindow>>,
+        Res<Msaa>,
+        Query<(&DirectionalLight, &CascadeShadowConfig), With<GrifLight>>,
+        Res<DirectionalLightShadowMap>,
+    ),
+    mut exit: EventWriter<AppExit>,
+    (mut running, mut mipmaps_paused): (ResMut<BenchmarkRunning>, ResMut<MipmapGeneratorPaused>),
+    mut warned: Local<bool>,
+) {
+    if input.just_pressed(KeyCode::KeyB) && bench_started.is_none() && !bookmarks.ready {
+        println!("The benchmark views are set once the scene has loaded, try again then");
+    } else if input.just_pressed(KeyCode::KeyB) && bench_started.is_none() {
+        *bench_started = Some(Instant::now());
+        running.0 = true;
+        mipmaps_paused.0 = true;
+        *count_per_step = 0;
+        *sizing = StepSizing::default();
+    }
+    if bench_started.is_none() {
+        return;
+    }
+    let Some((mut transform, camera_3d, taa, fxaa)) =
+        single_mut_or_warn(&mut camera, &mut warned, "benchmark")
+    else {
+        return;
+    };
+    if *count_per_step == 0 {
+        let Some(count) = sizing.frames_per_step(&diagnostics, &args) else {
+            *transform = bookmarks.views[0];
+            return;
+        };
+        *count_per_step = count;
+        *bench_started = Some(Instant::now());
+        *bench_frame = 0;
+        if args.bench_profile {
+            bench_profile::start_recording();
+        }
+        println!(
+            "Starting Benchmark with {} frames per step",
+            *count_per_step
+        );
+    }
+    if *bench_frame == 0 {
+        *transform = bookmarks.views[0]
+    } else if *bench_frame == *count_per_step {
+        *transform = bookmarks.views[1]
+    } else if *bench_frame == *count_per_step * 2 {
+        *transform = bookmarks.views[2]
+    } else if *bench_frame == *count_per_step * 3 {
+        let elapsed = bench_started.unwrap().elapsed().as_secs_f32();
+        let avg_frame_ms = (elapsed / *bench_frame as f32) * 1000.0;
+        let vram_mb = estimated_vram_bytes(&images, &meshes) as f32 / (1024.0 * 1024.0);
+        println!("Benchmark avg cpu frame time: {:.2}ms", avg_frame_ms);
+        println!("Estimated texture + mesh memory: {:.1}MB", vram_mb);
+        println!(
+            "Meshes: {}\nMesh Instances: {}\nMaterials: {}\nMaterial Instances: {}",
+            meshes.len(),
+            instances.iter().filter(|(mesh, _)| mesh.is_some()).count(),
+            materials.len(),
+            instances.iter().filter(|(_, material)| *material).count(),
+        );
+        let geometry = GeometryCounts::new(&meshes, instances.iter().filter_map(|(mesh, _)| mesh));
+        println!(
+            "Triangles: {} unique, {} instanced\nVertices: {} unique, {} instanced",
+            geometry.unique_triangles,
+            geometry.instanced_triangles,
+            geometry.unique_vertices,
+            geometry.instanced_vertices,
+        );
+        println!("{}", transmission_settings(camera_3d));
+        println!("{}", anti_aliasing_description(taa, fxaa, *msaa));
+        for (light, cascades) in &sun {
+            println!(
+                "{}",
+                shadows::shadow_description(&shadow_map, light, cascades)
+            );
+        }
+        if let Ok(window) = windows.get_single() {
+            println!(
+                "Window: {}x{} at scale factor {}, {:?}, {:?}",
+                window.physical_width(),
+                window.physical_height(),
+                window.scale_factor(),
+                window.present_mode,
+                window.mode
+            );
+            println!(
+                "{}",
+                present_mode::present_mode_report(window.present_mode, avg_frame_ms)
+            );
+        }
+        match args.scenes() {
+            Ok((true, false)) => println!("Exterior only"),
+            Ok((false, true)) => println!("Interior only"),
+            Ok((false, false)) => println!("Scenes: {}", args.scene.join(", ")),
+            _ => (),
+        }
+        if args.bench_profile {
+            bench_profile::print_top_systems(*bench_frame, 20);
+        }
+        *bench_started = None;
+        *bench_frame = 0;
+        *transform = bookmarks.views[0];
+        running.0 = false;
+        mipmaps_paused.0 = false;
+
+        if args.max_frame_ms.is_some() || args.max_vram_mb.is_some() {
+            let mut failed = false;
+            if let Some(max_frame_ms) = args.max_frame_ms.filter(|max| avg_frame_ms > *max) {
+                println!(
+                    "FAILED: avg frame time {:.2}ms exceeds budget of {:.2}ms",
+                    avg_frame_ms, max_frame_ms
+                );
+                failed = true;
+            }
+            if let Some(max_vram_mb) = args.max_vram_mb.filter(|max| vram_mb > *max) {
+                println!(
+                    "FAILED: estimated memory {:.1}MB exceeds budget of {:.1}MB",
+                    vram_mb, max_vram_mb
+                );
+                failed = true;
+            }
+            exit.send(if failed {
+                AppExit::error()
+            } else {
+                println!("Benchmark within budget");
+                AppExit::Success
+            });
+        }
+        return;
+    }
+    *bench_frame += 1;
+}
+
+/// Rough GPU memory use of the loaded textures and meshes, from their CPU side data.
+fn estimated_vram_bytes(images: &Assets<Image>, meshes: &Assets<Mesh>) -> u64 {
+    let image_bytes: u64 = images
+        .iter()
+        .map(|(_, image)| image.data.len() as u64)
+        .sum();
+    let mesh_bytes: u64 = meshes
+        .iter()
+        .map(|(_, mesh)| {
+            let vertex_bytes = mesh.get_vertex_size() * mesh.count_vertices() as u64;
+            let index_bytes = match mesh.indices() {
+                Some(Indices::U16(indices)) => indices.len() as u64 * 2,
+                Some(Indices::U32(indices)) => indices.len() as u64 * 4,
+                None => 0,
+            };
+            vertex_bytes + index_bytes
+        })
+        .sum();
+    image_bytes + mesh_bytes
+}
+
+/// Triangle and vertex counts of the meshes in use, once per mesh asset (unique) and once per
+/// entity drawing it (instanced).
+#[derive(Default, Debug)]
+pub struct GeometryCounts {
+    pub unique_triangles: u64,
+    pub unique_vertices: u64,
+    pub instanced_triangles: u64,
+    pub instanced_vertices: u64,
+}
+
+impl GeometryCounts {
+    pub fn new<'a>(
+        meshes: &Assets<Mesh>,
+        instances: impl Iterator<Item = &'a Handle<Mesh>>,
+    ) -> Self {
+        let mut counts = GeometryCounts::default();
+        let mut seen = HashSet::new();
+        for mesh_h in instances {
+            let Some(mesh) = meshes.get(mesh_h) else {
+                continue;
+            };
+            let (triangles, vertices) = (triangle_count(mesh), mesh.count_vertices() as u64);
+            counts.instanced_triangles += triangles;
+            counts.instanced_vertices += vertices;
+            if seen.insert(mesh_h.id()) {
+                counts.unique_triangles += triangles;
+                counts.unique_vertices += vertices;
+            }
+        }
+        counts
+    }
+}
+
+/// Triangles drawn for the mesh, from its indices if it has them. Zero for points and lines.
+pub fn triangle_count(mesh: &Mesh) -> u64 {
+    let count = mesh
+        .indices()
+        .map_or(mesh.count_vertices(), |indices| indices.len()) as u64;
+    match mesh.primitive_topology() {
+        PrimitiveTopology::TriangleList => count / 3,
+        PrimitiveTopology::TriangleStrip => count.saturating_sub(2),
+        _ => 0,
+    }
+}
+
+pub fn add_no_frustum_culling(
+    mut commands: Commands,
+    convert_query: Query<Entity, (Without<NoFrustumCulling>, With<Handle<StandardMaterial>>)>,
+) {
+    for entity in convert_query.iter() {
+        commands.entity(entity).insert(NoFrustumCulling);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::render_asset::RenderAssetUsages;
+
+    use super::*;
+    use crate::test_dir::TempDir;
+
+    fn triangle() -> Mesh {
+        Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::default(),
+        )
+        .with_inserted_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0.0, 0.0, 1.0]; 3])
+        .with_inserted_attribute(
+            Mesh::ATTRIBUTE_UV_0,
+            vec![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]],
+        )
+        .with_inserted_indices(Indices::U32(vec![0, 1, 2]))
+    }
+
+    #[test]
+    fn tangents_wait_for_the_mesh_and_material() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_asset::<Image>()
+            .init_asset::<Mesh>()
+            .init_asset::<StandardMaterial>()
+            .add_systems(Update, generate_missing_tangents);
+        let world = app.world_mut();
+        let mesh_h = world.resource::<Assets<Mesh>>().reserve_handle();
+        let material_h = world
+            .resource::<Assets<StandardMaterial>>()
+            .reserve_handle();
+        world.spawn((mesh_h.clone(), material_h.clone()));
+        app.update();
+
+        // The material loads first, the mesh a few frames later
+        app.world_mut()
+            .resource_mut::<Assets<StandardMaterial>>()
+            .insert(
+                &material_h,
+                StandardMaterial {
+                    normal_map_texture: Some(Handle::default()),
+                    ..default()
+                },
+            );
+        app.update();
+        app.update();
+        app.world_mut()
+            .resource_mut::<Assets<Mesh>>()
+            .insert(&mesh_h, triangle());
+        app.update();
+
+        let meshes = app.world().resource::<Assets<Mesh>>();
+        assert!(meshes
+            .get(&mesh_h)
+            .unwrap()
+            .contains_attribute(Mesh::ATTRIBUTE_TANGENT));
+    }
+
+    #[test]
+    fn bad_vectors_fall_back_to_the_defaults() {
+        let args = Args::from_args(
+            &["bistro"],
+            &[
+                "--cam-pos",
+                "1,2",
+                "--cam-look",
+                "1, 2.5, -3",
+                "--interior-offset",
+                "a,b,c",
+            ],
+        )
+        .unwrap();
+        assert_eq!(args.cam_pos(), None);
+        assert_eq!(args.cam_look(), Some(Vec3::new(1.0, 2.5, -3.0)));
+        assert_eq!(args.interior_offset(), DEFAULT_INTERIOR_OFFSET);
+        let transform = initial_camera_transform(&args).unwrap();
+        assert_eq!(transform.translation, DEFAULT_CAM_POS);
+        assert!(parse_vec3("1,2,inf").is_err());
+    }
+
+    #[test]
+    fn bad_config_exits_instead_of_panicking() {
+        let dir = TempDir::new("bad_config");
+        let overrides = dir.join("mip_overrides.ron");
+        std::fs::write(&overrides, "[(path: ").unwrap();
+        let path = overrides.to_str().unwrap();
+        for args in [
+            &["--exterior-only", "--interior-only"][..],
+            &["--msaa", "4", "--transmission-steps", "1"],
+            &["--mip-overrides", path],
+        ] {
+            let args = Args::from_args(&["bistro"], args).unwrap();
+            assert!(args.check().is_err());
+            let mut app = App::new();
+            app.add_plugins((MinimalPlugins, BistroScenePlugin::new(args)));
+            assert_eq!(app.should_exit(), Some(AppExit::error()));
+        }
+    }
+
+    #[test]
+    fn steps_are_sized_from_uncapped_frames() {
+        let diagnostics = DiagnosticsStore::default();
+        let args = Args::from_args(&["bistro"], &["--bench-min-frames", "5"]).unwrap();
+        // No history, the minimum right away
+        assert_eq!(
+            StepSizing::default().frames_per_step(&diagnostics, &args),
+            Some(5)
+        );
+
+        let args =
+            Args::from_args(&["bistro"], &["--fps-cap", "30", "--bench-seconds", "0"]).unwrap();
+        let mut sizing = StepSizing::default();
+        for _ in 0..UNCAPPED_WARMUP_FRAMES {
+            assert_eq!(sizing.frames_per_step(&diagnostics, &args), None);
+        }
+        assert_eq!(sizing.frames_per_step(&diagnostics, &args), Some(30));
+    }
+
+    #[test]
+    fn prune_switches() {
+        let prune_mode = |args: &[&str]| Args::from_args(&["bistro"], args).unwrap().prune_mode();
+        assert_eq!(prune_mode(&[]), None);
+        assert_eq!(prune_mode(&["--convert-prune"]), Some(PruneMode::Delete));
+        assert_eq!(
+            prune_mode(&["--convert-prune-archive"]),
+            Some(PruneMode::Archive)
+        );
+        assert_eq!(
+            prune_mode(&["--convert-prune", "--convert-prune-archive"]),
+            Some(PruneMode::Archive)
+        );
+    }
+
+    #[test]
+    fn convert_report_goes_to_the_assets_dir() {
+        let report = |args: &[&str]| {
+            let args = Args::from_args(&["bistro"], args).unwrap();
+            args.convert_settings().unwrap().report.unwrap()
+        };
+        assert_eq!(report(&[]), Path::new("./assets/convert_report.txt"));
+        assert_eq!(
+            report(&["--assets-dir", "/data/bistro"]),
+            Path::new("/data/bistro/convert_report.txt")
+        );
+        assert_eq!(
+            report(&["--convert-report", "out/report.txt"]),
+            Path::new("out/report.txt")
+        );
+    }
+}
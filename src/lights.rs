@@ -0,0 +1,185 @@
+use bevy::prelude::*;
+
+use crate::{Args, GrifLight, MainCamera};
+
+/// Lights closer together than this, in meters, are reported as duplicates.
+const DUPLICATE_DISTANCE: f32 = 0.001;
+
+/// A point or spot light from the scene, as far as the checks below are concerned.
+#[derive(Clone, Debug)]
+struct SceneLight {
+    name: String,
+    position: Vec3,
+    intensity: f32,
+    range: f32,
+}
+
+/// Indices of the lights with no intensity or range, they light nothing.
+fn degenerate_lights(lights: &[SceneLight]) -> Vec<usize> {
+    (0..lights.len())
+        .filter(|&i| lights[i].intensity <= 0.0 || lights[i].range <= 0.0)
+        .collect()
+}
+
+/// Pairs of indices of lights within `DUPLICATE_DISTANCE` of each other, the first of each pair
+/// is the lower index.
+fn duplicate_lights(lights: &[SceneLight]) -> Vec<(usize, usize)> {
+    let mut order = (0..lights.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| lights[a].position.x.total_cmp(&lights[b].position.x));
+    let mut pairs = Vec::new();
+    for (n, &a) in order.iter().enumerate() {
+        for &b in &order[n + 1..] {
+            if lights[b].position.x - lights[a].position.x > DUPLICATE_DISTANCE {
+                break;
+            }
+            if lights[a].position.distance(lights[b].position) <= DUPLICATE_DISTANCE {
+                pairs.push((a.min(b), a.max(b)));
+            }
+        }
+    }
+    pairs.sort();
+    pairs
+}
+
+fn report_light_problems(lights: &[SceneLight]) {
+    let degenerate = degenerate_lights(lights);
+    if !degenerate.is_empty() {
+        println!(
+            "Warning: {} lights have zero intensity or range and light nothing:",
+            degenerate.len()
+        );
+        for i in degenerate {
+            let light = &lights[i];
+            println!(
+                "    {:?} at {} (intensity {}, range {})",
+                light.name, light.position, light.intensity, light.range
+            );
+        }
+    }
+    let duplicates = duplicate_lights(lights);
+    if !duplicates.is_empty() {
+        println!(
+            "Warning: {} lights are at the same position as another one:",
+            duplicates.len()
+        );
+        for (a, b) in duplicates {
+            println!(
+                "    {:?} and {:?} at {}",
+                lights[a].name, lights[b].name, lights[a].position
+            );
+        }
+    }
+}
+
+/// Reports the lights that came with the scenes as they spawn, and the ones that light nothing
+/// or sit on top of another light. With `--max-lights N`, despawns
+/// all but the N point and spot lights that are brightest from the camera, estimated as
+/// intensity over squared distance. Directional lights are counted but always kept.
+#[allow(clippy::type_complexity)]
+pub fn report_lights(
+    mut commands: Commands,
+    args: Res<Args>,
+    added: Query<
+        (),
+        (
+            Or<(Added<PointLight>, Added<SpotLight>, Added<DirectionalLight>)>,
+            Without<GrifLight>,
+        ),
+    >,
+    point_lights: Query<(Entity, &PointLight, &GlobalTransform, Option<&Name>), Without<GrifLight>>,
+    spot_lights: Query<(Entity, &SpotLight, &GlobalTransform, Option<&Name>), Without<GrifLight>>,
+    directional_lights: Query<(), (With<DirectionalLight>, Without<GrifLight>)>,
+    camera: Query<&GlobalTransform, With<MainCamera>>,
+) {
+    if added.is_empty() {
+        return;
+    }
+    println!(
+        "Scene lights: {} point, {} spot, {} directional",
+        point_lights.iter().len(),
+        spot_lights.iter().len(),
+        directional_lights.iter().len()
+    );
+    let scene_light =
+        |position: &GlobalTransform, name: Option<&Name>, intensity, range| SceneLight {
+            name: name.map(|name| name.to_string()).unwrap_or_default(),
+            position: position.translation(),
+            intensity,
+            range,
+        };
+    let scene_lights = point_lights
+        .iter()
+        .map(|(_, light, transform, name)| {
+            scene_light(transform, name, light.intensity, light.range)
+        })
+        .chain(spot_lights.iter().map(|(_, light, transform, name)| {
+            scene_light(transform, name, light.intensity, light.range)
+        }))
+        .collect::<Vec<_>>();
+    report_light_problems(&scene_lights);
+    let Some(max_lights) = args.max_lights else {
+        return;
+    };
+    let camera_position = camera
+        .iter()
+        .next()
+        .map(|transform| transform.translation())
+        .unwrap_or_default();
+    let brightness = |intensity: f32, transform: &GlobalTransform| {
+        intensity
+            / transform
+                .translation()
+                .distance_squared(camera_position)
+                .max(1.0)
+    };
+    let mut lights =
+        point_lights
+            .iter()
+            .map(|(entity, light, transform, _)| (entity, brightness(light.intensity, transform)))
+            .chain(spot_lights.iter().map(|(entity, light, transform, _)| {
+                (entity, brightness(light.intensity, transform))
+            }))
+            .collect::<Vec<_>>();
+    if lights.len() <= max_lights {
+        return;
+    }
+    lights.sort_by(|a, b| b.1.total_cmp(&a.1));
+    for (entity, _) in &lights[max_lights..] {
+        commands.entity(*entity).despawn_recursive();
+    }
+    println!(
+        "Kept the {max_lights} brightest of {} point and spot lights (--max-lights)",
+        lights.len()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn light(name: &str, position: [f32; 3], intensity: f32, range: f32) -> SceneLight {
+        SceneLight {
+            name: name.to_string(),
+            position: Vec3::from_array(position),
+            intensity,
+            range,
+        }
+    }
+
+    #[test]
+    fn degenerate_and_duplicate_lights() {
+        let lights = [
+            light("lamp", [1.0, 2.0, 3.0], 1000.0, 20.0),
+            light("off", [5.0, 0.0, 0.0], 0.0, 20.0),
+            light("no_range", [6.0, 0.0, 0.0], 1000.0, 0.0),
+            light("lamp_copy", [1.0, 2.0, 3.0005], 1000.0, 20.0),
+            light("near_lamp", [1.0, 2.1, 3.0], 1000.0, 20.0),
+            light("far", [-4.0, 0.0, 0.0], 500.0, 10.0),
+            light("far_copy", [-4.0, 0.0, 0.0], 500.0, 10.0),
+        ];
+        assert_eq!(degenerate_lights(&lights), [1, 2]);
+        assert_eq!(duplicate_lights(&lights), [(0, 3), (5, 6)]);
+        assert!(duplicate_lights(&lights[..3]).is_empty());
+        assert!(duplicate_lights(&[]).is_empty());
+    }
+}
@@ -0,0 +1,140 @@
+use std::path::{Path, PathBuf};
+
+use bevy::{
+    asset::AssetPath, gltf::Gltf, prelude::*, render::render_resource::Face, utils::HashMap,
+};
+use serde::Deserialize;
+
+use crate::convert::{glob_match, load_ron_list};
+
+pub const DEFAULT_MATERIAL_OVERRIDES_PATH: &str = "material_overrides.ron";
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum CullMode {
+    Back,
+    Front,
+    None,
+}
+
+impl CullMode {
+    fn face(self) -> Option<Face> {
+        match self {
+            CullMode::Back => Some(Face::Back),
+            CullMode::Front => Some(Face::Front),
+            CullMode::None => None,
+        }
+    }
+}
+
+/// Per-material settings from `material_overrides.ron`, applied by `proc_scene` after its alpha
+/// mode heuristic. Every entry that matches is applied in order, so later entries win. Unset
+/// fields keep what the heuristic chose.
+///
+/// ```ron
+/// [
+///     (name: "Foliage_*", thickness: 0.1),
+///     (name: "MASTER_Glass_Clean", double_sided: true, cull_mode: none),
+///     (file: "BistroExterior.gltf", index: 42, cull_mode: back, diffuse_transmission: 0.0),
+/// ]
+/// ```
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct MaterialOverride {
+    /// Matched against the glTF material name, supports `*` and `?` wildcards.
+    pub name: Option<String>,
+    /// Matched against the glTF file name, supports `*` and `?` wildcards.
+    pub file: Option<String>,
+    /// Index of the material in its glTF file, for unnamed materials.
+    pub index: Option<usize>,
+    pub double_sided: Option<bool>,
+    pub cull_mode: Option<CullMode>,
+    /// 0 turns diffuse transmission off.
+    pub diffuse_transmission: Option<f32>,
+    pub thickness: Option<f32>,
+}
+
+#[derive(Resource, Default)]
+pub struct MaterialOverrides(pub Vec<MaterialOverride>);
+
+impl MaterialOverrides {
+    /// Empty if the file doesn't exist.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        load_ron_list(path).map(Self)
+    }
+
+    /// Applies the matching entries, returns whether any matched.
+    pub fn apply(
+        &self,
+        material: &mut StandardMaterial,
+        path: Option<&AssetPath>,
+        names: &MaterialNames,
+    ) -> bool {
+        let key = path.and_then(material_key);
+        let file = key
+            .as_ref()
+            .and_then(|(file, _)| file.file_name())
+            .map(|name| name.to_string_lossy());
+        let name = key.as_ref().and_then(|key| names.0.get(key));
+        let mut matched = false;
+        for entry in &self.0 {
+            let matches = entry
+                .name
+                .as_ref()
+                .is_none_or(|glob| name.is_some_and(|name| glob_match(glob, name)))
+                && entry
+                    .file
+                    .as_ref()
+                    .is_none_or(|glob| file.as_ref().is_some_and(|file| glob_match(glob, file)))
+                && entry
+                    .index
+                    .is_none_or(|index| key.as_ref().is_some_and(|key| key.1 == index));
+            if !matches {
+                continue;
+            }
+            matched = true;
+            if let Some(double_sided) = entry.double_sided {
+                material.double_sided = double_sided;
+            }
+            if let Some(cull_mode) = entry.cull_mode {
+                material.cull_mode = cull_mode.face();
+            }
+            if let Some(diffuse_transmission) = entry.diffuse_transmission {
+                material.diffuse_transmission = diffuse_transmission;
+            }
+            if let Some(thickness) = entry.thickness {
+                material.thickness = thickness;
+            }
+        }
+        matched
+    }
+}
+
+/// glTF material names by file and material index.
+#[derive(Default)]
+pub struct MaterialNames(HashMap<(PathBuf, usize), String>);
+
+impl MaterialNames {
+    pub fn new(gltfs: &Assets<Gltf>) -> Self {
+        let mut names = HashMap::new();
+        for (_, gltf) in gltfs.iter() {
+            for (name, handle) in &gltf.named_materials {
+                if let Some(key) = handle.path().and_then(material_key) {
+                    names.insert(key, name.to_string());
+                }
+            }
+        }
+        Self(names)
+    }
+}
+
+/// The glTF file and material index from a `Material{index}` label. Materials of meshes with
+/// an inverted scale are a separate asset labeled `Material{index} (inverted)`, they get the
+/// same key.
+fn material_key(path: &AssetPath) -> Option<(PathBuf, usize)> {
+    let index = path.label()?.strip_prefix("Material")?;
+    let digits = index
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(index.len());
+    Some((path.path().to_path_buf(), index[..digits].parse().ok()?))
+}
@@ -0,0 +1,105 @@
+use bevy::{
+    prelude::*,
+    render::{
+        mesh::{Indices, VertexAttributeValues},
+        render_resource::PrimitiveTopology,
+    },
+    utils::HashSet,
+};
+
+/// Problem meshes listed by name per summary, the rest are only counted.
+const MAX_LISTED: usize = 20;
+
+/// What's wrong with a mesh, empty if nothing is. Missing UVs leave it untextured, missing
+/// normals unlit, and zero-area triangles break tangent generation.
+fn mesh_problems(mesh: &Mesh) -> Vec<String> {
+    let mut problems = Vec::new();
+    if !mesh.contains_attribute(Mesh::ATTRIBUTE_UV_0) {
+        problems.push("no UVs".to_string());
+    }
+    if !mesh.contains_attribute(Mesh::ATTRIBUTE_NORMAL) {
+        problems.push("no normals".to_string());
+    }
+    let degenerate = degenerate_triangles(mesh);
+    if degenerate > 0 {
+        problems.push(format!("{degenerate} zero-area triangles"));
+    }
+    problems
+}
+
+/// Triangles whose corners are the same point or on a line, with an angle under about 1e-5
+/// radians between their edges, so the check doesn't depend on the scale of the mesh.
+fn degenerate_triangles(mesh: &Mesh) -> usize {
+    if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+        return 0;
+    }
+    let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+    else {
+        return 0;
+    };
+    let indices = match mesh.indices() {
+        Some(Indices::U16(indices)) => indices.iter().map(|i| *i as usize).collect(),
+        Some(Indices::U32(indices)) => indices.iter().map(|i| *i as usize).collect(),
+        None => (0..positions.len()).collect::<Vec<_>>(),
+    };
+    indices
+        .chunks_exact(3)
+        .filter(|triangle| {
+            let [a, b, c] = [0, 1, 2].map(|i| Vec3::from(positions[triangle[i]]));
+            let (ab, ac) = (b - a, c - a);
+            ab.cross(ac).length_squared() <= 1e-10 * ab.length_squared() * ac.length_squared()
+        })
+        .count()
+}
+
+/// Checks every mesh once it's loaded and lists the ones with problems by entity name, or by
+/// asset path for unnamed entities.
+#[allow(clippy::type_complexity)]
+pub fn report_mesh_problems(
+    new_meshes: Query<(&Handle<Mesh>, Option<&Name>), Added<Handle<Mesh>>>,
+    meshes: Res<Assets<Mesh>>,
+    mut pending: Local<Vec<(Handle<Mesh>, Option<Name>)>>,
+    mut checked: Local<HashSet<AssetId<Mesh>>>,
+) {
+    pending.extend(
+        new_meshes
+            .iter()
+            .map(|(mesh_h, name)| (mesh_h.clone(), name.cloned())),
+    );
+    if pending.is_empty() {
+        return;
+    }
+    let mut count = 0;
+    let mut found = Vec::new();
+    pending.retain(|(mesh_h, name)| {
+        let Some(mesh) = meshes.get(mesh_h) else {
+            // Not loaded yet
+            return true;
+        };
+        if !checked.insert(mesh_h.id()) {
+            return false;
+        }
+        count += 1;
+        let problems = mesh_problems(mesh);
+        if !problems.is_empty() {
+            let name = match (name, mesh_h.path()) {
+                (Some(name), _) => name.to_string(),
+                (None, Some(path)) => path.to_string(),
+                (None, None) => format!("{:?}", mesh_h.id()),
+            };
+            found.push((name, problems));
+        }
+        false
+    });
+    if found.is_empty() {
+        return;
+    }
+    println!("{} of {count} new meshes have problems:", found.len());
+    for (name, problems) in found.iter().take(MAX_LISTED) {
+        println!("    {name}: {}", problems.join(", "));
+    }
+    if found.len() > MAX_LISTED {
+        println!("    and {} more", found.len() - MAX_LISTED);
+    }
+}
@@ -0,0 +1,238 @@
+use std::{
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    io,
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use bevy::prelude::*;
+
+use crate::mipmap_generator::{
+    generate_mips_texture, MipContent, MipLimits, MipmapGeneratorSettings,
+};
+use crate::mipmap_gpu::chain_len;
+
+const MAGIC: &[u8; 4] = b"BMIP";
+/// Bumped when the generated mips change for the same settings, so old entries are missed.
+const VERSION: u32 = 1;
+const HEADER_LEN: usize = 28;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CacheStatus {
+    Off,
+    Hit,
+    Miss,
+}
+
+/// Hashes the source image and everything that changes its mips. `DefaultHasher::new` always
+/// uses the same keys, so the key is stable between runs of the same build.
+fn cache_key(
+    image: &Image,
+    settings: &MipmapGeneratorSettings,
+    limits: MipLimits,
+    normal_map: bool,
+) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    VERSION.hash(&mut hasher);
+    image.texture_descriptor.format.hash(&mut hasher);
+    image.texture_descriptor.size.width.hash(&mut hasher);
+    image.texture_descriptor.size.height.hash(&mut hasher);
+    image.data.hash(&mut hasher);
+    settings.filter.hash(&mut hasher);
+    limits
+        .level_count(image.width(), image.height())
+        .hash(&mut hasher);
+    MipContent::of(image, normal_map).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The mip level count and data of an entry, `None` if it's missing or for another image.
+fn read(path: &Path, image: &Image, key: u64) -> Option<(u32, Vec<u8>)> {
+    let bytes = fs::read(path).ok()?;
+    let word = |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+    if bytes.len() < HEADER_LEN
+        || &bytes[..4] != MAGIC
+        || word(4) != VERSION
+        || word(8) != image.texture_descriptor.size.width
+        || word(12) != image.texture_descriptor.size.height
+        || u64::from_le_bytes(bytes[20..28].try_into().unwrap()) != key
+    {
+        return None;
+    }
+    let mip_level_count = word(16);
+    let data = &bytes[HEADER_LEN..];
+    (data.len() == chain_len(image, mip_level_count)).then(|| (mip_level_count, data.to_vec()))
+}
+
+fn write(path: &Path, image: &Image, key: u64) -> io::Result<()> {
+    let descriptor = &image.texture_descriptor;
+    let mut bytes = Vec::with_capacity(HEADER_LEN + image.data.len());
+    bytes.extend_from_slice(MAGIC);
+    for word in [
+        VERSION,
+        descriptor.size.width,
+        descriptor.size.height,
+        descriptor.mip_level_count,
+    ] {
+        bytes.extend_from_slice(&word.to_le_bytes());
+    }
+    bytes.extend_from_slice(&key.to_le_bytes());
+    bytes.extend_from_slice(&image.data);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // Written next to it and renamed, so a run that's closed halfway doesn't leave a torn entry.
+    // The name is unique to this write, two writers of the same entry would interleave otherwise.
+    static WRITES: AtomicU64 = AtomicU64::new(0);
+    let partial = path.with_extension(format!(
+        "{}.{}.partial",
+        std::process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result = fs::write(&partial, bytes).and_then(|()| fs::rename(&partial, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    result
+}
+
+/// Loads the mips of the image from the cache directory, or generates them and stores them
+/// there. An entry whose source changed has another key and is simply never read again.
+pub fn load_or_generate(
+    dir: &Path,
+    image: &mut Image,
+    settings: &MipmapGeneratorSettings,
+    limits: MipLimits,
+    normal_map: bool,
+) -> CacheStatus {
+    let key = cache_key(image, settings, limits, normal_map);
+    let path = dir.join(format!("{key:016x}.mips"));
+    if let Some((mip_level_count, data)) = read(&path, image, key) {
+        image.texture_descriptor.mip_level_count = mip_level_count;
+        image.data = data;
+        return CacheStatus::Hit;
+    }
+    if let Err(e) = generate_mips_texture(image, settings, limits, normal_map) {
+        warn!("{}", e);
+        return CacheStatus::Miss;
+    }
+    if let Err(e) = write(&path, image, key) {
+        warn!("Failed to write mipmap cache entry {:?}: {e}", path);
+    }
+    CacheStatus::Miss
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, sync::Arc, thread};
+
+    use bevy::render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+    };
+
+    use super::*;
+    use crate::test_dir::TempDir;
+
+    const LIMITS: MipLimits = MipLimits {
+        max_levels: None,
+        min_size: 1,
+    };
+
+    fn source(width: u32, height: u32) -> Image {
+        let data = (0..width * height * 4).map(|i| (i * 7) as u8).collect();
+        Image::new(
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            data,
+            TextureFormat::Rgba8Unorm,
+            RenderAssetUsages::default(),
+        )
+    }
+
+    fn entry_path(dir: &Path, image: &Image, settings: &MipmapGeneratorSettings) -> PathBuf {
+        let key = cache_key(image, settings, LIMITS, false);
+        dir.join(format!("{key:016x}.mips"))
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = TempDir::new("mip_cache_round_trip");
+        let settings = MipmapGeneratorSettings::default();
+        let mut generated = source(16, 8);
+        let status = load_or_generate(&dir, &mut generated, &settings, LIMITS, false);
+        assert_eq!(status, CacheStatus::Miss);
+        assert_eq!(generated.texture_descriptor.mip_level_count, 4);
+
+        let mut cached = source(16, 8);
+        let status = load_or_generate(&dir, &mut cached, &settings, LIMITS, false);
+        assert_eq!(status, CacheStatus::Hit);
+        assert_eq!(cached.texture_descriptor.mip_level_count, 4);
+        assert_eq!(cached.data, generated.data);
+    }
+
+    #[test]
+    fn stale_and_corrupt_entries_are_missed() {
+        let dir = TempDir::new("mip_cache_stale");
+        let settings = MipmapGeneratorSettings::default();
+        let original = source(16, 8);
+        let mut generated = original.clone();
+        load_or_generate(&dir, &mut generated, &settings, LIMITS, false);
+        let path = entry_path(&dir, &original, &settings);
+        let key = cache_key(&original, &settings, LIMITS, false);
+        assert!(read(&path, &original, key).is_some());
+
+        // Another key, or an image of another size under the same name
+        assert!(read(&path, &original, key ^ 1).is_none());
+        assert!(read(&path, &source(8, 16), key).is_none());
+
+        // A truncated entry is regenerated and rewritten
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(read(&path, &original, key).is_none());
+        fs::write(&path, &bytes[..HEADER_LEN - 1]).unwrap();
+        assert!(read(&path, &original, key).is_none());
+        let mut regenerated = original.clone();
+        let status = load_or_generate(&dir, &mut regenerated, &settings, LIMITS, false);
+        assert_eq!(status, CacheStatus::Miss);
+        assert_eq!(regenerated.data, generated.data);
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+    }
+
+    #[test]
+    fn concurrent_writers_leave_a_whole_entry() {
+        let dir = TempDir::new("mip_cache_concurrent");
+        let settings = MipmapGeneratorSettings::default();
+        let original = source(64, 64);
+        let key = cache_key(&original, &settings, LIMITS, false);
+        let path = entry_path(&dir, &original, &settings);
+        let mut image = original.clone();
+        generate_mips_texture(&mut image, &settings, LIMITS, false).unwrap();
+        let image = Arc::new(image);
+
+        let writers = (0..8)
+            .map(|_| {
+                let (path, image) = (path.clone(), image.clone());
+                thread::spawn(move || {
+                    for _ in 0..16 {
+                        write(&path, &image, key).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let (mip_level_count, data) = read(&path, &original, key).unwrap();
+        assert_eq!(mip_level_count, image.texture_descriptor.mip_level_count);
+        assert_eq!(data, image.data);
+        // Every partial file was renamed over the entry
+        assert_eq!(fs::read_dir(&*dir).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,14 @@
+// Fills one mip level from the one above it for the GPU path of the mipmap generator. Every
+// output texel sits on the corner of four source texels, so the bilinear sample is their box
+// filtered average. sRGB textures are decoded on sampling and encoded on write, so the average
+// is computed in linear space.
+
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var source_sampler: sampler;
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(source, source_sampler, in.uv);
+}
@@ -2,18 +2,32 @@
 
 use anyhow::anyhow;
 
+use std::{
+    f32::consts::PI,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
 use bevy::render::render_asset::RenderAssetUsages;
 use bevy::{
+    asset::AssetPath,
+    ecs::schedule::SystemConfigs,
+    pbr::{ExtendedMaterial, MaterialExtension},
     prelude::*,
     render::{
         render_resource::{Extent3d, TextureDimension, TextureFormat},
         texture::{ImageSampler, ImageSamplerDescriptor},
     },
     tasks::{AsyncComputeTaskPool, Task},
-    utils::HashMap,
+    utils::{HashMap, HashSet},
 };
 use futures_lite::future;
-use image::{imageops::FilterType, DynamicImage, ImageBuffer};
+use image::{DynamicImage, ImageBuffer};
+use serde::Deserialize;
+
+use crate::convert::glob_match;
+use crate::mipmap_cache::{load_or_generate, CacheStatus};
+use crate::mipmap_gpu::{allocate_mips, gpu_supported, GpuMipmapPlugin, GpuMipmapQueue};
 
 #[derive(Resource, Deref)]
 pub struct DefaultSampler(ImageSamplerDescriptor);
@@ -22,32 +36,252 @@ pub struct DefaultSampler(ImageSamplerDescriptor);
 pub struct MipmapGeneratorSettings {
     /// Valid values: 1, 2, 4, 8, and 16.
     pub anisotropic_filtering: u16,
-    pub filter_type: FilterType,
-    pub minimum_mip_resolution: u32,
+    pub filter: MipFilter,
+    /// Levels are generated until the next one would be smaller than this on either side, 1
+    /// for the whole chain.
+    pub min_mip_size: u32,
+    /// Levels to generate including the full size one, `None` for the whole chain.
+    pub max_mip_levels: Option<u32>,
+    /// Per-image `min_mip_size` and `max_mip_levels`, see `MipOverride`.
+    pub overrides: Vec<MipOverride>,
+    /// Fill the mip levels with render passes on the GPU instead of resizing on the CPU. The
+    /// GPU path is always a box filter, normal maps and formats it can't render to still use
+    /// the CPU.
+    pub gpu: bool,
+    /// CPU tasks running at once, materials with more images wait for the next frames. Every
+    /// task holds a copy of its image.
+    pub max_concurrent_tasks: usize,
+    /// Finished images swapped into `Assets<Image>` per frame by `apply_mipmaps`, each one is
+    /// uploaded to the GPU again.
+    pub max_applied_per_frame: usize,
+    /// Directory the CPU generated mip chains are stored in and loaded from on later runs,
+    /// keyed by a hash of the source image and the settings above.
+    pub cache_dir: Option<PathBuf>,
 }
 
 ///Mipmaps will not be generated for materials found on entities that also have the `NoMipmapGeneration` component.
 #[derive(Component)]
 pub struct NoMipmapGeneration;
 
+/// The filter every mip level is downsampled with from the level above it.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum MipFilter {
+    /// Averages the 2x2 texels under each texel. Sharp, but fine detail shimmers in the distance.
+    Box,
+    /// A tent over the 4x4 texels around each texel.
+    Triangle,
+    /// Lanczos with 3 lobes over 12x12 texels. Keeps more detail than the triangle filter with
+    /// less shimmering than the box filter, but can ring around hard edges.
+    Lanczos3,
+}
+
+impl MipFilter {
+    /// Half the width of the filter, in texels of the smaller level.
+    fn support(self) -> f32 {
+        match self {
+            MipFilter::Box => 0.5,
+            MipFilter::Triangle => 1.0,
+            MipFilter::Lanczos3 => 3.0,
+        }
+    }
+
+    fn weight(self, x: f32) -> f32 {
+        let sinc = |x: f32| {
+            if x == 0.0 {
+                1.0
+            } else {
+                (x * PI).sin() / (x * PI)
+            }
+        };
+        match self {
+            MipFilter::Box => (x.abs() <= 0.5) as u8 as f32,
+            MipFilter::Triangle => (1.0 - x.abs()).max(0.0),
+            MipFilter::Lanczos3 if x.abs() < 3.0 => sinc(x) * sinc(x / 3.0),
+            MipFilter::Lanczos3 => 0.0,
+        }
+    }
+}
+
+/// How the texels are decoded before filtering and encoded again after.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum MipContent {
+    /// Data like roughness, metallic and occlusion, filtered as stored.
+    Linear,
+    /// sRGB color, averaged in linear space so the smaller levels don't get darker. Alpha is
+    /// linear.
+    Srgb,
+    /// Tangent space normals, renormalized on every level.
+    NormalMap,
+}
+
+impl MipContent {
+    pub fn of(image: &Image, normal_map: bool) -> Self {
+        if normal_map {
+            MipContent::NormalMap
+        } else if image.texture_descriptor.format.is_srgb() {
+            MipContent::Srgb
+        } else {
+            MipContent::Linear
+        }
+    }
+}
+
+impl MipmapGeneratorSettings {
+    /// The limits for an image, with the matching overrides applied in order.
+    pub fn limits_for(&self, path: Option<&AssetPath>) -> MipLimits {
+        let mut limits = MipLimits {
+            max_levels: self.max_mip_levels,
+            min_size: self.min_mip_size,
+        };
+        let path = path.map(|path| path.to_string());
+        for entry in &self.overrides {
+            if !path
+                .as_ref()
+                .is_some_and(|path| glob_match(&entry.path, path))
+            {
+                continue;
+            }
+            if let Some(max_levels) = entry.max_levels {
+                limits.max_levels = Some(max_levels);
+            }
+            if let Some(min_size) = entry.min_size {
+                limits.min_size = min_size;
+            }
+        }
+        limits
+    }
+}
+
+/// How many levels to generate for an image.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct MipLimits {
+    pub max_levels: Option<u32>,
+    pub min_size: u32,
+}
+
+impl MipLimits {
+    pub fn level_count(self, width: u32, height: u32) -> u32 {
+        mip_level_count(width, height, self.min_size)
+            .min(self.max_levels.unwrap_or(u32::MAX).max(1))
+    }
+}
+
+/// Per-image mip limits from `mip_overrides.ron`. Every entry whose `path` glob matches the
+/// image's asset path is applied in order, so later entries win. Unset fields keep the global
+/// setting.
+///
+/// ```ron
+/// [
+///     (path: "*/Textures/*Decal*", min_size: 64),
+///     (path: "bistro_exterior/Textures/MenuBoard_*", max_levels: 4),
+/// ]
+/// ```
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct MipOverride {
+    /// Matched against the asset path, supports `*` and `?` wildcards.
+    pub path: String,
+    pub max_levels: Option<u32>,
+    pub min_size: Option<u32>,
+}
+
+pub const DEFAULT_MIP_OVERRIDES_PATH: &str = "mip_overrides.ron";
+
 impl Default for MipmapGeneratorSettings {
     fn default() -> Self {
         Self {
             // Default to 8x anisotropic filtering
             anisotropic_filtering: 8,
-            filter_type: FilterType::Triangle,
-            minimum_mip_resolution: 1,
+            filter: MipFilter::Triangle,
+            min_mip_size: 1,
+            max_mip_levels: None,
+            overrides: Vec::new(),
+            gpu: false,
+            max_concurrent_tasks: 16,
+            max_applied_per_frame: 2,
+            cache_dir: None,
         }
     }
 }
 
+/// While set, `generate_mipmaps` doesn't start new tasks and `apply_mipmaps` doesn't swap in
+/// finished images. New materials are queued and handled once it's cleared. Tasks already
+/// running keep running.
+#[derive(Resource, Default)]
+pub struct MipmapGeneratorPaused(pub bool);
+
+/// Sent by `generate_mipmaps` and `apply_mipmaps` when the counts of `MipmapGeneratorState`
+/// change.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct MipmapProgress {
+    pub completed: usize,
+    pub total: usize,
+}
+
+/// What the mipmap generator has done so far, for all material types. Images are only counted
+/// once their material and the image itself are loaded, so it's idle in between scenes loading.
+#[derive(Resource, Default, Debug)]
+pub struct MipmapGeneratorState {
+    /// Images looked at, including the ones that didn't need mips.
+    pub total: usize,
+    /// Images that are done: skipped, swapped in by `apply_mipmaps` or handed to the GPU.
+    pub completed: usize,
+    /// Of `completed`, the images that were compressed or already had mips.
+    pub skipped: usize,
+    /// Materials waiting for a task slot or for the generator to be unpaused.
+    pub deferred_materials: usize,
+    /// Images whose mips were loaded from or stored in `cache_dir`.
+    pub cache_hits: usize,
+    pub cache_misses: usize,
+    started: Option<Instant>,
+    last_completed: Option<Instant>,
+}
+
+impl MipmapGeneratorState {
+    pub fn is_idle(&self) -> bool {
+        self.completed == self.total && self.deferred_materials == 0
+    }
+
+    /// From the first image looked at to the last one completed, or to now while busy.
+    pub fn elapsed(&self) -> Duration {
+        let Some(started) = self.started else {
+            return Duration::ZERO;
+        };
+        match self.last_completed {
+            Some(last) if self.is_idle() => last - started,
+            _ => started.elapsed(),
+        }
+    }
+
+    pub fn progress(&self) -> MipmapProgress {
+        MipmapProgress {
+            completed: self.completed,
+            total: self.total,
+        }
+    }
+
+    fn discovered(&mut self) {
+        self.started.get_or_insert_with(Instant::now);
+        self.total += 1;
+    }
+
+    fn complete(&mut self) {
+        self.last_completed = Some(Instant::now());
+        self.completed += 1;
+    }
+}
+
 pub struct MipmapGeneratorPlugin;
 impl Plugin for MipmapGeneratorPlugin {
     fn build(&self, app: &mut App) {
         if let Some(image_plugin) = app.get_added_plugins::<ImagePlugin>().first() {
             let default_sampler = image_plugin.default_sampler.clone();
             app.insert_resource(DefaultSampler(default_sampler))
-                .init_resource::<MipmapGeneratorSettings>();
+                .init_resource::<MipmapGeneratorSettings>()
+                .init_resource::<MipmapGeneratorPaused>()
+                .init_resource::<MipmapGeneratorState>()
+                .add_event::<MipmapProgress>()
+                .add_plugins(GpuMipmapPlugin);
         } else {
             warn!("No ImagePlugin found. Try adding MipmapGeneratorPlugin after DefaultPlugins");
         }
@@ -55,10 +289,16 @@ impl Plugin for MipmapGeneratorPlugin {
 }
 
 #[derive(Resource, Default, Deref, DerefMut)]
-pub struct MipmapTasks<M: Material + GetImages>(HashMap<Handle<Image>, (Task<Image>, Handle<M>)>);
+#[allow(clippy::type_complexity)]
+pub struct MipmapTasks<M: Material + MipmappableMaterial>(
+    HashMap<Handle<Image>, (Task<(Image, CacheStatus)>, Handle<M>)>,
+);
 
+/// Starts a task on the `AsyncComputeTaskPool` for every image of new materials that is missing
+/// mips, up to `max_concurrent_tasks`. `apply_mipmaps` swaps in the results. Every image is only
+/// looked at once, compressed images and images that already have mips just get the sampler.
 #[allow(clippy::too_many_arguments)]
-pub fn generate_mipmaps<M: Material + GetImages>(
+pub fn generate_mipmaps<M: Material + MipmappableMaterial>(
     mut commands: Commands,
     mut material_events: EventReader<AssetEvent<M>>,
     mut materials: ResMut<Assets<M>>,
@@ -67,7 +307,32 @@ pub fn generate_mipmaps<M: Material + GetImages>(
     default_sampler: Res<DefaultSampler>,
     settings: Res<MipmapGeneratorSettings>,
     mut tasks_res: Option<ResMut<MipmapTasks<M>>>,
+    paused: Res<MipmapGeneratorPaused>,
+    mut deferred: Local<Vec<AssetId<M>>>,
+    mut gpu_queue: ResMut<GpuMipmapQueue>,
+    mut done_images: Local<HashSet<AssetId<Image>>>,
+    mut done_materials: Local<HashSet<AssetId<M>>>,
+    mut state: ResMut<MipmapGeneratorState>,
+    mut progress: EventWriter<MipmapProgress>,
 ) {
+    // Events are only kept for a couple of frames, so hold on to them while paused
+    let new_materials = material_events.read().filter_map(|event| match event {
+        AssetEvent::Added { id } => Some(*id),
+        AssetEvent::LoadedWithDependencies { id } => Some(*id),
+        // Materials whose images weren't all loaded yet are looked at again
+        AssetEvent::Modified { id } if !done_materials.contains(id) => Some(*id),
+        _ => None,
+    });
+    if paused.0 {
+        deferred.extend(new_materials);
+        if state.deferred_materials != deferred.len() {
+            state.deferred_materials = deferred.len();
+        }
+        return;
+    }
+    let counts = (state.total, state.completed);
+    let mut material_ids = deferred.drain(..).chain(new_materials).collect::<Vec<_>>();
+
     let mut new_tasks = MipmapTasks(HashMap::new());
 
     let tasks = if let Some(ref mut tasks) = tasks_res {
@@ -77,86 +342,180 @@ pub fn generate_mipmaps<M: Material + GetImages>(
     };
 
     let thread_pool = AsyncComputeTaskPool::get();
-    'outer: for event in material_events.read() {
-        let material_h = match event {
-            AssetEvent::Added { id } => id,
-            AssetEvent::LoadedWithDependencies { id } => id,
-            _ => continue,
-        };
+    let mut handled = 0;
+    'outer: for material_h in &material_ids {
+        if tasks.len() >= settings.max_concurrent_tasks.max(1) {
+            break;
+        }
+        handled += 1;
         for m in no_mipmap.iter() {
             if m.id() == *material_h {
                 continue 'outer;
             }
         }
-        // get_mut(material_h) here so we see the filtering right away
-        // and even if mipmaps aren't made, we still get the filtering
-        if let Some(material) = materials.get_mut(*material_h) {
-            for image_h in material.get_images().into_iter() {
-                if tasks.contains_key(image_h) {
-                    continue; //There is already a task for this image
-                }
-                if let Some(image) = images.get_mut(image_h) {
-                    let mut descriptor = match image.sampler.clone() {
-                        ImageSampler::Default => default_sampler.0.clone(),
-                        ImageSampler::Descriptor(descriptor) => descriptor,
-                    };
-                    descriptor.anisotropy_clamp = settings.anisotropic_filtering;
-                    image.sampler = ImageSampler::Descriptor(descriptor);
-                    if image.texture_descriptor.mip_level_count == 1
-                        && check_image_compatible(image).is_ok()
-                    {
-                        let mut image = image.clone();
-                        let settings = settings.clone();
-                        let task = thread_pool.spawn(async move {
-                            match generate_mips_texture(&mut image, &settings.clone()) {
-                                Ok(_) => (),
-                                Err(e) => warn!("{}", e),
-                            }
-                            image
-                        });
-                        tasks.insert(image_h.clone(), (task, Handle::Weak(*material_h)));
+        if done_materials.contains(material_h) {
+            continue;
+        }
+        let Some(material) = materials.get(*material_h) else {
+            continue;
+        };
+        let normal_map = material.normal_map().map(|image_h| image_h.id());
+        let image_handles = material
+            .mipmap_images()
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut touched = false;
+        let mut complete = true;
+        for image_h in &image_handles {
+            if done_images.contains(&image_h.id()) || tasks.contains_key(image_h) {
+                continue;
+            }
+            let Some(image) = images.get(image_h) else {
+                // Not loaded yet, the material is looked at again when it's modified
+                complete = false;
+                continue;
+            };
+            // Compressed images (ktx2, dds) and images with mips are done once they have the
+            // sampler, and never touched again
+            let needs_mips = image.texture_descriptor.mip_level_count == 1
+                && check_image_compatible(image).is_ok();
+            let needs_sampler = !matches!(&image.sampler, ImageSampler::Descriptor(descriptor)
+                if descriptor.anisotropy_clamp == settings.anisotropic_filtering);
+            done_images.insert(image_h.id());
+            state.discovered();
+            if !needs_mips {
+                state.complete();
+                state.skipped += 1;
+            }
+            if !needs_mips && !needs_sampler {
+                continue;
+            }
+            // Mutating the image uploads it again, so the sampler is only set when it changes
+            let image = images.get_mut(image_h).unwrap();
+            let mut descriptor = match image.sampler.clone() {
+                ImageSampler::Default => default_sampler.0.clone(),
+                ImageSampler::Descriptor(descriptor) => descriptor,
+            };
+            descriptor.anisotropy_clamp = settings.anisotropic_filtering;
+            image.sampler = ImageSampler::Descriptor(descriptor);
+            touched = true;
+            if !needs_mips {
+                continue;
+            }
+            let is_normal_map = normal_map == Some(image_h.id());
+            let limits = settings.limits_for(image_h.path());
+            if settings.gpu && !is_normal_map && gpu_supported(image.texture_descriptor.format) {
+                let size = image.texture_descriptor.size;
+                allocate_mips(image, limits.level_count(size.width, size.height));
+                gpu_queue.0.push(image_h.id());
+                state.complete();
+            } else {
+                let mut image = image.clone();
+                let settings = settings.clone();
+                let task = thread_pool.spawn(async move {
+                    if let Some(dir) = &settings.cache_dir {
+                        let status =
+                            load_or_generate(dir, &mut image, &settings, limits, is_normal_map);
+                        return (image, status);
                     }
-                }
+                    match generate_mips_texture(&mut image, &settings, limits, is_normal_map) {
+                        Ok(_) => (),
+                        Err(e) => warn!("{}", e),
+                    }
+                    (image, CacheStatus::Off)
+                });
+                tasks.insert(image_h.clone(), (task, Handle::Weak(*material_h)));
             }
         }
+        if complete {
+            done_materials.insert(*material_h);
+        }
+        // Touch the material so it picks up the new samplers and GPU mips right away
+        if touched {
+            let _ = materials.get_mut(*material_h);
+        }
+    }
+
+    // Picked up again once tasks finish, images that already have a task are skipped then
+    deferred.extend(material_ids.drain(handled..));
+    if state.deferred_materials != deferred.len() {
+        state.deferred_materials = deferred.len();
+    }
+    if counts != (state.total, state.completed) {
+        progress.send(state.progress());
     }
 
+    if tasks_res.is_none() {
+        commands.insert_resource(new_tasks);
+    }
+}
+
+/// Swaps in up to `max_applied_per_frame` images whose mip task finished.
+pub fn apply_mipmaps<M: Material + MipmappableMaterial>(
+    mut materials: ResMut<Assets<M>>,
+    mut images: ResMut<Assets<Image>>,
+    settings: Res<MipmapGeneratorSettings>,
+    tasks: Option<ResMut<MipmapTasks<M>>>,
+    paused: Res<MipmapGeneratorPaused>,
+    mut state: ResMut<MipmapGeneratorState>,
+    mut progress: EventWriter<MipmapProgress>,
+) {
+    let Some(mut tasks) = tasks else {
+        return;
+    };
+    if paused.0 {
+        return;
+    }
     let mut completed = Vec::new();
 
     for (image_h, inner) in tasks.iter_mut() {
+        if completed.len() >= settings.max_applied_per_frame.max(1) {
+            break;
+        }
         // TODO couldn't get &mut in destructure to work correctly for (task, material_h)
-        if let Some(new_image) = future::block_on(future::poll_once(&mut inner.0)) {
+        if let Some((new_image, status)) = future::block_on(future::poll_once(&mut inner.0)) {
             if let Some(image) = images.get_mut(image_h) {
                 *image = new_image;
             }
+            match status {
+                CacheStatus::Hit => state.cache_hits += 1,
+                CacheStatus::Miss => state.cache_misses += 1,
+                CacheStatus::Off => (),
+            }
             // Touch material to trigger change detection
             let _ = materials.get_mut(&inner.1);
             completed.push(image_h.clone());
         }
     }
 
-    for image_h in completed {
-        tasks.remove(&image_h);
-    }
-
-    if tasks_res.is_none() {
-        commands.insert_resource(new_tasks);
+    if !completed.is_empty() {
+        for image_h in completed {
+            tasks.remove(&image_h);
+            state.complete();
+        }
+        progress.send(state.progress());
+        if settings.cache_dir.is_some() && state.is_idle() {
+            println!(
+                "Mipmap cache: {} hits, {} misses",
+                state.cache_hits, state.cache_misses
+            );
+        }
     }
 }
 
 pub fn generate_mips_texture(
     image: &mut Image,
     settings: &MipmapGeneratorSettings,
+    limits: MipLimits,
+    normal_map: bool,
 ) -> anyhow::Result<()> {
     check_image_compatible(image)?;
+    let content = MipContent::of(image, normal_map);
     match try_into_dynamic(image.clone()) {
-        Ok(mut dyn_image) => {
-            let (mip_level_count, image_data) = generate_mips(
-                &mut dyn_image,
-                settings.minimum_mip_resolution,
-                u32::MAX,
-                settings.filter_type,
-            );
+        Ok(dyn_image) => {
+            let (mip_level_count, image_data) =
+                generate_mips(&dyn_image, limits, settings.filter, content);
             image.texture_descriptor.mip_level_count = mip_level_count;
             image.data = image_data;
             Ok(())
@@ -165,34 +524,181 @@ pub fn generate_mips_texture(
     }
 }
 
+/// The number of levels `generate_mips` makes for an image of this size.
+pub fn mip_level_count(mut width: u32, mut height: u32, minimum_mip_resolution: u32) -> u32 {
+    let mut mip_level_count = 1;
+    while width / 2 >= minimum_mip_resolution.max(1) && height / 2 >= minimum_mip_resolution.max(1)
+    {
+        width /= 2;
+        height /= 2;
+        mip_level_count += 1;
+    }
+    mip_level_count
+}
+
 /// Returns the number of mip levels, and a vec of bytes containing the image data.
-/// The `max_mip_count` includes the first input mip level. So setting this to 2 will
+/// `limits.max_levels` includes the first input mip level. So setting this to 2 will
 /// result in a single additional mip level being generated, for a total of 2 levels.
+/// Only 8 bit images are supported. Every level is filtered from the unquantized level above.
 pub fn generate_mips(
-    dyn_image: &mut DynamicImage,
-    minimum_mip_resolution: u32,
-    max_mip_count: u32,
-    filter_type: FilterType,
+    dyn_image: &DynamicImage,
+    limits: MipLimits,
+    filter: MipFilter,
+    content: MipContent,
 ) -> (u32, Vec<u8>) {
     let mut image_data = dyn_image.as_bytes().to_vec();
     let mut mip_level_count = 1;
     let mut width = dyn_image.width();
     let mut height = dyn_image.height();
+    let channels = dyn_image.color().channel_count() as usize;
+    let mut level = decode_texels(&image_data, channels, content);
+    let level_count = limits.level_count(width, height);
 
-    while width / 2 >= minimum_mip_resolution.max(1)
-        && height / 2 >= minimum_mip_resolution.max(1)
-        && mip_level_count < max_mip_count
-    {
+    while mip_level_count < level_count {
+        level = downsample(&level, channels, (width, height), filter);
         width /= 2;
         height /= 2;
-        *dyn_image = dyn_image.resize_exact(width, height, filter_type);
-        image_data.append(&mut dyn_image.as_bytes().to_vec());
+        if content == MipContent::NormalMap {
+            renormalize(&mut level, channels);
+        }
+        image_data.extend(encode_texels(&level, channels, content));
         mip_level_count += 1;
     }
 
     (mip_level_count, image_data)
 }
 
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// The channels that hold color (rgb) or the normal (xyz), alpha and anything after is linear.
+fn is_vector_channel(channel: usize, channels: usize) -> bool {
+    channel < 3 && (channels >= 3 || channel < 2)
+}
+
+fn decode_texels(bytes: &[u8], channels: usize, content: MipContent) -> Vec<f32> {
+    let srgb_table = (0..=255u8)
+        .map(|v| srgb_to_linear(v as f32 / 255.0))
+        .collect::<Vec<_>>();
+    bytes
+        .iter()
+        .enumerate()
+        .map(|(i, &v)| {
+            let vector = is_vector_channel(i % channels, channels);
+            match content {
+                MipContent::Srgb if vector && channels >= 3 => srgb_table[v as usize],
+                MipContent::NormalMap if vector => v as f32 / 255.0 * 2.0 - 1.0,
+                _ => v as f32 / 255.0,
+            }
+        })
+        .collect()
+}
+
+fn encode_texels(texels: &[f32], channels: usize, content: MipContent) -> Vec<u8> {
+    texels
+        .iter()
+        .enumerate()
+        .map(|(i, &v)| {
+            let vector = is_vector_channel(i % channels, channels);
+            let v = match content {
+                MipContent::Srgb if vector && channels >= 3 => linear_to_srgb(v.max(0.0)),
+                MipContent::NormalMap if vector => v * 0.5 + 0.5,
+                _ => v,
+            };
+            (v.clamp(0.0, 1.0) * 255.0).round() as u8
+        })
+        .collect()
+}
+
+/// Makes the normals unit length again, averaging shortens them. Two channel normal maps have
+/// their z reconstructed in the shader, so only normals longer than 1 are shortened.
+fn renormalize(texels: &mut [f32], channels: usize) {
+    if channels < 2 {
+        return;
+    }
+    let vector_len = channels.min(3);
+    for texel in texels.chunks_exact_mut(channels) {
+        let normal = &mut texel[..vector_len];
+        let len = normal.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if len > 0.0 && (vector_len == 3 || len > 1.0) {
+            normal.iter_mut().for_each(|v| *v /= len);
+        }
+    }
+}
+
+/// The source texels and weights of every texel along one axis of the smaller level. Texels
+/// past the edge are clamped.
+fn axis_weights(src_len: u32, dst_len: u32, filter: MipFilter) -> Vec<Vec<(usize, f32)>> {
+    let scale = src_len as f32 / dst_len as f32;
+    let radius = filter.support() * scale;
+    (0..dst_len)
+        .map(|i| {
+            let center = (i as f32 + 0.5) * scale;
+            let first = (center - radius).floor() as i64;
+            let last = (center + radius).ceil() as i64;
+            let mut weights = (first..=last)
+                .map(|src| {
+                    let weight = filter.weight((src as f32 + 0.5 - center) / scale);
+                    (src.clamp(0, src_len as i64 - 1) as usize, weight)
+                })
+                .filter(|(_, weight)| *weight != 0.0)
+                .collect::<Vec<_>>();
+            let total = weights.iter().map(|(_, weight)| weight).sum::<f32>();
+            weights.iter_mut().for_each(|(_, weight)| *weight /= total);
+            weights
+        })
+        .collect()
+}
+
+/// Halves the size of the level, horizontally and then vertically.
+fn downsample(
+    texels: &[f32],
+    channels: usize,
+    (width, height): (u32, u32),
+    filter: MipFilter,
+) -> Vec<f32> {
+    let (dst_width, dst_height) = ((width / 2).max(1), (height / 2).max(1));
+    let (width, dst_width) = (width as usize, dst_width as usize);
+    let mut rows = vec![0.0; dst_width * height as usize * channels];
+    let x_weights = axis_weights(width as u32, dst_width as u32, filter);
+    for y in 0..height as usize {
+        for (x, weights) in x_weights.iter().enumerate() {
+            let dst = (y * dst_width + x) * channels;
+            for &(src_x, weight) in weights {
+                let src = (y * width + src_x) * channels;
+                for c in 0..channels {
+                    rows[dst + c] += texels[src + c] * weight;
+                }
+            }
+        }
+    }
+    let mut out = vec![0.0; dst_width * dst_height as usize * channels];
+    let y_weights = axis_weights(height, dst_height, filter);
+    for (y, weights) in y_weights.iter().enumerate() {
+        for &(src_y, weight) in weights {
+            let src_row = &rows[src_y * dst_width * channels..][..dst_width * channels];
+            let dst_row = &mut out[y * dst_width * channels..][..dst_width * channels];
+            for (dst, src) in dst_row.iter_mut().zip(src_row) {
+                *dst += src * weight;
+            }
+        }
+    }
+    out
+}
+
 /// Extract a specific individual mip level as a new image.
 #[allow(dead_code)]
 pub fn extract_mip_level(image: &Image, mip_level: u32) -> anyhow::Result<Image> {
@@ -265,24 +771,71 @@ pub fn check_image_compatible(image: &Image) -> anyhow::Result<()> {
     Ok(())
 }
 
-// Implement the GetImages trait for any materials that need conversion
-pub trait GetImages {
-    fn get_images(&self) -> Vec<&Handle<Image>>;
+/// The texture slots of a material that get mips and the anisotropic sampler. Slots that aren't
+/// listed, like lookup tables that must not be filtered, are left alone. Implemented by hand for
+/// custom materials, `ExtendedMaterial`s get it through `MipmappableExtension`.
+pub trait MipmappableMaterial {
+    fn mipmap_images(&self) -> Vec<&Handle<Image>>;
+
+    /// Renormalized on every mip level instead of filtered like color. Should also be one of
+    /// `mipmap_images`.
+    fn normal_map(&self) -> Option<&Handle<Image>> {
+        None
+    }
 }
 
-impl GetImages for StandardMaterial {
-    fn get_images(&self) -> Vec<&Handle<Image>> {
+impl MipmappableMaterial for StandardMaterial {
+    fn mipmap_images(&self) -> Vec<&Handle<Image>> {
         vec![
             &self.base_color_texture,
             &self.emissive_texture,
             &self.metallic_roughness_texture,
             &self.normal_map_texture,
             &self.occlusion_texture,
+            &self.depth_map,
         ]
         .into_iter()
         .flatten()
         .collect()
     }
+
+    fn normal_map(&self) -> Option<&Handle<Image>> {
+        self.normal_map_texture.as_ref()
+    }
+}
+
+/// The slots of a `MaterialExtension` that get mips, on top of those of the base material.
+/// Implement it with no methods to only mip the base material's slots.
+pub trait MipmappableExtension {
+    fn mipmap_images(&self) -> Vec<&Handle<Image>> {
+        Vec::new()
+    }
+}
+
+impl<B, E> MipmappableMaterial for ExtendedMaterial<B, E>
+where
+    B: Material + MipmappableMaterial,
+    E: MaterialExtension + MipmappableExtension,
+{
+    fn mipmap_images(&self) -> Vec<&Handle<Image>> {
+        let mut images = self.base.mipmap_images();
+        images.extend(self.extension.mipmap_images());
+        images
+    }
+
+    fn normal_map(&self) -> Option<&Handle<Image>> {
+        self.base.normal_map()
+    }
+}
+
+/// `generate_mipmaps` and `apply_mipmaps` for one material type. Added once per type, the
+/// generator state and settings are shared.
+pub fn mipmap_systems<M: Material + MipmappableMaterial>() -> SystemConfigs {
+    (
+        generate_mipmaps::<M>,
+        apply_mipmaps::<M>.after(generate_mipmaps::<M>),
+    )
+        .into_configs()
 }
 
 pub fn try_into_dynamic(image: Image) -> anyhow::Result<DynamicImage> {
@@ -326,3 +879,371 @@ pub fn try_into_dynamic(image: Image) -> anyhow::Result<DynamicImage> {
         )
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_matches_asset_paths() {
+        // mip_overrides.ron matches whole asset paths, `*` crosses directories
+        assert!(glob_match(
+            "bistro_exterior/*_Normal.png",
+            "bistro_exterior/Textures/Paris_Wall_Normal.png"
+        ));
+        assert!(!glob_match(
+            "bistro_interior_wine/*",
+            "bistro_exterior/Textures/Paris_Wall_Normal.png"
+        ));
+    }
+
+    #[test]
+    fn overrides_match_asset_paths() {
+        let settings = MipmapGeneratorSettings {
+            min_mip_size: 1,
+            overrides: vec![
+                MipOverride {
+                    path: "*/Textures/*Decal*".to_string(),
+                    min_size: Some(64),
+                    ..default()
+                },
+                MipOverride {
+                    path: "bistro_exterior/Textures/MenuBoard_?.png".to_string(),
+                    max_levels: Some(4),
+                    ..default()
+                },
+                // Later entries win
+                MipOverride {
+                    path: "*MenuBoard_2*".to_string(),
+                    max_levels: Some(2),
+                    ..default()
+                },
+            ],
+            ..default()
+        };
+        let limits = |path: &str| settings.limits_for(Some(&AssetPath::from(path.to_string())));
+
+        let decal = limits("bistro_interior_wine/Textures/Wall_Decal.png");
+        assert_eq!(decal.min_size, 64);
+        assert_eq!(decal.max_levels, None);
+        assert_eq!(
+            limits("bistro_exterior/Textures/MenuBoard_1.png").max_levels,
+            Some(4)
+        );
+        assert_eq!(
+            limits("bistro_exterior/Textures/MenuBoard_2.png").max_levels,
+            Some(2)
+        );
+        // `?` is a single character, and matching is case sensitive
+        assert_eq!(
+            limits("bistro_exterior/Textures/MenuBoard_10.png").max_levels,
+            None
+        );
+        assert_eq!(
+            limits("bistro_exterior/Textures/wall_decal.png").min_size,
+            1
+        );
+        assert_eq!(settings.limits_for(None).min_size, 1);
+    }
+
+    const FILTERS: [MipFilter; 3] = [MipFilter::Box, MipFilter::Triangle, MipFilter::Lanczos3];
+    /// Includes odd, 1 pixel wide and 1 pixel high sizes.
+    const SIZES: [(u32, u32); 7] = [(8, 8), (7, 5), (5, 7), (1, 1), (1, 6), (3, 1), (2, 1)];
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{a} != {b}");
+    }
+
+    #[test]
+    fn constant_image_stays_constant() {
+        for filter in FILTERS {
+            for (width, height) in SIZES {
+                let texels = vec![0.3; (width * height * 4) as usize];
+                let out = downsample(&texels, 4, (width, height), filter);
+                let expected = (width / 2).max(1) * (height / 2).max(1) * 4;
+                assert_eq!(out.len(), expected as usize, "{filter:?} {width}x{height}");
+                out.iter().for_each(|v| assert_close(*v, 0.3));
+            }
+        }
+    }
+
+    #[test]
+    fn weights_are_normalized_and_in_bounds() {
+        for filter in FILTERS {
+            for src_len in [1, 2, 3, 5, 8, 13] {
+                let dst_len = (src_len / 2).max(1);
+                for weights in axis_weights(src_len, dst_len, filter) {
+                    assert!(weights.iter().all(|(src, _)| *src < src_len as usize));
+                    assert_close(weights.iter().map(|(_, weight)| weight).sum(), 1.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn filters_golden_row() {
+        // A 4x1 row of alternating texels
+        let row = [0.0, 1.0, 0.0, 1.0];
+        let golden = [
+            (MipFilter::Box, [0.5, 0.5]),
+            // Tent over 4 texels, the edge texel is clamped and counts twice
+            (MipFilter::Triangle, [0.375, 0.625]),
+        ];
+        for (filter, expected) in golden {
+            let out = downsample(&row, 1, (4, 1), filter);
+            assert_eq!(out.len(), 2);
+            assert_close(out[0], expected[0]);
+            assert_close(out[1], expected[1]);
+        }
+        // Lanczos rings, but stays symmetric and keeps the average
+        let out = downsample(&[0.0, 1.0, 1.0, 0.0], 1, (4, 1), MipFilter::Lanczos3);
+        assert_close(out[0], out[1]);
+        let out = downsample(&[0.5; 8], 1, (8, 1), MipFilter::Lanczos3);
+        out.iter().for_each(|v| assert_close(*v, 0.5));
+    }
+
+    #[test]
+    fn srgb_round_trip() {
+        let bytes = (0..=255u8).flat_map(|v| [v, v, v, v]).collect::<Vec<_>>();
+        for content in [MipContent::Linear, MipContent::Srgb] {
+            let texels = decode_texels(&bytes, 4, content);
+            assert_eq!(encode_texels(&texels, 4, content), bytes);
+        }
+    }
+
+    #[test]
+    fn srgb_doesnt_darken() {
+        // Black and white texels average to half the light, which is 188 in sRGB, not 128
+        let bytes = [0u8, 0, 0, 255, 255, 255, 255, 255];
+        let texels = decode_texels(&bytes, 4, MipContent::Srgb);
+        let out = encode_texels(
+            &downsample(&texels, 4, (2, 1), MipFilter::Box),
+            4,
+            MipContent::Srgb,
+        );
+        assert_eq!(out, [188, 188, 188, 255]);
+
+        // A constant color keeps its value on every level
+        let image = DynamicImage::ImageRgba8(ImageBuffer::from_pixel(
+            8,
+            8,
+            image::Rgba([90, 140, 200, 77]),
+        ));
+        for filter in FILTERS {
+            let limits = MipLimits {
+                max_levels: None,
+                min_size: 1,
+            };
+            let (levels, data) = generate_mips(&image, limits, filter, MipContent::Srgb);
+            assert_eq!(levels, 4);
+            assert!(data
+                .chunks_exact(4)
+                .all(|texel| texel == [90, 140, 200, 77]));
+        }
+    }
+
+    #[test]
+    fn renormalize_keeps_unit_normals() {
+        // Normals tilted in different directions shorten when averaged
+        let bytes = [
+            [255u8, 128, 128, 255],
+            [0, 128, 128, 255],
+            [128, 255, 200, 255],
+            [128, 0, 255, 255],
+        ]
+        .concat();
+        let texels = decode_texels(&bytes, 4, MipContent::NormalMap);
+        for filter in FILTERS {
+            let mut out = downsample(&texels, 4, (2, 2), filter);
+            renormalize(&mut out, 4);
+            for texel in out.chunks_exact(4) {
+                assert_close(Vec3::from_slice(&texel[..3]).length(), 1.0);
+            }
+        }
+
+        // Two channel normals only get shorter, z is reconstructed from them
+        let mut short = [0.3, 0.4, 0.6, 0.8];
+        renormalize(&mut short, 2);
+        assert_eq!(short, [0.3, 0.4, 0.6, 0.8]);
+        let mut long = [0.6, 0.8, 1.2, 1.6];
+        renormalize(&mut long, 2);
+        long.iter()
+            .zip([0.6, 0.8, 0.6, 0.8])
+            .for_each(|(a, b)| assert_close(*a, b));
+    }
+
+    #[test]
+    fn odd_sizes_generate_every_level() {
+        for filter in FILTERS {
+            for (width, height) in SIZES {
+                let image =
+                    DynamicImage::ImageRgba8(ImageBuffer::from_fn(width, height, |x, y| {
+                        image::Rgba([(x * 30) as u8, (y * 30) as u8, 7, 255])
+                    }));
+                let limits = MipLimits {
+                    max_levels: None,
+                    min_size: 1,
+                };
+                let (levels, data) = generate_mips(&image, limits, filter, MipContent::NormalMap);
+                assert_eq!(levels, mip_level_count(width, height, 1));
+                let expected = (0..levels)
+                    .map(|level| (width >> level) * (height >> level) * 4)
+                    .sum::<u32>();
+                assert_eq!(data.len(), expected as usize, "{filter:?} {width}x{height}");
+            }
+        }
+    }
+
+    fn test_app(settings: MipmapGeneratorSettings) -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_asset::<Image>()
+            .init_asset::<StandardMaterial>()
+            .insert_resource(DefaultSampler(ImageSamplerDescriptor::default()))
+            .insert_resource(settings)
+            .init_resource::<MipmapGeneratorPaused>()
+            .init_resource::<MipmapGeneratorState>()
+            .init_resource::<GpuMipmapQueue>()
+            .add_event::<MipmapProgress>()
+            .add_systems(Update, mipmap_systems::<StandardMaterial>());
+        app
+    }
+
+    fn test_image() -> Image {
+        Image::new_fill(
+            Extent3d {
+                width: 8,
+                height: 8,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &[200, 100, 50, 255],
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::default(),
+        )
+    }
+
+    /// Runs the app until the generator is idle with `images` images counted, returns the last
+    /// progress event.
+    fn run_until_idle(
+        app: &mut App,
+        images: usize,
+        mut each_update: impl FnMut(&mut App),
+    ) -> Option<MipmapProgress> {
+        let mut reader = app
+            .world()
+            .resource::<Events<MipmapProgress>>()
+            .get_reader();
+        let mut last = None;
+        for _ in 0..1000 {
+            app.update();
+            each_update(app);
+            let events = app.world().resource::<Events<MipmapProgress>>();
+            last = reader.read(events).last().copied().or(last);
+            let state = app.world().resource::<MipmapGeneratorState>();
+            if state.total == images && state.is_idle() {
+                return last;
+            }
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        panic!(
+            "not idle: {:?}",
+            app.world().resource::<MipmapGeneratorState>()
+        );
+    }
+
+    #[test]
+    fn progress_counts_add_up() {
+        let mut app = test_app(MipmapGeneratorSettings::default());
+        let world = app.world_mut();
+        let mut images = world.resource_mut::<Assets<Image>>();
+        let base_color = images.add(test_image());
+        let normal_map = images.add(test_image());
+        let mut compressed = test_image();
+        compressed.texture_descriptor.format = TextureFormat::Bc7RgbaUnormSrgb;
+        compressed.data = vec![0; 4 * 16];
+        let compressed = images.add(compressed);
+        let mut mipped = test_image();
+        mipped.texture_descriptor.mip_level_count = 4;
+        let mipped = images.add(mipped);
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+        // Kept alive, dropped handles free the materials before the systems see them
+        let _first = materials.add(StandardMaterial {
+            base_color_texture: Some(base_color.clone()),
+            normal_map_texture: Some(normal_map.clone()),
+            ..default()
+        });
+        let _second = materials.add(StandardMaterial {
+            base_color_texture: Some(compressed),
+            metallic_roughness_texture: Some(mipped),
+            // Shared images are only counted once
+            occlusion_texture: Some(base_color.clone()),
+            ..default()
+        });
+
+        let last = run_until_idle(&mut app, 4, |_| ()).unwrap();
+
+        let state = app.world().resource::<MipmapGeneratorState>();
+        assert_eq!(state.total, 4);
+        assert_eq!(state.completed, state.total);
+        assert_eq!(state.skipped, 2);
+        assert_eq!(state.deferred_materials, 0);
+        let images = app.world().resource::<Assets<Image>>();
+        for image_h in [&base_color, &normal_map] {
+            assert_eq!(
+                images
+                    .get(image_h)
+                    .unwrap()
+                    .texture_descriptor
+                    .mip_level_count,
+                4
+            );
+        }
+        assert_eq!((last.completed, last.total), (4, 4));
+    }
+
+    #[test]
+    fn applies_at_most_max_per_frame() {
+        let mut app = test_app(MipmapGeneratorSettings {
+            max_applied_per_frame: 1,
+            ..default()
+        });
+        let world = app.world_mut();
+        let mut images = world.resource_mut::<Assets<Image>>();
+        let image_handles = [(); 3].map(|_| images.add(test_image()));
+        let _material = world
+            .resource_mut::<Assets<StandardMaterial>>()
+            .add(StandardMaterial {
+                base_color_texture: Some(image_handles[0].clone()),
+                normal_map_texture: Some(image_handles[1].clone()),
+                emissive_texture: Some(image_handles[2].clone()),
+                ..default()
+            });
+
+        let mut applied = 0;
+        let mut updates = 0;
+        run_until_idle(&mut app, 3, |app| {
+            let images = app.world().resource::<Assets<Image>>();
+            let now = image_handles
+                .iter()
+                .filter(|image_h| {
+                    images
+                        .get(*image_h)
+                        .unwrap()
+                        .texture_descriptor
+                        .mip_level_count
+                        > 1
+                })
+                .count();
+            assert!(
+                now - applied <= 1,
+                "{} images applied in one update",
+                now - applied
+            );
+            applied = now;
+            updates += 1;
+        });
+        assert_eq!(applied, 3);
+        assert!(updates >= 3);
+    }
+}
@@ -0,0 +1,249 @@
+use bevy::{
+    asset::load_internal_asset,
+    core_pipeline::fullscreen_vertex_shader::fullscreen_shader_vertex_state,
+    prelude::*,
+    render::{
+        render_asset::RenderAssets,
+        render_resource::{
+            binding_types::{sampler, texture_2d},
+            BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, ColorTargetState,
+            ColorWrites, CommandEncoderDescriptor, FilterMode, FragmentState, LoadOp,
+            MultisampleState, Operations, PipelineCache, PrimitiveState, RenderPassColorAttachment,
+            RenderPassDescriptor, RenderPipelineDescriptor, Sampler, SamplerBindingType,
+            SamplerDescriptor, ShaderStages, SpecializedRenderPipeline, SpecializedRenderPipelines,
+            StoreOp, TextureFormat, TextureId, TextureSampleType, TextureUsages,
+            TextureViewDescriptor,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        texture::GpuImage,
+        ExtractSchedule, MainWorld, Render, RenderApp, RenderSet,
+    },
+    utils::HashMap,
+};
+
+const DOWNSAMPLE_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(0x7d4e_19a2_c0b8_4f35_a6e1_52c9_0b3d_e874);
+
+/// Images `generate_mipmaps` reallocated with a mip chain for the render world to fill.
+#[derive(Resource, Default)]
+pub struct GpuMipmapQueue(pub Vec<AssetId<Image>>);
+
+/// Renderable and filterable formats the downsample pass can write. Anything else, including
+/// all compressed formats, goes through the CPU path or is skipped.
+pub fn gpu_supported(format: TextureFormat) -> bool {
+    matches!(
+        format,
+        TextureFormat::R8Unorm
+            | TextureFormat::Rg8Unorm
+            | TextureFormat::Rgba8Unorm
+            | TextureFormat::Rgba8UnormSrgb
+            | TextureFormat::Bgra8Unorm
+            | TextureFormat::Bgra8UnormSrgb
+            | TextureFormat::R16Float
+            | TextureFormat::Rg16Float
+            | TextureFormat::Rgba16Float
+    )
+}
+
+/// The length of the whole mip chain of the image with `mip_level_count` levels.
+pub fn chain_len(image: &Image, mip_level_count: u32) -> usize {
+    let descriptor = &image.texture_descriptor;
+    let block_size = descriptor.format.block_copy_size(None).unwrap() as usize;
+    let (mut width, mut height) = (descriptor.size.width, descriptor.size.height);
+    let mut len = 0;
+    for _ in 0..mip_level_count {
+        len += width as usize * height as usize * block_size;
+        width = (width / 2).max(1);
+        height = (height / 2).max(1);
+    }
+    len
+}
+
+/// Gives the image a mip chain of `mip_level_count` levels for the GPU to fill. The data of the
+/// new levels is zeroed, the texture is created with it and overwritten before it's sampled.
+pub fn allocate_mips(image: &mut Image, mip_level_count: u32) {
+    let len = chain_len(image, mip_level_count);
+    let descriptor = &mut image.texture_descriptor;
+    descriptor.mip_level_count = mip_level_count;
+    descriptor.usage |= TextureUsages::RENDER_ATTACHMENT;
+    image.data.resize(len, 0);
+}
+
+pub struct GpuMipmapPlugin;
+
+impl Plugin for GpuMipmapPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            DOWNSAMPLE_SHADER_HANDLE,
+            "mipmap_downsample.wgsl",
+            Shader::from_wgsl
+        );
+        app.init_resource::<GpuMipmapQueue>();
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .init_resource::<PendingGpuMipmaps>()
+            .add_systems(ExtractSchedule, extract_gpu_mipmap_queue)
+            .add_systems(Render, fill_mip_levels.in_set(RenderSet::PrepareResources));
+    }
+
+    fn finish(&self, app: &mut App) {
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .init_resource::<DownsamplePipeline>()
+            .init_resource::<SpecializedRenderPipelines<DownsamplePipeline>>();
+    }
+}
+
+/// The queued images, with the texture their mips were last filled for. If the image is
+/// extracted again, for example after its sampler changed, the new texture only has the zeroed
+/// levels from the main world and is filled again. Filled images are dropped once they are
+/// unloaded.
+#[derive(Resource, Default)]
+struct PendingGpuMipmaps(HashMap<AssetId<Image>, Option<TextureId>>);
+
+fn extract_gpu_mipmap_queue(
+    mut main_world: ResMut<MainWorld>,
+    mut pending: ResMut<PendingGpuMipmaps>,
+) {
+    let mut queue = main_world.resource_mut::<GpuMipmapQueue>();
+    for id in queue.0.drain(..) {
+        pending.0.insert(id, None);
+    }
+}
+
+#[derive(Resource)]
+struct DownsamplePipeline {
+    layout: BindGroupLayout,
+    sampler: Sampler,
+}
+
+impl FromWorld for DownsamplePipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let layout = render_device.create_bind_group_layout(
+            "mipmap_downsample_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::FRAGMENT,
+                (
+                    texture_2d(TextureSampleType::Float { filterable: true }),
+                    sampler(SamplerBindingType::Filtering),
+                ),
+            ),
+        );
+        let sampler = render_device.create_sampler(&SamplerDescriptor {
+            label: Some("mipmap_downsample_sampler"),
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..default()
+        });
+        Self { layout, sampler }
+    }
+}
+
+impl SpecializedRenderPipeline for DownsamplePipeline {
+    type Key = TextureFormat;
+
+    fn specialize(&self, format: Self::Key) -> RenderPipelineDescriptor {
+        RenderPipelineDescriptor {
+            label: Some("mipmap_downsample_pipeline".into()),
+            layout: vec![self.layout.clone()],
+            push_constant_ranges: Vec::new(),
+            vertex: fullscreen_shader_vertex_state(),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                shader: DOWNSAMPLE_SHADER_HANDLE,
+                shader_defs: Vec::new(),
+                entry_point: "fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+        }
+    }
+}
+
+/// Runs after the images are prepared and before the render graph, so the levels are filled
+/// before anything samples them. Images whose texture or pipeline isn't ready yet are retried
+/// the next frame.
+fn fill_mip_levels(
+    mut pending: ResMut<PendingGpuMipmaps>,
+    gpu_images: Res<RenderAssets<GpuImage>>,
+    downsample_pipeline: Res<DownsamplePipeline>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<DownsamplePipeline>>,
+    pipeline_cache: Res<PipelineCache>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    pending
+        .0
+        .retain(|id, filled| filled.is_none() || gpu_images.get(*id).is_some());
+    let mut encoder = None;
+    for (id, filled) in pending.0.iter_mut() {
+        let Some(gpu_image) = gpu_images.get(*id) else {
+            continue;
+        };
+        if gpu_image.mip_level_count < 2 || *filled == Some(gpu_image.texture.id()) {
+            continue;
+        }
+        let pipeline_id = pipelines.specialize(
+            &pipeline_cache,
+            &downsample_pipeline,
+            gpu_image.texture_format,
+        );
+        let Some(pipeline) = pipeline_cache.get_render_pipeline(pipeline_id) else {
+            continue;
+        };
+        let encoder = encoder.get_or_insert_with(|| {
+            render_device.create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("mipmap_downsample"),
+            })
+        });
+        let level_view = |level: u32| {
+            gpu_image.texture.create_view(&TextureViewDescriptor {
+                label: Some("mipmap_downsample_level"),
+                base_mip_level: level,
+                mip_level_count: Some(1),
+                ..default()
+            })
+        };
+        for level in 1..gpu_image.mip_level_count {
+            let source = level_view(level - 1);
+            let target = level_view(level);
+            let bind_group = render_device.create_bind_group(
+                "mipmap_downsample_bind_group",
+                &downsample_pipeline.layout,
+                &BindGroupEntries::sequential((&source, &downsample_pipeline.sampler)),
+            );
+            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("mipmap_downsample"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &target,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(default()),
+                        store: StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+        *filled = Some(gpu_image.texture.id());
+    }
+    if let Some(encoder) = encoder {
+        render_queue.submit([encoder.finish()]);
+    }
+}
@@ -0,0 +1,94 @@
+use bevy::{
+    core_pipeline::{experimental::taa::TemporalAntiAliasSettings, prepass::DeferredPrepass},
+    pbr::ScreenSpaceAmbientOcclusionSettings,
+    prelude::*,
+    render::camera::TemporalJitter,
+};
+
+/// Effects that don't work with MSAA. Every place that turns one on checks `allowed_with` first,
+/// `enforce_msaa_compatibility` turns off whatever got past that.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MsaaConflict {
+    /// Jitters and resolves its own history, it has no multisampled path.
+    Taa,
+    /// Reads the depth and normal prepasses, which it doesn't support multisampled.
+    Ssao,
+    /// Samples a copy of the opaque pass that isn't resolved yet with MSAA, so transmissive
+    /// materials show garbage.
+    Transmission,
+    /// The deferred G-buffer has one sample per pixel.
+    Deferred,
+}
+
+impl MsaaConflict {
+    pub fn name(self) -> &'static str {
+        match self {
+            MsaaConflict::Taa => "TAA",
+            MsaaConflict::Ssao => "SSAO",
+            MsaaConflict::Transmission => "Screen space transmission",
+            MsaaConflict::Deferred => "Deferred rendering",
+        }
+    }
+
+    /// An error saying what to do if the effect can't be turned on with `msaa`.
+    pub fn allowed_with(self, msaa: Msaa) -> Result<(), String> {
+        if msaa == Msaa::Off {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} needs MSAA off, it's at {}x (restart with --msaa off)",
+                self.name(),
+                msaa.samples()
+            ))
+        }
+    }
+}
+
+/// Turns off what conflicts with MSAA on every camera, with a warning. Catches what isn't checked
+/// up front, like a restored session or components added by an app using the plugin.
+#[allow(clippy::type_complexity)]
+pub fn enforce_msaa_compatibility(
+    mut commands: Commands,
+    msaa: Res<Msaa>,
+    mut cameras: Query<(
+        Entity,
+        &mut Camera3d,
+        Has<TemporalAntiAliasSettings>,
+        Has<ScreenSpaceAmbientOcclusionSettings>,
+        Has<DeferredPrepass>,
+    )>,
+) {
+    if *msaa == Msaa::Off {
+        return;
+    }
+    for (entity, mut camera_3d, taa, ssao, deferred) in &mut cameras {
+        let mut turned_off = Vec::new();
+        if taa {
+            commands
+                .entity(entity)
+                .remove::<(TemporalAntiAliasSettings, TemporalJitter)>();
+            turned_off.push(MsaaConflict::Taa);
+        }
+        if ssao {
+            commands
+                .entity(entity)
+                .remove::<ScreenSpaceAmbientOcclusionSettings>();
+            turned_off.push(MsaaConflict::Ssao);
+        }
+        if camera_3d.screen_space_specular_transmission_steps > 0 {
+            camera_3d.screen_space_specular_transmission_steps = 0;
+            turned_off.push(MsaaConflict::Transmission);
+        }
+        if deferred {
+            commands.entity(entity).remove::<DeferredPrepass>();
+            turned_off.push(MsaaConflict::Deferred);
+        }
+        for conflict in turned_off {
+            warn!(
+                "{} turned off: {}",
+                conflict.name(),
+                conflict.allowed_with(*msaa).unwrap_err()
+            );
+        }
+    }
+}
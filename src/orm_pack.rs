@@ -0,0 +1,725 @@
+use image::{imageops::FilterType, Rgba, RgbaImage};
+use percent_encoding::utf8_percent_encode;
+use serde_json::{Map, Value};
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::convert::{is_source_image, uri_to_path, URI_PATH};
+use crate::glb::{read_gltf_json, write_gltf_json};
+
+/// A packed texture to write, and the materials that will use it.
+#[derive(Debug)]
+pub struct OrmPack {
+    pub occlusion: PathBuf,
+    pub metallic_roughness: PathBuf,
+    pub output: PathBuf,
+    /// `output` relative to the glTF, percent-encoded.
+    pub uri: String,
+    /// Sampler of the metallic/roughness texture, reused by the packed texture.
+    pub sampler: Option<u64>,
+    pub materials: Vec<usize>,
+}
+
+#[derive(Debug)]
+pub struct OrmPackPlan {
+    pub gltf: PathBuf,
+    pub packs: Vec<OrmPack>,
+    /// Materials with separate maps that can't be packed, as (material name, reason).
+    pub skipped: Vec<(String, String)>,
+}
+
+/// Finds the materials with separate occlusion and metallic/roughness images. Materials whose
+/// slots already point at the same image are left alone. Materials using the same pair of images
+/// share one packed texture.
+pub fn plan_orm_packing(gltf_path: &Path) -> Result<OrmPackPlan, String> {
+    let json = read_gltf_json(gltf_path)?;
+    let base = gltf_path.parent().unwrap_or(Path::new("."));
+    let mut plan = OrmPackPlan {
+        gltf: gltf_path.to_path_buf(),
+        packs: Vec::new(),
+        skipped: Vec::new(),
+    };
+    let mut existing = json["images"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|image| image["uri"].as_str())
+        .map(|uri| uri_to_path(base, uri))
+        .collect::<Vec<_>>();
+    let mut by_pair = HashMap::new();
+
+    for (i, material) in json["materials"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+    {
+        let occlusion = &material["occlusionTexture"];
+        let metallic_roughness = &material["pbrMetallicRoughness"]["metallicRoughnessTexture"];
+        let (Some(occlusion_texture), Some(mr_texture)) = (
+            occlusion["index"].as_u64(),
+            metallic_roughness["index"].as_u64(),
+        ) else {
+            continue;
+        };
+        let occlusion_source = json["textures"][occlusion_texture as usize]["source"].as_u64();
+        let mr_source = json["textures"][mr_texture as usize]["source"].as_u64();
+        // Two textures without a `source` can still use different images
+        if occlusion_texture == mr_texture
+            || (occlusion_source.is_some() && occlusion_source == mr_source)
+        {
+            continue;
+        }
+
+        let name = material["name"].as_str().unwrap_or("unnamed").to_string();
+        // e.g. KHR_texture_basisu or EXT_texture_webp without a fallback
+        let (Some(occlusion_source), Some(mr_source)) = (occlusion_source, mr_source) else {
+            plan.skipped
+                .push((name, "the image is only set by an extension".to_string()));
+            continue;
+        };
+        let uri = |source: u64| json["images"][source as usize]["uri"].as_str();
+        let (Some(occlusion_uri), Some(mr_uri)) = (uri(occlusion_source), uri(mr_source)) else {
+            plan.skipped.push((name, "embedded image".to_string()));
+            continue;
+        };
+        let occlusion_path = uri_to_path(base, occlusion_uri);
+        let mr_path = uri_to_path(base, mr_uri);
+        if !is_source_image(&occlusion_path) || !is_source_image(&mr_path) {
+            plan.skipped
+                .push((name, "only png, jpg and tga can be packed".to_string()));
+            continue;
+        }
+        let tex_coord = |info: &Value| info["texCoord"].as_u64().unwrap_or(0);
+        if tex_coord(occlusion) != tex_coord(metallic_roughness) {
+            plan.skipped
+                .push((name, "the maps use different texCoords".to_string()));
+            continue;
+        }
+        // e.g. KHR_texture_transform, which a single texture can't have two of
+        if occlusion["extensions"] != metallic_roughness["extensions"] {
+            plan.skipped
+                .push((name, "the maps use different extensions".to_string()));
+            continue;
+        }
+
+        let pack = *by_pair
+            .entry((occlusion_path.clone(), mr_path.clone()))
+            .or_insert_with(|| {
+                let stem = mr_path.file_stem().unwrap().to_string_lossy();
+                let dir = mr_path.parent().unwrap_or(base);
+                let mut output = dir.join(format!("{stem}_orm.png"));
+                let mut n = 2;
+                while existing.contains(&output) {
+                    output = dir.join(format!("{stem}_orm_{n}.png"));
+                    n += 1;
+                }
+                existing.push(output.clone());
+                let relative = output.strip_prefix(base).unwrap_or(&output);
+                plan.packs.push(OrmPack {
+                    occlusion: occlusion_path,
+                    metallic_roughness: mr_path,
+                    uri: utf8_percent_encode(&relative.to_string_lossy(), URI_PATH).to_string(),
+                    output,
+                    sampler: json["textures"][mr_texture as usize]["sampler"].as_u64(),
+                    materials: Vec::new(),
+                });
+                plan.packs.len() - 1
+            });
+        plan.packs[pack].materials.push(i);
+    }
+    Ok(plan)
+}
+
+pub fn print_orm_plan(plan: &OrmPackPlan) {
+    println!("{:?}: {} ORM textures to pack", plan.gltf, plan.packs.len());
+    for pack in &plan.packs {
+        println!(
+            "    {:?} + {:?} -> {:?} ({} materials)",
+            pack.occlusion,
+            pack.metallic_roughness,
+            pack.output,
+            pack.materials.len()
+        );
+    }
+    for (material, reason) in &plan.skipped {
+        println!("    Not packing {:?}: {}", material, reason);
+    }
+}
+
+/// Occlusion from the red channel of `occlusion`, roughness and metallic from the green and blue
+/// channels of `metallic_roughness`. The smaller image is resized to the larger one.
+fn composite(occlusion: &Path, metallic_roughness: &Path) -> Result<RgbaImage, String> {
+    let open = |path: &Path| {
+        image::open(path)
+            .map(|image| image.to_rgba8())
+            .map_err(|e| format!("could not decode {:?}: {e}", path))
+    };
+    let (occlusion, metallic_roughness) = (open(occlusion)?, open(metallic_roughness)?);
+    let width = occlusion.width().max(metallic_roughness.width());
+    let height = occlusion.height().max(metallic_roughness.height());
+    let fit = |image: RgbaImage| {
+        if image.dimensions() == (width, height) {
+            image
+        } else {
+            image::imageops::resize(&image, width, height, FilterType::Triangle)
+        }
+    };
+    let (occlusion, metallic_roughness) = (fit(occlusion), fit(metallic_roughness));
+    Ok(RgbaImage::from_fn(width, height, |x, y| {
+        let mr = metallic_roughness.get_pixel(x, y);
+        Rgba([occlusion.get_pixel(x, y)[0], mr[1], mr[2], 255])
+    }))
+}
+
+/// Writes the packed images and points the materials at them. Textures and images that were
+/// only used by the replaced slots are removed from the glTF, their files are left on disk.
+/// Returns the URIs of the removed images. On an error the glTF is left as it was and none of
+/// the packed images are left behind.
+pub fn pack_orm(plan: &OrmPackPlan) -> Result<Vec<String>, String> {
+    if plan.packs.is_empty() {
+        return Ok(Vec::new());
+    }
+    let path = &plan.gltf;
+    let mut json = read_gltf_json(path)?;
+    let textures_used_before = texture_usage(&mut json);
+    let images_used_before = image_usage(&mut json);
+
+    for pack in &plan.packs {
+        let images = array_mut(&mut json, "images");
+        images.push(serde_json::json!({ "uri": pack.uri }));
+        let image = images.len() - 1;
+        let mut texture = serde_json::json!({ "source": image });
+        if let Some(sampler) = pack.sampler {
+            texture["sampler"] = sampler.into();
+        }
+        let textures = array_mut(&mut json, "textures");
+        textures.push(texture);
+        let texture = textures.len() - 1;
+
+        for i in &pack.materials {
+            let material = &mut json["materials"][*i];
+            material["occlusionTexture"]["index"] = texture.into();
+            material["pbrMetallicRoughness"]["metallicRoughnessTexture"]["index"] = texture.into();
+        }
+    }
+    let removed = remove_unreferenced(&mut json, &textures_used_before, &images_used_before)?;
+
+    // Every image is written to a temporary file first and only renamed once all of them were
+    // written, so a failed pack doesn't leave the earlier ones on disk
+    let temps = plan
+        .packs
+        .iter()
+        .map(|pack| pack.output.with_extension("tmp.png"))
+        .collect::<Vec<_>>();
+    let mut renamed = Vec::new();
+    let result =
+        write_packs(plan, &temps, &mut renamed).and_then(|()| write_gltf_json(path, &json));
+    if result.is_err() {
+        for file in temps.iter().chain(&renamed) {
+            let _ = fs::remove_file(file);
+        }
+    }
+    result.map(|()| removed)
+}
+
+/// Writes the packed images to `temps`, then renames them to their outputs. The outputs that were
+/// renamed are added to `renamed`.
+fn write_packs(
+    plan: &OrmPackPlan,
+    temps: &[PathBuf],
+    renamed: &mut Vec<PathBuf>,
+) -> Result<(), String> {
+    for (pack, temp) in plan.packs.iter().zip(temps) {
+        composite(&pack.occlusion, &pack.metallic_roughness)?
+            .save(temp)
+            .map_err(|e| format!("could not write {:?}: {e}", pack.output))?;
+    }
+    for (pack, temp) in plan.packs.iter().zip(temps) {
+        fs::rename(temp, &pack.output)
+            .map_err(|e| format!("could not write {:?}: {e}", pack.output))?;
+        renamed.push(pack.output.clone());
+    }
+    Ok(())
+}
+
+fn array_mut<'a>(json: &'a mut Value, key: &str) -> &'a mut Vec<Value> {
+    if !json[key].is_array() {
+        json[key] = Value::Array(Vec::new());
+    }
+    json[key].as_array_mut().unwrap()
+}
+
+/// Calls `f` on every textureInfo (`baseColorTexture`, `occlusionTexture`, extension textures,
+/// ...) found under `value`.
+fn visit_texture_infos(value: &mut Value, f: &mut impl FnMut(&mut Map<String, Value>)) {
+    match value {
+        Value::Object(object) => {
+            for (key, child) in object.iter_mut() {
+                if key.ends_with("Texture") {
+                    if let Some(info) = child
+                        .as_object_mut()
+                        .filter(|info| info.contains_key("index"))
+                    {
+                        f(info);
+                    }
+                }
+                visit_texture_infos(child, f);
+            }
+        }
+        Value::Array(array) => {
+            for child in array {
+                visit_texture_infos(child, f);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Calls `f` on the image index of every texture, including extension sources like
+/// `KHR_texture_basisu`.
+fn visit_texture_sources(textures: &mut [Value], f: &mut impl FnMut(&mut Value)) {
+    for texture in textures {
+        if let Some(source) = texture.get_mut("source") {
+            f(source);
+        }
+        if let Some(extensions) = texture.get_mut("extensions").and_then(Value::as_object_mut) {
+            for extension in extensions.values_mut() {
+                if let Some(source) = extension.get_mut("source") {
+                    f(source);
+                }
+            }
+        }
+    }
+}
+
+/// Keeps the elements whose index is in `used`, returns the new index of each old one.
+fn retain_used(array: &mut Vec<Value>, used: &[bool]) -> Vec<Option<u64>> {
+    let mut remap = Vec::with_capacity(array.len());
+    let mut next = 0;
+    for is_used in used {
+        remap.push(is_used.then(|| {
+            next += 1;
+            next - 1
+        }));
+    }
+    let mut i = 0;
+    array.retain(|_| {
+        i += 1;
+        used[i - 1]
+    });
+    remap
+}
+
+fn texture_usage(json: &mut Value) -> Vec<bool> {
+    let mut used = vec![false; json["textures"].as_array().map_or(0, Vec::len)];
+    visit_texture_infos(&mut json["materials"], &mut |info| {
+        if let Some(used) = info["index"]
+            .as_u64()
+            .and_then(|i| used.get_mut(i as usize))
+        {
+            *used = true;
+        }
+    });
+    used
+}
+
+fn image_usage(json: &mut Value) -> Vec<bool> {
+    let mut used = vec![false; json["images"].as_array().map_or(0, Vec::len)];
+    visit_texture_sources(array_mut(json, "textures"), &mut |source| {
+        if let Some(used) = source.as_u64().and_then(|i| used.get_mut(i as usize)) {
+            *used = true;
+        }
+    });
+    used
+}
+
+/// Drops the textures and images that were referenced before packing (`used_before`) but aren't
+/// anymore, and renumbers the references to the ones that are left. Anything that was already
+/// unreferenced is kept as it was. A reference to a texture or image that doesn't exist is an
+/// error.
+fn remove_unreferenced(
+    json: &mut Value,
+    textures_used_before: &[bool],
+    images_used_before: &[bool],
+) -> Result<Vec<String>, String> {
+    let keep = |used_now: Vec<bool>, used_before: &[bool]| {
+        used_now
+            .iter()
+            .enumerate()
+            .map(|(i, now)| *now || !used_before.get(i).copied().unwrap_or(false))
+            .collect::<Vec<_>>()
+    };
+
+    let keep_textures = keep(texture_usage(json), textures_used_before);
+    let remap = retain_used(array_mut(json, "textures"), &keep_textures);
+    let mut missing = None;
+    visit_texture_infos(&mut json["materials"], &mut |info| {
+        let Some(i) = info["index"].as_u64() else {
+            return;
+        };
+        match remap.get(i as usize) {
+            Some(Some(index)) => {
+                info.insert("index".to_string(), (*index).into());
+            }
+            Some(None) => (),
+            None => missing = Some(i),
+        }
+    });
+    if let Some(i) = missing {
+        return Err(format!("a material uses texture {i}, which doesn't exist"));
+    }
+
+    let keep_images = keep(image_usage(json), images_used_before);
+    let removed = json["images"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .zip(&keep_images)
+        .filter(|(_, keep)| !**keep)
+        .map(|(image, _)| image["uri"].as_str().unwrap_or("embedded").to_string())
+        .collect();
+    let remap = retain_used(array_mut(json, "images"), &keep_images);
+    let mut missing = None;
+    visit_texture_sources(array_mut(json, "textures"), &mut |source| {
+        let Some(i) = source.as_u64() else {
+            return;
+        };
+        match remap.get(i as usize) {
+            Some(Some(index)) => *source = (*index).into(),
+            Some(None) => (),
+            None => missing = Some(i),
+        }
+    });
+    if let Some(i) = missing {
+        return Err(format!("a texture uses image {i}, which doesn't exist"));
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_dir::TempDir;
+
+    /// An empty directory for one test's files, with a `textures` directory in it.
+    fn fixture_dir(name: &str) -> TempDir {
+        let dir = TempDir::new(&format!("orm_{name}"));
+        fs::create_dir_all(dir.join("textures")).unwrap();
+        dir
+    }
+
+    fn write_image(path: &Path, width: u32, height: u32, f: impl Fn(u32, u32) -> [u8; 4]) {
+        RgbaImage::from_fn(width, height, |x, y| Rgba(f(x, y)))
+            .save(path)
+            .unwrap();
+    }
+
+    /// Two materials sharing an occlusion + metallic/roughness pair, one without occlusion and one
+    /// whose occlusion is embedded.
+    fn write_gltf(dir: &Path) -> PathBuf {
+        let gltf = json!({
+            "images": [
+                { "uri": "textures/base.png" },
+                { "uri": "textures/ao.png" },
+                { "uri": "textures/mr.png" },
+                { "bufferView": 0, "mimeType": "image/png" },
+            ],
+            "samplers": [{}],
+            "textures": [
+                { "source": 0 },
+                { "source": 1 },
+                { "source": 2, "sampler": 0 },
+                { "source": 3 },
+            ],
+            "materials": [
+                {
+                    "name": "Wall",
+                    "occlusionTexture": { "index": 1 },
+                    "pbrMetallicRoughness": {
+                        "baseColorTexture": { "index": 0 },
+                        "metallicRoughnessTexture": { "index": 2 },
+                    },
+                },
+                {
+                    "name": "Wall2",
+                    "occlusionTexture": { "index": 1 },
+                    "pbrMetallicRoughness": { "metallicRoughnessTexture": { "index": 2 } },
+                },
+                {
+                    "name": "NoOcclusion",
+                    "pbrMetallicRoughness": { "metallicRoughnessTexture": { "index": 2 } },
+                },
+                {
+                    "name": "Embedded",
+                    "occlusionTexture": { "index": 3 },
+                    "pbrMetallicRoughness": { "metallicRoughnessTexture": { "index": 2 } },
+                },
+            ],
+        });
+        let path = dir.join("scene.gltf");
+        fs::write(&path, serde_json::to_vec(&gltf).unwrap()).unwrap();
+        path
+    }
+
+    #[test]
+    fn plans_shared_pairs_and_skips_the_rest() {
+        let dir = fixture_dir("plan");
+        let plan = plan_orm_packing(&write_gltf(&dir)).unwrap();
+
+        assert_eq!(plan.packs.len(), 1);
+        let pack = &plan.packs[0];
+        assert_eq!(pack.occlusion, dir.join("textures/ao.png"));
+        assert_eq!(pack.metallic_roughness, dir.join("textures/mr.png"));
+        assert_eq!(pack.output, dir.join("textures/mr_orm.png"));
+        assert_eq!(pack.uri, "textures/mr_orm.png");
+        assert_eq!(pack.sampler, Some(0));
+        assert_eq!(pack.materials, [0, 1]);
+        // Without an occlusion texture there's nothing to pack, it isn't reported
+        assert_eq!(
+            plan.skipped,
+            [("Embedded".to_string(), "embedded image".to_string())]
+        );
+    }
+
+    #[test]
+    fn extension_only_images_are_skipped() {
+        let dir = fixture_dir("extension_images");
+        let extension = |source: u64| json!({ "KHR_texture_basisu": { "source": source } });
+        let gltf = json!({
+            "images": [{ "uri": "textures/ao.ktx2" }, { "uri": "textures/mr.ktx2" }],
+            "textures": [
+                { "extensions": extension(0) },
+                { "extensions": extension(1) },
+            ],
+            "materials": [{
+                "name": "Basisu",
+                "occlusionTexture": { "index": 0 },
+                "pbrMetallicRoughness": { "metallicRoughnessTexture": { "index": 1 } },
+            }],
+        });
+        let path = dir.join("scene.gltf");
+        fs::write(&path, serde_json::to_vec(&gltf).unwrap()).unwrap();
+
+        let plan = plan_orm_packing(&path).unwrap();
+        assert!(plan.packs.is_empty());
+        assert_eq!(
+            plan.skipped,
+            [(
+                "Basisu".to_string(),
+                "the image is only set by an extension".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn packs_channels_and_rewrites_gltf() {
+        let dir = fixture_dir("pack");
+        let gltf = write_gltf(&dir);
+        write_image(&dir.join("textures/ao.png"), 2, 2, |x, y| {
+            [10 + (x + 2 * y) as u8, 200, 201, 202]
+        });
+        write_image(&dir.join("textures/mr.png"), 2, 2, |x, y| {
+            [99, 50 + (x + 2 * y) as u8, 150 + (x + 2 * y) as u8, 7]
+        });
+
+        let removed = pack_orm(&plan_orm_packing(&gltf).unwrap()).unwrap();
+
+        // Occlusion in red, roughness in green, metallic in blue, opaque
+        let packed = image::open(dir.join("textures/mr_orm.png"))
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(packed.dimensions(), (2, 2));
+        for (x, y, pixel) in packed.enumerate_pixels() {
+            let i = (x + 2 * y) as u8;
+            assert_eq!(pixel.0, [10 + i, 50 + i, 150 + i, 255]);
+        }
+
+        // Only the occlusion texture and image lost all their users
+        assert_eq!(removed, ["textures/ao.png"]);
+        let json = read_gltf_json(&gltf).unwrap();
+        let uris = json["images"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|image| image["uri"].as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            uris,
+            [
+                Some("textures/base.png"),
+                Some("textures/mr.png"),
+                None,
+                Some("textures/mr_orm.png")
+            ]
+        );
+        assert_eq!(
+            json["textures"],
+            json!([
+                { "source": 0 },
+                { "source": 1, "sampler": 0 },
+                { "source": 2 },
+                { "source": 3, "sampler": 0 },
+            ])
+        );
+        let materials = &json["materials"];
+        for material in [&materials[0], &materials[1]] {
+            assert_eq!(material["occlusionTexture"]["index"], 3);
+            assert_eq!(
+                material["pbrMetallicRoughness"]["metallicRoughnessTexture"]["index"],
+                3
+            );
+        }
+        assert_eq!(
+            materials[0]["pbrMetallicRoughness"]["baseColorTexture"]["index"],
+            0
+        );
+        assert_eq!(
+            materials[2]["pbrMetallicRoughness"]["metallicRoughnessTexture"]["index"],
+            1
+        );
+        assert_eq!(materials[3]["occlusionTexture"]["index"], 2);
+        assert_eq!(
+            materials[3]["pbrMetallicRoughness"]["metallicRoughnessTexture"]["index"],
+            1
+        );
+    }
+
+    #[test]
+    fn resizes_the_smaller_map() {
+        let dir = fixture_dir("resize");
+        let gltf = write_gltf(&dir);
+        write_image(&dir.join("textures/ao.png"), 1, 1, |_, _| [128, 0, 0, 255]);
+        write_image(&dir.join("textures/mr.png"), 4, 2, |_, _| [0, 64, 32, 255]);
+
+        pack_orm(&plan_orm_packing(&gltf).unwrap()).unwrap();
+
+        let packed = image::open(dir.join("textures/mr_orm.png"))
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(packed.dimensions(), (4, 2));
+        assert!(packed.pixels().all(|pixel| pixel.0 == [128, 64, 32, 255]));
+    }
+
+    #[test]
+    fn missing_image_leaves_gltf_alone() {
+        let dir = fixture_dir("missing");
+        let gltf = write_gltf(&dir);
+        let before = fs::read(&gltf).unwrap();
+        // No metallic/roughness image on disk
+        write_image(&dir.join("textures/ao.png"), 1, 1, |_, _| [128, 0, 0, 255]);
+
+        let error = pack_orm(&plan_orm_packing(&gltf).unwrap()).unwrap_err();
+
+        assert!(error.contains("mr.png"), "{error}");
+        assert_eq!(fs::read(&gltf).unwrap(), before);
+        assert!(!dir.join("textures/mr_orm.png").exists());
+    }
+
+    #[test]
+    fn no_packs_without_separate_maps() {
+        let dir = fixture_dir("none");
+        let gltf = dir.join("scene.gltf");
+        let json = json!({
+            "images": [{ "uri": "textures/orm.png" }],
+            "textures": [{ "source": 0 }],
+            "materials": [{
+                "occlusionTexture": { "index": 0 },
+                "pbrMetallicRoughness": { "metallicRoughnessTexture": { "index": 0 } },
+            }],
+        });
+        fs::write(&gltf, serde_json::to_vec(&json).unwrap()).unwrap();
+
+        let plan = plan_orm_packing(&gltf).unwrap();
+
+        assert!(plan.packs.is_empty() && plan.skipped.is_empty());
+        assert_eq!(pack_orm(&plan).unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn malformed_gltf_is_an_error() {
+        let dir = fixture_dir("malformed");
+        let gltf = dir.join("scene.gltf");
+        fs::write(&gltf, r#"{ "materials": ["#).unwrap();
+        let error = plan_orm_packing(&gltf).unwrap_err();
+        assert!(error.contains("scene.gltf"), "{error}");
+    }
+
+    #[test]
+    fn out_of_range_indices_are_errors() {
+        let dir = fixture_dir("out_of_range");
+        let gltf = write_gltf(&dir);
+        let mut json = read_gltf_json(&gltf).unwrap();
+        json["materials"][0]["emissiveTexture"] = json!({ "index": 9 });
+        fs::write(&gltf, serde_json::to_vec(&json).unwrap()).unwrap();
+        write_image(&dir.join("textures/ao.png"), 1, 1, |_, _| [128, 0, 0, 255]);
+        write_image(&dir.join("textures/mr.png"), 1, 1, |_, _| [0, 64, 32, 255]);
+        let before = fs::read(&gltf).unwrap();
+
+        let error = pack_orm(&plan_orm_packing(&gltf).unwrap()).unwrap_err();
+        assert!(error.contains("texture 9"), "{error}");
+        assert_eq!(fs::read(&gltf).unwrap(), before);
+        assert!(!dir.join("textures/mr_orm.png").exists());
+
+        json["materials"][0]
+            .as_object_mut()
+            .unwrap()
+            .remove("emissiveTexture");
+        json["textures"][0]["source"] = 7.into();
+        fs::write(&gltf, serde_json::to_vec(&json).unwrap()).unwrap();
+        let error = pack_orm(&plan_orm_packing(&gltf).unwrap()).unwrap_err();
+        assert!(error.contains("image 7"), "{error}");
+    }
+
+    #[test]
+    fn failed_pack_removes_the_written_ones() {
+        let dir = fixture_dir("partial");
+        let gltf = dir.join("scene.gltf");
+        let json = json!({
+            "images": [
+                { "uri": "textures/ao.png" },
+                { "uri": "textures/mr.png" },
+                { "uri": "textures/ao2.png" },
+                { "uri": "textures/mr2.png" },
+            ],
+            "textures": [{ "source": 0 }, { "source": 1 }, { "source": 2 }, { "source": 3 }],
+            "materials": [
+                {
+                    "occlusionTexture": { "index": 0 },
+                    "pbrMetallicRoughness": { "metallicRoughnessTexture": { "index": 1 } },
+                },
+                {
+                    "occlusionTexture": { "index": 2 },
+                    "pbrMetallicRoughness": { "metallicRoughnessTexture": { "index": 3 } },
+                },
+            ],
+        });
+        fs::write(&gltf, serde_json::to_vec(&json).unwrap()).unwrap();
+        write_image(&dir.join("textures/ao.png"), 1, 1, |_, _| [128, 0, 0, 255]);
+        write_image(&dir.join("textures/mr.png"), 1, 1, |_, _| [0, 64, 32, 255]);
+        // The second pack has no metallic/roughness image on disk
+        write_image(&dir.join("textures/ao2.png"), 1, 1, |_, _| [128, 0, 0, 255]);
+        let before = fs::read(&gltf).unwrap();
+
+        let plan = plan_orm_packing(&gltf).unwrap();
+        assert_eq!(plan.packs.len(), 2);
+        let error = pack_orm(&plan).unwrap_err();
+
+        assert!(error.contains("mr2.png"), "{error}");
+        assert_eq!(fs::read(&gltf).unwrap(), before);
+        let mut files = fs::read_dir(dir.join("textures"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(files, ["ao.png", "ao2.png", "mr.png"]);
+    }
+}
@@ -0,0 +1,135 @@
+use bevy::{
+    diagnostic::{
+        DiagnosticPath, DiagnosticsStore, EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin,
+    },
+    prelude::*,
+};
+
+use crate::{help::KeyBindings, Args, BenchmarkRunning, MainCamera};
+
+/// Seconds between text updates, every frame would lay the text out again every frame.
+const UPDATE_INTERVAL: f32 = 0.25;
+
+#[derive(Component)]
+pub struct FpsOverlay;
+
+pub fn spawn_overlay(
+    mut commands: Commands,
+    args: Res<Args>,
+    camera: Query<Entity, With<MainCamera>>,
+) {
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 18.0,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(8.0),
+            right: Val::Px(8.0),
+            padding: UiRect::all(Val::Px(6.0)),
+            ..default()
+        })
+        .with_background_color(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+        // Only on the main camera, with --split-screen in the corner of the left half
+        TargetCamera(camera),
+        if args.overlay {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        },
+        FpsOverlay,
+    ));
+}
+
+/// The sum of the top level render passes, only there with `RenderDiagnosticsPlugin` on a GPU
+/// with timestamp queries.
+fn gpu_time(diagnostics: &DiagnosticsStore) -> Option<f64> {
+    let times = diagnostics
+        .iter()
+        .filter(|diagnostic| {
+            let path = diagnostic.path().as_str();
+            path.starts_with("render/")
+                && path.ends_with("/elapsed_gpu")
+                && path.matches('/').count() == 2
+        })
+        .filter_map(|diagnostic| diagnostic.smoothed())
+        .collect::<Vec<_>>();
+    (!times.is_empty()).then(|| times.iter().sum())
+}
+
+fn smoothed(diagnostics: &DiagnosticsStore, path: &DiagnosticPath) -> Option<f64> {
+    diagnostics
+        .get(path)
+        .and_then(|diagnostic| diagnostic.smoothed())
+}
+
+pub fn register_keys(mut bindings: ResMut<KeyBindings>) {
+    bindings.add("Debug", "F10", "Show or hide the FPS overlay");
+}
+
+// F10: show or hide the FPS overlay. It's hidden while the benchmark runs so the text doesn't
+// end up in its frame times, and comes back after.
+pub fn update_overlay(
+    input: Res<ButtonInput<KeyCode>>,
+    diagnostics: Res<DiagnosticsStore>,
+    running: Res<BenchmarkRunning>,
+    time: Res<Time>,
+    mut overlay: Query<(&mut Text, &mut Visibility), With<FpsOverlay>>,
+    mut shown: Local<Option<bool>>,
+    mut since_update: Local<f32>,
+) {
+    let Ok((mut text, mut visibility)) = overlay.get_single_mut() else {
+        return;
+    };
+    let shown = shown.get_or_insert(*visibility != Visibility::Hidden);
+    let toggled = input.just_pressed(KeyCode::F10);
+    if toggled {
+        *shown = !*shown;
+        println!("FPS overlay {}", if *shown { "on" } else { "off" });
+    }
+    let visible = *shown && !running.0;
+    if visible != (*visibility != Visibility::Hidden) {
+        if *shown && !toggled {
+            println!(
+                "FPS overlay {}",
+                if running.0 {
+                    "hidden for the benchmark"
+                } else {
+                    "back on"
+                }
+            );
+        }
+        *visibility = if visible {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        // Fresh numbers as soon as it shows up
+        *since_update = UPDATE_INTERVAL;
+    }
+    if !visible {
+        return;
+    }
+    *since_update += time.delta_seconds();
+    if *since_update < UPDATE_INTERVAL {
+        return;
+    }
+    *since_update = 0.0;
+
+    let fps = smoothed(&diagnostics, &FrameTimeDiagnosticsPlugin::FPS).unwrap_or(0.0);
+    let frame_ms = smoothed(&diagnostics, &FrameTimeDiagnosticsPlugin::FRAME_TIME).unwrap_or(0.0);
+    let entities = smoothed(&diagnostics, &EntityCountDiagnosticsPlugin::ENTITY_COUNT)
+        .map_or_else(|| "-".to_string(), |count| format!("{count:.0}"));
+    let gpu =
+        gpu_time(&diagnostics).map_or_else(|| "n/a".to_string(), |gpu_ms| format!("{gpu_ms:.2}ms"));
+    text.sections[0].value =
+        format!("{fps:.0} FPS\n{frame_ms:.2}ms frame\nGPU {gpu}\n{entities} entities");
+}
@@ -0,0 +1,342 @@
+use bevy::{
+    prelude::*,
+    render::{
+        mesh::{Indices, PrimitiveTopology, VertexAttributeValues},
+        primitives::Aabb,
+    },
+    window::PrimaryWindow,
+};
+
+use crate::{
+    camera_controller::CameraController, debug_view::DebugViewOriginal, help::KeyBindings,
+    wind::Swaying, CameraTransition, MainCamera,
+};
+
+pub struct RayHit {
+    pub entity: Entity,
+    pub point: Vec3,
+    pub distance: f32,
+}
+
+/// Ray from the camera through the cursor position of the primary window.
+pub fn cursor_ray(
+    windows: &Query<&Window, With<PrimaryWindow>>,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+) -> Option<Ray3d> {
+    let cursor = windows.get_single().ok()?.cursor_position()?;
+    camera.viewport_to_world(camera_transform, cursor)
+}
+
+/// Ray through the cursor from the active camera under it. With --split-screen each camera
+/// covers part of the window.
+fn ray_under_cursor(
+    windows: &Query<&Window, With<PrimaryWindow>>,
+    cameras: &Query<(&Camera, &GlobalTransform)>,
+) -> Option<Ray3d> {
+    let cursor = windows
+        .get_single()
+        .ok()
+        .and_then(|window| window.cursor_position());
+    let (camera, camera_transform) = cameras.iter().find(|(camera, _)| {
+        camera.is_active
+            && camera
+                .logical_viewport_rect()
+                .zip(cursor)
+                .is_none_or(|(rect, cursor)| rect.contains(cursor))
+    })?;
+    cursor_ray(windows, camera, camera_transform)
+}
+
+/// Finds the closest mesh hit by the ray. Meshes are first tested against their `Aabb`, then
+/// against their triangles if they are a triangle list.
+pub fn raycast_meshes<'a>(
+    ray: Ray3d,
+    meshes: &Assets<Mesh>,
+    candidates: impl Iterator<Item = (Entity, &'a Handle<Mesh>, &'a GlobalTransform, &'a Aabb)>,
+) -> Option<RayHit> {
+    let mut closest: Option<RayHit> = None;
+    for (entity, mesh_h, transform, aabb) in candidates {
+        // Work in mesh space so the aabb and vertex positions can be used directly
+        let world_from_local = transform.affine();
+        let local_from_world = world_from_local.inverse();
+        let origin = local_from_world.transform_point3(ray.origin);
+        let direction = local_from_world.transform_vector3(*ray.direction);
+
+        let Some(aabb_t) = ray_aabb(origin, direction, aabb) else {
+            continue;
+        };
+        let local_t = match meshes.get(mesh_h) {
+            Some(mesh) if mesh.primitive_topology() == PrimitiveTopology::TriangleList => {
+                let Some(t) = ray_mesh(origin, direction, mesh) else {
+                    continue;
+                };
+                t
+            }
+            _ => aabb_t,
+        };
+        let point = world_from_local.transform_point3(origin + direction * local_t);
+        let distance = point.distance(ray.origin);
+        if closest.as_ref().is_none_or(|hit| distance < hit.distance) {
+            closest = Some(RayHit {
+                entity,
+                point,
+                distance,
+            });
+        }
+    }
+    closest
+}
+
+/// Slab test, returns the entry distance along the ray.
+fn ray_aabb(origin: Vec3, direction: Vec3, aabb: &Aabb) -> Option<f32> {
+    let inv_dir = direction.recip();
+    let t0 = (Vec3::from(aabb.min()) - origin) * inv_dir;
+    let t1 = (Vec3::from(aabb.max()) - origin) * inv_dir;
+    let t_min = t0.min(t1).max_element();
+    let t_max = t0.max(t1).min_element();
+    (t_max >= t_min.max(0.0)).then_some(t_min.max(0.0))
+}
+
+fn ray_mesh(origin: Vec3, direction: Vec3, mesh: &Mesh) -> Option<f32> {
+    let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+    else {
+        return None;
+    };
+    let indices: Vec<usize> = match mesh.indices() {
+        Some(Indices::U16(indices)) => indices.iter().map(|i| *i as usize).collect(),
+        Some(Indices::U32(indices)) => indices.iter().map(|i| *i as usize).collect(),
+        None => (0..positions.len()).collect(),
+    };
+    indices
+        .chunks_exact(3)
+        .filter_map(|tri| {
+            ray_triangle(
+                origin,
+                direction,
+                [
+                    Vec3::from(positions[tri[0]]),
+                    Vec3::from(positions[tri[1]]),
+                    Vec3::from(positions[tri[2]]),
+                ],
+            )
+        })
+        .min_by(|a, b| a.total_cmp(b))
+}
+
+/// Möller–Trumbore, double sided.
+fn ray_triangle(origin: Vec3, direction: Vec3, [a, b, c]: [Vec3; 3]) -> Option<f32> {
+    let edge1 = b - a;
+    let edge2 = c - a;
+    let p = direction.cross(edge2);
+    let det = edge1.dot(p);
+    if det.abs() < f32::EPSILON {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let s = origin - a;
+    let u = s.dot(p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(edge1);
+    let v = direction.dot(q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = edge2.dot(q) * inv_det;
+    (t > 0.0).then_some(t)
+}
+
+pub fn register_keys(mut bindings: ResMut<KeyBindings>) {
+    bindings
+        .add("Views", "F", "Frame the mesh under the cursor")
+        .add("Debug", "P", "Print the material and mesh under the cursor");
+}
+
+/// Press P to print the material and mesh under the cursor.
+#[allow(clippy::too_many_arguments)]
+pub fn pick_info(
+    input: Res<ButtonInput<KeyCode>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    candidates: Query<(Entity, &Handle<Mesh>, &GlobalTransform, &Aabb)>,
+    names: Query<&Name>,
+    has_std_mat: Query<&Handle<StandardMaterial>>,
+    swaying: Query<&Swaying>,
+    debug_originals: Query<&DebugViewOriginal>,
+    materials: Res<Assets<StandardMaterial>>,
+    meshes: Res<Assets<Mesh>>,
+) {
+    if !input.just_pressed(KeyCode::KeyP) {
+        return;
+    }
+    let Some(ray) = ray_under_cursor(&windows, &cameras) else {
+        return;
+    };
+    let Some(hit) = raycast_meshes(ray, &meshes, candidates.iter()) else {
+        println!("Nothing under the cursor");
+        return;
+    };
+
+    println!(
+        "Picked {:?} {:?} at {:.2} ({:.2}m away)",
+        hit.entity,
+        names.get(hit.entity).map(|n| n.as_str()).unwrap_or(""),
+        hit.point,
+        hit.distance
+    );
+    // Foliage swapped to a wind material and meshes shown with a debug view keep their original
+    // material around
+    if let Some(mat) = has_std_mat
+        .get(hit.entity)
+        .ok()
+        .or_else(|| swaying.get(hit.entity).ok().map(|s| &s.original))
+        .or_else(|| debug_originals.get(hit.entity).ok().map(|d| &d.0))
+        .and_then(|h| materials.get(h))
+    {
+        println!("  Material:");
+        println!("    alpha_mode: {:?}", mat.alpha_mode);
+        println!("    base_color: {:?}", mat.base_color);
+        println!(
+            "    perceptual_roughness: {} metallic: {} reflectance: {}",
+            mat.perceptual_roughness, mat.metallic, mat.reflectance
+        );
+        println!(
+            "    diffuse_transmission: {} specular_transmission: {} thickness: {}",
+            mat.diffuse_transmission, mat.specular_transmission, mat.thickness
+        );
+        println!(
+            "    double_sided: {} cull_mode: {:?} flip_normal_map_y: {}",
+            mat.double_sided, mat.cull_mode, mat.flip_normal_map_y
+        );
+        for (name, texture) in [
+            ("base_color_texture", &mat.base_color_texture),
+            ("emissive_texture", &mat.emissive_texture),
+            (
+                "metallic_roughness_texture",
+                &mat.metallic_roughness_texture,
+            ),
+            ("normal_map_texture", &mat.normal_map_texture),
+            ("occlusion_texture", &mat.occlusion_texture),
+        ] {
+            if let Some(texture) = texture {
+                println!("    {name}: {:?}", texture.path());
+            }
+        }
+    }
+    if let Some(mesh) = candidates
+        .get(hit.entity)
+        .ok()
+        .and_then(|(_, h, _, _)| meshes.get(h))
+    {
+        let known = [
+            Mesh::ATTRIBUTE_POSITION,
+            Mesh::ATTRIBUTE_NORMAL,
+            Mesh::ATTRIBUTE_UV_0,
+            Mesh::ATTRIBUTE_UV_1,
+            Mesh::ATTRIBUTE_TANGENT,
+            Mesh::ATTRIBUTE_COLOR,
+            Mesh::ATTRIBUTE_JOINT_WEIGHT,
+            Mesh::ATTRIBUTE_JOINT_INDEX,
+        ];
+        let names = known
+            .iter()
+            .filter(|a| mesh.contains_attribute(a.id))
+            .map(|a| a.name)
+            .collect::<Vec<_>>();
+        let vertex_count = mesh.count_vertices();
+        let index_count = mesh.indices().map_or(vertex_count, |i| i.len());
+        println!("  Mesh:");
+        println!(
+            "    attributes: {} ({} total)",
+            names.join(", "),
+            mesh.attributes().count()
+        );
+        println!(
+            "    {:?}, {} vertices, {} triangles",
+            mesh.primitive_topology(),
+            vertex_count,
+            index_count / 3
+        );
+    }
+}
+
+/// Press F to frame the mesh under the cursor: the main camera keeps its view direction and
+/// backs up until the mesh's bounding sphere fits the fov, then orbits around its center.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn frame_picked(
+    mut commands: Commands,
+    input: Res<ButtonInput<KeyCode>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    mut main_camera: Query<
+        (
+            Entity,
+            &mut Transform,
+            &Projection,
+            Option<&mut CameraController>,
+        ),
+        With<MainCamera>,
+    >,
+    candidates: Query<(Entity, &Handle<Mesh>, &GlobalTransform, &Aabb)>,
+    names: Query<&Name>,
+    meshes: Res<Assets<Mesh>>,
+) {
+    if !input.just_pressed(KeyCode::KeyF) {
+        return;
+    }
+    let Some(ray) = ray_under_cursor(&windows, &cameras) else {
+        return;
+    };
+    let Some(hit) = raycast_meshes(ray, &meshes, candidates.iter()) else {
+        println!("Nothing under the cursor to frame");
+        return;
+    };
+    let Ok((_, _, transform, aabb)) = candidates.get(hit.entity) else {
+        return;
+    };
+    let world_from_local = transform.affine();
+    let (center, half) = (Vec3::from(aabb.center), Vec3::from(aabb.half_extents));
+    let mut min = Vec3::MAX;
+    let mut max = Vec3::MIN;
+    for corner in [
+        Vec3::new(-1.0, -1.0, -1.0),
+        Vec3::new(-1.0, -1.0, 1.0),
+        Vec3::new(-1.0, 1.0, -1.0),
+        Vec3::new(-1.0, 1.0, 1.0),
+        Vec3::new(1.0, -1.0, -1.0),
+        Vec3::new(1.0, -1.0, 1.0),
+        Vec3::new(1.0, 1.0, -1.0),
+        Vec3::new(1.0, 1.0, 1.0),
+    ] {
+        let p = world_from_local.transform_point3(center + half * corner);
+        min = min.min(p);
+        max = max.max(p);
+    }
+    let center = (min + max) * 0.5;
+    // Flat meshes still get some distance
+    let radius = (min.distance(max) * 0.5).max(0.1);
+
+    for (entity, mut transform, projection, controller) in &mut main_camera {
+        let fov = match projection {
+            Projection::Perspective(perspective) => perspective.fov,
+            Projection::Orthographic(_) => std::f32::consts::FRAC_PI_3,
+        };
+        let distance = radius / (fov * 0.5).sin();
+        commands.entity(entity).remove::<CameraTransition>();
+        transform.translation = center - *transform.forward() * distance;
+        match controller {
+            Some(mut controller) => controller.look_at(&mut transform, center),
+            None => transform.look_at(center, Vec3::Y),
+        }
+    }
+    println!(
+        "Framed {:?} {:?}, center {:.2}, radius {:.2}m",
+        hit.entity,
+        names.get(hit.entity).map(|n| n.as_str()).unwrap_or(""),
+        center,
+        radius
+    );
+}
@@ -0,0 +1,171 @@
+use std::sync::{Arc, Mutex};
+
+use bevy::{
+    prelude::*,
+    render::{
+        renderer::{RenderAdapter, RenderInstance},
+        view::{create_surfaces, ExtractedWindows},
+        Render, RenderApp, RenderSet,
+    },
+    utils::HashMap,
+    window::PresentMode,
+};
+use wgpu::SurfaceTargetUnsafe;
+
+/// Present modes the render world replaced, by window, for the main world to apply.
+#[derive(Resource, Clone, Default)]
+struct PresentModeFallbacks(Arc<Mutex<Vec<(Entity, PresentMode)>>>);
+
+/// `PresentMode::Immediate`, `Mailbox` and `FifoRelaxed` panic when the window's surface doesn't
+/// support them. This replaces them with the closest supported mode before the surface is
+/// created, with a warning, and sets the replacement on the `Window` too.
+pub struct PresentModeFallbackPlugin;
+
+impl Plugin for PresentModeFallbackPlugin {
+    fn build(&self, app: &mut App) {
+        let fallbacks = PresentModeFallbacks::default();
+        app.insert_resource(fallbacks.clone())
+            .add_systems(First, apply_present_mode_fallbacks);
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app.insert_resource(fallbacks).add_systems(
+            Render,
+            check_present_modes
+                .in_set(RenderSet::ManageViews)
+                .before(create_surfaces),
+        );
+    }
+}
+
+fn to_wgpu(present_mode: PresentMode) -> Option<wgpu::PresentMode> {
+    match present_mode {
+        PresentMode::Immediate => Some(wgpu::PresentMode::Immediate),
+        PresentMode::Mailbox => Some(wgpu::PresentMode::Mailbox),
+        PresentMode::FifoRelaxed => Some(wgpu::PresentMode::FifoRelaxed),
+        // Fifo is always supported, the Auto modes fall back on their own
+        PresentMode::Fifo | PresentMode::AutoVsync | PresentMode::AutoNoVsync => None,
+    }
+}
+
+/// Without vsync first, Fifo is supported everywhere.
+fn fallback(present_mode: PresentMode, supported: &[wgpu::PresentMode]) -> PresentMode {
+    let candidates: &[PresentMode] = match present_mode {
+        PresentMode::Immediate => &[PresentMode::Mailbox],
+        PresentMode::Mailbox => &[PresentMode::Immediate],
+        _ => &[],
+    };
+    candidates
+        .iter()
+        .copied()
+        .find(|mode| to_wgpu(*mode).is_some_and(|mode| supported.contains(&mode)))
+        .unwrap_or(PresentMode::Fifo)
+}
+
+/// Reads the supported modes from a throwaway surface, once per window and mode, since the one
+/// bevy creates for the window isn't accessible. Runs where `create_surfaces` runs for the same
+/// reason: some platforms only allow creating surfaces on the main thread.
+fn check_present_modes(
+    #[cfg(any(target_os = "macos", target_os = "ios"))] _marker: Option<
+        NonSend<bevy::core::NonSendMarker>,
+    >,
+    mut windows: ResMut<ExtractedWindows>,
+    instance: Res<RenderInstance>,
+    adapter: Res<RenderAdapter>,
+    fallbacks: Res<PresentModeFallbacks>,
+    mut replacements: Local<HashMap<(Entity, PresentMode), Option<PresentMode>>>,
+) {
+    for window in windows.windows.values_mut() {
+        let Some(wanted) = to_wgpu(window.present_mode) else {
+            continue;
+        };
+        let replacement = *replacements
+            .entry((window.entity, window.present_mode))
+            .or_insert_with(|| {
+                let target = SurfaceTargetUnsafe::RawHandle {
+                    raw_display_handle: window.handle.display_handle,
+                    raw_window_handle: window.handle.window_handle,
+                };
+                // SAFETY: The same handles bevy creates the window's surface from
+                let supported = match unsafe { instance.create_surface_unsafe(target) } {
+                    Ok(surface) => surface.get_capabilities(&adapter).present_modes,
+                    // Left for bevy to report
+                    Err(_) => return None,
+                };
+                if supported.contains(&wanted) {
+                    return None;
+                }
+                let replacement = fallback(window.present_mode, &supported);
+                warn!(
+                    "Present mode {:?} isn't supported here, using {:?}",
+                    window.present_mode, replacement
+                );
+                fallbacks
+                    .0
+                    .lock()
+                    .unwrap()
+                    .push((window.entity, replacement));
+                Some(replacement)
+            });
+        if let Some(replacement) = replacement {
+            window.present_mode = replacement;
+            window.present_mode_changed = true;
+        }
+    }
+}
+
+/// Until the `Window` has the replacement, the render world replaces it every frame.
+fn apply_present_mode_fallbacks(
+    fallbacks: Res<PresentModeFallbacks>,
+    mut windows: Query<&mut Window>,
+) {
+    for (entity, present_mode) in fallbacks.0.lock().unwrap().drain(..) {
+        if let Ok(mut window) = windows.get_mut(entity) {
+            window.present_mode = present_mode;
+        }
+    }
+}
+
+/// Common display refresh rates, for `likely_vsync_limited`.
+const REFRESH_RATES: [f32; 7] = [30.0, 60.0, 75.0, 120.0, 144.0, 165.0, 240.0];
+/// How close to a refresh interval the average frame time has to be, relative.
+const VSYNC_TOLERANCE: f32 = 0.02;
+
+/// Whether the present mode waits for the display to refresh.
+pub fn vsync_on(present_mode: PresentMode) -> bool {
+    matches!(
+        present_mode,
+        PresentMode::Fifo | PresentMode::FifoRelaxed | PresentMode::AutoVsync
+    )
+}
+
+/// A heuristic: with vsync on, the refresh rate whose interval the average frame time is within
+/// `VSYNC_TOLERANCE` of. Frames that fit the interval wait for the display, so the real cost is
+/// lower than the average says.
+pub fn likely_vsync_limited(present_mode: PresentMode, avg_frame_ms: f32) -> Option<f32> {
+    if !vsync_on(present_mode) {
+        return None;
+    }
+    REFRESH_RATES.into_iter().find(|hz| {
+        let interval_ms = 1000.0 / hz;
+        (avg_frame_ms - interval_ms).abs() <= interval_ms * VSYNC_TOLERANCE
+    })
+}
+
+/// The present mode line of the benchmark report, with the vsync note if it applies.
+pub fn present_mode_report(present_mode: PresentMode, avg_frame_ms: f32) -> String {
+    let mut report = format!(
+        "Present mode: {present_mode:?} (vsync {})",
+        if vsync_on(present_mode) { "on" } else { "off" }
+    );
+    if let Some(hz) = likely_vsync_limited(present_mode, avg_frame_ms) {
+        report.push_str(&format!(
+            "\nLikely vsync limited (heuristic): the average frame time is within {}% of the \
+            {hz}Hz refresh interval ({:.2}ms), use --present-mode immediate to measure the real \
+            cost",
+            VSYNC_TOLERANCE * 100.0,
+            1000.0 / hz
+        ));
+    }
+    report
+}
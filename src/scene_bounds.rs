@@ -0,0 +1,183 @@
+use bevy::{pbr::CascadeShadowConfig, prelude::*, render::primitives::Aabb, scene::SceneInstance};
+
+use crate::{
+    all_children, camera_controller::CameraController, shadows::ShadowSettings, Args,
+    CameraBookmarks, GrifLight, MainCamera,
+};
+
+/// Meshes under entities with this component are included in `SceneBounds`.
+#[derive(Component)]
+pub struct SceneBoundsRoot;
+
+/// Combined world space bounds of the meshes under every `SceneBoundsRoot`.
+#[derive(Resource, Default, Debug)]
+pub struct SceneBounds {
+    pub min: Vec3,
+    pub max: Vec3,
+    pub mesh_count: usize,
+    /// Set once the scenes are spawned, every mesh has bounds and nothing changed for
+    /// `STABLE_FRAMES` frames. The bounds are not recomputed after that.
+    pub stable: bool,
+    unchanged_frames: u32,
+}
+
+impl SceneBounds {
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn diagonal(&self) -> f32 {
+        self.min.distance(self.max)
+    }
+}
+
+const STABLE_FRAMES: u32 = 30;
+
+/// Meshes and their textures stream in over many frames, so this keeps recomputing until the
+/// result stops changing.
+pub fn compute_scene_bounds(
+    mut bounds: ResMut<SceneBounds>,
+    roots: Query<(Entity, Option<&SceneInstance>), With<SceneBoundsRoot>>,
+    scene_spawner: Res<SceneSpawner>,
+    children_query: Query<&Children>,
+    meshes: Query<(Option<&Aabb>, &GlobalTransform), With<Handle<Mesh>>>,
+) {
+    if bounds.stable || roots.is_empty() {
+        return;
+    }
+    let mut ready = roots.iter().all(|(_, instance)| {
+        instance.is_some_and(|instance| scene_spawner.instance_is_ready(**instance))
+    });
+    let mut min = Vec3::MAX;
+    let mut max = Vec3::MIN;
+    let mut mesh_count = 0;
+    for (root, _) in &roots {
+        let Ok(children) = children_query.get(root) else {
+            continue;
+        };
+        all_children(children, &children_query, &mut |entity| {
+            let Ok((aabb, transform)) = meshes.get(entity) else {
+                return;
+            };
+            // The Aabb is only added once the mesh asset has loaded
+            let Some(aabb) = aabb else {
+                ready = false;
+                return;
+            };
+            let world_from_local = transform.affine();
+            let (center, half) = (Vec3::from(aabb.center), Vec3::from(aabb.half_extents));
+            for corner in [
+                Vec3::new(-1.0, -1.0, -1.0),
+                Vec3::new(-1.0, -1.0, 1.0),
+                Vec3::new(-1.0, 1.0, -1.0),
+                Vec3::new(-1.0, 1.0, 1.0),
+                Vec3::new(1.0, -1.0, -1.0),
+                Vec3::new(1.0, -1.0, 1.0),
+                Vec3::new(1.0, 1.0, -1.0),
+                Vec3::new(1.0, 1.0, 1.0),
+            ] {
+                let p = world_from_local.transform_point3(center + half * corner);
+                min = min.min(p);
+                max = max.max(p);
+            }
+            mesh_count += 1;
+        });
+    }
+    if mesh_count == 0 {
+        return;
+    }
+
+    let unchanged = ready
+        && mesh_count == bounds.mesh_count
+        && min.abs_diff_eq(bounds.min, 1e-4)
+        && max.abs_diff_eq(bounds.max, 1e-4);
+    bounds.unchanged_frames = if unchanged {
+        bounds.unchanged_frames + 1
+    } else {
+        0
+    };
+    bounds.min = min;
+    bounds.max = max;
+    bounds.mesh_count = mesh_count;
+    if bounds.unchanged_frames >= STABLE_FRAMES {
+        bounds.stable = true;
+        println!(
+            "Scene bounds {:.2} to {:.2}, {} meshes, diagonal {:.2}m",
+            bounds.min,
+            bounds.max,
+            bounds.mesh_count,
+            bounds.diagonal()
+        );
+    }
+}
+
+/// Yaw of the bookmark views of a `--scene`, a third of a turn apart, and their pitch.
+const BOOKMARK_YAWS: [f32; 3] = [0.0, 2.0944, 4.1888];
+const BOOKMARK_PITCH: f32 = -0.35;
+
+/// With `--fit-camera` or `--scene`, frames the scene bounds once they are stable and sizes the
+/// sun's shadow cascades to the scene diagonal. With `--scene` the bookmarks and benchmark views
+/// also become three views around the bounds.
+#[allow(clippy::type_complexity)]
+pub fn fit_camera_to_bounds(
+    bounds: Res<SceneBounds>,
+    args: Res<Args>,
+    mut bookmarks: ResMut<CameraBookmarks>,
+    mut done: Local<bool>,
+    mut cameras: Query<
+        (
+            &mut Transform,
+            &mut Projection,
+            Option<&mut CameraController>,
+        ),
+        With<MainCamera>,
+    >,
+    mut lights: Query<&mut CascadeShadowConfig, With<GrifLight>>,
+    shadow_settings: Res<ShadowSettings>,
+) {
+    let custom_scene = !args.scene.is_empty();
+    if !(args.fit_camera || custom_scene) || *done || !bounds.stable {
+        return;
+    }
+    *done = true;
+
+    let center = bounds.center();
+    let radius = (bounds.diagonal() * 0.5).max(0.1);
+    for (mut transform, mut projection, controller) in &mut cameras {
+        let fov = match projection.as_ref() {
+            Projection::Perspective(perspective) => perspective.fov,
+            Projection::Orthographic(_) => std::f32::consts::FRAC_PI_3,
+        };
+        // Keep the current view direction, back up until the bounding sphere fits the fov
+        let distance = radius / (fov * 0.5).sin();
+        let frame = |forward: Vec3| {
+            Transform::from_translation(center - forward * distance).looking_at(center, Vec3::Y)
+        };
+        *transform = frame(*transform.forward());
+        if custom_scene {
+            bookmarks.views = BOOKMARK_YAWS.map(|yaw| {
+                frame(Quat::from_euler(EulerRot::YXZ, yaw, BOOKMARK_PITCH, 0.0) * Vec3::NEG_Z)
+            });
+            bookmarks.ready = true;
+            *transform = bookmarks.views[0];
+        }
+        if let Projection::Perspective(perspective) = projection.as_mut() {
+            perspective.far = perspective.far.max(distance + radius);
+        }
+        if let Some(mut controller) = controller {
+            controller.initialized = false;
+        }
+    }
+
+    // An explicit --shadow-distance wins
+    let maximum_distance = shadow_settings
+        .distance
+        .unwrap_or(bounds.diagonal().max(1.0));
+    for mut config in &mut lights {
+        *config = shadow_settings.cascades(Some(maximum_distance));
+    }
+    println!(
+        "Fit camera to scene bounds, shadow maximum_distance {:.2}m",
+        maximum_distance
+    );
+}
@@ -0,0 +1,139 @@
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{prelude::*, render::view::screenshot::ScreenshotManager, window::PrimaryWindow};
+
+use crate::{help::KeyBindings, transform_const, MainCamera};
+
+const SCREENSHOT_DIR: &str = "screenshots";
+
+/// `YYYYMMDD_HHMMSS` in UTC.
+fn timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    let (days, day_secs) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    // Civil date from days since 1970-01-01, see Howard Hinnant's days_from_civil
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}{month:02}{day:02}_{:02}{:02}{:02}",
+        day_secs / 3600,
+        day_secs / 60 % 60,
+        day_secs % 60
+    )
+}
+
+/// A path that isn't on disk or queued yet, with a counter for presses within the same second.
+fn unused_path(stem: &str, queued: &VecDeque<(PathBuf, Transform)>) -> PathBuf {
+    (1..)
+        .map(|i| {
+            let name = if i == 1 {
+                format!("{stem}.png")
+            } else {
+                format!("{stem}_{i}.png")
+            };
+            Path::new(SCREENSHOT_DIR).join(name)
+        })
+        .find(|path| !path.exists() && queued.iter().all(|(queued, _)| queued != path))
+        .unwrap()
+}
+
+/// The camera of the shot, to get back to it with `--cam-pos`/`--cam-look` or as a bookmark.
+fn write_sidecar(path: &Path, transform: &Transform) -> std::io::Result<()> {
+    let Vec3 { x, y, z } = transform.translation;
+    let look = transform.translation + transform.forward() * 10.0;
+    let contents = format!(
+        "--cam-pos {x},{y},{z} --cam-look {},{},{}\n\n{}\n",
+        look.x,
+        look.y,
+        look.z,
+        transform_const(transform)
+    );
+    std::fs::write(path.with_extension("txt"), contents)
+}
+
+pub fn register_keys(mut bindings: ResMut<KeyBindings>) {
+    bindings.add("Tools", "F12", "Save a screenshot to screenshots/");
+}
+
+// F12: save a screenshot of the window to screenshots/bistro_<UTC time>.png, with the camera
+// transform next to it in a .txt. The renderer takes one screenshot per frame, more presses wait
+// for the next frames.
+pub fn screenshot_input(
+    input: Res<ButtonInput<KeyCode>>,
+    mut screenshots: ResMut<ScreenshotManager>,
+    window: Query<Entity, With<PrimaryWindow>>,
+    camera: Query<&Transform, With<MainCamera>>,
+    mut queued: Local<VecDeque<(PathBuf, Transform)>>,
+) {
+    if input.just_pressed(KeyCode::F12) {
+        if let Err(e) = std::fs::create_dir_all(SCREENSHOT_DIR) {
+            println!("Failed to create {SCREENSHOT_DIR}: {e}");
+            return;
+        }
+        let stem = format!("bistro_{}", timestamp(SystemTime::now()));
+        let path = unused_path(&stem, &queued);
+        let transform = camera.get_single().copied().unwrap_or_default();
+        queued.push_back((path, transform));
+    }
+    let Ok(window) = window.get_single() else {
+        return;
+    };
+    let Some((path, transform)) = queued.front() else {
+        return;
+    };
+    // Already requested this frame, try again in the next
+    if screenshots.save_screenshot_to_disk(window, path).is_err() {
+        return;
+    }
+    match write_sidecar(path, transform) {
+        Ok(()) => println!("Saving screenshot to {}", path.display()),
+        Err(e) => println!(
+            "Saving screenshot to {}, failed to write the camera transform: {e}",
+            path.display()
+        ),
+    }
+    queued.pop_front();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn at(secs: u64) -> String {
+        timestamp(UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    #[test]
+    fn timestamps_at_known_dates() {
+        assert_eq!(at(0), "19700101_000000");
+        assert_eq!(at(1_709_210_096), "20240229_123456");
+        assert_eq!(at(1_704_067_199), "20231231_235959");
+        assert_eq!(at(946_684_799), "19991231_235959");
+        assert_eq!(at(946_684_800), "20000101_000000");
+        // 2100 is not a leap year
+        assert_eq!(at(4_107_542_399), "21000228_235959");
+        assert_eq!(at(4_107_542_400), "21000301_000000");
+    }
+
+    #[test]
+    fn times_before_the_epoch_clamp_to_it() {
+        assert_eq!(
+            timestamp(UNIX_EPOCH - Duration::from_secs(60)),
+            "19700101_000000"
+        );
+    }
+}
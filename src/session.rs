@@ -0,0 +1,254 @@
+use std::{fs, path::Path};
+
+use bevy::{
+    core_pipeline::{
+        bloom::BloomSettings, contrast_adaptive_sharpening::ContrastAdaptiveSharpeningSettings,
+        experimental::taa::TemporalAntiAliasSettings,
+    },
+    pbr::ScreenSpaceAmbientOcclusionSettings,
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera_controller::CameraController, clamp_logged, effects, environment_map::EnvironmentMaps,
+    help::KeyBindings, parse_transmission_quality, Args, GrifLight, MainCamera, MAX_AMBIENT,
+    MAX_SHADOW_BIAS,
+};
+
+/// What `--session` saves on exit and with F5, and restores on startup. Every field is optional
+/// so session files from older versions still load, missing fields keep the startup value.
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct Session {
+    pub camera_translation: Option<[f32; 3]>,
+    pub camera_rotation: Option<[f32; 4]>,
+    pub camera_speed_multiplier: Option<f32>,
+    pub bloom: Option<bool>,
+    pub bloom_intensity: Option<f32>,
+    pub ssao: Option<bool>,
+    pub taa: Option<bool>,
+    pub sharpening: Option<f32>,
+    pub sun_shadows: Option<bool>,
+    pub shadow_depth_bias: Option<f32>,
+    pub shadow_normal_bias: Option<f32>,
+    pub ambient: Option<f32>,
+    pub environment_map: Option<String>,
+    pub transmission_steps: Option<usize>,
+    /// low, medium, high or ultra.
+    pub transmission_quality: Option<String>,
+}
+
+impl Session {
+    /// Returns `None` if the file doesn't exist or can't be read or parsed.
+    fn load(path: &Path) -> Option<Self> {
+        if !path.exists() {
+            println!(
+                "No session in {:?} yet, it is saved on exit or with F5",
+                path
+            );
+            return None;
+        }
+        let contents = fs::read_to_string(path)
+            .map_err(|e| println!("Ignoring session {:?}, failed to read it: {e}", path))
+            .ok()?;
+        ron::Options::default()
+            .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+            .from_str(&contents)
+            .map_err(|e| println!("Ignoring session {:?}, failed to parse it: {e}", path))
+            .ok()
+    }
+
+    fn save(&self, path: &Path) {
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()).unwrap();
+        match fs::write(path, contents) {
+            Ok(()) => println!("Saved session to {:?}", path),
+            Err(e) => println!("Failed to save session to {:?}: {e}", path),
+        }
+    }
+}
+
+/// Applies the `--session` file after `setup` spawned the camera and the sun.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn restore_session(
+    mut commands: Commands,
+    args: Res<Args>,
+    mut cameras: Query<
+        (
+            Entity,
+            &mut Transform,
+            &mut Camera3d,
+            Option<&mut CameraController>,
+            Option<&ContrastAdaptiveSharpeningSettings>,
+        ),
+        With<MainCamera>,
+    >,
+    mut environment_lights: Query<&mut EnvironmentMapLight>,
+    mut sun: Query<&mut DirectionalLight, With<GrifLight>>,
+    mut ambient: ResMut<AmbientLight>,
+    mut environment_maps: ResMut<EnvironmentMaps>,
+    asset_server: Res<AssetServer>,
+    msaa: Res<Msaa>,
+) {
+    let Some(path) = &args.session else {
+        return;
+    };
+    let Some(session) = Session::load(path) else {
+        return;
+    };
+
+    for (entity, mut transform, mut camera_3d, controller, cas) in &mut cameras {
+        if let (Some(translation), Some(rotation)) =
+            (session.camera_translation, session.camera_rotation)
+        {
+            *transform = Transform {
+                translation: Vec3::from_array(translation),
+                rotation: Quat::from_array(rotation).normalize(),
+                scale: Vec3::ONE,
+            };
+        }
+        if let Some(mut controller) = controller {
+            // Pick up the restored pitch/yaw
+            controller.initialized = false;
+            if let Some(multiplier) = session.camera_speed_multiplier {
+                controller.speed_multiplier = multiplier;
+            }
+        }
+        let mut camera = commands.entity(entity);
+        match session.bloom {
+            Some(true) => {
+                camera.insert(BloomSettings {
+                    intensity: session.bloom_intensity.unwrap_or(effects::BLOOM_INTENSITY),
+                    ..default()
+                });
+            }
+            Some(false) => {
+                camera.remove::<BloomSettings>();
+            }
+            None => (),
+        }
+        if let Some(on) = session.ssao {
+            if let Err(e) = effects::set_ssao(&mut camera, on, *msaa) {
+                println!("Not restoring SSAO: {e}");
+            }
+        }
+        if let Some(on) = session.taa {
+            if let Err(e) = effects::set_taa(&mut camera, on, *msaa) {
+                println!("Not restoring TAA: {e}");
+            }
+        }
+        if let Some(strength) = session.sharpening {
+            let strength = strength.clamp(0.0, 1.0);
+            camera.insert(ContrastAdaptiveSharpeningSettings {
+                enabled: strength > 0.0,
+                sharpening_strength: strength,
+                ..cas.cloned().unwrap_or_default()
+            });
+        }
+        if let Some(steps) = session.transmission_steps {
+            camera_3d.screen_space_specular_transmission_steps = steps;
+        }
+        if let Some(quality) = &session.transmission_quality {
+            match parse_transmission_quality(quality) {
+                Ok(quality) => camera_3d.screen_space_specular_transmission_quality = quality,
+                Err(e) => println!("Session: {e}"),
+            }
+        }
+    }
+
+    for mut light in &mut sun {
+        if let Some(shadows) = session.sun_shadows {
+            light.shadows_enabled = shadows;
+        }
+        if let Some(bias) = session.shadow_depth_bias {
+            light.shadow_depth_bias =
+                clamp_logged("Session shadow depth bias", bias, MAX_SHADOW_BIAS);
+        }
+        if let Some(bias) = session.shadow_normal_bias {
+            light.shadow_normal_bias =
+                clamp_logged("Session shadow normal bias", bias, MAX_SHADOW_BIAS);
+        }
+    }
+    if let Some(brightness) = session.ambient {
+        ambient.brightness = clamp_logged("Session ambient brightness", brightness, MAX_AMBIENT);
+    }
+    if let Some(name) = &session.environment_map {
+        match environment_maps
+            .available
+            .iter()
+            .position(|pair| &pair.name == name)
+        {
+            Some(index) => {
+                environment_maps.current = index;
+                for mut light in &mut environment_lights {
+                    *light = environment_maps.light(&asset_server);
+                }
+            }
+            None => println!("Session: environment map {name} not found, keeping the current one"),
+        }
+    }
+    println!("Restored session from {:?}", path);
+}
+
+pub fn register_keys(args: Res<Args>, mut bindings: ResMut<KeyBindings>) {
+    if args.session.is_some() {
+        bindings.add("Tools", "F5", "Save the session");
+    }
+}
+
+/// Saves the `--session` file on F5 and when the app exits.
+#[allow(clippy::type_complexity)]
+pub fn save_session(
+    input: Res<ButtonInput<KeyCode>>,
+    mut exit: EventReader<AppExit>,
+    args: Res<Args>,
+    cameras: Query<
+        (
+            &Transform,
+            &Camera3d,
+            Option<&CameraController>,
+            Option<&BloomSettings>,
+            Has<ScreenSpaceAmbientOcclusionSettings>,
+            Has<TemporalAntiAliasSettings>,
+            Option<&ContrastAdaptiveSharpeningSettings>,
+        ),
+        With<MainCamera>,
+    >,
+    sun: Query<&DirectionalLight, With<GrifLight>>,
+    ambient: Res<AmbientLight>,
+    environment_maps: Res<EnvironmentMaps>,
+) {
+    let Some(path) = &args.session else {
+        return;
+    };
+    if !input.just_pressed(KeyCode::F5) && exit.read().count() == 0 {
+        return;
+    }
+    let Some((transform, camera_3d, controller, bloom, ssao, taa, cas)) = cameras.iter().next()
+    else {
+        return;
+    };
+    let session = Session {
+        camera_translation: Some(transform.translation.to_array()),
+        camera_rotation: Some(transform.rotation.to_array()),
+        camera_speed_multiplier: controller.map(|controller| controller.speed_multiplier),
+        bloom: Some(bloom.is_some()),
+        bloom_intensity: bloom.map(|bloom| bloom.intensity),
+        ssao: Some(ssao),
+        taa: Some(taa),
+        sharpening: Some(
+            cas.filter(|cas| cas.enabled)
+                .map_or(0.0, |cas| cas.sharpening_strength),
+        ),
+        sun_shadows: sun.iter().next().map(|light| light.shadows_enabled),
+        shadow_depth_bias: sun.iter().next().map(|light| light.shadow_depth_bias),
+        shadow_normal_bias: sun.iter().next().map(|light| light.shadow_normal_bias),
+        ambient: Some(ambient.brightness),
+        environment_map: Some(environment_maps.current().name.clone()),
+        transmission_steps: Some(camera_3d.screen_space_specular_transmission_steps),
+        transmission_quality: Some(
+            format!("{:?}", camera_3d.screen_space_specular_transmission_quality).to_lowercase(),
+        ),
+    };
+    session.save(path);
+}
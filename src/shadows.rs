@@ -0,0 +1,155 @@
+use bevy::{
+    pbr::{CascadeShadowConfig, CascadeShadowConfigBuilder, DirectionalLightShadowMap},
+    prelude::*,
+};
+
+use crate::Args;
+
+pub const DEFAULT_SHADOW_MAP_SIZE: usize = 2048;
+pub const DEFAULT_CASCADES: usize = 4;
+pub const DEFAULT_SHADOW_DISTANCE: f32 = 100.0;
+pub const DEFAULT_FIRST_CASCADE_BOUND: f32 = 5.0;
+
+const SHADOW_MAP_SIZES: (usize, usize) = (256, 8192);
+/// Bevy's `MAX_CASCADES_PER_LIGHT`.
+const MAX_CASCADES: usize = 4;
+const SHADOW_DISTANCES: (f32, f32) = (1.0, 10_000.0);
+const MINIMUM_DISTANCE: f32 = 0.1;
+/// Bevy asserts the first bound is past `MINIMUM_DISTANCE` with more than one cascade.
+const MIN_FIRST_CASCADE_BOUND: f32 = MINIMUM_DISTANCE * 2.0;
+// Every shadow distance leaves room for the first cascade
+const _: () = assert!(SHADOW_DISTANCES.0 > MIN_FIRST_CASCADE_BOUND);
+
+/// The sun shadow map and cascades from `--shadow-map-size`, `--cascades`, `--shadow-distance`
+/// and `--first-cascade-bound`, clamped to what works.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct ShadowSettings {
+    pub map_size: usize,
+    pub cascades: usize,
+    /// `None` without `--shadow-distance`, `--fit-camera` sizes it to the scene then.
+    pub distance: Option<f32>,
+    pub first_cascade_bound: Option<f32>,
+}
+
+/// Like `clamp_logged` with a lower bound.
+fn clamp_range<T: PartialOrd + Copy + std::fmt::Display>(
+    name: &str,
+    value: T,
+    (min, max): (T, T),
+) -> T {
+    // NaN
+    let clamped = if value.partial_cmp(&value).is_none() || value < min {
+        min
+    } else if value > max {
+        max
+    } else {
+        value
+    };
+    if clamped != value {
+        println!("{name} {value} is out of range, clamped to {clamped}");
+    }
+    clamped
+}
+
+impl ShadowSettings {
+    pub fn from_args(args: &Args) -> Self {
+        let distance = args
+            .shadow_distance
+            .map(|distance| clamp_range("Shadow distance", distance, SHADOW_DISTANCES));
+        let first_cascade_bound = args.first_cascade_bound.map(|bound| {
+            let max = distance.unwrap_or(DEFAULT_SHADOW_DISTANCE);
+            clamp_range("First cascade bound", bound, (MIN_FIRST_CASCADE_BOUND, max))
+        });
+        Self {
+            map_size: clamp_range("Shadow map size", args.shadow_map_size, SHADOW_MAP_SIZES),
+            cascades: clamp_range("Cascades", args.cascades, (1, MAX_CASCADES)),
+            distance,
+            first_cascade_bound,
+        }
+    }
+
+    pub fn shadow_map(&self) -> DirectionalLightShadowMap {
+        DirectionalLightShadowMap {
+            size: self.map_size,
+        }
+    }
+
+    /// The cascades out to `--shadow-distance`, or `fitted_distance` without it.
+    pub fn cascades(&self, fitted_distance: Option<f32>) -> CascadeShadowConfig {
+        let maximum_distance = self
+            .distance
+            .or(fitted_distance)
+            .unwrap_or(DEFAULT_SHADOW_DISTANCE);
+        // Short distances would put the default bound inside the minimum distance
+        let first_cascade_far_bound = self
+            .first_cascade_bound
+            .unwrap_or((maximum_distance * 0.05).min(DEFAULT_FIRST_CASCADE_BOUND))
+            .min(maximum_distance)
+            .max(MIN_FIRST_CASCADE_BOUND);
+        CascadeShadowConfigBuilder {
+            num_cascades: self.cascades,
+            minimum_distance: MINIMUM_DISTANCE,
+            maximum_distance,
+            first_cascade_far_bound,
+            overlap_proportion: 0.2,
+        }
+        .into()
+    }
+}
+
+/// The shadow setup for the benchmark report, from what's in use.
+pub fn shadow_description(
+    shadow_map: &DirectionalLightShadowMap,
+    light: &DirectionalLight,
+    cascades: &CascadeShadowConfig,
+) -> String {
+    if !light.shadows_enabled {
+        return "Sun shadows: off".to_string();
+    }
+    format!(
+        "Sun shadows: {}px shadow map, {} cascades to {:.1}m, first to {:.1}m",
+        shadow_map.size,
+        cascades.bounds.len(),
+        cascades.bounds.last().copied().unwrap_or(0.0),
+        cascades.bounds.first().copied().unwrap_or(0.0),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use argh::FromArgs;
+
+    use super::*;
+
+    fn settings(args: &[&str]) -> ShadowSettings {
+        ShadowSettings::from_args(&Args::from_args(&["bistro"], args).unwrap())
+    }
+
+    #[test]
+    fn short_distances_keep_the_first_bound_past_the_minimum() {
+        for args in [
+            &["--shadow-distance", "1"][..],
+            &["--shadow-distance", "0.01"],
+            &["--first-cascade-bound", "0.1"],
+            &["--first-cascade-bound", "0"],
+            &["--shadow-distance", "1", "--first-cascade-bound", "0.1"],
+        ] {
+            let config = settings(args).cascades(None);
+            assert_eq!(config.bounds.len(), DEFAULT_CASCADES, "{args:?}");
+            assert!(config.bounds[0] > MINIMUM_DISTANCE, "{args:?}");
+        }
+    }
+
+    #[test]
+    fn bounds_in_range_are_kept() {
+        let config =
+            settings(&["--shadow-distance", "50", "--first-cascade-bound", "2"]).cascades(None);
+        assert_eq!(config.bounds[0], 2.0);
+        // The builder spaces the cascades with powf, which lands just off the distance
+        let last = *config.bounds.last().unwrap();
+        assert!((last - 50.0).abs() < 1e-3, "{last}");
+        // A single cascade has no first bound to keep apart from the minimum
+        let config = settings(&["--cascades", "1", "--shadow-distance", "1"]).cascades(None);
+        assert_eq!(config.bounds, [1.0]);
+    }
+}
@@ -0,0 +1,78 @@
+use bevy::{
+    prelude::*,
+    render::camera::Viewport,
+    window::{PrimaryWindow, WindowResized},
+};
+
+use crate::MainCamera;
+
+/// With `--split-screen`, renders the right half of the window without post processing (bloom,
+/// anti-aliasing, SSAO, sharpening). Its transform and projection follow the `MainCamera`.
+#[derive(Component)]
+pub struct ComparisonCamera;
+
+/// Splits the primary window between the main camera (left) and the comparison camera (right).
+pub fn update_split_viewports(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut resized: EventReader<WindowResized>,
+    mut main_cameras: Query<&mut Camera, (With<MainCamera>, Without<ComparisonCamera>)>,
+    mut comparison_cameras: Query<&mut Camera, With<ComparisonCamera>>,
+    mut initialized: Local<bool>,
+) {
+    if resized.read().count() == 0 && *initialized {
+        return;
+    }
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let size = window.physical_size();
+    if size.x < 2 || size.y == 0 {
+        return;
+    }
+    *initialized = true;
+    let left = size.x / 2;
+    for mut camera in &mut main_cameras {
+        camera.viewport = Some(Viewport {
+            physical_position: UVec2::ZERO,
+            physical_size: UVec2::new(left, size.y),
+            ..default()
+        });
+    }
+    for mut camera in &mut comparison_cameras {
+        camera.viewport = Some(Viewport {
+            physical_position: UVec2::new(left, 0),
+            physical_size: UVec2::new(size.x - left, size.y),
+            ..default()
+        });
+    }
+}
+
+/// Copies the main camera's transform, fov and clip planes. The aspect ratio is left alone, it
+/// comes from each camera's own viewport.
+#[allow(clippy::type_complexity)]
+pub fn sync_comparison_camera(
+    main_cameras: Query<(&Transform, &Projection), (With<MainCamera>, Without<ComparisonCamera>)>,
+    mut comparison_cameras: Query<(&mut Transform, &mut Projection), With<ComparisonCamera>>,
+) {
+    let Ok((main_transform, main_projection)) = main_cameras.get_single() else {
+        return;
+    };
+    for (mut transform, mut projection) in &mut comparison_cameras {
+        if *transform != *main_transform {
+            *transform = *main_transform;
+        }
+        if let (Projection::Perspective(main), Projection::Perspective(perspective)) =
+            (main_projection, projection.as_ref())
+        {
+            if (main.fov, main.near, main.far)
+                != (perspective.fov, perspective.near, perspective.far)
+            {
+                if let Projection::Perspective(perspective) = projection.as_mut() {
+                    perspective.fov = main.fov;
+                    perspective.near = main.near;
+                    perspective.far = main.far;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use bevy::prelude::*;
+
+use crate::{help::KeyBindings, Args, GrifLight, MAX_CLEAR_COLOR};
+
+/// The angles of the original hardcoded sun rotation.
+pub const DEFAULT_SUN_AZIMUTH: f32 = 136.37;
+pub const DEFAULT_SUN_ELEVATION: f32 = 54.86;
+pub const MAX_SUN_ILLUMINANCE: f32 = 1_000_000.0;
+pub const SUN_COLOR: Color = Color::srgb(1.0, 0.87, 0.78);
+
+const SUN_STEP: f32 = 5.0;
+const SUN_STEP_FINE: f32 = 1.0;
+
+/// Below this elevation the clear color and ambient light fade towards `LOW_SUN_TINT`.
+const LOW_SUN_ELEVATION: f32 = 20.0;
+const LOW_SUN_TINT: Vec3 = Vec3::new(0.45, 0.3, 0.22);
+
+/// Where the sun is, in degrees. The azimuth goes around the vertical axis like the camera yaw
+/// (0 is towards -Z, 90 towards -X), the elevation is above the horizon.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct SunAngles {
+    pub azimuth: f32,
+    pub elevation: f32,
+}
+
+impl SunAngles {
+    pub fn new(azimuth: f32, elevation: f32) -> Self {
+        Self {
+            azimuth: azimuth.rem_euclid(360.0),
+            elevation: elevation.clamp(0.0, 90.0),
+        }
+    }
+
+    /// The light shines away from the sun.
+    pub fn rotation(&self) -> Quat {
+        Quat::from_euler(
+            EulerRot::YXZ,
+            (self.azimuth + 180.0).to_radians(),
+            -self.elevation.to_radians(),
+            0.0,
+        )
+    }
+
+    /// Multiplies the clear color and ambient light, white unless the sun is low.
+    pub fn sky_tint(&self) -> Vec3 {
+        let t = (self.elevation / LOW_SUN_ELEVATION).clamp(0.0, 1.0);
+        LOW_SUN_TINT.lerp(Vec3::ONE, t * t * (3.0 - 2.0 * t))
+    }
+}
+
+/// The `--clear-color` multiplied by `tint`, usually `SunAngles::sky_tint`.
+pub fn clear_color(args: &Args, tint: Vec3) -> Color {
+    let [r, g, b] = args.clear_color.map(|c| c.clamp(0.0, MAX_CLEAR_COLOR));
+    Color::srgb(r * tint.x, g * tint.y, b * tint.z)
+}
+
+pub fn ambient_color(tint: Vec3) -> Color {
+    Color::srgb(tint.x, tint.y, tint.z)
+}
+
+pub fn register_keys(mut bindings: ResMut<KeyBindings>) {
+    bindings
+        .add(
+            "Lighting",
+            "L+Left/Right",
+            "Move the sun around, Shift for 1 degree steps",
+        )
+        .add("Lighting", "L+Up/Down", "Raise or lower the sun")
+        .add(
+            "Lighting",
+            "L+PageUp/PageDown",
+            "Make the sun brighter or darker",
+        );
+}
+
+// Hold L: left/right arrows move the sun around, up/down raise/lower it, PageUp/PageDown make it
+// brighter/darker. Add Shift for smaller steps.
+pub fn sun_input(
+    input: Res<ButtonInput<KeyCode>>,
+    args: Res<Args>,
+    mut angles: ResMut<SunAngles>,
+    mut sun: Query<(&mut Transform, &mut DirectionalLight), With<GrifLight>>,
+    mut clear_color: ResMut<ClearColor>,
+    mut ambient: ResMut<AmbientLight>,
+) {
+    // Alt+arrows nudge the interior
+    if !input.pressed(KeyCode::KeyL) || input.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]) {
+        return;
+    }
+    let step = if input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        SUN_STEP_FINE
+    } else {
+        SUN_STEP
+    };
+    let mut azimuth = 0.0;
+    let mut elevation = 0.0;
+    for (key, a, e) in [
+        (KeyCode::ArrowLeft, step, 0.0),
+        (KeyCode::ArrowRight, -step, 0.0),
+        (KeyCode::ArrowUp, 0.0, step),
+        (KeyCode::ArrowDown, 0.0, -step),
+    ] {
+        if input.just_pressed(key) {
+            azimuth += a;
+            elevation += e;
+        }
+    }
+    let brightness = if input.just_pressed(KeyCode::PageUp) {
+        1.0 + step / 20.0
+    } else if input.just_pressed(KeyCode::PageDown) {
+        1.0 / (1.0 + step / 20.0)
+    } else {
+        1.0
+    };
+    if azimuth == 0.0 && elevation == 0.0 && brightness == 1.0 {
+        return;
+    }
+    *angles = SunAngles::new(angles.azimuth + azimuth, angles.elevation + elevation);
+    clear_color.0 = self::clear_color(&args, angles.sky_tint());
+    ambient.color = ambient_color(angles.sky_tint());
+    for (mut transform, mut light) in &mut sun {
+        transform.rotation = angles.rotation();
+        light.illuminance = (light.illuminance * brightness).min(MAX_SUN_ILLUMINANCE);
+        println!(
+            "Sun: --sun-azimuth {:.1} --sun-elevation {:.1} --sun-illuminance {:.0}",
+            angles.azimuth, angles.elevation, light.illuminance
+        );
+    }
+}
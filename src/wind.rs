@@ -0,0 +1,129 @@
+use bevy::{
+    asset::load_internal_asset,
+    pbr::{ExtendedMaterial, MaterialExtension},
+    prelude::*,
+    render::render_resource::{AsBindGroup, ShaderRef},
+};
+
+use crate::{convert::glob_match, extended_materials::ExtendedMaterials, Args};
+
+const WIND_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(0x3f0c_52d1_9a7e_4b6c_8e21_d4f7_a605_b913);
+
+pub type WindMaterial = ExtendedMaterial<StandardMaterial, WindExtension>;
+
+/// Displaces the vertices of a `StandardMaterial` over time, see `wind.wgsl`.
+#[derive(Asset, AsBindGroup, Reflect, Debug, Clone)]
+pub struct WindExtension {
+    #[uniform(100)]
+    pub strength: f32,
+}
+
+impl MaterialExtension for WindExtension {
+    fn vertex_shader() -> ShaderRef {
+        WIND_SHADER_HANDLE.into()
+    }
+
+    fn prepass_vertex_shader() -> ShaderRef {
+        WIND_SHADER_HANDLE.into()
+    }
+}
+
+/// Meshes with an alpha masked material, added by `proc_scene`.
+#[derive(Component)]
+pub struct Foliage;
+
+/// Foliage whose material was replaced by a `WindMaterial`. Keeps the original material alive
+/// so changes to it (e.g. from mipmap generation) can be copied over.
+#[derive(Component)]
+pub struct Swaying {
+    pub original: Handle<StandardMaterial>,
+}
+
+/// With `--wind`, sways the `Foliage` selected by `--wind-filter`.
+pub struct WindPlugin;
+
+impl Plugin for WindPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(app, WIND_SHADER_HANDLE, "wind.wgsl", Shader::from_wgsl);
+        app.add_plugins(MaterialPlugin::<WindMaterial>::default())
+            .init_resource::<ExtendedMaterials<WindExtension>>()
+            .add_systems(Update, (apply_wind, sync_wind_materials));
+    }
+}
+
+/// Without filters every piece of foliage sways, otherwise the mesh name or the file name of
+/// the base color texture has to match one of them.
+fn is_selected(filters: &[String], name: Option<&Name>, material: &StandardMaterial) -> bool {
+    if filters.is_empty() {
+        return true;
+    }
+    let texture = material
+        .base_color_texture
+        .as_ref()
+        .and_then(|texture| texture.path())
+        .and_then(|path| path.path().file_name())
+        .map(|name| name.to_string_lossy().to_string());
+    filters.iter().any(|glob| {
+        name.is_some_and(|name| glob_match(glob, name.as_str()))
+            || texture
+                .as_ref()
+                .is_some_and(|texture| glob_match(glob, texture))
+    })
+}
+
+#[allow(clippy::type_complexity)]
+fn apply_wind(
+    mut commands: Commands,
+    foliage: Query<(Entity, &Handle<StandardMaterial>, Option<&Name>), Added<Foliage>>,
+    standard_materials: Res<Assets<StandardMaterial>>,
+    mut wind_materials: ResMut<Assets<WindMaterial>>,
+    mut swapped: ResMut<ExtendedMaterials<WindExtension>>,
+    args: Res<Args>,
+) {
+    let strength = args.wind.unwrap_or(0.0);
+    let mut count = 0;
+    for (entity, material_h, name) in &foliage {
+        let Some(material) = standard_materials.get(material_h) else {
+            continue;
+        };
+        if !is_selected(&args.wind_filter, name, material) {
+            continue;
+        }
+        let wind_h = swapped.get_or_add(material_h.id(), material, &mut wind_materials, || {
+            WindExtension { strength }
+        });
+        commands
+            .entity(entity)
+            .remove::<Handle<StandardMaterial>>()
+            .insert((
+                wind_h,
+                Swaying {
+                    original: material_h.clone(),
+                },
+            ));
+        count += 1;
+    }
+    if count > 0 {
+        println!("Wind: {count} foliage meshes swaying");
+    }
+}
+
+fn sync_wind_materials(
+    mut events: EventReader<AssetEvent<StandardMaterial>>,
+    standard_materials: Res<Assets<StandardMaterial>>,
+    mut wind_materials: ResMut<Assets<WindMaterial>>,
+    swapped: Res<ExtendedMaterials<WindExtension>>,
+) {
+    for event in events.read() {
+        let AssetEvent::Modified { id } = event else {
+            continue;
+        };
+        let (Some(wind_h), Some(material)) = (swapped.get(*id), standard_materials.get(*id)) else {
+            continue;
+        };
+        if let Some(wind) = wind_materials.get_mut(wind_h) {
+            wind.base = material.clone();
+        }
+    }
+}
@@ -0,0 +1,115 @@
+// Vertex shader for both the main pass and the prepass (depth, normals, motion vectors and
+// shadows) of `WindMaterial`. Static meshes only, skinning and morph targets are ignored.
+
+#import bevy_pbr::{
+    mesh_functions,
+    view_transformations::position_world_to_clip,
+}
+#import bevy_render::globals::Globals
+
+#ifdef PREPASS_PIPELINE
+#import bevy_pbr::prepass_io::{Vertex, VertexOutput}
+// The prepass view bind group has the globals at binding 1
+@group(0) @binding(1) var<uniform> globals: Globals;
+#else
+#import bevy_pbr::forward_io::{Vertex, VertexOutput}
+#import bevy_pbr::mesh_view_bindings::globals
+#endif
+
+@group(2) @binding(100) var<uniform> wind_strength: f32;
+
+// World space offset, a slow sway of the whole plant with a faster flutter per leaf. The phase
+// comes from the position so neighbouring plants don't move in lockstep.
+fn wind_offset(world_position: vec3<f32>, time: f32) -> vec3<f32> {
+    let phase = dot(world_position.xz, vec2(0.35, 0.25));
+    let sway = sin(time * 1.3 + phase) + 0.4 * sin(time * 3.1 + phase * 2.7);
+    let flutter = 0.3 * sin(time * 7.0 + dot(world_position, vec3(3.1, 2.3, 4.7)));
+    return vec3(1.0, 0.2, 0.6) * (sway + flutter) * wind_strength * 0.05;
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+
+    let world_from_local = mesh_functions::get_world_from_local(vertex.instance_index);
+    let rest_position = mesh_functions::mesh_position_local_to_world(
+        world_from_local,
+        vec4<f32>(vertex.position, 1.0)
+    );
+    out.world_position = vec4(
+        rest_position.xyz + wind_offset(rest_position.xyz, globals.time),
+        rest_position.w
+    );
+    out.position = position_world_to_clip(out.world_position.xyz);
+
+#ifdef VERTEX_UVS_A
+    out.uv = vertex.uv;
+#endif
+#ifdef VERTEX_UVS_B
+    out.uv_b = vertex.uv_b;
+#endif
+#ifdef VERTEX_COLORS
+    out.color = vertex.color;
+#endif
+#ifdef VERTEX_OUTPUT_INSTANCE_INDEX
+    out.instance_index = vertex.instance_index;
+#endif
+
+#ifdef PREPASS_PIPELINE
+
+#ifdef DEPTH_CLAMP_ORTHO
+    out.clip_position_unclamped = out.position;
+    out.position.z = min(out.position.z, 1.0);
+#endif
+#ifdef NORMAL_PREPASS_OR_DEFERRED_PREPASS
+    out.world_normal = mesh_functions::mesh_normal_local_to_world(
+        vertex.normal,
+        vertex.instance_index
+    );
+#ifdef VERTEX_TANGENTS
+    out.world_tangent = mesh_functions::mesh_tangent_local_to_world(
+        world_from_local,
+        vertex.tangent,
+        vertex.instance_index
+    );
+#endif
+#endif
+#ifdef MOTION_VECTOR_PREPASS
+    // Where the vertex was last frame, so TAA sees the sway as motion
+    let previous_rest_position = mesh_functions::mesh_position_local_to_world(
+        mesh_functions::get_previous_world_from_local(vertex.instance_index),
+        vec4<f32>(vertex.position, 1.0)
+    );
+    out.previous_world_position = vec4(
+        previous_rest_position.xyz
+            + wind_offset(previous_rest_position.xyz, globals.time - globals.delta_time),
+        previous_rest_position.w
+    );
+#endif
+
+#else // PREPASS_PIPELINE
+
+#ifdef VERTEX_NORMALS
+    out.world_normal = mesh_functions::mesh_normal_local_to_world(
+        vertex.normal,
+        vertex.instance_index
+    );
+#endif
+#ifdef VERTEX_TANGENTS
+    out.world_tangent = mesh_functions::mesh_tangent_local_to_world(
+        world_from_local,
+        vertex.tangent,
+        vertex.instance_index
+    );
+#endif
+#ifdef VISIBILITY_RANGE_DITHER
+    out.visibility_range_dither = mesh_functions::get_visibility_range_dither_level(
+        vertex.instance_index,
+        world_from_local[3]
+    );
+#endif
+
+#endif // PREPASS_PIPELINE
+
+    return out;
+}